@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use url::Url;
+use user_agent::DomainMap;
+
+/// The hosts requested in a crawl of `sites` sites, a few subdomains deep
+fn hosts(sites: usize) -> Vec<Url> {
+    (0..sites)
+        .map(|i| Url::parse(&format!("https://www.app{}.site{}.com/", i % 7, i)).unwrap())
+        .collect()
+}
+
+/// The closest of `domains` to the host of `url`, by each parent domain of the host as a
+/// `String`, as rules were looked up before `DomainMap`
+fn closest_by_suffixes<'d>(domains: &'d [String], url: &Url) -> Option<&'d String> {
+    let host = url.host_str()?.to_lowercase();
+    let mut suffixes = vec![host.clone()];
+    let mut rest = &host[..];
+    while let Some(i) = rest.find('.') {
+        rest = &rest[i + 1..];
+        suffixes.push(rest.to_owned());
+    }
+    suffixes
+        .iter()
+        .find_map(|suffix| domains.iter().find(|domain| *domain == suffix))
+}
+
+fn many_domains(c: &mut Criterion) {
+    let mut group = c.benchmark_group("many_domains");
+    for sites in [10, 100, 1000].iter() {
+        let domains = (0..*sites)
+            .map(|i| format!("site{}.com", i))
+            .collect::<Vec<_>>();
+        let mut map = DomainMap::new();
+        for domain in &domains {
+            map.insert(domain, ());
+        }
+        let urls = hosts(*sites);
+        group.bench_with_input(BenchmarkId::new("suffixes", sites), &urls, |b, urls| {
+            b.iter(|| {
+                urls.iter()
+                    .filter(|url| closest_by_suffixes(&domains, url).is_some())
+                    .count()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("domain_map", sites), &urls, |b, urls| {
+            b.iter(|| urls.iter().filter(|url| map.matches(url)).count())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, many_domains);
+criterion_main!(benches);
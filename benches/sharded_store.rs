@@ -0,0 +1,42 @@
+use cookie::Cookie as RawCookie;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::thread;
+use url::Url;
+use user_agent::{CookieStorage, ShardedCookieStore};
+
+const TASKS: usize = 128;
+const REQUESTS: usize = 100;
+
+/// `TASKS` threads, each making `REQUESTS` requests (a lookup and a Set-Cookie) to its own site
+fn requests(store: &ShardedCookieStore) {
+    let tasks = (0..TASKS)
+        .map(|task| {
+            let mut store = store.clone();
+            thread::spawn(move || {
+                let url = Url::parse(&format!("https://www.site{}.com/", task)).unwrap();
+                for i in 0..REQUESTS {
+                    let _ = store.get_request_cookies(&url);
+                    let cookie = RawCookie::parse(format!("visit={}; Max-Age=60", i)).unwrap();
+                    store.insert_raw(&cookie, &url).unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.join().unwrap();
+    }
+}
+
+fn concurrent_sites(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_sites");
+    for shards in [1, 8, 64].iter() {
+        let store = ShardedCookieStore::new(*shards);
+        group.bench_with_input(BenchmarkId::new("shards", shards), &store, |b, store| {
+            b.iter(|| requests(store))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, concurrent_sites);
+criterion_main!(benches);
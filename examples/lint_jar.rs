@@ -0,0 +1,27 @@
+//! Check the jar of a command-line tool (by default, that of the `persistent_login` example) for
+//! damaged, expired, or suspicious cookies, as ops tooling might before shipping a jar around.
+//!
+//! ```sh
+//! cargo run --example lint_jar -- [jar]
+//! ```
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use user_agent::{default_jar_path, jar};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+fn main() -> Result<(), Error> {
+    let path = match std::env::args().nth(1) {
+        Some(path) => PathBuf::from(path),
+        None => default_jar_path("user_agent-example").ok_or("no config directory")?,
+    };
+    let findings = jar::lint(BufReader::new(File::open(&path)?));
+    for finding in &findings {
+        println!("{}: {}", path.display(), finding);
+    }
+    if !findings.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
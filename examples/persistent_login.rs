@@ -0,0 +1,56 @@
+//! A command-line tool staying logged in to a site across runs: the session is loaded from a jar
+//! in the user's config directory, logs in only if the jar holds no login, and is saved back to
+//! the jar as the tool exits, even if fetching the page fails.
+//!
+//! ```sh
+//! USER_AGENT_USERNAME=me USER_AGENT_PASSWORD=secret \
+//!     cargo run --example persistent_login -- https://example.com/login https://example.com/account sid
+//! ```
+use std::env;
+use url::Url;
+use user_agent::{default_jar_path, ReqwestSession};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+fn main() -> Result<(), Error> {
+    env_logger::init();
+    let mut args = env::args().skip(1);
+    let usage = "usage: persistent_login <login url> <page url> <login cookie>";
+    let login_url = Url::parse(&args.next().ok_or(usage)?)?;
+    let page_url = Url::parse(&args.next().ok_or(usage)?)?;
+    let login_cookie = args.next().ok_or(usage)?;
+
+    let jar = default_jar_path("user_agent-example").ok_or("no config directory")?;
+    let mut session = ReqwestSession::open_jar(reqwest::blocking::Client::new(), &jar)?;
+    let fetched = fetch(&mut session, &login_url, &page_url, &login_cookie);
+    let report = session.shutdown()?;
+    println!(
+        "saved {} cookies to {}",
+        report.cookies_saved,
+        jar.display()
+    );
+    let body = fetched?;
+    println!("{}", body);
+    Ok(())
+}
+
+fn fetch(
+    session: &mut ReqwestSession,
+    login_url: &Url,
+    page_url: &Url,
+    login_cookie: &str,
+) -> Result<String, Error> {
+    let logged_in = session.ensure_login(page_url, login_cookie, |session| {
+        let username = env::var("USER_AGENT_USERNAME")?;
+        let password = env::var("USER_AGENT_PASSWORD")?;
+        let response = session.post_with(login_url.clone(), |request| {
+            request.form(&[("username", username), ("password", password)])
+        })?;
+        response.error_for_status()?;
+        Ok(())
+    })?;
+    if logged_in {
+        eprintln!("logged in to {}", login_url);
+    }
+    Ok(session.get(page_url.clone())?.text()?)
+}
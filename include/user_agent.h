@@ -0,0 +1,60 @@
+/*
+ * C declarations for the `ffi` module of the user_agent crate, a C ABI over `ReqwestSession`.
+ * Build the shared library with e.g.
+ * `cargo rustc --release --features ffi -- --crate-type cdylib`.
+ *
+ * Strings passed in are NUL-terminated UTF-8; strings returned are owned by the caller and must
+ * be released with `ua_string_free`. On failure (including a panic in the library), functions
+ * return `NULL` or -1, and `ua_last_error` describes the failure.
+ */
+#ifndef USER_AGENT_H
+#define USER_AGENT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque handle to a session */
+typedef struct UaSession UaSession;
+
+/* The message for the last failure on this thread, or `NULL` if none. Owned by the library and
+ * valid until the next failing call on this thread. */
+const char *ua_last_error(void);
+
+/* Create a new session with an empty cookie store */
+UaSession *ua_session_new(void);
+
+/* Create a new session with cookies loaded from the `save_json` formatted file at `path` */
+UaSession *ua_session_load_json(const char *path);
+
+/* Save the persistent cookies of `session` to the file at `path`. Returns 0 on success. */
+int ua_session_save_json(const UaSession *session, const char *path);
+
+/* Release `session`. Passing `NULL` is a no-op. */
+void ua_session_free(UaSession *session);
+
+/* Store `set_cookie` (a Set-Cookie header value) as if received in a response from `url`.
+ * Returns 0 on success. */
+int ua_session_add_cookie(UaSession *session, const char *set_cookie, const char *url);
+
+/* The Cookie header value `session` would send in a request to `url`; an empty string if no
+ * cookies match. */
+char *ua_session_cookies_for_url(const UaSession *session, const char *url);
+
+/* Send a `method` request to `url` through `session`, with the `body_len` bytes at `body` as the
+ * request body (`body` may be `NULL` for no body). Returns the response body, and writes the
+ * status code to `status`, if non-`NULL`. */
+char *ua_session_request(UaSession *session, const char *method, const char *url,
+                         const uint8_t *body, size_t body_len, uint16_t *status);
+
+/* Release a string returned by this library. Passing `NULL` is a no-op. */
+void ua_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* USER_AGENT_H */
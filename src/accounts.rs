@@ -0,0 +1,347 @@
+use crate::domains::DomainMap;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use cookie::Cookie as RawCookie;
+use url::Url;
+
+/// Separates an account label from the name of a cookie stored for the account
+const SEPARATOR: char = '~';
+
+/// How a `Session` namespaces the cookies of the accounts it makes requests as
+#[derive(Debug, Default)]
+pub(crate) struct AccountRouting {
+    /// The domains whose cookies are namespaced; all domains', if empty
+    domains: DomainMap<()>,
+    /// The account requests are currently made as, if not the default
+    active: Option<String>,
+    /// Whether accounts are in use (see `Session::with_accounts` and `with_account_domain`); if
+    /// not, no cookies are namespaced, so names containing `~` are left alone
+    in_use: bool,
+}
+
+fn is_valid_account(account: &str) -> bool {
+    !account.is_empty()
+        && account
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl AccountRouting {
+    fn is_routed(&self, url: &Url) -> bool {
+        self.in_use && (self.domains.is_empty() || self.domains.matches(url))
+    }
+
+    /// The cookies of the active account among `cookies`, to be sent to `url`, with their
+    /// account labels removed
+    pub(crate) fn outgoing(
+        &self,
+        url: &Url,
+        cookies: Vec<RawCookie<'static>>,
+    ) -> Vec<RawCookie<'static>> {
+        self.unlabel(url, self.select(url, cookies))
+    }
+
+    /// The cookies of the active account among `cookies`, to be sent to `url`, still labelled as
+    /// stored
+    pub(crate) fn select(
+        &self,
+        url: &Url,
+        cookies: Vec<RawCookie<'static>>,
+    ) -> Vec<RawCookie<'static>> {
+        if !self.is_routed(url) {
+            return cookies;
+        }
+        cookies
+            .into_iter()
+            .filter(
+                |cookie| match (self.active.as_ref(), cookie.name().split_once(SEPARATOR)) {
+                    (Some(active), Some((account, _))) => account == active,
+                    (None, None) => true,
+                    _ => false,
+                },
+            )
+            .collect()
+    }
+
+    /// `cookies`, as selected by `select` to be sent to `url`, with their account labels removed
+    pub(crate) fn unlabel(
+        &self,
+        url: &Url,
+        cookies: Vec<RawCookie<'static>>,
+    ) -> Vec<RawCookie<'static>> {
+        if self.active.is_none() || !self.is_routed(url) {
+            return cookies;
+        }
+        cookies
+            .into_iter()
+            .map(|mut cookie| {
+                if let Some((_, name)) = cookie.name().split_once(SEPARATOR) {
+                    let name = name.to_owned();
+                    cookie.set_name(name);
+                }
+                cookie
+            })
+            .collect()
+    }
+
+    /// `cookies`, received from `url`, labelled with the active account
+    pub(crate) fn incoming(
+        &self,
+        url: &Url,
+        cookies: Vec<RawCookie<'static>>,
+    ) -> Vec<RawCookie<'static>> {
+        let active = match self.active {
+            Some(ref active) if self.is_routed(url) => active,
+            _ => return cookies,
+        };
+        cookies
+            .into_iter()
+            .map(|mut cookie| {
+                let name = format!("{}{}{}", active, SEPARATOR, cookie.name());
+                cookie.set_name(name);
+                cookie
+            })
+            .collect()
+    }
+}
+
+/// A `Session` making requests as one of several accounts, as returned by `Session::as_account`
+pub struct AccountSession<'s, C: SessionClient, S: CookieStorage> {
+    session: &'s mut Session<C, S>,
+    account: String,
+}
+
+macro_rules! define_account_fns {
+    ($with_fn: ident, $send_fn: ident) => {
+        pub fn $with_fn<U, P>(
+            &mut self,
+            url: U,
+            prepare: P,
+        ) -> Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+        where
+            P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+            U: IntoUrl,
+        {
+            self.with_account(|session| session.$with_fn(url, prepare))
+        }
+
+        pub fn $send_fn<U: IntoUrl>(
+            &mut self,
+            url: U,
+        ) -> Result<<C as SessionClient>::Response, <C as SessionClient>::SendError> {
+            self.with_account(|session| session.$send_fn(url))
+        }
+    };
+}
+
+impl<'s, C: SessionClient, S: CookieStorage> AccountSession<'s, C, S> {
+    fn with_account<T, F: FnOnce(&mut Session<C, S>) -> T>(&mut self, f: F) -> T {
+        let previous = self.session.accounts.active.replace(self.account.clone());
+        let result = f(self.session);
+        self.session.accounts.active = previous;
+        result
+    }
+
+    define_account_fns!(get_with, get);
+    define_account_fns!(put_with, put);
+    define_account_fns!(head_with, head);
+    define_account_fns!(delete_with, delete);
+    define_account_fns!(post_with, post);
+    define_account_fns!(patch_with, patch);
+    define_account_fns!(options_with, options);
+
+    /// Remove the account's cookies from the store (as by `Session::remove_cookie`), e.g. to log
+    /// the account out, returning the number of unexpired cookies removed
+    pub fn clear_cookies(&mut self) -> usize {
+        let prefix = format!("{}{}", self.account, SEPARATOR);
+        let cookies = self
+            .session
+            .store
+            .iter_unexpired()
+            .filter(|c| c.name().starts_with(&prefix))
+            .collect::<Vec<_>>();
+        cookies
+            .iter()
+            .filter_map(|c| {
+                let (domain, path) = (String::from(&c.domain), String::from(&c.path));
+                self.session.remove_cookie(&domain, &path, c.name())
+            })
+            .count()
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Namespace cookies by account (see `as_account`), for every domain unless limited with
+    /// `with_account_domain`. Until enabled, cookies whose names contain `~` are sent and stored
+    /// like any other; once enabled, those of the store (e.g. as loaded from a jar) are kept to
+    /// their accounts from the first request.
+    pub fn with_accounts(mut self) -> Self {
+        self.accounts.in_use = true;
+        self
+    }
+
+    /// Namespace the cookies of `domain` and its subdomains by account (see `as_account`),
+    /// enabling accounts as by `with_accounts`. If no domain is given, the cookies of every
+    /// domain are namespaced.
+    pub fn with_account_domain(mut self, domain: &str) -> Self {
+        self.accounts.domains.insert(domain, ());
+        self.accounts.in_use = true;
+        self
+    }
+
+    /// Make requests as `account`, sharing the session's client and store, but sending and
+    /// storing only the account's cookies for namespaced domains (see `with_account_domain`).
+    /// An account's cookies are kept in the store (and its jar) named `{account}~{name}`, and
+    /// requests made directly through the session then use only unlabelled cookies.
+    ///
+    /// # Panics
+    ///
+    /// If accounts are not enabled (see `with_accounts`), or `account` is empty or has characters
+    /// other than ASCII letters, digits, `-`, and `_`.
+    pub fn as_account(&mut self, account: &str) -> AccountSession<'_, C, S> {
+        assert!(self.accounts.in_use, "accounts not enabled; see with_accounts");
+        assert!(is_valid_account(account), "invalid account {:?}", account);
+        AccountSession {
+            session: self,
+            account: account.to_owned(),
+        }
+    }
+
+    /// The accounts with unexpired cookies in the store, in order
+    pub fn accounts(&self) -> Vec<String> {
+        let mut accounts = self
+            .store
+            .iter_unexpired()
+            .filter_map(|c| {
+                let (account, _) = c.name().split_once(SEPARATOR)?;
+                Some(account.to_owned()).filter(|account| is_valid_account(account))
+            })
+            .collect::<Vec<_>>();
+        accounts.sort();
+        accounts.dedup();
+        accounts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AccountRouting;
+    use crate::domains::DomainMap;
+    use cookie::Cookie as RawCookie;
+    use url::Url;
+
+    #[test]
+    fn accounts() {
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let other = Url::parse("https://www.example.org/").unwrap();
+        let mut routing = AccountRouting {
+            domains: DomainMap::new(),
+            active: Some("alice".to_owned()),
+            in_use: true,
+        };
+        routing.domains.insert("example.com", ());
+        let cookies = || vec![RawCookie::new("id", "1")];
+        assert_eq!("alice~id", routing.incoming(&url, cookies())[0].name());
+        assert_eq!("id", routing.incoming(&other, cookies())[0].name());
+
+        let stored = vec![
+            RawCookie::new("alice~id", "1"),
+            RawCookie::new("bob~id", "2"),
+            RawCookie::new("id", "3"),
+        ];
+        let sent = routing.outgoing(&url, stored.clone());
+        assert_eq!(vec![RawCookie::new("id", "1")], sent);
+        assert_eq!(3, routing.outgoing(&other, stored.clone()).len());
+        routing.active = None;
+        assert_eq!(
+            vec![RawCookie::new("id", "3")],
+            routing.outgoing(&url, stored)
+        );
+    }
+
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn sessions() {
+        use crate::ReqwestSession;
+
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new()).with_accounts();
+        let active = session
+            .as_account("alice")
+            .with_account(|session| session.accounts.active.clone());
+        assert_eq!(Some("alice".to_owned()), active);
+        assert_eq!(None, session.accounts.active);
+
+        for set_cookie in &["bob~id=2", "alice~id=1", "alice~pref=1", "id=3", "a~b~c=4"] {
+            session.store.parse(set_cookie, &url).unwrap();
+        }
+        let accounts = vec!["a".to_owned(), "alice".to_owned(), "bob".to_owned()];
+        assert_eq!(accounts, session.accounts());
+        assert_eq!(2, session.as_account("alice").clear_cookies());
+        assert_eq!(vec!["a", "bob"], session.accounts());
+        assert!(session.get_cookie("www.example.com", "/", "id").is_some());
+    }
+
+    #[test]
+    fn unused() {
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let mut routing = AccountRouting::default();
+        let stored = vec![RawCookie::new("a~b", "1"), RawCookie::new("id", "2")];
+        assert_eq!(stored, routing.outgoing(&url, stored.clone()));
+        routing.in_use = true;
+        assert_eq!(
+            vec![RawCookie::new("id", "2")],
+            routing.outgoing(&url, stored)
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn plain_session() {
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let client = MockClient::new()
+            .with_response(MockResponse::new(200).with_set_cookie("a~b=1"))
+            .with_response(MockResponse::new(200));
+        let mut session = Session::new(client);
+        session.get("https://www.example.com/").unwrap();
+        session.get("https://www.example.com/").unwrap();
+        let last = session.client.last_request().unwrap();
+        assert_eq!(vec!["a~b"], last.cookie_names());
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn loaded_jar() {
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let mut store = cookie_store::CookieStore::default();
+        for set_cookie in &["alice~sid=1", "bob~sid=2", "theme=dark"] {
+            store.parse(set_cookie, &url).unwrap();
+        }
+        let client = MockClient::new()
+            .with_response(MockResponse::new(200))
+            .with_response(MockResponse::new(200));
+        let mut session = Session::new(client).with_store(store).with_accounts();
+        session.get(url.clone()).unwrap();
+        let last = session.client.last_request().unwrap();
+        assert_eq!(vec!["theme"], last.cookie_names());
+        session.as_account("bob").get(url).unwrap();
+        let last = session.client.last_request().unwrap();
+        assert_eq!(Some("sid=2"), last.header_value("cookie"));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    #[should_panic(expected = "accounts not enabled")]
+    fn not_enabled() {
+        use crate::testing::MockClient;
+        use crate::Session;
+
+        Session::new(MockClient::new()).as_account("alice");
+    }
+}
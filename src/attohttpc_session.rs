@@ -0,0 +1,367 @@
+//! A `Session` over [attohttpc](https://crates.io/crates/attohttpc) 0.16.
+//!
+//! attohttpc does not report the URL a redirected response was served from, so requests are
+//! created with `follow_redirects(false)`, and each response's `final_url` is the URL it was
+//! requested from; use `RedirectMode::Session` to have the session follow redirects, storing the
+//! cookies of each. Requests are created by an `attohttpc::Session`, with its settings, except
+//! for those with methods it has no constructor for (e.g. WebDAV methods), which are created
+//! with default settings.
+use crate::content::{BodyChunks, ContentResponse};
+use crate::paginate::HeaderResponse;
+use crate::session::{
+    ClassifySendError, SendErrorKind, Session, SessionClient, SessionRequest, SessionResponse,
+};
+use crate::spec::{SpecRequest, StatusResponse};
+use crate::utils::parse_set_cookie_values;
+use attohttpc::body::Bytes;
+use attohttpc::header::{HeaderName, HeaderValue, CONTENT_TYPE, COOKIE, DATE, SET_COOKIE};
+use attohttpc::{ErrorKind, RequestBuilder, Response, StatusCode};
+use cookie::Cookie as RawCookie;
+use log::debug;
+use std::io::Read;
+use url::Url;
+
+/// A request to be sent by an `attohttpc::Session`, with a body of bytes (set with `bytes`)
+pub type AttohttpcRequest = RequestBuilder<Bytes<Vec<u8>>>;
+
+impl SessionRequest for AttohttpcRequest {
+    fn add_cookies(self, cookies: Vec<&RawCookie<'static>>) -> Self {
+        if cookies.is_empty() {
+            debug!("no cookies to add to request");
+            return self;
+        }
+        let pairs = cookies
+            .iter()
+            .map(|rc| rc.encoded().to_string())
+            .collect::<Vec<_>>();
+        self.add_cookie_pairs(pairs)
+    }
+
+    fn add_cookie_pairs(self, pairs: Vec<String>) -> Self {
+        if pairs.is_empty() {
+            self
+        } else {
+            self.add_header(COOKIE.as_str(), &pairs.join("; "))
+        }
+    }
+
+    fn add_header(self, name: &str, value: &str) -> Self {
+        // attohttpc panics on invalid headers, so they are checked first
+        match (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            (Ok(name), Ok(value)) => self.header(name, value),
+            _ => {
+                debug!("not adding invalid header {}", name);
+                self
+            }
+        }
+    }
+}
+
+impl SpecRequest for AttohttpcRequest {
+    fn header(self, name: &str, value: &str) -> Self {
+        self.add_header(name, value)
+    }
+
+    fn body(self, body: String) -> Self {
+        self.bytes(body.into_bytes())
+    }
+}
+
+/// A response received by an `attohttpc::Session`, whose body is read as it is consumed
+#[derive(Debug)]
+pub struct AttohttpcResponse {
+    response: Response,
+    url: Url,
+}
+
+impl AttohttpcResponse {
+    pub fn status(&self) -> StatusCode {
+        self.response.status()
+    }
+
+    pub fn headers(&self) -> &attohttpc::header::HeaderMap {
+        self.response.headers()
+    }
+
+    /// The URL the response was requested from
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn into_inner(self) -> Response {
+        self.response
+    }
+
+    fn header_strs(&self, name: &str) -> impl Iterator<Item = &str> {
+        self.headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+    }
+}
+
+impl SessionResponse for AttohttpcResponse {
+    type Url = Url;
+
+    fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+        parse_set_cookie_values(
+            self.headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .map(|set_cookie| set_cookie.as_bytes()),
+        )
+    }
+
+    fn final_url(&self) -> Option<&Url> {
+        Some(&self.url)
+    }
+
+    fn date(&self) -> Option<String> {
+        self.header_strs(DATE.as_str()).next().map(str::to_owned)
+    }
+
+    fn raw_set_cookie(&self) -> Vec<String> {
+        let headers = self.headers();
+        headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .chain(headers.get_all("set-cookie2").iter())
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+            .collect()
+    }
+
+    fn clear_site_data(&self) -> Option<String> {
+        let values = self.header_strs("clear-site-data").collect::<Vec<_>>();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    fn is_proxy_response(&self) -> bool {
+        self.status() == StatusCode::PROXY_AUTHENTICATION_REQUIRED
+    }
+
+    fn status_code(&self) -> Option<u16> {
+        Some(self.status().as_u16())
+    }
+
+    fn retry_after(&self) -> Option<String> {
+        self.header_strs("retry-after").next().map(str::to_owned)
+    }
+
+    fn location(&self) -> Option<String> {
+        self.header_strs("location").next().map(str::to_owned)
+    }
+
+    fn strict_transport_security(&self) -> Option<String> {
+        self.header_strs("strict-transport-security")
+            .next()
+            .map(str::to_owned)
+    }
+
+    fn www_authenticate(&self) -> Vec<String> {
+        self.header_strs("www-authenticate")
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+impl StatusResponse for AttohttpcResponse {
+    fn status(&self) -> u16 {
+        AttohttpcResponse::status(self).as_u16()
+    }
+}
+
+impl HeaderResponse for AttohttpcResponse {
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.header_strs(name).collect()
+    }
+
+    fn header_names(&self) -> Vec<String> {
+        self.headers().keys().map(|k| k.as_str().to_owned()).collect()
+    }
+}
+
+impl ContentResponse for AttohttpcResponse {
+    type BodyError = AttohttpcSessionError;
+
+    fn content_type(&self) -> Option<&str> {
+        self.header_strs(CONTENT_TYPE.as_str()).next()
+    }
+
+    fn into_body(self) -> Result<Vec<u8>, Self::BodyError> {
+        self.response
+            .bytes()
+            .map_err(AttohttpcSessionError::Attohttpc)
+    }
+
+    fn into_chunks(self) -> BodyChunks<Self::BodyError> {
+        let mut response = self.response;
+        let mut buffer = [0; 8192];
+        Box::new(std::iter::from_fn(move || {
+            match response.read(&mut buffer) {
+                Ok(0) => None,
+                Ok(read) => Some(Ok(buffer[..read].to_vec())),
+                Err(e) => Some(Err(AttohttpcSessionError::Body(e))),
+            }
+        }))
+    }
+}
+
+#[derive(Debug)]
+pub enum AttohttpcSessionError {
+    ParseUrlError(url::ParseError),
+    /// Sending the request (or reading its response body) failed
+    Attohttpc(attohttpc::Error),
+    /// Reading a response body failed
+    Body(std::io::Error),
+}
+
+impl std::fmt::Display for AttohttpcSessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AttohttpcSessionError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            AttohttpcSessionError::Attohttpc(e) => write!(f, "attohttpc error: {}", e),
+            AttohttpcSessionError::Body(e) => write!(f, "Body error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AttohttpcSessionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AttohttpcSessionError::ParseUrlError(e) => Some(e),
+            AttohttpcSessionError::Attohttpc(e) => Some(e),
+            AttohttpcSessionError::Body(e) => Some(e),
+        }
+    }
+}
+
+impl ClassifySendError for AttohttpcSessionError {
+    fn kind(&self) -> SendErrorKind {
+        let io_kind = |e: &std::io::Error| match e.kind() {
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => SendErrorKind::Timeout,
+            std::io::ErrorKind::ConnectionRefused => SendErrorKind::Connect,
+            _ => SendErrorKind::Other,
+        };
+        match self {
+            AttohttpcSessionError::ParseUrlError(_) => SendErrorKind::Url,
+            AttohttpcSessionError::Attohttpc(e) => match e.kind() {
+                ErrorKind::InvalidBaseUrl
+                | ErrorKind::InvalidUrlHost
+                | ErrorKind::InvalidUrlPort => SendErrorKind::Url,
+                ErrorKind::Io(e) => io_kind(e),
+                ErrorKind::Tls(_) => SendErrorKind::Tls,
+                ErrorKind::StatusCode(status) => SendErrorKind::Status(status.as_u16()),
+                _ => SendErrorKind::Other,
+            },
+            AttohttpcSessionError::Body(e) => io_kind(e),
+        }
+    }
+}
+
+impl From<url::ParseError> for AttohttpcSessionError {
+    fn from(e: url::ParseError) -> Self {
+        AttohttpcSessionError::ParseUrlError(e)
+    }
+}
+
+pub type AttohttpcSession = Session<attohttpc::Session>;
+
+impl SessionClient for attohttpc::Session {
+    type Request = AttohttpcRequest;
+    type Response = AttohttpcResponse;
+    type SendError = AttohttpcSessionError;
+
+    fn request(&self, method: &http::Method, url: &Url) -> Self::Request {
+        let builder = match method.as_str() {
+            "GET" => self.get(url),
+            "PUT" => self.put(url),
+            "HEAD" => self.head(url),
+            "DELETE" => self.delete(url),
+            "POST" => self.post(url),
+            "PATCH" => self.patch(url),
+            "OPTIONS" => self.options(url),
+            "TRACE" => self.trace(url),
+            _ => RequestBuilder::new(method.clone(), url),
+        };
+        builder.follow_redirects(false).bytes(vec![])
+    }
+
+    fn send(&self, mut request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let url = request.inspect().url().clone();
+        let response = request.send().map_err(AttohttpcSessionError::Attohttpc)?;
+        Ok(AttohttpcResponse { response, url })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AttohttpcSession;
+    use crate::{ClassifySendError, ContentResponse, Method, SendErrorKind};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve `responses` in turn, one per connection, returning the requests' headers
+    fn serve(responses: Vec<&'static str>) -> (u16, thread::JoinHandle<Vec<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let reader = BufReader::new(stream.try_clone().unwrap());
+                    let headers = reader
+                        .lines()
+                        .map(Result::unwrap)
+                        .take_while(|line| !line.is_empty())
+                        .collect::<Vec<_>>();
+                    (&stream).write_all(response.as_bytes()).unwrap();
+                    headers
+                })
+                .collect()
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn attohttpc_session() {
+        let (port, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nSet-Cookie: id=1\r\nSet-Cookie: theme=dark\r\n\
+             Content-Length: 5\r\nConnection: close\r\n\r\nhello",
+            "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 207 Multi-Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let url = format!("http://127.0.0.1:{}/", port);
+        let mut session = AttohttpcSession::new(attohttpc::Session::new());
+        let response = session.get(&url).unwrap();
+        assert_eq!(b"hello".to_vec(), response.into_body().unwrap());
+        session
+            .get_with(&url, |r| r.header("x-probe", "1"))
+            .unwrap();
+        let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+        session.request_with(&propfind, &url, |r| r).unwrap();
+
+        let requests = server.join().unwrap();
+        let has = |headers: &[String], header: &str| {
+            headers.iter().any(|h| h.eq_ignore_ascii_case(header))
+        };
+        assert!(!requests[0]
+            .iter()
+            .any(|h| h.to_lowercase().starts_with("cookie:")));
+        assert!(has(&requests[1], "x-probe: 1"));
+        assert!(has(&requests[1], "cookie: id=1; theme=dark"));
+        assert_eq!("PROPFIND / HTTP/1.1", requests[2][0]);
+        assert!(has(&requests[2], "cookie: id=1; theme=dark"));
+
+        let refused = session.get(&url).err().unwrap();
+        assert_eq!(SendErrorKind::Connect, refused.kind());
+    }
+}
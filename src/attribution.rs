@@ -0,0 +1,210 @@
+use crate::public_suffix::PublicSuffixes;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use log::{debug, warn};
+use std::net::IpAddr;
+use url::{Host, Url};
+
+/// What a `Session` does with the cookies of a response for which the URL it was ultimately
+/// served from is unknown: the response provides neither a `final_url` nor its
+/// `redirect_locations`, so it may have been redirected from the request URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownFinalUrl {
+    /// Attribute the cookies to the request URL (the default)
+    #[default]
+    UseRequestUrl,
+    /// As `UseRequestUrl`, but log a warning
+    Warn,
+    /// Do not store the cookies; they are reported to any `StoreEventSink`s as rejected with
+    /// `RejectionReason::UnknownUrl`
+    Discard,
+}
+
+/// Which URL(s) a `Session` attributes a response's cookies to when the response was served from
+/// a different site than was requested, e.g. after a redirect to a single sign-on provider.
+/// Sites are compared by their registrable domains, per any public suffix list (see
+/// `Session::with_public_suffix_list`), or else by their last two domain labels (so
+/// `www.example.com` and `login.example.com` are the same site); or by IP address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrossSiteRedirect {
+    /// Store the cookies as received from the final URL (the default, as browsers do)
+    #[default]
+    FinalSite,
+    /// Store the cookies as if received from the request URL
+    RequestSite,
+    /// Attempt to store the cookies for both the request and final URLs
+    Both,
+}
+
+/// The site of `url`: its last two domain labels, or its IP address
+pub(crate) fn site(url: &Url) -> Option<String> {
+    match url.host()? {
+        Host::Domain(domain) => Some(domain_site(domain)),
+        host => Some(host.to_string()),
+    }
+}
+
+/// The site of `domain`: its last two labels, or the whole of an IP address
+pub(crate) fn domain_site(domain: &str) -> String {
+    let address = domain.trim_start_matches('[').trim_end_matches(']');
+    if address.parse::<IpAddr>().is_ok() {
+        return domain.to_lowercase();
+    }
+    let labels = domain.trim_end_matches('.').rsplit('.').take(2);
+    let mut labels = labels.collect::<Vec<_>>();
+    labels.reverse();
+    labels.join(".").to_lowercase()
+}
+
+/// The URLs to attribute the cookies of a response for `request_url`, served from `final_url`, to
+pub(crate) fn redirect_targets(
+    policy: CrossSiteRedirect,
+    suffixes: &PublicSuffixes,
+    request_url: &Url,
+    final_url: &Url,
+) -> Vec<Url> {
+    if suffixes.site(request_url) == suffixes.site(final_url) {
+        return vec![final_url.clone()];
+    }
+    debug!(
+        "response for {} served from a different site, {}",
+        request_url, final_url
+    );
+    match policy {
+        CrossSiteRedirect::FinalSite => vec![final_url.clone()],
+        CrossSiteRedirect::RequestSite => vec![request_url.clone()],
+        CrossSiteRedirect::Both => vec![request_url.clone(), final_url.clone()],
+    }
+}
+
+/// Follow the chain of `Location` header values `locations` from `url`, returning the final
+/// URL, or `None` if a location cannot be resolved
+pub(crate) fn follow_locations(url: &Url, locations: &[String]) -> Option<Url> {
+    locations.iter().try_fold(url.clone(), |current, location| {
+        current
+            .join(location)
+            .map_err(|e| {
+                debug!(
+                    "unable to resolve Location {} from {}: {}",
+                    location, current, e
+                )
+            })
+            .ok()
+    })
+}
+
+/// Determine the URL to attribute a response's cookies to, when the response has no
+/// `final_url`. Returns `None` if the cookies should be discarded.
+pub(crate) fn attribute(policy: UnknownFinalUrl, url: &Url, locations: &[String]) -> Option<Url> {
+    if !locations.is_empty() {
+        let final_url = follow_locations(url, locations);
+        if final_url.is_none() && policy != UnknownFinalUrl::Discard {
+            warn!(
+                "unable to follow redirects from {}; attributing cookies to it",
+                url
+            );
+            return Some(url.clone());
+        }
+        return final_url;
+    }
+    match policy {
+        UnknownFinalUrl::UseRequestUrl => Some(url.clone()),
+        UnknownFinalUrl::Warn => {
+            warn!("response has no final URL; attributing cookies to {}", url);
+            Some(url.clone())
+        }
+        UnknownFinalUrl::Discard => None,
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Set the handling of cookies from responses whose final URL is unknown
+    pub fn with_unknown_final_url(mut self, policy: UnknownFinalUrl) -> Self {
+        self.unknown_final_url = policy;
+        self
+    }
+
+    /// Set the handling of cookies from responses served from a different site than was
+    /// requested
+    pub fn with_cross_site_redirect(mut self, policy: CrossSiteRedirect) -> Self {
+        self.cross_site_redirect = policy;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        attribute, domain_site, redirect_targets, site, CrossSiteRedirect, UnknownFinalUrl,
+    };
+    use crate::public_suffix::PublicSuffixes;
+    use url::Url;
+
+    #[test]
+    fn locations() {
+        let url = Url::parse("http://www.example.com/login").unwrap();
+        let locations = vec![
+            "https://sso.example.org/auth?next=1".to_owned(),
+            "/done".to_owned(),
+        ];
+        assert_eq!(
+            Some(Url::parse("https://sso.example.org/done").unwrap()),
+            attribute(UnknownFinalUrl::Discard, &url, &locations)
+        );
+        assert_eq!(
+            Some(url.clone()),
+            attribute(UnknownFinalUrl::Warn, &url, &[])
+        );
+        assert_eq!(None, attribute(UnknownFinalUrl::Discard, &url, &[]));
+    }
+
+    #[test]
+    fn cross_site() {
+        let url = |s| Url::parse(s).unwrap();
+        assert_eq!(
+            Some("example.com".to_owned()),
+            site(&url("https://WWW.Example.com./a"))
+        );
+        assert_eq!(
+            Some("127.0.0.1".to_owned()),
+            site(&url("http://127.0.0.1:8080/"))
+        );
+        assert_eq!("127.0.0.1", domain_site("127.0.0.1"));
+        assert_eq!("[::1]", domain_site("[::1]"));
+
+        let suffixes = PublicSuffixes::default();
+        let request = url("https://www.example.com/login");
+        let same_site = url("https://login.example.com/");
+        let idp = url("https://idp.example.org/");
+        assert_eq!(
+            vec![same_site.clone()],
+            redirect_targets(
+                CrossSiteRedirect::RequestSite,
+                &suffixes,
+                &request,
+                &same_site
+            )
+        );
+        assert_eq!(
+            vec![idp.clone()],
+            redirect_targets(CrossSiteRedirect::FinalSite, &suffixes, &request, &idp)
+        );
+        assert_eq!(
+            vec![request.clone()],
+            redirect_targets(CrossSiteRedirect::RequestSite, &suffixes, &request, &idp)
+        );
+        assert_eq!(
+            vec![request.clone(), idp.clone()],
+            redirect_targets(CrossSiteRedirect::Both, &suffixes, &request, &idp)
+        );
+
+        // sites under a public suffix are distinct, per the list
+        let suffixes = PublicSuffixes::new(|domain| domain == "co.uk");
+        let request = url("https://a.co.uk/login");
+        let other = url("https://b.co.uk/");
+        assert_eq!(
+            vec![request.clone()],
+            redirect_targets(CrossSiteRedirect::RequestSite, &suffixes, &request, &other)
+        );
+    }
+}
@@ -0,0 +1,534 @@
+use crate::session::{Exchange, Session, SessionClient, SessionRequest, SessionResponse};
+use crate::storage::CookieStorage;
+use log::debug;
+use std::collections::HashMap;
+use std::fmt;
+use url::Url;
+
+/// A credential an `AuthManager` answers the challenges of an origin with
+pub enum Credential {
+    /// Sent as `Authorization: Basic`, answering `Basic` challenges
+    Basic { username: String, password: String },
+    /// Sent as `Authorization: Bearer`, answering `Bearer` challenges
+    Bearer(String),
+    /// A bearer token returned by the function, which is called when the origin first challenges,
+    /// and again for a new token whenever the origin rejects the last one; returning `None`
+    /// leaves the challenge unanswered
+    Refresh(Box<dyn FnMut() -> Option<String> + Send>),
+}
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credential::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+            Credential::Bearer(_) => f.write_str("Bearer(<redacted>)"),
+            Credential::Refresh(_) => f.write_str("Refresh(..)"),
+        }
+    }
+}
+
+impl Credential {
+    /// The (lowercase) scheme of the challenges the credential answers
+    fn scheme(&self) -> &'static str {
+        match self {
+            Credential::Basic { .. } => "basic",
+            Credential::Bearer(_) | Credential::Refresh(_) => "bearer",
+        }
+    }
+}
+
+/// A credential registered with an `AuthManager`
+#[derive(Debug)]
+struct Registered {
+    origin: String,
+    /// The realm the credential is for, or `None` for any realm of the origin
+    realm: Option<String>,
+    credential: Credential,
+    /// The token last returned by a `Credential::Refresh`
+    token: Option<String>,
+}
+
+/// An authentication challenge of a `WWW-Authenticate` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Challenge {
+    /// The scheme, in lowercase
+    scheme: String,
+    realm: Option<String>,
+}
+
+/// Answers the `401 Unauthorized` challenges of origins with the credentials registered for
+/// them, set with `Session::with_auth_manager`.
+///
+/// When a response is a `401` whose `WWW-Authenticate` header challenges with a scheme (and
+/// realm, for a credential registered for one) a credential of its origin answers, the session
+/// re-sends the request once with that credential in its `Authorization` header; a credential
+/// registered for the challenged realm is preferred over one for any realm. The manager then
+/// remembers which realm and credential the origin needs, and sends the credential with later
+/// requests to the origin without waiting to be challenged. Credentials are never sent to other
+/// origins, including when redirected to one. Requests which cannot be copied (e.g. with a
+/// streaming body) are not re-sent, but are still sent the credential the origin is known to
+/// need.
+///
+/// ```
+/// # #[cfg(feature = "reqwest-session")]
+/// # {
+/// use url::Url;
+/// use user_agent::{AuthManager, ReqwestSession};
+///
+/// let api = Url::parse("https://api.example.com/").unwrap();
+/// let auth = AuthManager::new().with_basic(&api, "user", "secret");
+/// let session = ReqwestSession::new(reqwest::blocking::Client::new()).with_auth_manager(auth);
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct AuthManager {
+    credentials: Vec<Registered>,
+    /// The realm each origin last challenged in, and the credential (by its index in
+    /// `credentials`) which answered it
+    needed: HashMap<String, (Option<String>, usize)>,
+}
+
+/// The origin of `url`, if it has a (non-opaque) one
+fn origin_of(url: &Url) -> Option<String> {
+    let origin = url.origin();
+    if origin.is_tuple() {
+        Some(origin.ascii_serialization())
+    } else {
+        None
+    }
+}
+
+/// `value` split at each comma outside a quoted string
+fn split_unquoted(value: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&value[start..]);
+    items
+}
+
+/// The challenges of the `WWW-Authenticate` header `values`, each of which may hold several
+fn parse_challenges(values: &[String]) -> Vec<Challenge> {
+    let mut challenges: Vec<Challenge> = vec![];
+    for value in values {
+        for item in split_unquoted(value).into_iter().map(str::trim) {
+            let (first, rest) = match item.split_once(char::is_whitespace) {
+                Some((first, rest)) => (first, rest.trim()),
+                None => (item, ""),
+            };
+            // a challenge starts with its scheme, which is followed by its parameters; any other
+            // item is a further parameter of the last challenge
+            let param = if first.is_empty() || first.contains('=') {
+                item
+            } else {
+                challenges.push(Challenge {
+                    scheme: first.to_ascii_lowercase(),
+                    realm: None,
+                });
+                rest
+            };
+            if let (Some((name, value)), Some(challenge)) =
+                (param.split_once('='), challenges.last_mut())
+            {
+                if name.trim().eq_ignore_ascii_case("realm") {
+                    challenge.realm = Some(value.trim().trim_matches('"').to_owned());
+                }
+            }
+        }
+    }
+    challenges
+}
+
+/// `bytes` in (padded) base64
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let byte = |i: usize| u32::from(chunk.get(i).copied().unwrap_or(0));
+        let bits = byte(0) << 16 | byte(1) << 8 | byte(2);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let sextet = (bits >> (18 - 6 * i)) & 0x3f;
+                encoded.push(char::from(ALPHABET[sextet as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+impl AuthManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the challenges of the origin of `origin` in `realm` (or in any realm, for `None`)
+    /// with `credential`
+    pub fn with_credential(
+        mut self,
+        origin: &Url,
+        realm: Option<&str>,
+        credential: Credential,
+    ) -> Self {
+        match origin_of(origin) {
+            Some(origin) => self.credentials.push(Registered {
+                origin,
+                realm: realm.map(str::to_owned),
+                credential,
+                token: None,
+            }),
+            None => debug!("ignoring credential for {}, which has no origin", origin),
+        }
+        self
+    }
+
+    /// Answer the `Basic` challenges of the origin of `origin` with `username` and `password`
+    pub fn with_basic(self, origin: &Url, username: &str, password: &str) -> Self {
+        let credential = Credential::Basic {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        };
+        self.with_credential(origin, None, credential)
+    }
+
+    /// Answer the `Bearer` challenges of the origin of `origin` with `token`
+    pub fn with_bearer(self, origin: &Url, token: &str) -> Self {
+        self.with_credential(origin, None, Credential::Bearer(token.to_owned()))
+    }
+
+    /// Answer the `Bearer` challenges of the origin of `origin` with the tokens returned by
+    /// `refresh` (see `Credential::Refresh`)
+    pub fn with_token_refresh<F>(self, origin: &Url, refresh: F) -> Self
+    where
+        F: FnMut() -> Option<String> + Send + 'static,
+    {
+        self.with_credential(origin, None, Credential::Refresh(Box::new(refresh)))
+    }
+
+    /// Whether any credential is registered for the origin of `url`
+    pub(crate) fn has_credentials(&self, url: &Url) -> bool {
+        let origin = origin_of(url);
+        self.credentials
+            .iter()
+            .any(|registered| Some(&registered.origin) == origin.as_ref())
+    }
+
+    /// The `Authorization` header value of the credential at `index`, refreshing the token of a
+    /// `Credential::Refresh` if `refresh` or it has none yet
+    fn authorization_of(&mut self, index: usize, refresh: bool) -> Option<String> {
+        let registered = &mut self.credentials[index];
+        match registered.credential {
+            Credential::Basic {
+                ref username,
+                ref password,
+            } => {
+                let pair = format!("{}:{}", username, password);
+                Some(format!("Basic {}", base64(pair.as_bytes())))
+            }
+            Credential::Bearer(ref token) => Some(format!("Bearer {}", token)),
+            Credential::Refresh(ref mut refresh_token) => {
+                if refresh || registered.token.is_none() {
+                    registered.token = refresh_token();
+                }
+                registered.token.as_ref().map(|t| format!("Bearer {}", t))
+            }
+        }
+    }
+
+    /// The credential (by its index) and the `Authorization` header value the origin of `url` is
+    /// known to need, if any
+    pub(crate) fn authorization(&mut self, url: &Url) -> Option<(usize, String)> {
+        let index = self.needed.get(&origin_of(url)?)?.1;
+        self.authorization_of(index, false)
+            .map(|value| (index, value))
+    }
+
+    /// The credential (by its index) of `origin` which answers one of `challenges`, and the
+    /// realm of that challenge
+    fn choose(&self, origin: &str, challenges: &[Challenge]) -> Option<(usize, Option<String>)> {
+        let mut any_realm = None;
+        let registered = self
+            .credentials
+            .iter()
+            .enumerate()
+            .filter(|(_, registered)| registered.origin == origin);
+        for (index, registered) in registered {
+            if challenges.is_empty() && registered.realm.is_none() {
+                any_realm.get_or_insert((index, None));
+            }
+            for challenge in challenges {
+                if registered.credential.scheme() != challenge.scheme {
+                    continue;
+                }
+                match registered.realm {
+                    Some(ref realm) if Some(realm) == challenge.realm.as_ref() => {
+                        return Some((index, challenge.realm.clone()))
+                    }
+                    Some(_) => {}
+                    None => {
+                        any_realm.get_or_insert((index, challenge.realm.clone()));
+                    }
+                }
+            }
+        }
+        any_realm
+    }
+
+    /// The `Authorization` header value answering the `WWW-Authenticate` header `values` of a
+    /// `401` from `url`, to which the credential at `sent` (if any) was sent, if the request
+    /// should be re-sent with it. A credential which was sent is not sent again, unless its
+    /// token can be refreshed.
+    pub(crate) fn answer(
+        &mut self,
+        url: &Url,
+        values: &[String],
+        sent: Option<usize>,
+    ) -> Option<String> {
+        let origin = origin_of(url)?;
+        let challenges = parse_challenges(values);
+        let (index, realm) = self.choose(&origin, &challenges)?;
+        let rejected = sent == Some(index);
+        if rejected && !matches!(self.credentials[index].credential, Credential::Refresh(_)) {
+            debug!("{} rejected the credential for it", url);
+            return None;
+        }
+        let value = self.authorization_of(index, rejected)?;
+        debug!("answering the challenge of {} in realm {:?}", origin, realm);
+        self.needed.insert(origin, (realm, index));
+        Some(value)
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Answer the `401 Unauthorized` challenges of origins per `auth`. Requests to origins `auth`
+    /// has credentials for are prepared before their cookies are added, so that they can be
+    /// re-sent (see `SessionRequest::try_clone`).
+    pub fn with_auth_manager(mut self, auth: AuthManager) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Send `request` for `exchange` as `run_exchange` does, with any credential its origin is
+    /// known to need, re-sending it once if the response is a challenge the `AuthManager`
+    /// answers. As for retries, the request is prepared once, and copied before the credential
+    /// and cookies are added to it.
+    pub(crate) fn run_with_auth<P>(
+        &mut self,
+        request: <C as SessionClient>::Request,
+        exchange: &Exchange<'_>,
+        prepare: P,
+    ) -> ::std::result::Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+    where
+        P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+    {
+        let url = exchange.url;
+        let request = prepare(request);
+        let copy = request.try_clone();
+        let sent = self.auth.as_mut().and_then(|auth| auth.authorization(url));
+        let request = match sent {
+            Some((_, ref value)) => request.add_header("Authorization", value),
+            None => request,
+        };
+        let response = self.run_exchange(request, exchange, |request| request)?;
+        let copy = match copy {
+            Some(copy) if response.status_code() == Some(401) => copy,
+            _ => return Ok(response),
+        };
+        let values = response.www_authenticate();
+        let sent = sent.map(|(index, _)| index);
+        let answer = self
+            .auth
+            .as_mut()
+            .and_then(|auth| auth.answer(url, &values, sent));
+        match answer {
+            Some(value) => {
+                let request = copy.add_header("Authorization", &value);
+                self.run_exchange(request, exchange, |request| request)
+            }
+            None => Ok(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, parse_challenges, AuthManager, Challenge, Credential};
+    use url::Url;
+
+    #[test]
+    fn challenges() {
+        let challenge = |scheme: &str, realm: Option<&str>| Challenge {
+            scheme: scheme.to_owned(),
+            realm: realm.map(str::to_owned),
+        };
+        assert_eq!(
+            vec![challenge("basic", Some("admin, ops"))],
+            parse_challenges(&["Basic realm=\"admin, ops\", charset=\"UTF-8\"".to_owned()])
+        );
+        assert_eq!(
+            vec![
+                challenge("newauth", Some("apps")),
+                challenge("bearer", Some("api")),
+                challenge("negotiate", None),
+            ],
+            parse_challenges(&[
+                "Newauth realm=\"apps\", type=1, Bearer error=\"invalid_token\", realm=api"
+                    .to_owned(),
+                "Negotiate".to_owned(),
+            ])
+        );
+        assert!(parse_challenges(&[]).is_empty());
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9v", base64(b"foo"));
+        assert_eq!("dXNlcjpwYXNz", base64(b"user:pass"));
+    }
+
+    #[test]
+    fn choose() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let auth = AuthManager::new()
+            .with_basic(&url, "any", "pass")
+            .with_credential(
+                &url,
+                Some("admin"),
+                Credential::Basic {
+                    username: "admin".to_owned(),
+                    password: "pass".to_owned(),
+                },
+            )
+            .with_bearer(&url, "token")
+            .with_bearer(&Url::parse("https://example.org/").unwrap(), "other");
+        let choose = |value: &str| {
+            let challenges = parse_challenges(&[value.to_owned()]);
+            auth.choose("https://example.com", &challenges)
+                .map(|(index, _)| index)
+        };
+        assert_eq!(Some(1), choose("Basic realm=\"admin\""));
+        assert_eq!(Some(0), choose("Basic realm=\"users\""));
+        assert_eq!(Some(2), choose("Bearer"));
+        assert_eq!(None, choose("Digest realm=\"admin\""));
+        assert!(auth.has_credentials(&Url::parse("https://example.com/a").unwrap()));
+        assert!(!auth.has_credentials(&Url::parse("http://example.com/").unwrap()));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn with_auth_manager() {
+        use crate::session::SessionResponse;
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let client = MockClient::new()
+            .with_response(MockResponse::new(401).with_header("www-authenticate", "Basic realm=x"))
+            .with_response(MockResponse::new(200))
+            .with_response(MockResponse::new(200))
+            .with_response(MockResponse::new(200));
+        let url = Url::parse("https://example.com/private").unwrap();
+        let auth = AuthManager::new().with_basic(&url, "user", "pass");
+        let mut session = Session::new(client).with_auth_manager(auth);
+        let response = session.get(url.clone()).unwrap();
+        assert_eq!(Some(200), response.status_code());
+        let requests = session.client.requests();
+        assert_eq!(None, requests[0].header_value("authorization"));
+        let basic = Some("Basic dXNlcjpwYXNz");
+        assert_eq!(basic, requests[1].header_value("authorization"));
+        // the origin is known to need the credential; other origins are never sent it
+        session.get("https://example.com/other").unwrap();
+        let last = session.client.last_request().unwrap();
+        assert_eq!(basic, last.header_value("authorization"));
+        session.get("https://example.org/").unwrap();
+        let last = session.client.last_request().unwrap();
+        assert_eq!(None, last.header_value("authorization"));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn prepare_once() {
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let client = MockClient::new()
+            .with_response(MockResponse::new(401).with_header("www-authenticate", "Basic"))
+            .with_response(MockResponse::new(200));
+        let url = Url::parse("https://example.com/").unwrap();
+        let auth = AuthManager::new().with_basic(&url, "user", "pass");
+        let mut session = Session::new(client).with_auth_manager(auth);
+        let mut prepared = 0;
+        session
+            .get_with(url, |r| {
+                prepared += 1;
+                r.header("x-trace", "1")
+            })
+            .unwrap();
+        assert_eq!(1, prepared);
+        let requests = session.client.requests();
+        assert_eq!(2, requests.len());
+        for request in &requests {
+            // prepared before the credential was added
+            assert_eq!("x-trace", request.headers[0].0);
+        }
+        assert!(requests[1].header_value("authorization").is_some());
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn token_refresh() {
+        use crate::session::SessionResponse;
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let challenge = || MockResponse::new(401).with_header("www-authenticate", "Bearer");
+        let client = MockClient::new()
+            .with_response(challenge())
+            .with_response(MockResponse::new(200))
+            .with_response(challenge())
+            .with_response(MockResponse::new(200))
+            .with_response(challenge());
+        let url = Url::parse("https://api.example.com/").unwrap();
+        let mut issued = 0;
+        let auth = AuthManager::new().with_token_refresh(&url, move || {
+            issued += 1;
+            if issued < 3 {
+                Some(format!("t{}", issued))
+            } else {
+                None
+            }
+        });
+        let mut session = Session::new(client).with_auth_manager(auth);
+        session.get(url.clone()).unwrap();
+        session.get(url.clone()).unwrap();
+        let authorizations = session
+            .client
+            .requests()
+            .iter()
+            .map(|request| request.header_value("authorization").map(str::to_owned))
+            .collect::<Vec<_>>();
+        let bearer = |token: &str| Some(format!("Bearer {}", token));
+        assert_eq!(
+            vec![None, bearer("t1"), bearer("t1"), bearer("t2")],
+            authorizations
+        );
+        // the refresh returning no token leaves the challenge unanswered
+        let response = session.get(url).unwrap();
+        assert_eq!(Some(401), response.status_code());
+        assert_eq!(5, session.client.requests().len());
+    }
+}
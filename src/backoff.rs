@@ -0,0 +1,190 @@
+use crate::retry::parse_retry_after;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::unix_secs;
+use log::debug;
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime};
+use url::Url;
+
+/// Cools down hosts which ask a `Session` to back off, set with `Session::with_host_backoff`.
+///
+/// When a host responds with one of the backoff statuses (429 and 503, unless configured), the
+/// session remembers a cool-down for it, as long as the response's `Retry-After` header asks
+/// (or the default cool-down, without one), up to the maximum cool-down. Until it elapses, the
+/// session sleeps before sending any request to the host; to fail fast instead, check
+/// `Session::host_backoff` before making a request. A response with any other status ends the
+/// host's cool-down.
+#[derive(Debug, Clone)]
+pub struct HostBackoff {
+    statuses: Vec<u16>,
+    default_cooldown: Duration,
+    max_cooldown: Duration,
+    /// When each cooling down host may next be sent a request
+    hosts: HashMap<String, Instant>,
+}
+
+impl Default for HostBackoff {
+    /// Cooling down on 429 and 503, for 1s without a `Retry-After`, and at most 5 minutes
+    fn default() -> Self {
+        HostBackoff {
+            statuses: vec![429, 503],
+            default_cooldown: Duration::from_secs(1),
+            max_cooldown: Duration::from_secs(300),
+            hosts: HashMap::new(),
+        }
+    }
+}
+
+impl HostBackoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cool down hosts responding with any of `statuses`, rather than 429 and 503
+    pub fn with_statuses<I: IntoIterator<Item = u16>>(mut self, statuses: I) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Cool down for `cooldown` after a backoff status without a (valid) `Retry-After`
+    pub fn with_default_cooldown(mut self, cooldown: Duration) -> Self {
+        self.default_cooldown = cooldown;
+        self
+    }
+
+    /// Cool down for at most `max_cooldown`, whatever `Retry-After` asks
+    pub fn with_max_cooldown(mut self, max_cooldown: Duration) -> Self {
+        self.max_cooldown = max_cooldown;
+        self
+    }
+
+    /// The cool-down a response with `status` asks for, if any, given its `retry_after`, at
+    /// `now` (in seconds since the Unix epoch)
+    fn cooldown(&self, status: u16, retry_after: Option<&str>, now: i64) -> Option<Duration> {
+        if !self.statuses.contains(&status) {
+            return None;
+        }
+        let cooldown = retry_after
+            .and_then(|value| parse_retry_after(value, now))
+            .unwrap_or(self.default_cooldown);
+        Some(cooldown.min(self.max_cooldown))
+    }
+
+    /// Record the response from `url` with `status`, and its `retry_after`
+    pub(crate) fn record(&mut self, url: &Url, status: u16, retry_after: Option<&str>) {
+        let host = url.host_str().unwrap_or_default();
+        match self.cooldown(status, retry_after, unix_secs(SystemTime::now())) {
+            Some(cooldown) => {
+                debug!(
+                    "{} responded {}; cooling down for {:?}",
+                    host, status, cooldown
+                );
+                self.hosts
+                    .insert(host.to_owned(), Instant::now() + cooldown);
+            }
+            None => {
+                self.hosts.remove(host);
+            }
+        }
+    }
+
+    /// The rest of the cool-down of `host` at `now`, if it is cooling down
+    fn remaining(&self, host: &str, now: Instant) -> Option<Duration> {
+        self.hosts
+            .get(host)
+            .filter(|until| **until > now)
+            .map(|until| *until - now)
+    }
+
+    /// Sleep until any cool-down of the host of `url` has elapsed
+    pub(crate) fn wait(&self, url: &Url) {
+        let now = Instant::now();
+        if let Some(remaining) = self.remaining(url.host_str().unwrap_or_default(), now) {
+            debug!(
+                "host cooling down; waiting {:?} to send to {}",
+                remaining, url
+            );
+            std::thread::sleep(remaining);
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Cool down hosts which ask the session to back off per `backoff`
+    pub fn with_host_backoff(mut self, backoff: HostBackoff) -> Self {
+        self.backoff = Some(backoff);
+        self
+    }
+
+    /// The rest of the cool-down of the host of `url`, if it is cooling down (see `HostBackoff`)
+    pub fn host_backoff(&self, url: &Url) -> Option<Duration> {
+        let host = url.host_str().unwrap_or_default();
+        self.backoff
+            .as_ref()
+            .and_then(|backoff| backoff.remaining(host, Instant::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HostBackoff;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn cooldown() {
+        let backoff = HostBackoff::new().with_max_cooldown(Duration::from_secs(60));
+        let secs = |status, retry_after| {
+            backoff
+                .cooldown(status, retry_after, 784_111_777)
+                .map(|cooldown| cooldown.as_secs())
+        };
+        assert_eq!(None, secs(200, Some("10")));
+        assert_eq!(None, secs(500, None));
+        assert_eq!(Some(10), secs(429, Some("10")));
+        assert_eq!(Some(60), secs(503, Some("3600")));
+        assert_eq!(Some(1), secs(429, None));
+        assert_eq!(Some(1), secs(429, Some("soon")));
+        assert_eq!(Some(30), secs(429, Some("Sun, 06 Nov 1994 08:50:07 GMT")));
+        let teapot = HostBackoff::new().with_statuses(vec![418]);
+        assert!(teapot.cooldown(429, None, 0).is_none());
+        assert!(teapot.cooldown(418, None, 0).is_some());
+    }
+
+    #[test]
+    fn remaining() {
+        let start = Instant::now();
+        let mut backoff = HostBackoff::new();
+        backoff
+            .hosts
+            .insert("a.example".to_owned(), start + Duration::from_secs(10));
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(
+            Some(Duration::from_secs(6)),
+            backoff.remaining("a.example", at(4))
+        );
+        assert_eq!(None, backoff.remaining("a.example", at(10)));
+        assert_eq!(None, backoff.remaining("b.example", at(4)));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn host_backoff() {
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+        use url::Url;
+
+        let client = MockClient::new()
+            .with_response(MockResponse::new(429).with_header("retry-after", "120"))
+            .with_response(MockResponse::new(200));
+        let mut session = Session::new(client).with_host_backoff(HostBackoff::new());
+        let limited = Url::parse("https://a.example/").unwrap();
+        let other = Url::parse("https://b.example/").unwrap();
+        assert_eq!(None, session.host_backoff(&limited));
+        session.get(limited.clone()).unwrap();
+        let remaining = session.host_backoff(&limited).unwrap();
+        assert!(remaining > Duration::from_secs(110) && remaining <= Duration::from_secs(120));
+        session.get(other.clone()).unwrap();
+        assert_eq!(None, session.host_backoff(&other));
+    }
+}
@@ -0,0 +1,314 @@
+use crate::error::SessionError;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::unix_secs;
+use cookie::Cookie as RawCookie;
+use log::debug;
+use rusqlite::{params, Connection, Row};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use url::Url;
+
+/// Seconds from 1601-01-01 (the epoch of Chromium's timestamps) to the Unix epoch
+const CHROMIUM_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// What `Session::import_from_firefox` or `import_from_chromium` did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// The cookies imported into the store
+    pub imported: usize,
+    /// The cookies whose values are encrypted, which are not imported
+    pub encrypted: usize,
+    /// The cookies which had expired, which are not imported
+    pub expired: usize,
+    /// The cookies the store refused, e.g. for invalid names or domains
+    pub invalid: usize,
+}
+
+/// A cookie as read from a browser's database
+struct BrowserCookie {
+    host: String,
+    path: String,
+    name: String,
+    value: String,
+    /// When the cookie expires, in seconds since the Unix epoch, or `None` for session cookies
+    expires: Option<i64>,
+    secure: bool,
+    http_only: bool,
+    /// The `SameSite` attribute, if specified
+    same_site: Option<&'static str>,
+}
+
+impl BrowserCookie {
+    /// The cookie as a Set-Cookie, as received from the URL it is to be stored for
+    fn to_set_cookie(&self, now: i64) -> Option<(RawCookie<'static>, Url)> {
+        let host = self.host.trim_start_matches('.');
+        let mut set_cookie = format!("{}={}; Path={}", self.name, self.value, self.path);
+        // a leading dot marks a domain cookie; others are host-only
+        if self.host.starts_with('.') {
+            set_cookie.push_str(&format!("; Domain={}", host));
+        }
+        if let Some(expires) = self.expires {
+            set_cookie.push_str(&format!("; Max-Age={}", expires - now));
+        }
+        if self.secure {
+            set_cookie.push_str("; Secure");
+        }
+        if self.http_only {
+            set_cookie.push_str("; HttpOnly");
+        }
+        if let Some(same_site) = self.same_site {
+            set_cookie.push_str(&format!("; SameSite={}", same_site));
+        }
+        let scheme = if self.secure { "https" } else { "http" };
+        let url = Url::parse(&format!("{}://{}{}", scheme, host, self.path)).ok()?;
+        let cookie = RawCookie::parse(set_cookie).ok()?;
+        Some((cookie, url))
+    }
+}
+
+/// Read the cookies of the database at `path` with `query`, each row of which is read by `row`,
+/// or `None` for a cookie whose value is encrypted. The database is copied first, as browsers
+/// hold a lock on it while running.
+fn read_database<F>(
+    path: &Path,
+    query: &str,
+    row: F,
+) -> Result<Vec<Option<BrowserCookie>>, SessionError>
+where
+    F: Fn(&Row<'_>) -> rusqlite::Result<Option<BrowserCookie>>,
+{
+    let copy = std::env::temp_dir().join(format!(
+        "user_agent-import-{}-{}",
+        std::process::id(),
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("cookies")
+    ));
+    std::fs::copy(path, &copy)?;
+    let read = || -> Result<_, SessionError> {
+        let connection = Connection::open(&copy)?;
+        let mut statement = connection.prepare(query)?;
+        let cookies = statement
+            .query_map(params![], |r| row(r))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(cookies)
+    };
+    let cookies = read();
+    if let Err(e) = std::fs::remove_file(&copy) {
+        debug!("unable to remove {}: {}", copy.display(), e);
+    }
+    cookies
+}
+
+/// The cookie database of the profile at `profile`, as the first of `names` in it, or
+/// `profile` itself if it is a file
+fn database(profile: &Path, names: &[&str]) -> Result<PathBuf, SessionError> {
+    if profile.is_file() {
+        return Ok(profile.to_owned());
+    }
+    names
+        .iter()
+        .map(|name| profile.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("no cookie database in {}", profile.display()).into())
+}
+
+fn firefox_cookie(row: &Row<'_>) -> rusqlite::Result<Option<BrowserCookie>> {
+    let expiry: i64 = row.get(4)?;
+    Ok(Some(BrowserCookie {
+        name: row.get(0)?,
+        value: row.get(1)?,
+        host: row.get(2)?,
+        path: row.get(3)?,
+        // recent versions record expiry in milliseconds
+        expires: Some(if expiry > 100_000_000_000 {
+            expiry / 1000
+        } else {
+            expiry
+        }),
+        secure: row.get::<_, i64>(5)? != 0,
+        http_only: row.get::<_, i64>(6)? != 0,
+        same_site: match row.get::<_, i64>(7)? {
+            1 => Some("Lax"),
+            2 => Some("Strict"),
+            _ => Some("None"),
+        },
+    }))
+}
+
+fn chromium_cookie(row: &Row<'_>) -> rusqlite::Result<Option<BrowserCookie>> {
+    let value: String = row.get(2)?;
+    let encrypted: Vec<u8> = row.get(3)?;
+    if value.is_empty() && !encrypted.is_empty() {
+        return Ok(None);
+    }
+    let expires: i64 = row.get(5)?;
+    Ok(Some(BrowserCookie {
+        host: row.get(0)?,
+        name: row.get(1)?,
+        value,
+        path: row.get(4)?,
+        expires: match expires {
+            0 => None,
+            micros => Some(micros / 1_000_000 - CHROMIUM_EPOCH_OFFSET),
+        },
+        secure: row.get::<_, i64>(6)? != 0,
+        http_only: row.get::<_, i64>(7)? != 0,
+        same_site: match row.get::<_, i64>(8)? {
+            0 => Some("None"),
+            1 => Some("Lax"),
+            2 => Some("Strict"),
+            _ => None,
+        },
+    }))
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    fn import(&mut self, cookies: Vec<Option<BrowserCookie>>) -> ImportReport {
+        let now = unix_secs(SystemTime::now());
+        let mut report = ImportReport::default();
+        for cookie in cookies {
+            let cookie = match cookie {
+                Some(cookie) => cookie,
+                None => {
+                    report.encrypted += 1;
+                    continue;
+                }
+            };
+            if cookie.expires.map(|at| at <= now).unwrap_or(false) {
+                report.expired += 1;
+                continue;
+            }
+            let inserted = cookie
+                .to_set_cookie(now)
+                .map(|(raw, url)| self.store.insert_raw(&raw, &url));
+            match inserted {
+                Some(Ok(_)) => report.imported += 1,
+                Some(Err(e)) => {
+                    debug!("unable to import cookie {}: {:?}", cookie.name, e);
+                    report.invalid += 1;
+                }
+                None => report.invalid += 1,
+            }
+        }
+        debug!("imported browser cookies: {:?}", report);
+        report
+    }
+
+    /// Import the cookies of the Firefox profile at `profile` (the directory holding
+    /// `cookies.sqlite`, or that file itself) into the store, e.g. to carry a login from the
+    /// browser over to the session
+    pub fn import_from_firefox<P: AsRef<Path>>(
+        &mut self,
+        profile: P,
+    ) -> Result<ImportReport, SessionError> {
+        let path = database(profile.as_ref(), &["cookies.sqlite"])?;
+        let cookies = read_database(
+            &path,
+            "SELECT name, value, host, path, expiry, isSecure, isHttpOnly, sameSite
+                FROM moz_cookies",
+            firefox_cookie,
+        )?;
+        Ok(self.import(cookies))
+    }
+
+    /// Import the cookies of the Chrome (or other Chromium-based browser) profile at `profile`
+    /// (the directory holding `Cookies` or `Network/Cookies`, or that file itself) into the
+    /// store. Only cookies stored in plaintext are imported; those encrypted (as by current
+    /// versions on most platforms) are counted in the report, and skipped.
+    pub fn import_from_chromium<P: AsRef<Path>>(
+        &mut self,
+        profile: P,
+    ) -> Result<ImportReport, SessionError> {
+        let path = database(profile.as_ref(), &["Network/Cookies", "Cookies"])?;
+        let cookies = read_database(
+            &path,
+            "SELECT host_key, name, value, encrypted_value, path, expires_utc, is_secure,
+                is_httponly, samesite FROM cookies",
+            chromium_cookie,
+        )?;
+        Ok(self.import(cookies))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImportReport, CHROMIUM_EPOCH_OFFSET};
+    use crate::utils::unix_secs;
+    use crate::ReqwestSession;
+    use rusqlite::{params, Connection};
+    use std::time::SystemTime;
+    use url::Url;
+
+    #[test]
+    fn import() {
+        let dir = std::env::temp_dir().join(format!("user_agent-browser-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("chromium/Network")).unwrap();
+        let later = unix_secs(SystemTime::now()) + 3600;
+        let firefox = Connection::open(dir.join("cookies.sqlite")).unwrap();
+        firefox
+            .execute_batch(
+                "CREATE TABLE moz_cookies (name TEXT, value TEXT, host TEXT, path TEXT,
+                    expiry INTEGER, isSecure INTEGER, isHttpOnly INTEGER, sameSite INTEGER)",
+            )
+            .unwrap();
+        for (name, host, expiry) in &[
+            ("sid", ".example.com", later),
+            ("host", "www.example.com", later * 1000),
+            ("old", "www.example.com", 1),
+        ] {
+            firefox
+                .execute(
+                    "INSERT INTO moz_cookies VALUES (?1, 'v', ?2, '/', ?3, 1, 1, 1)",
+                    params![name, host, expiry],
+                )
+                .unwrap();
+        }
+        drop(firefox);
+        let chromium = Connection::open(dir.join("chromium/Network/Cookies")).unwrap();
+        chromium
+            .execute_batch(
+                "CREATE TABLE cookies (host_key TEXT, name TEXT, value TEXT, encrypted_value BLOB,
+                    path TEXT, expires_utc INTEGER, is_secure INTEGER, is_httponly INTEGER,
+                    samesite INTEGER)",
+            )
+            .unwrap();
+        let expires = (later + CHROMIUM_EPOCH_OFFSET) * 1_000_000;
+        chromium
+            .execute_batch(&format!(
+                "INSERT INTO cookies VALUES ('.example.org', 'plain', 'v', x'', '/', {}, 0, 0, -1);
+                INSERT INTO cookies VALUES ('example.org', 'session', 'v', x'', '/', 0, 0, 0, 1);
+                INSERT INTO cookies VALUES ('example.org', 'enc', '', x'7631', '/', {}, 1, 0, 1);",
+                expires, expires
+            ))
+            .unwrap();
+        drop(chromium);
+
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new());
+        assert_eq!(
+            ImportReport {
+                imported: 2,
+                expired: 1,
+                ..ImportReport::default()
+            },
+            session.import_from_firefox(&dir).unwrap()
+        );
+        assert_eq!(
+            ImportReport {
+                imported: 2,
+                encrypted: 1,
+                ..ImportReport::default()
+            },
+            session.import_from_chromium(dir.join("chromium")).unwrap()
+        );
+        let url = Url::parse("https://api.example.com/").unwrap();
+        assert!(session
+            .store
+            .get_request_cookies(&url)
+            .any(|c| c.name() == "sid"));
+        assert!(session.store.contains("example.org", "/", "session"));
+        assert!(session.import_from_firefox(dir.join("missing")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,303 @@
+use crate::content::ContentResponse;
+#[cfg(feature = "persistence")]
+use crate::error::SessionError;
+use crate::owned::SessionResponseOwned;
+use crate::paginate::HeaderResponse;
+use crate::session::{Session, SessionClient, SessionRequest};
+use crate::spec::StatusResponse;
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use log::debug;
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(feature = "persistence")]
+use std::io::{BufRead, Write};
+use url::Url;
+
+/// A response kept by a `ResponseCache`, with the validators (its `ETag` and `Last-Modified`
+/// headers) it is revalidated with
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl CachedResponse {
+    /// The first value of the `name` header, if any
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn etag(&self) -> Option<&str> {
+        self.header("etag")
+    }
+
+    pub fn last_modified(&self) -> Option<&str> {
+        self.header("last-modified")
+    }
+
+    /// Update the headers with those of a `304 Not Modified` revalidating the response, as RFC
+    /// 9111 section 4.3.4 has caches do: each header of the 304 (but its `Content-Length`)
+    /// replaces those of its name
+    fn update_headers(&mut self, headers: &[(String, String)]) {
+        let updates = headers
+            .iter()
+            .filter(|(name, _)| !name.eq_ignore_ascii_case("content-length"))
+            .collect::<Vec<_>>();
+        self.headers.retain(|(name, _)| {
+            !updates
+                .iter()
+                .any(|(updated, _)| updated.eq_ignore_ascii_case(name))
+        });
+        self.headers.extend(updates.into_iter().cloned());
+    }
+
+    /// Whether `response` can be cached: it succeeded, has a validator, and is not `no-store`
+    fn is_cacheable(response: &SessionResponseOwned) -> bool {
+        let no_store = response
+            .header("cache-control")
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-store"));
+        response.status == 200
+            && !no_store
+            && (response.header("etag").is_some() || response.header("last-modified").is_some())
+    }
+}
+
+/// The responses cached by a `Session` for conditional requests (see `Session::get_cached`), by
+/// URL. Implemented by `MemoryResponseCache`, and for any other backend (e.g. a database) the
+/// cache should persist to.
+pub trait ResponseCache {
+    /// The response cached for `url`, if any
+    fn get(&self, url: &Url) -> Option<CachedResponse>;
+    /// Cache `response` for `url`, replacing any response cached for it
+    fn insert(&mut self, url: &Url, response: CachedResponse);
+    /// Remove the response cached for `url`, returning it if there was one
+    fn remove(&mut self, url: &Url) -> Option<CachedResponse>;
+}
+
+pub(crate) type ResponseCacheBox = Box<dyn ResponseCache + Send>;
+
+/// A `ResponseCache` held in memory, which can be saved to and loaded from JSON
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryResponseCache {
+    responses: BTreeMap<String, CachedResponse>,
+}
+
+impl MemoryResponseCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+
+    /// Write the cached responses to `writer`, as JSON
+    #[cfg(feature = "persistence")]
+    pub fn save_json<W: Write>(&self, writer: W) -> Result<(), SessionError> {
+        serde_json::to_writer(writer, &self.responses)?;
+        Ok(())
+    }
+
+    /// Load responses written by `save_json`
+    #[cfg(feature = "persistence")]
+    pub fn load_json<R: BufRead>(reader: R) -> Result<Self, SessionError> {
+        let responses = serde_json::from_reader(reader)?;
+        Ok(MemoryResponseCache { responses })
+    }
+}
+
+impl ResponseCache for MemoryResponseCache {
+    fn get(&self, url: &Url) -> Option<CachedResponse> {
+        self.responses.get(url.as_str()).cloned()
+    }
+
+    fn insert(&mut self, url: &Url, response: CachedResponse) {
+        self.responses.insert(url.as_str().to_owned(), response);
+    }
+
+    fn remove(&mut self, url: &Url) -> Option<CachedResponse> {
+        self.responses.remove(url.as_str())
+    }
+}
+
+/// Whether a response from `Session::get_cached` was served from the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// The server answered the conditional request with `304 Not Modified`, so the cached
+    /// response was returned
+    Hit,
+    /// The response was fetched (and cached, if it could be)
+    Miss,
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Cache responses with validators in `cache`, for revalidation by `get_cached`. Only
+    /// requests made with `get_cached` use the cache: those made with `get` and the like neither
+    /// read nor fill it.
+    pub fn with_response_cache<R>(mut self, cache: R) -> Self
+    where
+        R: ResponseCache + Send + 'static,
+    {
+        self.response_cache = Some(Box::new(cache));
+        self
+    }
+
+    /// GET `url` as `get_owned` does, revalidating any response cached for it (see
+    /// `with_response_cache`) with `If-None-Match` and `If-Modified-Since` headers: the cached
+    /// response is returned if the server answers `304 Not Modified`, with its headers (as
+    /// cached) updated by those of the 304. Otherwise, the response is
+    /// cached if it is a `200 OK` with an `ETag` or `Last-Modified` validator, and is not
+    /// `Cache-Control: no-store`. The cookies of every response are stored as usual.
+    pub fn get_cached<U>(
+        &mut self,
+        url: U,
+    ) -> Result<(SessionResponseOwned, CacheStatus), <C as SessionClient>::SendError>
+    where
+        U: IntoUrl,
+        <C as SessionClient>::Response: ContentResponse + StatusResponse + HeaderResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let url = self.request_url(url.into_url()?);
+        let cached = self
+            .response_cache
+            .as_ref()
+            .and_then(|cache| cache.get(&url));
+        let request = self.client.get_request(&url);
+        let response = self.run_request(request, "GET", &url, |mut request| {
+            if let Some(ref cached) = cached {
+                if let Some(etag) = cached.etag() {
+                    request = request.add_header("If-None-Match", etag);
+                }
+                if let Some(last_modified) = cached.last_modified() {
+                    request = request.add_header("If-Modified-Since", last_modified);
+                }
+            }
+            request
+        })?;
+        let response = self.read_owned(response)?;
+        if let (304, Some(mut cached)) = (response.status, cached) {
+            debug!("{} not modified; using the cached response", url);
+            cached.update_headers(&response.headers);
+            if let Some(ref mut cache) = self.response_cache {
+                cache.insert(&url, cached.clone());
+            }
+            let response = SessionResponseOwned {
+                status: cached.status,
+                headers: cached.headers,
+                url: response.url,
+                body: cached.body,
+            };
+            return Ok((response, CacheStatus::Hit));
+        }
+        if let Some(ref mut cache) = self.response_cache {
+            if CachedResponse::is_cacheable(&response) {
+                let cached = CachedResponse {
+                    status: response.status,
+                    headers: response.headers.clone(),
+                    body: response.body.clone(),
+                };
+                cache.insert(&url, cached);
+            } else if response.status == 200 {
+                cache.remove(&url);
+            }
+        }
+        Ok((response, CacheStatus::Miss))
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::{CacheStatus, MemoryResponseCache, ResponseCache};
+    use crate::testing::{MockClient, MockResponse};
+    use crate::Session;
+    use url::Url;
+
+    #[test]
+    fn get_cached() {
+        let client = MockClient::new()
+            .with_response(
+                MockResponse::new(200)
+                    .with_header("etag", "\"v1\"")
+                    .with_header("last-modified", "Sun, 06 Nov 1994 08:49:37 GMT")
+                    .with_body("hello"),
+            )
+            .with_response(
+                MockResponse::new(304)
+                    .with_set_cookie("seen=1")
+                    .with_header("etag", "\"v2\"")
+                    .with_header("content-length", "0"),
+            )
+            .with_response(MockResponse::new(200).with_body("uncacheable"));
+        let mut session = Session::new(client).with_response_cache(MemoryResponseCache::new());
+        let url = "https://www.example.com/page";
+
+        let (response, status) = session.get_cached(url).unwrap();
+        assert_eq!(
+            (Some("hello"), CacheStatus::Miss),
+            (response.text(), status)
+        );
+        assert!(session.client.requests()[0]
+            .header_value("if-none-match")
+            .is_none());
+
+        let (response, status) = session.get_cached(url).unwrap();
+        assert_eq!((200, CacheStatus::Hit), (response.status, status));
+        assert_eq!(Some("hello"), response.text());
+        let request = session.client.last_request().unwrap();
+        assert_eq!(Some("\"v1\""), request.header_value("if-none-match"));
+        assert_eq!(
+            Some("Sun, 06 Nov 1994 08:49:37 GMT"),
+            request.header_value("if-modified-since")
+        );
+        assert!(session.get_cookie("www.example.com", "/", "seen").is_some());
+        // updated by the headers of the 304, as is the cached response
+        assert_eq!(Some("\"v2\""), response.header("etag"));
+        assert_eq!(None, response.header("content-length"));
+        let cache = session.response_cache.as_ref().unwrap();
+        let cached = cache.get(&Url::parse(url).unwrap()).unwrap();
+        assert_eq!(Some("\"v2\""), cached.etag());
+        assert!(cached.last_modified().is_some());
+
+        // a response without validators replaces none cached
+        let (response, status) = session.get_cached(url).unwrap();
+        assert_eq!(
+            (Some("uncacheable"), CacheStatus::Miss),
+            (response.text(), status)
+        );
+        let cache = session.response_cache.as_ref().unwrap();
+        assert!(cache.get(&Url::parse(url).unwrap()).is_none());
+    }
+
+    #[test]
+    fn memory_response_cache() {
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let mut cache = MemoryResponseCache::new();
+        let response = super::CachedResponse {
+            status: 200,
+            headers: vec![("ETag".to_owned(), "\"v1\"".to_owned())],
+            body: b"hello".to_vec(),
+        };
+        cache.insert(&url, response.clone());
+        assert_eq!(Some("\"v1\""), cache.get(&url).unwrap().etag());
+        let mut json = vec![];
+        cache.save_json(&mut json).unwrap();
+        let loaded = MemoryResponseCache::load_json(&json[..]).unwrap();
+        assert_eq!(cache, loaded);
+        assert_eq!(Some(response), cache.remove(&url));
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::content::ContentResponse;
+use crate::paginate::HeaderResponse;
+use crate::session::{SessionClient, SessionResponse};
+use crate::spec::StatusResponse;
+use crate::testing::{MockError, MockRequest, MockResponse};
+use crate::utils::IntoUrl;
+use http::Method;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use url::Url;
+
+/// A response recorded by a `SessionRecorder`, with the method and URL of its request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// The URL the response was served from (e.g. after redirects), if other than `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    final_url: Option<String>,
+    /// The body, if it is UTF-8
+    #[serde(default)]
+    body: String,
+    /// The body, if it is not UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_bytes: Option<Vec<u8>>,
+}
+
+impl Interaction {
+    /// The recorded response, as served from `url`
+    fn response(&self, url: &Url) -> MockResponse {
+        let body = match self.body_bytes {
+            Some(ref bytes) => bytes.clone(),
+            None => self.body.clone().into_bytes(),
+        };
+        let response = self
+            .headers
+            .iter()
+            .fold(MockResponse::new(self.status), |response, (name, value)| {
+                response.with_header(name, value)
+            })
+            .with_body(body);
+        let final_url = self.final_url.as_ref().and_then(|u| Url::parse(u).ok());
+        response.with_url(final_url.unwrap_or_else(|| url.clone()))
+    }
+}
+
+/// The responses recorded by a `SessionRecorder`, with the method and URL of their requests, to
+/// be served by a `ReplayClient`. Cassettes are saved as JSON, with bodies as text where they
+/// are UTF-8, so that they can be reviewed (and edited) alongside the tests using them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of responses recorded
+    pub fn len(&self) -> usize {
+        self.interactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.interactions.is_empty()
+    }
+
+    pub fn read<R: Read>(reader: R) -> io::Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+
+    /// Read the cassette saved at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Save the cassette to `path`, replacing any file there
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+}
+
+/// A `SessionClient` wrapping another, which records each response it receives (with its
+/// status, headers, including Set-Cookie, and body) into a `Cassette`, keyed by the method and
+/// URL of its request, for a `ReplayClient` to serve back. Responses are read whole, and passed
+/// on as the `MockResponse` a `ReplayClient` would serve, so that a session sees the same when
+/// recording as when replaying.
+///
+/// Requests are keyed by the method and URL they were created with, so each must be sent before
+/// the next is created, as a `Session` does.
+///
+/// ```no_run
+/// # #[cfg(feature = "reqwest-session")]
+/// # {
+/// use user_agent::testing::SessionRecorder;
+/// use user_agent::Session;
+///
+/// let mut session = Session::new(SessionRecorder::new(reqwest::blocking::Client::new()));
+/// session.get("https://www.example.com/").unwrap();
+/// session.client.cassette().save("tests/cassettes/example.json").unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SessionRecorder<C> {
+    inner: C,
+    /// The method and URL of the request last created
+    creating: RefCell<Option<(Method, Url)>>,
+    cassette: RefCell<Cassette>,
+}
+
+impl<C: SessionClient> SessionRecorder<C> {
+    /// Wrap `inner`, recording into an empty cassette
+    pub fn new(inner: C) -> Self {
+        SessionRecorder {
+            inner,
+            creating: RefCell::new(None),
+            cassette: RefCell::new(Cassette::new()),
+        }
+    }
+
+    /// The wrapped client
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// The responses recorded so far
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.borrow().clone()
+    }
+
+    fn create(&self, method: Method, url: &Url) {
+        *self.creating.borrow_mut() = Some((method, url.clone()));
+    }
+}
+
+impl<C> SessionClient for SessionRecorder<C>
+where
+    C: SessionClient,
+    C::Response: ContentResponse + StatusResponse + HeaderResponse,
+    C::SendError: From<<C::Response as ContentResponse>::BodyError>,
+{
+    type Request = C::Request;
+    type Response = MockResponse;
+    type SendError = C::SendError;
+
+    fn get_request(&self, url: &Url) -> Self::Request {
+        self.create(Method::GET, url);
+        self.inner.get_request(url)
+    }
+    fn put_request(&self, url: &Url) -> Self::Request {
+        self.create(Method::PUT, url);
+        self.inner.put_request(url)
+    }
+    fn head_request(&self, url: &Url) -> Self::Request {
+        self.create(Method::HEAD, url);
+        self.inner.head_request(url)
+    }
+    fn delete_request(&self, url: &Url) -> Self::Request {
+        self.create(Method::DELETE, url);
+        self.inner.delete_request(url)
+    }
+    fn post_request(&self, url: &Url) -> Self::Request {
+        self.create(Method::POST, url);
+        self.inner.post_request(url)
+    }
+    fn patch_request(&self, url: &Url) -> Self::Request {
+        self.create(Method::PATCH, url);
+        self.inner.patch_request(url)
+    }
+    fn options_request(&self, url: &Url) -> Self::Request {
+        self.create(Method::OPTIONS, url);
+        self.inner.options_request(url)
+    }
+    fn request(&self, method: &Method, url: &Url) -> Self::Request {
+        self.create(method.clone(), url);
+        self.inner.request(method, url)
+    }
+
+    fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let response = self.inner.send(request)?;
+        let status = response.status();
+        let headers = response
+            .header_names()
+            .into_iter()
+            .flat_map(|name| {
+                let values = response.header_values(&name);
+                let values = values.into_iter().map(str::to_owned).collect::<Vec<_>>();
+                values.into_iter().map(move |value| (name.clone(), value))
+            })
+            .collect();
+        let served_from = match response.final_url() {
+            Some(url) => Some(url.clone().into_url()?),
+            None => None,
+        };
+        let body = response.into_body()?;
+        let (method, url) = match self.creating.borrow().clone() {
+            Some(creating) => creating,
+            None => {
+                warn!("not recording a response to a request not created by the recorder");
+                let response = MockResponse::new(status).with_body(body);
+                return Ok(match served_from {
+                    Some(url) => response.with_url(url),
+                    None => response,
+                });
+            }
+        };
+        let (body, body_bytes) = match String::from_utf8(body) {
+            Ok(body) => (body, None),
+            Err(e) => (String::new(), Some(e.into_bytes())),
+        };
+        let interaction = Interaction {
+            method: method.to_string(),
+            url: url.to_string(),
+            status,
+            headers,
+            final_url: served_from.filter(|u| *u != url).map(String::from),
+            body,
+            body_bytes,
+        };
+        let response = interaction.response(&url);
+        self.cassette.borrow_mut().interactions.push(interaction);
+        Ok(response)
+    }
+}
+
+/// A `SessionClient` serving the responses of a `Cassette`, e.g. one recorded by a
+/// `SessionRecorder`, so that tests of code built on `Session` run deterministically without a
+/// network. Each request is answered with the responses recorded for its method and URL, in the
+/// order recorded, the last being served again once they run out; a request with none fails
+/// with `MockError::Unrecorded`. As with a `MockClient`, the requests sent are recorded, with
+/// the cookies and headers the session added to them.
+#[derive(Debug, Default)]
+pub struct ReplayClient {
+    recorded: RefCell<HashMap<(String, String), VecDeque<Interaction>>>,
+    requests: RefCell<Vec<MockRequest>>,
+}
+
+impl ReplayClient {
+    pub fn new(cassette: Cassette) -> Self {
+        let mut recorded = HashMap::<_, VecDeque<_>>::new();
+        for interaction in cassette.interactions {
+            let key = (interaction.method.clone(), interaction.url.clone());
+            recorded.entry(key).or_default().push_back(interaction);
+        }
+        ReplayClient {
+            recorded: RefCell::new(recorded),
+            requests: RefCell::new(vec![]),
+        }
+    }
+
+    /// Serve the cassette saved at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(Cassette::load(path)?))
+    }
+
+    /// The requests sent so far, in order
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.borrow().clone()
+    }
+}
+
+impl SessionClient for ReplayClient {
+    type Request = MockRequest;
+    type Response = MockResponse;
+    type SendError = MockError;
+
+    fn request(&self, method: &Method, url: &Url) -> Self::Request {
+        MockRequest::new(method.clone(), url)
+    }
+
+    fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let (method, url) = (request.method.clone(), request.url.clone());
+        self.requests.borrow_mut().push(request);
+        let mut recorded = self.recorded.borrow_mut();
+        let responses = recorded.get_mut(&(method.to_string(), url.to_string()));
+        let interaction = match responses {
+            Some(responses) if responses.len() > 1 => responses.pop_front(),
+            Some(responses) => responses.front().cloned(),
+            None => None,
+        };
+        match interaction {
+            Some(interaction) => Ok(interaction.response(&url)),
+            None => Err(MockError::Unrecorded(method, url)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "reqwest-session"))]
+mod tests {
+    use super::{Cassette, ReplayClient, SessionRecorder};
+    use crate::testing::MockError;
+    use crate::{ContentResponse, Session, StatusResponse};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn record_replay() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let responses = [
+                "HTTP/1.1 200 OK\r\nConnection: close\r\nSet-Cookie: id=1; Path=/\r\n\
+                 Content-Length: 5\r\n\r\nhello",
+                "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n",
+            ];
+            for response in responses.iter() {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let login = format!("http://{}/login", addr);
+        let account = format!("http://{}/account", addr);
+        let client = SessionRecorder::new(reqwest::blocking::Client::new());
+        let mut session = Session::new(client);
+        let response = session.get(&login).unwrap();
+        assert_eq!(b"hello".to_vec(), response.into_body().unwrap());
+        session.post(&account).unwrap();
+        server.join().unwrap();
+
+        let mut saved = vec![];
+        session.client.cassette().write(&mut saved).unwrap();
+        let cassette = Cassette::read(&saved[..]).unwrap();
+        assert_eq!(2, cassette.len());
+
+        let mut replay = Session::new(ReplayClient::new(cassette));
+        let response = replay.get(&login).unwrap();
+        assert_eq!(b"hello".to_vec(), response.into_body().unwrap());
+        assert!(replay
+            .get_cookie(&addr.ip().to_string(), "/", "id")
+            .is_some());
+        assert_eq!(204, replay.post(&account).unwrap().status());
+        assert_eq!(vec!["id"], replay.client.requests()[1].cookie_names());
+        assert!(matches!(
+            replay.get(&account),
+            Err(MockError::Unrecorded(_, _))
+        ));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie_store::Cookie;
+use url::Url;
+
+/// How a Set-Cookie received by a `Session` changed a cookie of its store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieChangeKind {
+    /// A cookie was added to the store
+    Inserted,
+    /// A cookie already in the store was replaced (e.g. with a new value or expiry)
+    Updated,
+    /// A cookie already in the store was expired
+    Expired,
+}
+
+/// A change to a cookie of a `Session`'s store, made by a Set-Cookie received from `url`,
+/// reported to the callbacks registered with `Session::on_cookie_change`
+#[derive(Debug)]
+pub struct CookieChange<'a> {
+    pub kind: CookieChangeKind,
+    /// The cookie as it was before the change; `None` if it was inserted
+    pub old: Option<&'a Cookie<'static>>,
+    /// The cookie as it is after the change; `None` if it was expired
+    pub new: Option<&'a Cookie<'static>>,
+    pub url: &'a Url,
+}
+
+impl<'a> CookieChange<'a> {
+    /// The change from `old` to `new`, if there was one
+    pub(crate) fn between(
+        old: Option<&'a Cookie<'static>>,
+        new: Option<&'a Cookie<'static>>,
+        url: &'a Url,
+    ) -> Option<Self> {
+        let kind = match (old, new) {
+            (None, Some(_)) => CookieChangeKind::Inserted,
+            (Some(_), Some(_)) => CookieChangeKind::Updated,
+            (Some(_), None) => CookieChangeKind::Expired,
+            (None, None) => return None,
+        };
+        Some(CookieChange {
+            kind,
+            old,
+            new,
+            url,
+        })
+    }
+}
+
+pub(crate) type ChangeObservers = Vec<Box<dyn FnMut(&CookieChange<'_>) + Send>>;
+
+/// The unexpired cookie of `store` with the domain, path and name of `cookie`, if there is one
+pub(crate) fn stored<S: CookieStorage>(store: &S, cookie: &Cookie<'_>) -> Option<Cookie<'static>> {
+    let (domain, path) = (String::from(&cookie.domain), String::from(&cookie.path));
+    store.iter_unexpired().find(|c| {
+        String::from(&c.domain) == domain
+            && String::from(&c.path) == path
+            && c.name() == cookie.name()
+    })
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Call `callback` whenever a Set-Cookie received by the session inserts, updates, or expires
+    /// a cookie of the store, e.g. to persist the store incrementally, or to keep a view of it up
+    /// to date. Changes made other than by Set-Cookie headers (e.g. by `remove_cookie`, or by
+    /// `Clear-Site-Data` headers, reported as `StoreEvent::Cleared`) are not reported.
+    pub fn on_cookie_change<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&CookieChange<'_>) + Send + 'static,
+    {
+        self.change_observers.push(Box::new(callback));
+        self
+    }
+}
+
+#[cfg(all(test, feature = "reqwest-session"))]
+mod tests {
+    use super::CookieChangeKind;
+    use crate::ReqwestSession;
+    use cookie::Cookie as RawCookie;
+    use std::sync::{Arc, Mutex};
+    use url::Url;
+
+    #[test]
+    fn on_cookie_change() {
+        let changes = Arc::new(Mutex::new(vec![]));
+        let observed = changes.clone();
+        let mut session =
+            ReqwestSession::new(reqwest::blocking::Client::new()).on_cookie_change(move |change| {
+                let value =
+                    |c: Option<&cookie_store::Cookie<'static>>| c.map(|c| c.value().to_owned());
+                observed
+                    .lock()
+                    .unwrap()
+                    .push((change.kind, value(change.old), value(change.new)));
+            });
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let set_cookie = |s: &str| vec![RawCookie::parse(s.to_owned()).unwrap()];
+        session.store_response_cookies(set_cookie("id=1"), &url);
+        session.store_response_cookies(set_cookie("id=2; Max-Age=60"), &url);
+        session.store_response_cookies(set_cookie("id=; Max-Age=0"), &url);
+        session.store_response_cookies(set_cookie("gone=; Max-Age=0"), &url);
+        assert_eq!(
+            vec![
+                (CookieChangeKind::Inserted, None, Some("1".to_owned())),
+                (
+                    CookieChangeKind::Updated,
+                    Some("1".to_owned()),
+                    Some("2".to_owned())
+                ),
+                (CookieChangeKind::Expired, Some("2".to_owned()), None),
+            ],
+            *changes.lock().unwrap()
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use crate::content::{BodyChunks, ContentResponse};
+use crate::paginate::HeaderResponse;
+use crate::session::{ClassifySendError, SendErrorKind, SessionClient, SessionResponse};
+use crate::spec::StatusResponse;
+use cookie::Cookie as RawCookie;
+use http::Method;
+use log::debug;
+use std::cell::Cell;
+use std::time::Duration;
+use url::{ParseError as ParseUrlError, Url};
+
+/// A `SessionClient` wrapping another, which injects faults at configurable rates, so that
+/// applications built on `Session` can test their retry and reauthentication handling. Faults are
+/// chosen by a generator seeded at construction, so a given seed and sequence of requests
+/// always injects the same faults.
+///
+/// ```
+/// use user_agent::{ChaosClient, Session};
+///
+/// let client = ChaosClient::new(reqwest::blocking::Client::new(), 42)
+///     .with_dropped_connections(0.1)
+///     .with_server_errors(0.05, 503);
+/// let session = Session::new(client);
+/// ```
+#[derive(Debug)]
+pub struct ChaosClient<C> {
+    inner: C,
+    state: Cell<u64>,
+    latency: (f64, Duration),
+    dropped: f64,
+    server_errors: (f64, u16),
+    corrupt_set_cookie: f64,
+}
+
+impl<C: SessionClient> ChaosClient<C> {
+    /// Wrap `inner`, injecting no faults until configured
+    pub fn new(inner: C, seed: u64) -> Self {
+        ChaosClient {
+            inner,
+            // xorshift state must be non-zero
+            state: Cell::new(seed | 1),
+            latency: (0.0, Duration::from_secs(0)),
+            dropped: 0.0,
+            server_errors: (0.0, 503),
+            corrupt_set_cookie: 0.0,
+        }
+    }
+
+    /// Delay the given fraction of requests by `delay`
+    pub fn with_latency(mut self, rate: f64, delay: Duration) -> Self {
+        self.latency = (rate, delay);
+        self
+    }
+
+    /// Fail the given fraction of requests, without sending them, with `ChaosError::Dropped`
+    pub fn with_dropped_connections(mut self, rate: f64) -> Self {
+        self.dropped = rate;
+        self
+    }
+
+    /// Fail the given fraction of requests, after sending them, with `ChaosError::Status(status)`
+    /// (as an integration's error for an error status would)
+    pub fn with_server_errors(mut self, rate: f64, status: u16) -> Self {
+        self.server_errors = (rate, status);
+        self
+    }
+
+    /// Corrupt the Set-Cookie headers of the given fraction of responses: every cookie's value is
+    /// truncated to half its length, as if the header had been cut off
+    pub fn with_corrupt_set_cookie(mut self, rate: f64) -> Self {
+        self.corrupt_set_cookie = rate;
+        self
+    }
+
+    /// The wrapped client
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Whether to inject a fault with probability `rate`, advancing the generator (xorshift64*)
+    fn roll(&self, rate: f64) -> bool {
+        let mut x = self.state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state.set(x);
+        let sample = (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64;
+        sample < rate
+    }
+}
+
+/// The failure of a request sent through a `ChaosClient`
+#[derive(Debug)]
+pub enum ChaosError<E> {
+    /// An injected dropped connection
+    Dropped,
+    /// An injected error status
+    Status(u16),
+    /// A failure of the wrapped client
+    Inner(E),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for ChaosError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ChaosError::Dropped => write!(f, "injected dropped connection"),
+            ChaosError::Status(status) => write!(f, "injected status {}", status),
+            ChaosError::Inner(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for ChaosError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChaosError::Inner(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<E: From<ParseUrlError>> From<ParseUrlError> for ChaosError<E> {
+    fn from(e: ParseUrlError) -> Self {
+        ChaosError::Inner(E::from(e))
+    }
+}
+
+impl<E: ClassifySendError> ClassifySendError for ChaosError<E> {
+    fn kind(&self) -> SendErrorKind {
+        match self {
+            ChaosError::Dropped => SendErrorKind::Connect,
+            ChaosError::Status(status) => SendErrorKind::Status(*status),
+            ChaosError::Inner(e) => e.kind(),
+        }
+    }
+}
+
+/// A response received through a `ChaosClient`, whose Set-Cookie headers may be corrupted
+#[derive(Debug)]
+pub struct ChaosResponse<R> {
+    response: R,
+    corrupt: bool,
+}
+
+impl<R> ChaosResponse<R> {
+    /// The wrapped client's response
+    pub fn into_inner(self) -> R {
+        self.response
+    }
+}
+
+impl<R> std::ops::Deref for ChaosResponse<R> {
+    type Target = R;
+    fn deref(&self) -> &R {
+        &self.response
+    }
+}
+
+impl<R: SessionResponse> SessionResponse for ChaosResponse<R> {
+    type Url = R::Url;
+
+    fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+        let cookies = self.response.parse_set_cookie();
+        if !self.corrupt {
+            return cookies;
+        }
+        cookies
+            .into_iter()
+            .map(|mut cookie| {
+                let value = cookie.value().to_owned();
+                let mut end = value.len() / 2;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                cookie.set_value(value[..end].to_owned());
+                cookie
+            })
+            .collect()
+    }
+
+    fn final_url(&self) -> Option<&Self::Url> {
+        self.response.final_url()
+    }
+
+    fn redirect_locations(&self) -> Vec<String> {
+        self.response.redirect_locations()
+    }
+
+    fn date(&self) -> Option<String> {
+        self.response.date()
+    }
+
+    fn raw_set_cookie(&self) -> Vec<String> {
+        self.response.raw_set_cookie()
+    }
+
+    fn clear_site_data(&self) -> Option<String> {
+        self.response.clear_site_data()
+    }
+
+    fn connection_reused(&self) -> Option<bool> {
+        self.response.connection_reused()
+    }
+
+    fn is_proxy_response(&self) -> bool {
+        self.response.is_proxy_response()
+    }
+
+    fn status_code(&self) -> Option<u16> {
+        self.response.status_code()
+    }
+
+    fn retry_after(&self) -> Option<String> {
+        self.response.retry_after()
+    }
+
+    fn location(&self) -> Option<String> {
+        self.response.location()
+    }
+
+    fn strict_transport_security(&self) -> Option<String> {
+        self.response.strict_transport_security()
+    }
+
+    fn www_authenticate(&self) -> Vec<String> {
+        self.response.www_authenticate()
+    }
+}
+
+impl<R: StatusResponse> StatusResponse for ChaosResponse<R> {
+    fn status(&self) -> u16 {
+        self.response.status()
+    }
+}
+
+impl<R: HeaderResponse> HeaderResponse for ChaosResponse<R> {
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.response.header_values(name)
+    }
+}
+
+impl<R: ContentResponse> ContentResponse for ChaosResponse<R> {
+    type BodyError = R::BodyError;
+
+    fn content_type(&self) -> Option<&str> {
+        self.response.content_type()
+    }
+
+    fn into_body(self) -> Result<Vec<u8>, Self::BodyError> {
+        self.response.into_body()
+    }
+
+    fn into_chunks(self) -> BodyChunks<Self::BodyError> {
+        self.response.into_chunks()
+    }
+}
+
+impl<C: SessionClient> SessionClient for ChaosClient<C> {
+    type Request = C::Request;
+    type Response = ChaosResponse<C::Response>;
+    type SendError = ChaosError<C::SendError>;
+
+    fn get_request(&self, url: &Url) -> Self::Request {
+        self.inner.get_request(url)
+    }
+    fn put_request(&self, url: &Url) -> Self::Request {
+        self.inner.put_request(url)
+    }
+    fn head_request(&self, url: &Url) -> Self::Request {
+        self.inner.head_request(url)
+    }
+    fn delete_request(&self, url: &Url) -> Self::Request {
+        self.inner.delete_request(url)
+    }
+    fn post_request(&self, url: &Url) -> Self::Request {
+        self.inner.post_request(url)
+    }
+    fn patch_request(&self, url: &Url) -> Self::Request {
+        self.inner.patch_request(url)
+    }
+    fn options_request(&self, url: &Url) -> Self::Request {
+        self.inner.options_request(url)
+    }
+    fn request(&self, method: &Method, url: &Url) -> Self::Request {
+        self.inner.request(method, url)
+    }
+
+    fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let (rate, delay) = self.latency;
+        if self.roll(rate) {
+            debug!("injecting {:?} latency", delay);
+            std::thread::sleep(delay);
+        }
+        if self.roll(self.dropped) {
+            debug!("injecting dropped connection");
+            return Err(ChaosError::Dropped);
+        }
+        let response = self.inner.send(request).map_err(ChaosError::Inner)?;
+        let (rate, status) = self.server_errors;
+        if self.roll(rate) {
+            debug!("injecting status {}", status);
+            return Err(ChaosError::Status(status));
+        }
+        Ok(ChaosResponse {
+            response,
+            corrupt: self.roll(self.corrupt_set_cookie),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChaosClient, ChaosError};
+    use crate::session::{
+        ClassifySendError, SendErrorKind, Session, SessionClient, SessionRequest, SessionResponse,
+    };
+    use cookie::Cookie as RawCookie;
+    use http::Method;
+    use url::{ParseError as ParseUrlError, Url};
+
+    struct Client;
+    struct Request;
+    struct Response;
+    #[derive(Debug)]
+    struct Error;
+
+    impl SessionRequest for Request {
+        fn add_cookies(self, _: Vec<&RawCookie<'static>>) -> Self {
+            self
+        }
+    }
+    impl SessionResponse for Response {
+        type Url = Url;
+        fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+            vec![RawCookie::new("token", "abcdef")]
+        }
+        fn final_url(&self) -> Option<&Url> {
+            None
+        }
+    }
+    impl From<ParseUrlError> for Error {
+        fn from(_: ParseUrlError) -> Self {
+            Error
+        }
+    }
+    impl ClassifySendError for Error {}
+    impl SessionClient for Client {
+        type Request = Request;
+        type Response = Response;
+        type SendError = Error;
+        fn request(&self, _: &Method, _: &Url) -> Request {
+            Request
+        }
+        fn send(&self, _: Request) -> Result<Response, Error> {
+            Ok(Response)
+        }
+    }
+
+    #[test]
+    fn faults() {
+        let url = "http://www.example.com/";
+        let mut session = Session::new(ChaosClient::new(Client, 7).with_corrupt_set_cookie(1.0));
+        session.get(url).unwrap();
+        assert_eq!(
+            "abc",
+            session
+                .store
+                .get("www.example.com", "/", "token")
+                .unwrap()
+                .value()
+        );
+
+        let mut session = Session::new(ChaosClient::new(Client, 7).with_dropped_connections(1.0));
+        let e = session.get(url).err().unwrap();
+        assert!(matches!(e, ChaosError::Dropped));
+        assert!(e.is_retryable());
+
+        let mut session = Session::new(ChaosClient::new(Client, 7).with_server_errors(1.0, 502));
+        let e = session.get(url).err().unwrap();
+        assert_eq!(SendErrorKind::Status(502), e.kind());
+
+        // the same seed injects the same faults
+        let outcomes = |seed| {
+            let mut session =
+                Session::new(ChaosClient::new(Client, seed).with_dropped_connections(0.5));
+            (0..32)
+                .map(|_| session.get(url).is_ok())
+                .collect::<Vec<_>>()
+        };
+        let first = outcomes(1);
+        assert_eq!(first, outcomes(1));
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::attribution::domain_site;
+use crate::events::{notify, EventSinks, StoreEvent};
+use crate::journal::Journal;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::is_host_only;
+use cookie::Cookie as RawCookie;
+use cookie_store::Cookie;
+use log::{debug, warn};
+use url::{Host, Url};
+
+/// A directive of a `Clear-Site-Data` response header; the wildcard `"*"` is reported as every
+/// directive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClearSiteData {
+    Cache,
+    Cookies,
+    Storage,
+    ExecutionContexts,
+}
+
+type ClearSiteDataHandler = Box<dyn FnMut(&Url, &[ClearSiteData]) + Send>;
+
+/// How a `Session` handles `Clear-Site-Data` response headers
+#[derive(Default)]
+pub(crate) struct SiteDataClearing {
+    /// Whether to leave the store untouched by `"cookies"` directives
+    preserve_cookies: bool,
+    handler: Option<ClearSiteDataHandler>,
+}
+
+/// The directives of the `Clear-Site-Data` header `header`, in order and without duplicates;
+/// unquoted and unknown directives are ignored
+pub(crate) fn parse(header: &str) -> Vec<ClearSiteData> {
+    let mut directives = vec![];
+    for directive in header.split(',').map(str::trim) {
+        let parsed: &[ClearSiteData] = match directive {
+            "\"cache\"" => &[ClearSiteData::Cache],
+            "\"cookies\"" => &[ClearSiteData::Cookies],
+            "\"storage\"" => &[ClearSiteData::Storage],
+            "\"executionContexts\"" => &[ClearSiteData::ExecutionContexts],
+            "\"*\"" => &[
+                ClearSiteData::Cache,
+                ClearSiteData::Cookies,
+                ClearSiteData::Storage,
+                ClearSiteData::ExecutionContexts,
+            ],
+            _ => {
+                debug!("ignoring Clear-Site-Data directive {}", directive);
+                &[]
+            }
+        };
+        for directive in parsed {
+            if !directives.contains(directive) {
+                directives.push(*directive);
+            }
+        }
+    }
+    directives
+}
+
+/// A Set-Cookie expiring `cookie`, and the URL to store it as received from
+pub(crate) fn expiring(cookie: &Cookie<'_>) -> Result<(RawCookie<'static>, Url), String> {
+    let domain = String::from(&cookie.domain);
+    let path = String::from(&cookie.path);
+    let mut set_cookie = format!("{}=; Path={}; Max-Age=0", cookie.name(), path);
+    if !is_host_only(cookie) {
+        set_cookie.push_str(&format!("; Domain={}", domain));
+    }
+    let url = Url::parse(&format!("https://{}{}", domain, path)).map_err(|e| e.to_string())?;
+    let expired = RawCookie::parse(set_cookie).map_err(|e| e.to_string())?;
+    Ok((expired, url))
+}
+
+/// Remove the cookies of the site of `url` from `store`: those whose domain is of the same site
+/// (as approximated by `attribution::site`, in the absence of a public suffix list), or for IP
+/// address hosts, those of exactly that host. Each cookie is removed by storing an expired
+/// cookie in its place, so any `CookieStorage` supports it. Returns the number removed.
+fn clear_cookies<S: CookieStorage>(
+    store: &mut S,
+    sinks: &mut EventSinks,
+    journal: &mut Option<Journal>,
+    url: &Url,
+) -> usize {
+    let of_site = |domain: &str| match url.host() {
+        Some(Host::Domain(host)) => domain_site(domain) == domain_site(host),
+        Some(host) => domain == host.to_string(),
+        None => false,
+    };
+    let cookies = store
+        .iter_unexpired()
+        .filter(|c| of_site(&String::from(&c.domain)))
+        .collect::<Vec<_>>();
+    let mut cleared = 0;
+    for cookie in &cookies {
+        let removed = expiring(cookie).and_then(|(expired, cookie_url)| {
+            store
+                .insert_raw(&expired, &cookie_url)
+                .map_err(|e| e.to_string())?;
+            if let Some(journal) = journal {
+                journal.record(&expired, &cookie_url);
+            }
+            Ok(())
+        });
+        match removed {
+            Ok(()) => {
+                cleared += 1;
+                notify(sinks, StoreEvent::Cleared { cookie, url });
+            }
+            Err(e) => warn!("unable to clear cookie {}: {}", cookie.name(), e),
+        }
+    }
+    cleared
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Leave the store untouched by the `"cookies"` directive of `Clear-Site-Data` response
+    /// headers, e.g. to preserve a login across a logout page; directives are still reported to
+    /// any handler set with `with_clear_site_data_handler`
+    pub fn without_clear_site_data(mut self) -> Self {
+        self.site_data_clearing.preserve_cookies = true;
+        self
+    }
+
+    /// Call `handler` with the URL a response was served from and the directives of its
+    /// `Clear-Site-Data` header, for the callers to act on those the session does not (e.g.
+    /// `"cache"` and `"storage"`). Called after any cookies are cleared.
+    pub fn with_clear_site_data_handler<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&Url, &[ClearSiteData]) + Send + 'static,
+    {
+        self.site_data_clearing.handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Act on the `Clear-Site-Data` header `header` of a response served from `url`: unless
+    /// disabled, the cookies of its site are removed (including any just set by the same
+    /// response, as browsers do). As the header is only honored from secure contexts, it is
+    /// ignored from `http` URLs (other than local hosts in dev mode, whose URLs are canonicalized
+    /// to `https`).
+    pub(crate) fn clear_site_data(&mut self, url: &Url, header: &str) {
+        if url.scheme() != "https" {
+            debug!("ignoring Clear-Site-Data from insecure {}", url);
+            return;
+        }
+        let directives = parse(header);
+        if directives.contains(&ClearSiteData::Cookies) && !self.site_data_clearing.preserve_cookies
+        {
+            let cleared = clear_cookies(
+                &mut self.store,
+                &mut self.event_sinks,
+                &mut self.journal,
+                url,
+            );
+            debug!(
+                "cleared {} cookies for Clear-Site-Data from {}",
+                cleared, url
+            );
+        }
+        if let Some(ref mut handler) = self.site_data_clearing.handler {
+            if !directives.is_empty() {
+                handler(url, &directives);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "reqwest-session"))]
+mod tests {
+    use super::{parse, ClearSiteData};
+    use crate::ReqwestSession;
+    use std::sync::{Arc, Mutex};
+    use url::Url;
+
+    #[test]
+    fn clear_site_data() {
+        assert_eq!(
+            vec![ClearSiteData::Cookies, ClearSiteData::Cache],
+            parse("\"cookies\", \"cache\", cookies, \"unknown\", \"cookies\"")
+        );
+        assert_eq!(4, parse("\"*\", \"cache\"").len());
+
+        let reported = Arc::new(Mutex::new(vec![]));
+        let handler_reported = reported.clone();
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new())
+            .with_clear_site_data_handler(move |url, directives| {
+                handler_reported
+                    .lock()
+                    .unwrap()
+                    .push((url.clone(), directives.to_vec()))
+            });
+        let url = Url::parse("https://www.example.com/account/logout").unwrap();
+        let other = Url::parse("https://www.example.org/").unwrap();
+        session.store.parse("id=1; Path=/account", &url).unwrap();
+        session
+            .store
+            .parse("pref=2; Domain=example.com; Path=/", &url)
+            .unwrap();
+        session.store.parse("id=3", &other).unwrap();
+
+        let insecure = Url::parse("http://www.example.com/").unwrap();
+        session.clear_site_data(&insecure, "\"cookies\"");
+        assert_eq!(3, session.store.iter_unexpired().count());
+        session.clear_site_data(&url, "\"cookies\", \"storage\"");
+        let remaining = session.store.iter_unexpired().collect::<Vec<_>>();
+        assert_eq!(1, remaining.len());
+        assert_eq!("www.example.org", String::from(&remaining[0].domain));
+        assert_eq!(
+            vec![(url, vec![ClearSiteData::Cookies, ClearSiteData::Storage])],
+            *reported.lock().unwrap()
+        );
+
+        let mut session = session.without_clear_site_data();
+        session.clear_site_data(&other, "\"*\"");
+        assert_eq!(1, session.store.iter_unexpired().count());
+        assert_eq!(2, reported.lock().unwrap().len());
+    }
+}
@@ -0,0 +1,101 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::transfer::TransferStats;
+use log::warn;
+use std::collections::{BTreeMap, HashSet};
+use url::Url;
+
+/// The number of new connections made to an origin, none of them reused, after which keep-alive
+/// is reported as not working for it
+const KEEP_ALIVE_CHECK: u64 = 10;
+
+/// The connections the responses from an origin were received over, as reported by
+/// `SessionResponse::connection_reused`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Responses received over a newly established connection (e.g. paying a TLS handshake)
+    pub new: u64,
+    /// Responses received over a connection kept alive from an earlier request
+    pub reused: u64,
+}
+
+/// A snapshot of the activity of a `Session`, as returned by `Session::stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    pub requests: u64,
+    /// Requests which failed to be sent
+    pub send_errors: u64,
+    pub transfer: TransferStats,
+    /// The connection reuse of each origin, by ASCII serialized origin (e.g.
+    /// `https://www.example.com`); only responses of integrations which report their connection
+    /// are counted
+    pub connections: BTreeMap<String, ConnectionStats>,
+}
+
+/// The connection reuse of the origins a `Session` has received responses from
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionReuse {
+    by_origin: BTreeMap<String, ConnectionStats>,
+    /// The origins keep-alive has been reported as not working for
+    warned: HashSet<String>,
+}
+
+impl ConnectionReuse {
+    /// Record that a response from `url` was received over a `reused` connection, or a new one
+    pub(crate) fn record(&mut self, url: &Url, reused: bool) {
+        let origin = url.origin().ascii_serialization();
+        let stats = self.by_origin.entry(origin.clone()).or_default();
+        if reused {
+            stats.reused += 1;
+        } else {
+            stats.new += 1;
+        }
+        if stats.reused == 0 && stats.new >= KEEP_ALIVE_CHECK && self.warned.insert(origin) {
+            warn!(
+                "no connection to {} reused in {} requests; is keep-alive disabled?",
+                url.origin().ascii_serialization(),
+                stats.new
+            );
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// A snapshot of the requests the session has sent, the bytes it has transferred, and its
+    /// connection reuse, e.g. to verify that polling reuses connections rather than paying a TLS
+    /// handshake each request
+    pub fn stats(&self) -> SessionStats {
+        SessionStats {
+            requests: self.counters.requests,
+            send_errors: self.counters.send_errors,
+            transfer: self.transfer_stats(),
+            connections: self.connections.by_origin.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConnectionReuse, ConnectionStats};
+    use url::Url;
+
+    #[test]
+    fn connection_reuse() {
+        let mut reuse = ConnectionReuse::default();
+        let url = Url::parse("https://www.example.com/poll").unwrap();
+        let other = Url::parse("http://www.example.org/").unwrap();
+        reuse.record(&url, false);
+        reuse.record(&url, true);
+        reuse.record(&url, true);
+        for _ in 0..10 {
+            reuse.record(&other, false);
+        }
+        assert_eq!(
+            Some(&ConnectionStats { new: 1, reused: 2 }),
+            reuse.by_origin.get("https://www.example.com")
+        );
+        assert_eq!(10, reuse.by_origin["http://www.example.org"].new);
+        assert!(reuse.warned.contains("http://www.example.org"));
+        assert!(!reuse.warned.contains("https://www.example.com"));
+    }
+}
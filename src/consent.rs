@@ -0,0 +1,169 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use url::Url;
+
+/// A form POST which records consent with a site's consent management platform
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsentPost {
+    /// The URL to POST to, resolved against the URL navigated to
+    pub url: String,
+    /// The form fields to send
+    #[serde(default)]
+    pub form: BTreeMap<String, String>,
+}
+
+/// How to acknowledge the cookie consent prompt of the sites under `domain`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsentRecipe {
+    /// The domain the recipe applies to, including its subdomains
+    pub domain: String,
+    /// Cookies (in Set-Cookie header format) to store as if received from the URL navigated to
+    #[serde(default)]
+    pub cookies: Vec<String>,
+    /// A POST to send after the cookies are stored
+    #[serde(default)]
+    pub post: Option<ConsentPost>,
+}
+
+impl ConsentRecipe {
+    fn matches(&self, url: &Url) -> bool {
+        let domain = self.domain.trim_start_matches('.');
+        match url.host_str() {
+            Some(host) => {
+                host.eq_ignore_ascii_case(domain)
+                    || (host.len() > domain.len()
+                        && host
+                            .to_lowercase()
+                            .ends_with(&format!(".{}", domain.to_lowercase())))
+            }
+            None => false,
+        }
+    }
+}
+
+/// A set of `ConsentRecipe`s, and the domains they have been applied to, for use with
+/// `Session::acknowledge_consent`
+#[derive(Debug, Default)]
+pub struct ConsentRecipes {
+    recipes: Vec<ConsentRecipe>,
+    acknowledged: HashSet<String>,
+}
+
+impl ConsentRecipes {
+    pub fn new(recipes: Vec<ConsentRecipe>) -> Self {
+        ConsentRecipes {
+            recipes,
+            acknowledged: HashSet::new(),
+        }
+    }
+
+    /// Read recipes from a JSON array of `ConsentRecipe`s
+    pub fn from_json<R: Read>(reader: R) -> Result<Self, crate::Error> {
+        Ok(ConsentRecipes::new(serde_json::from_reader(reader)?))
+    }
+
+    /// Whether the recipe for `domain` has been applied
+    pub fn is_acknowledged(&self, domain: &str) -> bool {
+        self.acknowledged.contains(domain)
+    }
+
+    /// Forget which recipes have been applied, e.g. after the session's store is cleared
+    pub fn reset(&mut self) {
+        self.acknowledged.clear();
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Apply the recipe in `consent` matching `url`, if it has not been applied yet: store its
+    /// cookies as if received from `url`, then send its POST (prepared with the recipe's form by
+    /// `prepare_post`, e.g. `|req, post| req.form(&post.form)`) through the session. Intended to be
+    /// called after the first navigation to a site, so that later requests see the content behind
+    /// the consent prompt. Returns whether a recipe was applied.
+    pub fn acknowledge_consent<P>(
+        &mut self,
+        consent: &mut ConsentRecipes,
+        url: &Url,
+        prepare_post: P,
+    ) -> Result<bool, <C as SessionClient>::SendError>
+    where
+        P: FnOnce(<C as SessionClient>::Request, &ConsentPost) -> <C as SessionClient>::Request,
+    {
+        let recipe = match consent.recipes.iter().find(|r| r.matches(url)) {
+            Some(recipe) if !consent.acknowledged.contains(&recipe.domain) => recipe,
+            _ => return Ok(false),
+        };
+        debug!("acknowledging consent for {} via {}", url, recipe.domain);
+        for set_cookie in &recipe.cookies {
+            let inserted = RawCookie::parse(set_cookie.clone())
+                .map_err(|e| e.to_string())
+                .and_then(|cookie| {
+                    self.store
+                        .insert_raw(&cookie, url)
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = inserted {
+                warn!(
+                    "unable to store consent cookie {} for {}: {}",
+                    set_cookie, url, e
+                );
+            }
+        }
+        if let Some(ref post) = recipe.post {
+            let post_url = self.request_url(url.join(&post.url)?);
+            let request = self.client.post_request(&post_url);
+            self.run_request(request, "POST", &post_url, |request| prepare_post(request, post))?;
+        }
+        consent.acknowledged.insert(recipe.domain.clone());
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConsentRecipes;
+    use crate::ReqwestSession;
+    use url::Url;
+
+    #[test]
+    fn acknowledge() {
+        let mut consent = ConsentRecipes::from_json(
+            r#"[
+                { "domain": "example.com", "cookies": ["euconsent=granted; Max-Age=3600"] },
+                { "domain": "example.org", "post": { "url": "/consent", "form": { "all": "1" } } }
+            ]"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new());
+        let url = Url::parse("https://www.example.com/article").unwrap();
+        let post = |_: reqwest::blocking::RequestBuilder, _: &super::ConsentPost| unreachable!();
+
+        assert!(session
+            .acknowledge_consent(&mut consent, &url, post)
+            .unwrap());
+        assert!(consent.is_acknowledged("example.com"));
+        assert_eq!(
+            "granted",
+            session
+                .store
+                .get("www.example.com", "/", "euconsent")
+                .unwrap()
+                .value()
+        );
+        assert!(!session
+            .acknowledge_consent(&mut consent, &url, post)
+            .unwrap());
+        let other = Url::parse("https://notexample.com/").unwrap();
+        assert!(!session
+            .acknowledge_consent(&mut consent, &other, post)
+            .unwrap());
+
+        consent.reset();
+        assert!(!consent.is_acknowledged("example.com"));
+    }
+}
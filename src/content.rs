@@ -0,0 +1,474 @@
+#[cfg(feature = "reqwest-session")]
+use crate::reqwest_session::ReqwestSessionError;
+use crate::session::{Session, SessionClient, SessionResponse};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+#[cfg(feature = "persistence")]
+use serde::de::DeserializeOwned;
+#[cfg(all(feature = "persistence", feature = "reqwest-session"))]
+use serde::Serialize;
+#[cfg(feature = "reqwest-session")]
+use std::io::{self, Read};
+use std::string::FromUtf8Error;
+
+/// The chunks of a response body, each read only as it is pulled, so that a slow consumer holds
+/// back the transfer rather than buffering it; see `ContentResponse::into_chunks`
+pub type BodyChunks<E> = Box<dyn Iterator<Item = Result<Vec<u8>, E>>>;
+
+/// Chunks read from `reader` as they are pulled, ending at the end of its input or its first
+/// error
+#[cfg(feature = "reqwest-session")]
+struct ReadChunks<R> {
+    reader: Option<R>,
+}
+
+#[cfg(feature = "reqwest-session")]
+impl<R: Read> Iterator for ReadChunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let reader = self.reader.as_mut()?;
+        let mut chunk = vec![0; 8192];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => {
+                    chunk.truncate(n);
+                    return Some(Ok(chunk));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.reader = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.reader = None;
+        None
+    }
+}
+
+/// Responses whose Content-Type and body can be read, implemented for each integration's
+/// response type, for use with `Session::get_json`, `get_text`, and `get_bytes`
+pub trait ContentResponse: SessionResponse + 'static {
+    /// The error reading the body may fail with
+    type BodyError: 'static;
+    /// The value of the Content-Type header, if present
+    fn content_type(&self) -> Option<&str>;
+    /// Read the (decompressed) body
+    fn into_body(self) -> Result<Vec<u8>, Self::BodyError>;
+
+    /// Pass the body to `on_chunk` chunk by chunk as it is read, stopping early if `on_chunk`
+    /// returns `false`, so the session can account for (and throttle) the transfer, or spool it
+    /// to disk. By default, the chunks are those of `into_chunks`.
+    fn stream_body(self, on_chunk: &mut dyn FnMut(&[u8]) -> bool) -> Result<(), Self::BodyError>
+    where
+        Self: Sized,
+    {
+        for chunk in self.into_chunks() {
+            if !on_chunk(&chunk?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// The body as an iterator of chunks, each read as it is pulled, for consumers which read
+    /// the body at their own pace; backends reading bodies incrementally need implement only
+    /// this. By default, the body is read with `into_body` as a single chunk.
+    fn into_chunks(self) -> BodyChunks<Self::BodyError>
+    where
+        Self: Sized,
+    {
+        Box::new(std::iter::once(self.into_body()))
+    }
+
+    /// Read the body as with `into_body`, calling `on_read` with the size of each chunk as it is
+    /// read
+    fn read_body(self, on_read: &mut dyn FnMut(usize)) -> Result<Vec<u8>, Self::BodyError>
+    where
+        Self: Sized,
+    {
+        let mut body = vec![];
+        self.stream_body(&mut |chunk| {
+            on_read(chunk.len());
+            body.extend_from_slice(chunk);
+            true
+        })?;
+        Ok(body)
+    }
+}
+
+#[cfg(feature = "reqwest-session")]
+impl ContentResponse for reqwest::blocking::Response {
+    type BodyError = ReqwestSessionError;
+
+    fn content_type(&self) -> Option<&str> {
+        self.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+    }
+
+    fn into_body(self) -> Result<Vec<u8>, Self::BodyError> {
+        Ok(self.bytes()?.to_vec())
+    }
+
+    fn into_chunks(self) -> BodyChunks<Self::BodyError> {
+        let chunks = ReadChunks { reader: Some(self) };
+        Box::new(chunks.map(|chunk| chunk.map_err(ReqwestSessionError::from)))
+    }
+}
+
+/// The failure of a `Session::get_json`, `post_json`, `get_text`, `get_bytes`, or `get_spooled`
+#[derive(Debug)]
+pub enum ContentError<E> {
+    /// Sending the request, or reading the response body, failed
+    Send(E),
+    /// The response to a `get_json` or `post_json` request had an error (4xx or 5xx) status,
+    /// with the body given (e.g. an `application/problem+json` document)
+    Status {
+        status: u16,
+        body: Vec<u8>,
+    },
+    /// The response's Content-Type was not the kind requested
+    ContentType {
+        expected: &'static str,
+        found: Option<String>,
+    },
+    #[cfg(feature = "persistence")]
+    Json(serde_json::Error),
+    Utf8(FromUtf8Error),
+    /// Spooling the body to disk failed
+    Io(std::io::Error),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for ContentError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ContentError::Send(e) => write!(f, "{}", e),
+            ContentError::Status { status, .. } => write!(f, "error status {}", status),
+            ContentError::ContentType {
+                expected,
+                found: Some(found),
+            } => write!(f, "expected {} content, found {}", expected, found),
+            ContentError::ContentType {
+                expected,
+                found: None,
+            } => write!(f, "expected {} content, found no Content-Type", expected),
+            #[cfg(feature = "persistence")]
+            ContentError::Json(e) => write!(f, "JSON error: {}", e),
+            ContentError::Utf8(e) => write!(f, "body is not UTF-8: {}", e),
+            ContentError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for ContentError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ContentError::Send(e) => Some(e),
+            ContentError::Status { .. } | ContentError::ContentType { .. } => None,
+            #[cfg(feature = "persistence")]
+            ContentError::Json(e) => Some(e),
+            ContentError::Utf8(e) => Some(e),
+            ContentError::Io(e) => Some(e),
+        }
+    }
+}
+
+/// A name for a kind of content, and the test of whether a Content-Type is of that kind
+type ContentKind = (&'static str, fn(&str) -> bool);
+
+/// The media type of a Content-Type value, lowercased and without parameters
+fn essence(content_type: &str) -> String {
+    let essence = content_type.split(';').next().unwrap_or("");
+    essence.trim().to_lowercase()
+}
+
+#[cfg(feature = "persistence")]
+fn is_json(content_type: &str) -> bool {
+    let essence = essence(content_type);
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+fn is_text(content_type: &str) -> bool {
+    let essence = essence(content_type);
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/json" | "application/xml" | "application/javascript"
+        )
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// GET `url` and read the body of a response of any Content-Type. Like `get_text` and
+    /// `get_json`, the body read is subject to any `with_download_limit`.
+    pub fn get_bytes<U>(
+        &mut self,
+        url: U,
+    ) -> Result<Vec<u8>, ContentError<<C as SessionClient>::SendError>>
+    where
+        U: IntoUrl,
+        <C as SessionClient>::Response: ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        self.get_content(url, None)
+    }
+
+    /// GET `url` and read the body of a textual (`text/*`, JSON, or XML) response as UTF-8
+    pub fn get_text<U>(
+        &mut self,
+        url: U,
+    ) -> Result<String, ContentError<<C as SessionClient>::SendError>>
+    where
+        U: IntoUrl,
+        <C as SessionClient>::Response: ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let body = self.get_content(url, Some(("text", is_text)))?;
+        String::from_utf8(body).map_err(ContentError::Utf8)
+    }
+
+    /// GET `url` and deserialize the body of a JSON (`application/json` or `+json`) response,
+    /// failing with `ContentError::Status` for a response with an error status
+    #[cfg(feature = "persistence")]
+    pub fn get_json<U, T>(
+        &mut self,
+        url: U,
+    ) -> Result<T, ContentError<<C as SessionClient>::SendError>>
+    where
+        U: IntoUrl,
+        T: DeserializeOwned,
+        <C as SessionClient>::Response: ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let response = self.get(url).map_err(ContentError::Send)?;
+        self.read_json(response)
+    }
+
+    /// Deserialize the body of `response`, which must have a JSON Content-Type and a status
+    /// other than an error status
+    #[cfg(feature = "persistence")]
+    fn read_json<T>(
+        &mut self,
+        response: <C as SessionClient>::Response,
+    ) -> Result<T, ContentError<<C as SessionClient>::SendError>>
+    where
+        T: DeserializeOwned,
+        <C as SessionClient>::Response: ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        if let Some(status) = response.status_code().filter(|status| *status >= 400) {
+            let body = self.read_content(response, None)?;
+            return Err(ContentError::Status { status, body });
+        }
+        let body = self.read_content(response, Some(("JSON", is_json)))?;
+        serde_json::from_slice(&body).map_err(ContentError::Json)
+    }
+
+    /// GET `url`, check its Content-Type is that `expected` (if any), and read its body
+    fn get_content<U>(
+        &mut self,
+        url: U,
+        expected: Option<ContentKind>,
+    ) -> Result<Vec<u8>, ContentError<<C as SessionClient>::SendError>>
+    where
+        U: IntoUrl,
+        <C as SessionClient>::Response: ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let response = self.get(url).map_err(ContentError::Send)?;
+        self.read_content(response, expected)
+    }
+
+    /// Check the Content-Type of `response` is that `expected` (if any), and read its body
+    pub(crate) fn read_content(
+        &mut self,
+        response: <C as SessionClient>::Response,
+        expected: Option<ContentKind>,
+    ) -> Result<Vec<u8>, ContentError<<C as SessionClient>::SendError>>
+    where
+        <C as SessionClient>::Response: ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        if let Some((expected, matches)) = expected {
+            match response.content_type() {
+                Some(content_type) if matches(content_type) => {}
+                found => {
+                    return Err(ContentError::ContentType {
+                        expected,
+                        found: found.map(str::to_owned),
+                    })
+                }
+            }
+        }
+        response
+            .read_body(&mut |n| self.downloaded(n))
+            .map_err(|e| ContentError::Send(<C as SessionClient>::SendError::from(e)))
+    }
+}
+
+#[cfg(all(feature = "persistence", feature = "reqwest-session"))]
+impl<S: CookieStorage> Session<reqwest::blocking::Client, S> {
+    /// POST `body`, serialized as JSON, to `url`, and deserialize the body of a JSON response as
+    /// `get_json` does
+    pub fn post_json<U, B, T>(
+        &mut self,
+        url: U,
+        body: &B,
+    ) -> Result<T, ContentError<ReqwestSessionError>>
+    where
+        U: IntoUrl,
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let response = self
+            .post_with(url, |request| request.json(body))
+            .map_err(ContentError::Send)?;
+        self.read_json(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_text;
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn json_content_types() {
+        use super::is_json;
+
+        assert!(is_json("application/json; charset=utf-8"));
+        assert!(is_json("Application/Problem+JSON"));
+        assert!(!is_json("text/html"));
+    }
+
+    #[test]
+    fn content_types() {
+        assert!(is_text("text/html; charset=utf-8"));
+        assert!(is_text("application/atom+xml"));
+        assert!(!is_text("image/png"));
+    }
+
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn reqwest_response() {
+        use super::{ContentResponse, ReadChunks};
+
+        let response = reqwest::blocking::Response::from(
+            http::Response::builder()
+                .header("content-type", "application/json")
+                .body(r#"{"a":1}"#)
+                .unwrap(),
+        );
+        assert_eq!(Some("application/json"), response.content_type());
+        let mut read = 0;
+        let body = response.read_body(&mut |n| read += n).unwrap();
+        assert_eq!(br#"{"a":1}"#.to_vec(), body);
+        assert_eq!(7, read);
+
+        let body = vec![1; 20_000];
+        let chunks = ReadChunks {
+            reader: Some(&body[..]),
+        };
+        let sizes = chunks.map(|chunk| chunk.unwrap().len()).collect::<Vec<_>>();
+        assert_eq!(vec![8192, 8192, 3616], sizes);
+    }
+
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn chunked_body() {
+        use super::ContentResponse;
+
+        let response = || {
+            reqwest::blocking::Response::from(
+                http::Response::builder().body(vec![1; 20_000]).unwrap(),
+            )
+        };
+        let sizes = response()
+            .into_chunks()
+            .map(|chunk| chunk.unwrap().len())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![8192, 8192, 3616], sizes);
+        // read through `into_chunks` by default, stopping when asked
+        let mut sizes = vec![];
+        response()
+            .stream_body(&mut |chunk| {
+                sizes.push(chunk.len());
+                false
+            })
+            .unwrap();
+        assert_eq!(vec![8192], sizes);
+    }
+
+    #[cfg(all(feature = "persistence", feature = "reqwest-session"))]
+    #[test]
+    fn post_json() {
+        use super::ContentError;
+        use crate::ReqwestSession;
+        use serde_json::{json, Value};
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let responses = [
+                "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\n\
+                 Set-Cookie: id=1\r\nContent-Length: 9\r\nConnection: close\r\n\r\n{\"id\":7}\n",
+                "HTTP/1.1 422 Unprocessable Entity\r\nContent-Type: application/problem+json\r\n\
+                 Content-Length: 2\r\nConnection: close\r\n\r\n{}",
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                 Content-Length: 1\r\nConnection: close\r\n\r\n{",
+            ];
+            let mut requests = vec![];
+            for response in responses.iter() {
+                let (stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while reader.read_line(&mut request).unwrap() > 2 {}
+                let length = request
+                    .lines()
+                    .find_map(|h| {
+                        h.to_lowercase()
+                            .strip_prefix("content-length:")?
+                            .trim()
+                            .parse()
+                            .ok()
+                    })
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                (&stream).write_all(response.as_bytes()).unwrap();
+                requests.push(request.to_lowercase());
+            }
+            requests
+        });
+        let mut s = ReqwestSession::new(reqwest::blocking::Client::new());
+        let created: Value = s.post_json(&url[..], &json!({"name": "a"})).unwrap();
+        assert_eq!(json!({"id": 7}), created);
+        match s.post_json::<_, _, Value>(&url[..], &json!({})) {
+            Err(ContentError::Status { status, body }) => {
+                assert_eq!(422, status);
+                assert_eq!(b"{}".to_vec(), body);
+            }
+            other => panic!("expected a status error, got {:?}", other),
+        }
+        match s.post_json::<_, _, Value>(&url[..], &json!({})) {
+            Err(ContentError::Json(_)) => {}
+            other => panic!("expected a JSON error, got {:?}", other),
+        }
+        let requests = server.join().unwrap();
+        assert!(requests[0].contains("content-type: application/json"));
+        assert!(requests[1].contains("cookie: id=1"));
+    }
+}
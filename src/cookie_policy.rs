@@ -0,0 +1,279 @@
+use crate::events::RejectionReason;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use std::fmt;
+use std::sync::Arc;
+use url::Url;
+
+type CookieCheck = dyn Fn(&RawCookie<'static>, &Url) -> Result<(), String> + Send + Sync;
+
+/// Which Set-Cookies received by a `Session` are accepted for storing; those which are not are
+/// reported as `RejectionReason::Policy`
+#[derive(Clone, Default)]
+pub enum CookiePolicy {
+    /// Accept any cookie the store accepts (the default)
+    #[default]
+    Permissive,
+    /// Accept only cookies conforming to the grammar of RFC 6265 section 4.1: a name which is a
+    /// token, a value of cookie-octets (optionally in double quotes), and any `Path` beginning
+    /// with `/`
+    Rfc6265Strict,
+    /// Accept only cookies conforming to `Rfc6265Strict` which also follow the cookie prefix
+    /// rules of RFC 6265bis: a cookie named `__Secure-*` must be `Secure` and set from a secure
+    /// URL, and one named `__Host-*` must also have no `Domain` and a `Path` of `/`
+    Rfc6265bis,
+    /// Accept the cookies, received from a URL, for which the function returns `Ok`; the `Err`
+    /// is the reason the cookie is rejected
+    Custom(Arc<CookieCheck>),
+}
+
+impl fmt::Debug for CookiePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CookiePolicy::Permissive => f.write_str("Permissive"),
+            CookiePolicy::Rfc6265Strict => f.write_str("Rfc6265Strict"),
+            CookiePolicy::Rfc6265bis => f.write_str("Rfc6265bis"),
+            CookiePolicy::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl CookiePolicy {
+    /// A `CookiePolicy::Custom` accepting the cookies for which `check` returns `Ok`
+    pub fn custom<F>(check: F) -> Self
+    where
+        F: Fn(&RawCookie<'static>, &Url) -> Result<(), String> + Send + Sync + 'static,
+    {
+        CookiePolicy::Custom(Arc::new(check))
+    }
+
+    /// Why `cookie`, received from `url`, is not accepted under the policy, if it is not; e.g. for
+    /// a `Custom` policy to build on another
+    pub fn check(&self, cookie: &RawCookie<'static>, url: &Url) -> Result<(), String> {
+        match self {
+            CookiePolicy::Permissive => Ok(()),
+            CookiePolicy::Rfc6265Strict => check_rfc6265(cookie),
+            CookiePolicy::Rfc6265bis => {
+                check_rfc6265(cookie).and_then(|_| check_prefixes(cookie, url))
+            }
+            CookiePolicy::Custom(check) => check(cookie, url),
+        }
+    }
+
+    /// `cookies`, received from `url`, split into those accepted, and those rejected with the
+    /// reason for each
+    pub(crate) fn filter(
+        &self,
+        url: &Url,
+        cookies: Vec<RawCookie<'static>>,
+    ) -> (Vec<RawCookie<'static>>, Vec<(RawCookie<'static>, String)>) {
+        let mut rejected = vec![];
+        let accepted = cookies
+            .into_iter()
+            .filter_map(|cookie| match self.check(&cookie, url) {
+                Ok(()) => Some(cookie),
+                Err(reason) => {
+                    rejected.push((cookie, reason));
+                    None
+                }
+            })
+            .collect();
+        (accepted, rejected)
+    }
+}
+
+/// A Set-Cookie received by a `Session` which was not stored, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct RejectedCookie {
+    pub cookie: RawCookie<'static>,
+    /// The URL of the response which set the cookie
+    pub url: Url,
+    pub reason: RejectionReason,
+}
+
+/// Whether `c` is a `token` character of RFC 2616
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?={}".contains(c)
+}
+
+/// Whether `c` is a `cookie-octet` of RFC 6265
+fn is_cookie_octet(c: char) -> bool {
+    c.is_ascii_graphic() && !"\",;\\".contains(c)
+}
+
+fn check_rfc6265(cookie: &RawCookie<'static>) -> Result<(), String> {
+    let name = cookie.name();
+    if name.is_empty() || !name.chars().all(is_token_char) {
+        return Err(format!("name {:?} is not a token", name));
+    }
+    let value = cookie.value();
+    let unquoted = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    };
+    if !unquoted.chars().all(is_cookie_octet) {
+        return Err(format!("value {:?} is not made of cookie-octets", value));
+    }
+    match cookie.path() {
+        Some(path) if !path.starts_with('/') => {
+            Err(format!("Path {:?} does not begin with '/'", path))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether `name` begins with `prefix`, ignoring case
+fn has_prefix(name: &str, prefix: &str) -> bool {
+    name.len() >= prefix.len()
+        && name.is_char_boundary(prefix.len())
+        && name[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn check_prefixes(cookie: &RawCookie<'static>, url: &Url) -> Result<(), String> {
+    let name = cookie.name();
+    let host = has_prefix(name, "__Host-");
+    if !host && !has_prefix(name, "__Secure-") {
+        return Ok(());
+    }
+    if cookie.secure() != Some(true) {
+        return Err(format!("{} is not Secure", name));
+    }
+    if !matches!(url.scheme(), "https" | "wss") {
+        return Err(format!("{} was set from insecure {}", name, url));
+    }
+    if host && cookie.domain().is_some() {
+        return Err(format!("{} has a Domain", name));
+    }
+    if host && cookie.path() != Some("/") {
+        return Err(format!("{} does not have a Path of '/'", name));
+    }
+    Ok(())
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Set which Set-Cookies the session accepts for storing
+    pub fn with_cookie_policy(mut self, policy: CookiePolicy) -> Self {
+        self.cookie_policy = policy;
+        self
+    }
+
+    /// The Set-Cookies of the responses to the last request (including any redirects followed)
+    /// which were not stored, and why, e.g. to debug why a cookie did not stick. Rejections are
+    /// also reported as they happen to any `StoreEventSink` as `StoreEvent::Rejected`.
+    pub fn rejected_cookies(&self) -> &[RejectedCookie] {
+        &self.rejected_cookies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CookiePolicy;
+    use cookie::Cookie as RawCookie;
+    use url::Url;
+
+    #[test]
+    fn check() {
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let cookie = |s: &str| RawCookie::parse(s.to_owned()).unwrap();
+        let strict = CookiePolicy::Rfc6265Strict;
+        assert!(strict.check(&cookie("id=1; Path=/"), &url).is_ok());
+        assert!(strict.check(&cookie("id=\"1\""), &url).is_ok());
+        assert!(strict.check(&cookie("id=a b"), &url).is_err());
+        assert!(strict.check(&cookie("i(d)=1"), &url).is_err());
+        assert!(strict.check(&cookie("id=1; Path=account"), &url).is_err());
+        assert!(CookiePolicy::Permissive
+            .check(&cookie("id=a b"), &url)
+            .is_ok());
+
+        let bis = CookiePolicy::Rfc6265bis;
+        let insecure = Url::parse("http://www.example.com/").unwrap();
+        assert!(bis.check(&cookie("__Secure-id=1; Secure"), &url).is_ok());
+        assert!(bis.check(&cookie("__secure-id=1"), &url).is_err());
+        assert!(bis
+            .check(&cookie("__Secure-id=1; Secure"), &insecure)
+            .is_err());
+        assert!(bis
+            .check(&cookie("__Host-id=1; Secure; Path=/"), &url)
+            .is_ok());
+        assert!(bis.check(&cookie("__Host-id=1; Secure"), &url).is_err());
+        assert!(bis
+            .check(
+                &cookie("__Host-id=1; Secure; Path=/; Domain=example.com"),
+                &url
+            )
+            .is_err());
+        assert!(bis.check(&cookie("id=a b"), &url).is_err());
+        assert!(strict.check(&cookie("__Host-id=1"), &url).is_ok());
+
+        let policy = CookiePolicy::custom(|cookie, _| match cookie.name() {
+            "tracker" => Err("no trackers".to_owned()),
+            _ => Ok(()),
+        });
+        let (accepted, rejected) = policy.filter(&url, vec![cookie("id=1"), cookie("tracker=2")]);
+        assert_eq!(vec![cookie("id=1")], accepted);
+        assert_eq!(
+            vec![(cookie("tracker=2"), "no trackers".to_owned())],
+            rejected
+        );
+    }
+
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn rejected_cookies() {
+        use crate::events::RejectionReason;
+        use crate::ReqwestSession;
+
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new())
+            .with_cookie_policy(CookiePolicy::Rfc6265Strict);
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let cookies = vec![
+            RawCookie::parse("id=1").unwrap(),
+            RawCookie::parse("theme=a b").unwrap(),
+            RawCookie::parse("other=1; Domain=example.org").unwrap(),
+        ];
+        session.store_response_cookies(cookies, &url);
+        assert_eq!(1, session.store.iter_unexpired().count());
+        let rejected = session
+            .rejected_cookies()
+            .iter()
+            .map(|r| match r.reason {
+                RejectionReason::Policy(_) => format!("policy {}", r.cookie.name()),
+                RejectionReason::Invalid(_) => format!("invalid {}", r.cookie.name()),
+                _ => format!("other {}", r.cookie.name()),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["policy theme", "invalid other"], rejected);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn as_account() {
+        use crate::events::RejectionReason;
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let client = MockClient::new()
+            .with_response(
+                MockResponse::new(200)
+                    .with_set_cookie("__Host-id=1; Domain=example.com; Path=/x")
+                    .with_set_cookie("id=1"),
+            )
+            .with_response(MockResponse::new(200));
+        let mut session = Session::new(client)
+            .with_cookie_policy(CookiePolicy::Rfc6265bis)
+            .with_accounts();
+        let url = "http://www.example.com/x";
+        session.as_account("alice").get(url).unwrap();
+        let rejected = session.rejected_cookies();
+        assert_eq!(1, rejected.len());
+        assert_eq!("__Host-id", rejected[0].cookie.name());
+        assert!(matches!(rejected[0].reason, RejectionReason::Policy(_)));
+        assert_eq!(vec!["alice"], session.accounts());
+
+        session.as_account("alice").get(url).unwrap();
+        let last = session.client.last_request().unwrap();
+        assert_eq!(vec!["id"], last.cookie_names());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::attribution::domain_site;
+use crate::expiry::Expiry;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::{is_host_only, is_sensitive_name};
+use cookie::Cookie as RawCookie;
+use cookie_store::CookieError;
+use hmac::{Hmac, Mac, NewMac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// The names of the cookies which carry a login, per site, for `Session::export_credentials`
+#[derive(Debug, Clone, Default)]
+pub struct CredentialRecipes {
+    names: HashMap<String, Vec<String>>,
+}
+
+impl CredentialRecipes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Export only the cookies named `names` for `site` (and its subdomains)
+    pub fn with_recipe(mut self, site: &str, names: &[&str]) -> Self {
+        self.names.insert(
+            domain_site(site),
+            names.iter().map(|name| (*name).to_owned()).collect(),
+        );
+        self
+    }
+}
+
+/// A cookie within a `CredentialBundle`
+#[derive(Debug, Clone, PartialEq)]
+struct CredentialCookie {
+    /// The cookie as a Set-Cookie header, with explicit Domain (if any) and Path, and no expiry
+    /// attributes
+    set_cookie: String,
+    /// The URL to store the cookie as if received from
+    url: String,
+    /// When the cookie expires, in seconds since the Unix epoch; `None` for a session cookie
+    expires: Option<u64>,
+}
+
+/// The cookies carrying a login to a site, signed so that the receiving agent can verify their
+/// origin; see `Session::export_credentials`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CredentialBundle {
+    site: String,
+    cookies: Vec<CredentialCookie>,
+    signature: Vec<u8>,
+}
+
+/// Why a `CredentialBundle` could not be read or imported
+#[derive(Debug)]
+pub enum CredentialError {
+    /// The bundle is not valid JSON in the format written by `CredentialBundle::to_json`
+    Malformed(String),
+    /// The bundle's signature does not match its contents for the given key
+    BadSignature,
+    ParseUrlError(url::ParseError),
+    CookieError(CookieError),
+}
+
+impl std::fmt::Display for CredentialError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CredentialError::Malformed(e) => write!(f, "Malformed credential bundle: {}", e),
+            CredentialError::BadSignature => write!(f, "Credential bundle signature mismatch"),
+            CredentialError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            CredentialError::CookieError(e) => write!(f, "Cookie error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CredentialError {}
+
+impl From<url::ParseError> for CredentialError {
+    fn from(e: url::ParseError) -> Self {
+        CredentialError::ParseUrlError(e)
+    }
+}
+
+impl From<CookieError> for CredentialError {
+    fn from(e: CookieError) -> Self {
+        CredentialError::CookieError(e)
+    }
+}
+
+fn mac(key: &[u8], site: &str, cookies: &[CredentialCookie]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC accepts keys of any length");
+    mac.update(site.as_bytes());
+    for cookie in cookies {
+        let expires = cookie.expires.map(|e| e.to_string()).unwrap_or_default();
+        mac.update(format!("\n{}\t{}\t{}", cookie.set_cookie, cookie.url, expires).as_bytes());
+    }
+    mac
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 == 1 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+impl CredentialBundle {
+    /// The site the bundle holds the login for
+    pub fn site(&self) -> &str {
+        &self.site
+    }
+
+    /// The names of the cookies in the bundle
+    pub fn cookie_names(&self) -> Vec<String> {
+        self.cookies
+            .iter()
+            .filter_map(|c| RawCookie::parse(c.set_cookie.as_str()).ok())
+            .map(|c| c.name().to_owned())
+            .collect()
+    }
+
+    /// Serialize the bundle for transfer to another agent
+    pub fn to_json(&self) -> String {
+        let cookies = self
+            .cookies
+            .iter()
+            .map(|c| json!({ "set_cookie": c.set_cookie, "url": c.url, "expires": c.expires }))
+            .collect::<Vec<_>>();
+        json!({
+            "site": self.site,
+            "cookies": cookies,
+            "signature": to_hex(&self.signature),
+        })
+        .to_string()
+    }
+
+    /// Read a bundle written by `to_json`. The signature is checked on import.
+    pub fn from_json(s: &str) -> Result<CredentialBundle, CredentialError> {
+        let malformed = |what: &str| CredentialError::Malformed(what.to_owned());
+        let value = serde_json::from_str::<Value>(s)
+            .map_err(|e| CredentialError::Malformed(e.to_string()))?;
+        let string = |v: &Value, field: &str| {
+            v[field]
+                .as_str()
+                .map(str::to_owned)
+                .ok_or_else(|| malformed(field))
+        };
+        let cookies = value["cookies"]
+            .as_array()
+            .ok_or_else(|| malformed("cookies"))?
+            .iter()
+            .map(|c| {
+                Ok(CredentialCookie {
+                    set_cookie: string(c, "set_cookie")?,
+                    url: string(c, "url")?,
+                    expires: c["expires"].as_u64(),
+                })
+            })
+            .collect::<Result<Vec<_>, CredentialError>>()?;
+        Ok(CredentialBundle {
+            site: string(&value, "site")?,
+            cookies,
+            signature: from_hex(&string(&value, "signature")?)
+                .ok_or_else(|| malformed("signature"))?,
+        })
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Export the cookies needed to transplant a login to `site` (e.g. `example.com`) to another
+    /// agent, signed with `key`. If `recipes` names the login cookies for the site, only those are
+    /// exported; otherwise, cookies whose names suggest credentials (e.g. containing `session`,
+    /// `auth`, or `token`) are. The bundle may be imported with `import_credentials` given the
+    /// same `key`.
+    pub fn export_credentials(
+        &self,
+        site: &str,
+        recipes: &CredentialRecipes,
+        key: &[u8],
+    ) -> CredentialBundle {
+        let site = domain_site(site);
+        let recipe = recipes.names.get(&site);
+        let cookies = self
+            .store
+            .iter_unexpired()
+            .filter(|c| domain_site(&String::from(&c.domain)) == site)
+            .filter(|c| match recipe {
+                Some(names) => names.iter().any(|name| name == c.name()),
+                None => is_sensitive_name(c.name()),
+            })
+            .map(|c| {
+                let path = String::from(&c.path);
+                // the name-value pair, then its attributes, less those rewritten below
+                let mut set_cookie = c
+                    .to_string()
+                    .split(';')
+                    .map(str::trim)
+                    .enumerate()
+                    .filter(|(i, attr)| {
+                        let name = attr.split('=').next().unwrap_or_default().trim();
+                        *i == 0
+                            || !["domain", "path", "max-age", "expires"]
+                                .iter()
+                                .any(|a| name.eq_ignore_ascii_case(a))
+                    })
+                    .map(|(_, attr)| attr)
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                let domain = String::from(&c.domain);
+                if !is_host_only(&c) {
+                    set_cookie.push_str(&format!("; Domain={}", domain));
+                }
+                set_cookie.push_str(&format!("; Path={}", path));
+                let expires = Expiry::of(&c)
+                    .time()
+                    .and_then(|at| at.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs());
+                CredentialCookie {
+                    set_cookie,
+                    url: format!("https://{}{}", domain, path),
+                    expires,
+                }
+            })
+            .collect::<Vec<_>>();
+        let signature = mac(key, &site, &cookies).finalize().into_bytes().to_vec();
+        CredentialBundle {
+            site,
+            cookies,
+            signature,
+        }
+    }
+
+    /// Verify `bundle` was exported with `key`, and store its unexpired cookies. Returns the
+    /// number of cookies stored.
+    pub fn import_credentials(
+        &mut self,
+        bundle: &CredentialBundle,
+        key: &[u8],
+    ) -> Result<usize, CredentialError> {
+        mac(key, &bundle.site, &bundle.cookies)
+            .verify(&bundle.signature)
+            .map_err(|_| CredentialError::BadSignature)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut imported = 0;
+        for cookie in &bundle.cookies {
+            let set_cookie = match cookie.expires {
+                Some(expires) if expires <= now => continue,
+                Some(expires) => format!("{}; Max-Age={}", cookie.set_cookie, expires - now),
+                None => cookie.set_cookie.clone(),
+            };
+            let raw_cookie = RawCookie::parse(set_cookie)
+                .map_err(|e| CredentialError::Malformed(e.to_string()))?;
+            self.store
+                .insert_raw(&raw_cookie, &Url::parse(&cookie.url)?)?;
+            imported += 1;
+        }
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CredentialBundle, CredentialError, CredentialRecipes};
+    use crate::ReqwestSession;
+    use cookie::Cookie as RawCookie;
+    use url::Url;
+
+    #[test]
+    fn handoff() {
+        let mut from = ReqwestSession::new(reqwest::blocking::Client::new());
+        let url = Url::parse("https://www.example.com/account/").unwrap();
+        for set_cookie in &[
+            "session_id=s1; Max-Age=3600; Secure",
+            "remember=r1; Domain=example.com; Path=/; Max-Age=86400",
+            "theme=dark; Max-Age=3600",
+            "auth=a1",
+        ] {
+            let cookie = RawCookie::parse(set_cookie.to_string()).unwrap();
+            from.store.insert_raw(&cookie, &url).unwrap();
+        }
+        let other = Url::parse("https://www.example.org/").unwrap();
+        from.store
+            .insert_raw(&RawCookie::parse("sessionid=x").unwrap(), &other)
+            .unwrap();
+
+        let key = b"shared secret";
+        let mut names = from
+            .export_credentials("example.com", &CredentialRecipes::new(), key)
+            .cookie_names();
+        names.sort();
+        assert_eq!(vec!["auth", "session_id"], names);
+
+        let recipes =
+            CredentialRecipes::new().with_recipe("example.com", &["session_id", "remember"]);
+        let bundle = from.export_credentials("www.example.com", &recipes, key);
+        let bundle = CredentialBundle::from_json(&bundle.to_json()).unwrap();
+        assert_eq!("example.com", bundle.site());
+
+        let mut to = ReqwestSession::new(reqwest::blocking::Client::new());
+        match to.import_credentials(&bundle, b"wrong key") {
+            Err(CredentialError::BadSignature) => {}
+            other => panic!("unexpected import result {:?}", other),
+        }
+        assert_eq!(2, to.import_credentials(&bundle, key).unwrap());
+        let session_id = to
+            .store
+            .get("www.example.com", "/account", "session_id")
+            .unwrap();
+        assert_eq!("s1", session_id.value());
+        assert!(session_id.is_persistent());
+        assert!(session_id.secure().unwrap_or(false));
+        assert!(to.store.get("example.com", "/", "remember").is_some());
+        let sent = to
+            .store
+            .get_request_cookies(&Url::parse("https://login.example.com/").unwrap())
+            .map(|c| c.name())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["remember"], sent);
+    }
+
+    #[test]
+    fn attribute_names() {
+        let mut from = ReqwestSession::new(reqwest::blocking::Client::new());
+        let url = Url::parse("https://www.example.com/").unwrap();
+        for set_cookie in &[
+            "domain_session=d1; Domain=example.com; Max-Age=3600",
+            "expires_at=e1; Path=/; Max-Age=3600",
+        ] {
+            let cookie = RawCookie::parse(set_cookie.to_string()).unwrap();
+            from.store.insert_raw(&cookie, &url).unwrap();
+        }
+        let recipes =
+            CredentialRecipes::new().with_recipe("example.com", &["domain_session", "expires_at"]);
+        let key = b"shared secret";
+        let bundle = from.export_credentials("example.com", &recipes, key);
+
+        let mut to = ReqwestSession::new(reqwest::blocking::Client::new());
+        assert_eq!(2, to.import_credentials(&bundle, key).unwrap());
+        let domain_session = to.store.get("example.com", "/", "domain_session").unwrap();
+        assert_eq!("d1", domain_session.value());
+        let expires_at = to.store.get("www.example.com", "/", "expires_at").unwrap();
+        assert_eq!("e1", expires_at.value());
+        assert!(expires_at.is_persistent());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use cookie_store::Cookie;
+use std::collections::BTreeMap;
+
+type Key = (String, String, String);
+
+fn key(cookie: &Cookie<'_>) -> Key {
+    (
+        String::from(&cookie.domain),
+        String::from(&cookie.path),
+        cookie.name().to_owned(),
+    )
+}
+
+/// The changes a response made to a `Session`'s store, as reported by `Session::get_with_report`;
+/// or those between two stores, as reported by `Session::diff` and `Session::merge_from`
+#[derive(Debug, Clone, Default)]
+pub struct CookieDelta {
+    /// The cookies the response set which were not in the store
+    pub added: Vec<Cookie<'static>>,
+    /// The cookies the response changed the value, attributes, or expiry of, as changed
+    pub updated: Vec<Cookie<'static>>,
+    /// The cookies the response expired or removed (e.g. by `Clear-Site-Data`), as they were
+    pub expired: Vec<Cookie<'static>>,
+}
+
+impl CookieDelta {
+    /// Whether the response left the store unchanged
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.expired.is_empty()
+    }
+
+    /// The cookie named `name` the response added, if any
+    pub fn added(&self, name: &str) -> Option<&Cookie<'static>> {
+        self.added.iter().find(|c| c.name() == name)
+    }
+
+    /// The cookie named `name` the response updated, as updated, if any
+    pub fn updated(&self, name: &str) -> Option<&Cookie<'static>> {
+        self.updated.iter().find(|c| c.name() == name)
+    }
+
+    /// The cookie named `name` the response expired, as it was, if any
+    pub fn expired(&self, name: &str) -> Option<&Cookie<'static>> {
+        self.expired.iter().find(|c| c.name() == name)
+    }
+}
+
+/// The unexpired cookies of a store at some point, by domain, path, and name
+pub(crate) struct StoreSnapshot {
+    cookies: BTreeMap<Key, Cookie<'static>>,
+}
+
+impl StoreSnapshot {
+    pub(crate) fn take<S: CookieStorage>(store: &S) -> Self {
+        let cookies = store.iter_unexpired().map(|c| (key(&c), c)).collect();
+        StoreSnapshot { cookies }
+    }
+
+    /// The changes made to the store since the snapshot was taken of it, which is now `store`
+    pub(crate) fn delta<S: CookieStorage>(&self, store: &S) -> CookieDelta {
+        self.diff(&StoreSnapshot::take(store))
+    }
+
+    /// The changes from the cookies of the snapshot to those of `now`
+    pub(crate) fn diff(&self, now: &StoreSnapshot) -> CookieDelta {
+        let mut delta = CookieDelta::default();
+        for (key, cookie) in &now.cookies {
+            match self.cookies.get(key) {
+                None => delta.added.push(cookie.clone()),
+                Some(before) if !same(before, cookie) => delta.updated.push(cookie.clone()),
+                Some(_) => {}
+            }
+        }
+        delta.expired = self
+            .cookies
+            .iter()
+            .filter(|(key, _)| !now.cookies.contains_key(key))
+            .map(|(_, cookie)| cookie.clone())
+            .collect();
+        delta
+    }
+
+    /// The cookie with the domain, path, and name of `cookie`, as it was
+    pub(crate) fn before(&self, cookie: &Cookie<'_>) -> Option<&Cookie<'static>> {
+        self.cookies.get(&key(cookie))
+    }
+}
+
+/// Whether `a` and `b` have the same value, attributes, and expiry (to the second)
+pub(crate) fn same(a: &Cookie<'_>, b: &Cookie<'_>) -> bool {
+    // `SameSite` is not compared by `cookie`'s equality
+    a == b && a.same_site() == b.same_site()
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// As `get_with`, with the changes the response made to the store, e.g. for tests to assert
+    /// the cookie side effects of each request
+    pub fn get_with_report<U, P>(
+        &mut self,
+        url: U,
+        prepare: P,
+    ) -> Result<(<C as SessionClient>::Response, CookieDelta), <C as SessionClient>::SendError>
+    where
+        P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+        U: IntoUrl,
+    {
+        let snapshot = StoreSnapshot::take(&self.store);
+        let response = self.get_with(url, prepare)?;
+        Ok((response, snapshot.delta(&self.store)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StoreSnapshot;
+    use cookie_store::CookieStore;
+    use url::Url;
+
+    #[test]
+    fn delta() {
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let mut store = CookieStore::default();
+        for set_cookie in &["kept=1", "changed=1", "gone=1; Max-Age=3600"] {
+            store.parse(set_cookie, &url).unwrap();
+        }
+        let snapshot = StoreSnapshot::take(&store);
+        assert!(snapshot.delta(&store).is_empty());
+
+        for set_cookie in &["kept=1", "changed=2", "gone=; Max-Age=0", "new=1"] {
+            store.parse(set_cookie, &url).ok();
+        }
+        let delta = snapshot.delta(&store);
+        assert!(delta.added("new").is_some());
+        assert_eq!("2", delta.updated("changed").unwrap().value());
+        assert_eq!("1", delta.expired("gone").unwrap().value());
+        assert_eq!(
+            (1, 1, 1),
+            (delta.added.len(), delta.updated.len(), delta.expired.len())
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use url::{Host, Url};
+
+/// Values by domain, each applying to its domain and subdomains, for looking up the value of the
+/// closest domain of a request's host without allocating. Domains are normalized (lowercased,
+/// without leading or trailing dots) once, as inserted, and the most labels of any domain is
+/// kept, so that suffixes of a host too long to be in the map are skipped without hashing them.
+#[derive(Debug, Clone)]
+pub struct DomainMap<V> {
+    values: HashMap<Box<str>, V>,
+    /// The most labels of any domain in the map
+    max_labels: usize,
+}
+
+impl<V> Default for DomainMap<V> {
+    fn default() -> Self {
+        DomainMap {
+            values: HashMap::new(),
+            max_labels: 0,
+        }
+    }
+}
+
+/// The number of labels of the normalized domain `domain`
+fn labels(domain: &str) -> usize {
+    domain.bytes().filter(|b| *b == b'.').count() + 1
+}
+
+impl<V> DomainMap<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Set the value of `domain` (and its subdomains), returning its previous value, if any
+    pub fn insert(&mut self, domain: &str, value: V) -> Option<V> {
+        let domain = domain.trim_matches('.').to_lowercase();
+        self.max_labels = self.max_labels.max(labels(&domain));
+        self.values.insert(domain.into_boxed_str(), value)
+    }
+
+    /// The value of `host`, or failing that, of its closest parent domain with one. `host` is
+    /// expected to be lowercase, as the hosts of `Url`s are.
+    pub fn closest(&self, host: &str) -> Option<&V> {
+        if self.values.is_empty() {
+            return None;
+        }
+        let host = host.trim_end_matches('.');
+        let mut suffix = host;
+        let mut labels = labels(host);
+        while labels > self.max_labels {
+            match suffix.find('.') {
+                Some(i) => suffix = &suffix[i + 1..],
+                None => return None,
+            }
+            labels -= 1;
+        }
+        loop {
+            if let Some(value) = self.values.get(suffix) {
+                return Some(value);
+            }
+            match suffix.find('.') {
+                Some(i) => suffix = &suffix[i + 1..],
+                None => return None,
+            }
+        }
+    }
+
+    /// The value of the closest domain of the host of `url`; IP address hosts match only
+    /// themselves
+    pub fn for_url(&self, url: &Url) -> Option<&V> {
+        match url.host() {
+            Some(Host::Domain(domain)) => self.closest(domain),
+            Some(_) => url.host_str().and_then(|host| self.values.get(host)),
+            None => None,
+        }
+    }
+
+    /// Whether `url` is of a domain in the map, or a subdomain of one
+    pub fn matches(&self, url: &Url) -> bool {
+        self.for_url(url).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DomainMap;
+    use url::Url;
+
+    #[test]
+    fn closest() {
+        let mut domains = DomainMap::new();
+        domains.insert(".Example.com", 1);
+        domains.insert("api.example.com", 2);
+        domains.insert("127.0.0.1", 3);
+        let url = |url: &str| Url::parse(url).unwrap();
+        assert_eq!(Some(&1), domains.for_url(&url("https://www.example.com/")));
+        assert_eq!(Some(&1), domains.for_url(&url("https://example.com./")));
+        assert_eq!(
+            Some(&2),
+            domains.for_url(&url("https://v1.api.example.com/"))
+        );
+        assert_eq!(Some(&2), domains.closest("a.b.c.d.api.example.com"));
+        assert_eq!(None, domains.for_url(&url("https://example.org/")));
+        assert_eq!(None, domains.for_url(&url("https://notexample.com/")));
+        assert_eq!(Some(&3), domains.for_url(&url("http://127.0.0.1:8080/")));
+        assert!(!domains.matches(&url("http://0.0.1/")));
+    }
+}
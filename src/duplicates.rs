@@ -0,0 +1,114 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use log::debug;
+use std::collections::HashMap;
+
+/// Which of the Set-Cookies of one response for the same cookie name a `Session` stores; those
+/// not stored are reported to any `StoreEventSink` as `RejectionReason::Duplicate`, so that
+/// backends setting a cookie several times by mistake can be detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateSetCookies {
+    /// Store only the last Set-Cookie for each name
+    LastWins,
+    /// Store only the first Set-Cookie for each name, as some servers (wrongly) expect
+    FirstWins,
+    /// Store the last Set-Cookie for each name, `Domain`, and `Path`, so that cookies of the
+    /// same name scoped to different paths are all kept, as by RFC 6265 (the default)
+    #[default]
+    KeepAllByPath,
+}
+
+/// The scope within which a Set-Cookie supersedes others per `KeepAllByPath`
+fn scope(cookie: &RawCookie<'_>) -> (String, Option<String>, Option<String>) {
+    (
+        cookie.name().to_owned(),
+        cookie
+            .domain()
+            .map(|d| d.trim_start_matches('.').to_lowercase()),
+        cookie.path().map(str::to_owned),
+    )
+}
+
+impl DuplicateSetCookies {
+    /// `cookies`, set by one response, split into those to store, and the duplicates not to
+    pub(crate) fn dedupe(
+        self,
+        cookies: Vec<RawCookie<'static>>,
+    ) -> (Vec<RawCookie<'static>>, Vec<RawCookie<'static>>) {
+        let key = |cookie: &RawCookie<'_>| match self {
+            DuplicateSetCookies::KeepAllByPath => scope(cookie),
+            _ => (cookie.name().to_owned(), None, None),
+        };
+        // the index of the cookie kept for each key
+        let mut kept = HashMap::new();
+        for (i, cookie) in cookies.iter().enumerate() {
+            if self == DuplicateSetCookies::FirstWins {
+                kept.entry(key(cookie)).or_insert(i);
+            } else {
+                kept.insert(key(cookie), i);
+            }
+        }
+        if kept.len() == cookies.len() {
+            return (cookies, vec![]);
+        }
+        let (stored, duplicates): (Vec<_>, Vec<_>) = cookies
+            .into_iter()
+            .enumerate()
+            .partition(|(i, cookie)| kept.get(&key(cookie)) == Some(i));
+        for (_, cookie) in &duplicates {
+            debug!("not storing duplicate Set-Cookie '{}'", cookie);
+        }
+        let unindexed = |cookies: Vec<(usize, RawCookie<'static>)>| {
+            cookies.into_iter().map(|(_, cookie)| cookie).collect()
+        };
+        (unindexed(stored), unindexed(duplicates))
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Set which of the Set-Cookies of a response for the same cookie name are stored
+    pub fn with_duplicate_set_cookies(mut self, strategy: DuplicateSetCookies) -> Self {
+        self.duplicates = strategy;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DuplicateSetCookies;
+    use cookie::Cookie as RawCookie;
+
+    #[test]
+    fn dedupe() {
+        let cookies = || {
+            vec![
+                RawCookie::parse("id=1; Path=/").unwrap(),
+                RawCookie::parse("id=2; Path=/app").unwrap(),
+                RawCookie::parse("theme=dark").unwrap(),
+                RawCookie::parse("id=3; Path=/").unwrap(),
+            ]
+        };
+        let values = |cookies: Vec<RawCookie<'static>>| {
+            cookies
+                .iter()
+                .map(|c| format!("{}={}", c.name(), c.value()))
+                .collect::<Vec<_>>()
+        };
+        let (stored, duplicates) = DuplicateSetCookies::LastWins.dedupe(cookies());
+        assert_eq!(vec!["theme=dark", "id=3"], values(stored));
+        assert_eq!(vec!["id=1", "id=2"], values(duplicates));
+
+        let (stored, duplicates) = DuplicateSetCookies::FirstWins.dedupe(cookies());
+        assert_eq!(vec!["id=1", "theme=dark"], values(stored));
+        assert_eq!(vec!["id=2", "id=3"], values(duplicates));
+
+        let (stored, duplicates) = DuplicateSetCookies::KeepAllByPath.dedupe(cookies());
+        assert_eq!(vec!["id=2", "theme=dark", "id=3"], values(stored));
+        assert_eq!(vec!["id=1"], values(duplicates));
+
+        let (stored, duplicates) = DuplicateSetCookies::LastWins.dedupe(cookies()[1..3].to_vec());
+        assert_eq!(2, stored.len());
+        assert!(duplicates.is_empty());
+    }
+}
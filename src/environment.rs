@@ -0,0 +1,270 @@
+use crate::error::SessionError;
+use crate::lenient::Strictness;
+use crate::session::{Session, SessionClient};
+#[cfg(feature = "persistence")]
+use crate::shutdown::write_jar;
+use crate::storage::CookieStorage;
+use log::{debug, warn};
+use std::collections::HashMap;
+#[cfg(feature = "persistence")]
+use std::fs::File;
+#[cfg(feature = "persistence")]
+use std::io::BufReader;
+use std::path::PathBuf;
+use url::{ParseError as ParseUrlError, Url};
+
+/// A stack (e.g. staging or production) a `Session` can be pointed at with `use_environment`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Environment {
+    /// The URL `Session::environment_url` resolves paths against
+    pub base_url: Option<Url>,
+    /// Hosts to send requests for to other hosts instead, e.g. `api.example.com` to
+    /// `api.staging.example.com`
+    pub host_overrides: HashMap<String, String>,
+    /// The jar the environment's store is loaded from and saved to (with the `persistence`
+    /// feature)
+    pub jar: Option<PathBuf>,
+    pub strictness: Strictness,
+    /// Treat `http` URLs of local hosts as secure contexts (see `Session::with_dev_mode`)
+    pub dev_mode: bool,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_base_url(mut self, base_url: Url) -> Self {
+        self.base_url = Some(base_url);
+        self
+    }
+
+    pub fn with_host_override(mut self, host: &str, replacement: &str) -> Self {
+        self.host_overrides
+            .insert(host.to_lowercase(), replacement.to_lowercase());
+        self
+    }
+
+    pub fn with_jar<P: Into<PathBuf>>(mut self, jar: P) -> Self {
+        self.jar = Some(jar.into());
+        self
+    }
+
+    pub fn with_strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+
+    pub fn with_dev_mode(mut self) -> Self {
+        self.dev_mode = true;
+        self
+    }
+}
+
+/// The name given to `Session::use_environment` is not that of an environment of the session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownEnvironment(pub String);
+
+impl std::fmt::Display for UnknownEnvironment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "unknown environment: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownEnvironment {}
+
+/// The environments of a `Session`, and the stores of those not in use
+pub(crate) struct Environments<S> {
+    defined: HashMap<String, Environment>,
+    active: Option<String>,
+    stores: HashMap<String, S>,
+}
+
+impl<S> Default for Environments<S> {
+    fn default() -> Self {
+        Environments {
+            defined: HashMap::new(),
+            active: None,
+            stores: HashMap::new(),
+        }
+    }
+}
+
+impl<S> Environments<S> {
+    fn current(&self) -> Option<&Environment> {
+        self.defined.get(self.active.as_ref()?)
+    }
+
+    /// `url`, with its host replaced per the host overrides of the environment in use
+    pub(crate) fn rewrite(&self, mut url: Url) -> Url {
+        let replacement = self
+            .current()
+            .and_then(|environment| environment.host_overrides.get(url.host_str()?));
+        if let Some(replacement) = replacement {
+            debug!("sending request for {} to {}", url, replacement);
+            if let Err(e) = url.set_host(Some(replacement)) {
+                warn!("unable to override host of {}: {}", url, e);
+            }
+        }
+        url
+    }
+
+    /// The environments, with their stores replaced by those of type `T`: i.e. those not in use
+    /// are dropped
+    pub(crate) fn with_store<T>(self) -> Environments<T> {
+        Environments {
+            defined: self.defined,
+            active: self.active,
+            stores: HashMap::new(),
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Add the environment `environment` as `name`, for `use_environment`
+    pub fn with_environment(mut self, name: &str, environment: Environment) -> Self {
+        self.environments
+            .defined
+            .insert(name.to_owned(), environment);
+        self
+    }
+
+    /// Point the session at the environment `name`. The store of the environment in use, if
+    /// any, is saved to its jar and set aside, and the store of `name` (as set aside, or loaded
+    /// from its jar, or else empty) takes its place, along with its jar, strictness, and dev
+    /// mode; as each environment has its own store, cookies never pass between environments.
+    /// The store the session had before its first environment was used is dropped, so
+    /// environments should be configured before any requests are made.
+    pub fn use_environment(&mut self, name: &str) -> Result<(), SessionError>
+    where
+        S: Default,
+    {
+        let environment = match self.environments.defined.get(name) {
+            Some(environment) => environment.clone(),
+            None => return Err(UnknownEnvironment(name.to_owned()).into()),
+        };
+        if self.environments.active.as_deref() == Some(name) {
+            return Ok(());
+        }
+        #[cfg(feature = "persistence")]
+        if let (Some(_), Some(jar)) = (&self.environments.active, &self.jar) {
+            write_jar(&self.store, jar)?;
+        }
+        let store = match self.environments.stores.remove(name) {
+            Some(store) => store,
+            None => match environment.jar {
+                #[cfg(feature = "persistence")]
+                Some(ref jar) if jar.exists() => S::load_json(BufReader::new(File::open(jar)?))?,
+                _ => S::default(),
+            },
+        };
+        let previous = std::mem::replace(&mut self.store, store);
+        match self.environments.active.take() {
+            Some(active) => {
+                self.environments.stores.insert(active, previous);
+            }
+            None if previous.iter_any().next().is_some() => {
+                warn!("dropping cookies stored before using environment {}", name);
+            }
+            None => {}
+        }
+        debug!("using environment {}", name);
+        self.jar = environment.jar;
+        self.strictness = environment.strictness;
+        self.origin_aliases.dev_mode = environment.dev_mode;
+        self.environments.active = Some(name.to_owned());
+        Ok(())
+    }
+
+    /// The name of the environment in use, if any
+    pub fn environment(&self) -> Option<&str> {
+        self.environments.active.as_deref()
+    }
+
+    /// `path` resolved against the base URL of the environment in use
+    pub fn environment_url(&self, path: &str) -> Result<Url, ParseUrlError> {
+        match self
+            .environments
+            .current()
+            .and_then(|e| e.base_url.as_ref())
+        {
+            Some(base_url) => base_url.join(path),
+            None => Url::parse(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn environments() {
+        use super::Environment;
+        use crate::ReqwestSession;
+        use url::Url;
+
+        let dir =
+            std::env::temp_dir().join(format!("user_agent-environments-{}", std::process::id()));
+        let staging = Environment::new()
+            .with_base_url(Url::parse("https://staging.example.com/app/").unwrap())
+            .with_host_override("api.example.com", "api.staging.example.com")
+            .with_jar(dir.join("staging.json"));
+        let production = Environment::new()
+            .with_base_url(Url::parse("https://www.example.com/app/").unwrap())
+            .with_jar(dir.join("production.json"));
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new())
+            .with_environment("staging", staging)
+            .with_environment("production", production);
+        assert!(session.use_environment("dev").is_err());
+        assert_eq!(None, session.environment());
+
+        session.use_environment("staging").unwrap();
+        let url = session.environment_url("login").unwrap();
+        assert_eq!("https://staging.example.com/app/login", url.as_str());
+        let api = Url::parse("https://api.example.com/v1").unwrap();
+        assert_eq!(
+            "https://api.staging.example.com/v1",
+            session.environments.rewrite(api.clone()).as_str()
+        );
+        session.store.parse("id=1; Max-Age=3600", &url).unwrap();
+
+        session.use_environment("production").unwrap();
+        assert_eq!(Some("production"), session.environment());
+        assert_eq!(0, session.store.iter_any().count());
+        assert_eq!(api, session.environments.rewrite(api.clone()));
+        #[cfg(feature = "persistence")]
+        {
+            assert!(dir.join("staging.json").exists());
+            session.use_environment("staging").unwrap();
+            assert!(session.store.contains("staging.example.com", "/app", "id"));
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn redirects() {
+        use super::Environment;
+        use crate::testing::{MockClient, MockResponse};
+        use crate::{RedirectMode, Session};
+
+        let staging = Environment::new().with_host_override("api.example.com", "localhost");
+        let client = MockClient::new()
+            .with_response(
+                MockResponse::new(302).with_header("location", "https://api.example.com/v2"),
+            )
+            .with_response(MockResponse::new(200));
+        let mut session = Session::new(client)
+            .with_redirect_mode(RedirectMode::Session { max_redirects: 5 })
+            .with_environment("staging", staging);
+        session.use_environment("staging").unwrap();
+        session.get("https://api.example.com/v1").unwrap();
+        let urls = session
+            .client
+            .take_requests()
+            .into_iter()
+            .map(|request| request.url.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["https://localhost/v1", "https://localhost/v2"], urls);
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use std::io;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The failure of loading or saving a `Session` (or its store, jar, or journal), or of setting
+/// one up from a profile, environment, or browser
+#[derive(Debug)]
+pub enum SessionError {
+    /// Reading or writing failed
+    Io(io::Error),
+    /// Serialized cookies, or the JSON holding them, could not be read or written
+    #[cfg(feature = "persistence")]
+    Serde(serde_json::Error),
+    /// A cookie could not be parsed, e.g. by the `cookie_from_str` given to `Session::load`
+    CookieParse(BoxError),
+    /// The cookie store, or the backend it is kept in, failed, or does not support the operation,
+    /// or another failure (e.g. an `UnknownEnvironment`) occurred
+    Store(BoxError),
+}
+
+impl SessionError {
+    /// A `SessionError::CookieParse` for `e`
+    pub(crate) fn cookie_parse<E: Into<BoxError>>(e: E) -> Self {
+        SessionError::CookieParse(e.into())
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Io(e) => write!(f, "I/O error: {}", e),
+            #[cfg(feature = "persistence")]
+            SessionError::Serde(e) => write!(f, "serialization error: {}", e),
+            SessionError::CookieParse(e) => write!(f, "cookie parse error: {}", e),
+            SessionError::Store(e) => write!(f, "cookie store error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SessionError::Io(e) => Some(e),
+            #[cfg(feature = "persistence")]
+            SessionError::Serde(e) => Some(e),
+            SessionError::CookieParse(e) | SessionError::Store(e) => Some(&**e),
+        }
+    }
+}
+
+impl From<io::Error> for SessionError {
+    fn from(e: io::Error) -> Self {
+        SessionError::Io(e)
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl From<serde_json::Error> for SessionError {
+    fn from(e: serde_json::Error) -> Self {
+        SessionError::Serde(e)
+    }
+}
+
+impl From<cookie_store::CookieError> for SessionError {
+    fn from(e: cookie_store::CookieError) -> Self {
+        SessionError::cookie_parse(e)
+    }
+}
+
+/// The errors of `cookie_store`'s loading and saving, which are boxed, by their cause; errors of
+/// other causes are `Store` errors
+impl From<BoxError> for SessionError {
+    fn from(e: BoxError) -> Self {
+        let e = match e.downcast::<io::Error>() {
+            Ok(e) => return SessionError::Io(*e),
+            Err(e) => e,
+        };
+        #[cfg(feature = "persistence")]
+        let e = match e.downcast::<serde_json::Error>() {
+            Ok(e) => return SessionError::Serde(*e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<cookie_store::CookieError>() {
+            Ok(e) => return SessionError::CookieParse(e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<cookie::ParseError>() {
+            Ok(e) => return SessionError::CookieParse(e),
+            Err(e) => e,
+        };
+        match e.downcast::<SessionError>() {
+            Ok(e) => *e,
+            Err(e) => SessionError::Store(e),
+        }
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl From<crate::jar::LoadError> for SessionError {
+    fn from(e: crate::jar::LoadError) -> Self {
+        SessionError::cookie_parse(e)
+    }
+}
+
+impl From<url::ParseError> for SessionError {
+    fn from(e: url::ParseError) -> Self {
+        SessionError::cookie_parse(e)
+    }
+}
+
+impl From<cookie::ParseError> for SessionError {
+    fn from(e: cookie::ParseError) -> Self {
+        SessionError::cookie_parse(e)
+    }
+}
+
+#[cfg(feature = "redis-store")]
+impl From<redis::RedisError> for SessionError {
+    fn from(e: redis::RedisError) -> Self {
+        SessionError::Store(e.into())
+    }
+}
+
+#[cfg(any(feature = "browser-import", feature = "sqlite-store"))]
+impl From<rusqlite::Error> for SessionError {
+    fn from(e: rusqlite::Error) -> Self {
+        SessionError::Store(e.into())
+    }
+}
+
+impl From<crate::environment::UnknownEnvironment> for SessionError {
+    fn from(e: crate::environment::UnknownEnvironment) -> Self {
+        SessionError::Store(e.into())
+    }
+}
+
+impl From<&str> for SessionError {
+    fn from(message: &str) -> Self {
+        SessionError::Store(message.into())
+    }
+}
+
+impl From<String> for SessionError {
+    fn from(message: String) -> Self {
+        SessionError::Store(message.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoxError, SessionError};
+    use std::io;
+
+    #[test]
+    fn from_box_error() {
+        let boxed = |e: BoxError| SessionError::from(e);
+        let io = io::Error::new(io::ErrorKind::UnexpectedEof, "truncated");
+        assert!(matches!(boxed(io.into()), SessionError::Io(_)));
+        #[cfg(feature = "persistence")]
+        {
+            let serde = serde_json::from_str::<u32>("{").unwrap_err();
+            assert!(matches!(boxed(serde.into()), SessionError::Serde(_)));
+        }
+        let parse = cookie::Cookie::parse("no equals sign").unwrap_err();
+        assert!(matches!(boxed(parse.into()), SessionError::CookieParse(_)));
+        let nested = SessionError::cookie_parse("bad cookie");
+        assert!(matches!(boxed(nested.into()), SessionError::CookieParse(_)));
+        assert!(matches!(
+            boxed("backend down".into()),
+            SessionError::Store(_)
+        ));
+    }
+}
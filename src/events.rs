@@ -0,0 +1,91 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use cookie_store::{Cookie, CookieError};
+use url::Url;
+
+/// Why a Set-Cookie received by a `Session` was not stored
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectionReason {
+    /// The cookie store refused the cookie, e.g. for a Domain attribute not matching the URL
+    Invalid(CookieError),
+    /// The cookie would have updated a cookie pinned with `Session::pin_cookie`
+    Pinned,
+    /// The URL the response was served from was unknown, and the session is configured with
+    /// `UnknownFinalUrl::Discard`
+    UnknownUrl,
+    /// The response was from a proxy rather than the origin (see
+    /// `SessionResponse::is_proxy_response`)
+    ProxyResponse,
+    /// The response was for a cross-site subresource (see `Session::fetch_subresources`), and
+    /// the cookie was not `SameSite=None; Secure`
+    SameSite,
+    /// The response set the cookie more than once, and this Set-Cookie was not the one stored,
+    /// per `Session::with_duplicate_set_cookies`
+    Duplicate,
+    /// The cookie's `Domain` attribute was a public suffix, per the list given to
+    /// `Session::with_public_suffix_list`
+    PublicSuffix,
+    /// The cookie was not accepted under the session's `CookiePolicy`, for the reason given
+    Policy(String),
+    /// The cookie's name and value were larger than the session's `CookieLimits` allow
+    TooLarge,
+}
+
+/// A change to, or refusal of a change to, the contents of a `Session`'s cookie store
+#[derive(Debug)]
+pub enum StoreEvent<'a> {
+    /// A Set-Cookie received from `url` was not stored
+    Rejected {
+        cookie: &'a RawCookie<'static>,
+        url: &'a Url,
+        reason: RejectionReason,
+    },
+    /// A Set-Cookie received from `url` expired a cookie already in the store
+    Expired {
+        cookie: &'a RawCookie<'static>,
+        url: &'a Url,
+    },
+    /// A cookie was removed from the store to make room for others
+    Evicted { cookie: &'a Cookie<'static> },
+    /// A cookie was removed from the store by a `Clear-Site-Data` header of a response from `url`
+    Cleared {
+        cookie: &'a Cookie<'static>,
+        url: &'a Url,
+    },
+}
+
+/// Receives `StoreEvent`s from a `Session`; intended for tools (such as cookie managers) which
+/// need to explain why the store does or does not hold a given cookie. Implemented for closures
+/// taking a `&StoreEvent`.
+pub trait StoreEventSink {
+    fn on_event(&mut self, event: &StoreEvent<'_>);
+}
+
+impl<F> StoreEventSink for F
+where
+    F: FnMut(&StoreEvent<'_>),
+{
+    fn on_event(&mut self, event: &StoreEvent<'_>) {
+        self(event)
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Add `sink` to the sinks notified of `StoreEvent`s
+    pub fn with_event_sink<E>(mut self, sink: E) -> Self
+    where
+        E: StoreEventSink + Send + 'static,
+    {
+        self.event_sinks.push(Box::new(sink));
+        self
+    }
+}
+
+pub(crate) type EventSinks = Vec<Box<dyn StoreEventSink + Send>>;
+
+pub(crate) fn notify(sinks: &mut EventSinks, event: StoreEvent<'_>) {
+    for sink in sinks.iter_mut() {
+        sink.on_event(&event);
+    }
+}
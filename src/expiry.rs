@@ -0,0 +1,114 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::civil_time;
+use cookie_store::Cookie;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use time02::OffsetDateTime;
+
+/// When a cookie expires, as a `std::time::SystemTime`, independent of the version of the `time`
+/// crate used by `cookie_store`. (Creation times are not recorded by the store.)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    /// The cookie expires at the given time
+    At(SystemTime),
+    /// The cookie expires at the end of the session
+    SessionEnd,
+}
+
+impl Expiry {
+    /// The expiry of `cookie`, to the second, as `save_json` records it
+    pub fn of(cookie: &Cookie<'_>) -> Expiry {
+        if !cookie.is_persistent() {
+            return Expiry::SessionEnd;
+        }
+        // `cookie_store` does not export its `CookieExpiration`, so its time is found as the first
+        // second by which the cookie `expires_by`, within the years 1 to 9999 it can store
+        let at = |secs| OffsetDateTime::from_unix_timestamp(secs);
+        let (mut first, mut last) = (civil_time(1, 1, 1), civil_time(10_000, 1, 1) - 1);
+        while first < last {
+            let mid = first + (last - first) / 2;
+            if cookie.expires_by(&at(mid)) {
+                last = mid;
+            } else {
+                first = mid + 1;
+            }
+        }
+        // within the second before, unless expiring exactly on it
+        let secs = if cookie.expires == at(first - 1).into() {
+            first - 1
+        } else {
+            first
+        };
+        match secs {
+            secs if secs >= 0 => Expiry::At(UNIX_EPOCH + Duration::from_secs(secs as u64)),
+            secs => Expiry::At(UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())),
+        }
+    }
+
+    /// The time of expiry; `None` for `SessionEnd`
+    pub fn time(self) -> Option<SystemTime> {
+        match self {
+            Expiry::At(at) => Some(at),
+            Expiry::SessionEnd => None,
+        }
+    }
+
+    /// The time of expiry as a `chrono::DateTime`; `None` for `SessionEnd`
+    #[cfg(feature = "chrono")]
+    pub fn to_chrono(self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.time().map(Into::into)
+    }
+
+    /// The time of expiry as a `time::OffsetDateTime` (of `time` 0.3); `None` for `SessionEnd`
+    #[cfg(feature = "time03")]
+    pub fn to_time(self) -> Option<time03::OffsetDateTime> {
+        self.time().map(Into::into)
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// The expiry of the cookie identified by `domain`, `path`, and `name`, if it is in the store
+    /// and unexpired
+    pub fn cookie_expiry(&self, domain: &str, path: &str, name: &str) -> Option<Expiry> {
+        self.store
+            .iter_unexpired()
+            .find(|c| {
+                String::from(&c.domain) == domain
+                    && String::from(&c.path) == path
+                    && c.name() == name
+            })
+            .map(|c| Expiry::of(&c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Expiry;
+    use cookie::Cookie as RawCookie;
+    use cookie_store::Cookie;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use url::Url;
+
+    #[test]
+    fn expiry() {
+        let url = Url::parse("http://www.example.com/").unwrap();
+        let cookie = |s: &str| {
+            Cookie::try_from_raw_cookie(&RawCookie::parse(s.to_owned()).unwrap(), &url).unwrap()
+        };
+        assert_eq!(Expiry::SessionEnd, Expiry::of(&cookie("a=1")));
+        assert_eq!(None, Expiry::of(&cookie("a=1")).time());
+
+        let at = Expiry::of(&cookie("a=1; Max-Age=3600")).time().unwrap();
+        let expected = SystemTime::now() + Duration::from_secs(3600);
+        let diff = expected.duration_since(at).unwrap_or_else(|e| e.duration());
+        assert!(diff < Duration::from_secs(5));
+
+        let at = |s: &str| Expiry::of(&cookie(s)).time().unwrap();
+        let epoch = "a=1; Expires=Thu, 01 Jan 1970 00:00:00 GMT";
+        assert_eq!(UNIX_EPOCH, at(epoch));
+        let date = "a=1; Expires=Wed, 04 Mar 2020 05:06:07 GMT";
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(1_583_298_367), at(date));
+        let before = "a=1; Expires=Wed, 31 Dec 1969 00:00:00 GMT";
+        assert_eq!(UNIX_EPOCH - Duration::from_secs(86_400), at(before));
+    }
+}
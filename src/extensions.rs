@@ -0,0 +1,204 @@
+#[cfg(feature = "persistence")]
+use crate::error::SessionError;
+use crate::lenient::find_raw_cookie;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use cookie_store::Cookie;
+use std::collections::HashMap;
+#[cfg(feature = "persistence")]
+use std::io::{BufRead, Write};
+use url::Url;
+
+/// The Set-Cookie attributes understood by `cookie`, which are kept by the store itself
+const KNOWN_ATTRIBUTES: &[&str] = &[
+    "expires", "max-age", "domain", "path", "secure", "httponly", "samesite",
+];
+
+/// The field of `save_json` lines holding a cookie's extension attributes
+#[cfg(feature = "persistence")]
+pub(crate) const FIELD: &str = "extensions";
+
+type Key = (String, String, String);
+
+fn key(cookie: &Cookie<'_>) -> Key {
+    (
+        String::from(&cookie.domain),
+        String::from(&cookie.path),
+        cookie.name().to_owned(),
+    )
+}
+
+/// The unknown and extension attributes (e.g. `Priority=High`, or vendor-specific flags) of the
+/// cookies a `Session` has stored, which `cookie` does not keep, by domain, path, and name
+#[derive(Debug, Default)]
+pub(crate) struct ExtensionAttributes {
+    /// Whether `Session::save_json` writes the attributes
+    save: bool,
+    attributes: HashMap<Key, Vec<String>>,
+}
+
+impl ExtensionAttributes {
+    /// Record the extension attributes of `cookies`, to be stored as received from `url`, as
+    /// found in the raw Set-Cookie headers `raw`
+    pub(crate) fn record(&mut self, cookies: &[RawCookie<'static>], url: &Url, raw: &[String]) {
+        if raw.is_empty() {
+            return;
+        }
+        for cookie in cookies {
+            let key = match Cookie::try_from_raw_cookie(cookie, url) {
+                Ok(cookie) => key(&cookie),
+                Err(_) => continue,
+            };
+            let attributes = find_raw_cookie(raw, cookie.name())
+                .map(|set_cookie| {
+                    set_cookie
+                        .split(';')
+                        .skip(1)
+                        .map(str::trim)
+                        .filter(|attribute| {
+                            let name = attribute.split('=').next().unwrap_or("");
+                            let name = name.trim().to_lowercase();
+                            !name.is_empty() && !KNOWN_ATTRIBUTES.contains(&name.as_str())
+                        })
+                        .map(str::to_owned)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            if attributes.is_empty() {
+                self.attributes.remove(&key);
+            } else {
+                self.attributes.insert(key, attributes);
+            }
+        }
+    }
+
+    /// Write the `save_json` lines `json` to `writer`, with the extension attributes of each
+    /// cookie with any added as an `extensions` field
+    #[cfg(feature = "persistence")]
+    fn save_json<W: Write>(&self, json: &[u8], writer: &mut W) -> Result<(), SessionError> {
+        for line in json.lines() {
+            let line = line?;
+            let mut value = serde_json::from_str::<serde_json::Value>(&line)?;
+            let cookie = serde_json::from_value::<Cookie<'static>>(value.clone())?;
+            match (self.attributes.get(&key(&cookie)), value.as_object_mut()) {
+                (Some(attributes), Some(fields)) => {
+                    fields.insert(FIELD.to_owned(), attributes.clone().into());
+                    writeln!(writer, "{}", value)?;
+                }
+                _ => writeln!(writer, "{}", line)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the extension attributes of the `save_json` lines `json`; lines without any, or
+    /// which cannot be parsed, are skipped
+    #[cfg(feature = "persistence")]
+    fn load_json(&mut self, json: &[u8]) {
+        for line in json.lines().map_while(Result::ok) {
+            let value = match serde_json::from_str::<serde_json::Value>(&line) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            let attributes = match value.get(FIELD).cloned().map(serde_json::from_value) {
+                Some(Ok(attributes)) => attributes,
+                _ => continue,
+            };
+            if let Ok(cookie) = serde_json::from_value::<Cookie<'static>>(value) {
+                self.attributes.insert(key(&cookie), attributes);
+            }
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Write the extension attributes of cookies (see `extension_attributes`) with them in
+    /// `save_json`, as an `extensions` field which is ignored by `CookieStore::load_json`, but
+    /// restored by `Session::load_json`
+    pub fn with_extension_attributes_saved(mut self) -> Self {
+        self.extensions.save = true;
+        self
+    }
+
+    /// The extension attributes (e.g. `Priority=High`) the cookie with `domain`, `path`, and
+    /// `name` in the store was set with, as set, if it has any
+    pub fn extension_attributes(&self, domain: &str, path: &str, name: &str) -> Option<&[String]> {
+        let wanted = (domain.to_owned(), path.to_owned(), name.to_owned());
+        let attributes = self.extensions.attributes.get(&wanted)?;
+        if self.store.iter_unexpired().any(|c| key(&c) == wanted) {
+            Some(attributes)
+        } else {
+            None
+        }
+    }
+
+    /// Write the store per `save_json`, with extension attributes if configured
+    #[cfg(feature = "persistence")]
+    pub(crate) fn save_json_with_extensions<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SessionError> {
+        if !self.extensions.save {
+            return self.store.save_json(writer);
+        }
+        let mut json = vec![];
+        self.store.save_json(&mut json)?;
+        self.extensions.save_json(&json, writer)
+    }
+
+    /// Restore the extension attributes written to the `save_json` lines `json`
+    #[cfg(feature = "persistence")]
+    pub(crate) fn load_extension_attributes(&mut self, json: &[u8]) {
+        self.extensions.load_json(json);
+    }
+}
+
+#[cfg(all(test, feature = "persistence", feature = "reqwest-session"))]
+mod tests {
+    use crate::ReqwestSession;
+    use cookie::Cookie as RawCookie;
+    use url::Url;
+
+    #[test]
+    fn extension_attributes() {
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let raw = vec![
+            "id=1; Max-Age=3600; Priority=High; Secure; X-Vendor".to_owned(),
+            "pref=2; Max-Age=3600".to_owned(),
+        ];
+        let cookies = raw
+            .iter()
+            .map(|set_cookie| RawCookie::parse(set_cookie.clone()).unwrap())
+            .collect::<Vec<_>>();
+        let mut session =
+            ReqwestSession::new(reqwest::blocking::Client::new()).with_extension_attributes_saved();
+        session.extensions.record(&cookies, &url, &raw);
+        session.store_response_cookies(cookies, &url);
+        let extensions = ["Priority=High".to_owned(), "X-Vendor".to_owned()];
+        assert_eq!(
+            Some(&extensions[..]),
+            session.extension_attributes("www.example.com", "/", "id")
+        );
+        assert_eq!(
+            None,
+            session.extension_attributes("www.example.com", "/", "pref")
+        );
+
+        let mut json = vec![];
+        session.save_json(&mut json).unwrap();
+        assert_eq!(
+            1,
+            String::from_utf8_lossy(&json)
+                .matches("\"extensions\"")
+                .count()
+        );
+        let session =
+            ReqwestSession::load_json(reqwest::blocking::Client::new(), &json[..]).unwrap();
+        assert_eq!(2, session.store.iter_unexpired().count());
+        assert_eq!(
+            Some(&extensions[..]),
+            session.extension_attributes("www.example.com", "/", "id")
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Handing requests which hit a JavaScript challenge (e.g. a bot-mitigation interstitial) off to
+//! a headless browser. The browser driver is user-provided, via `BrowserFallback`; this module
+//! provides the challenge heuristics, and the export/import of cookies between a `Session`'s store
+//! and the browser.
+use crate::expiry::Expiry;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::{is_host_only, IntoUrl};
+use cookie::Cookie as RawCookie;
+use log::{debug, warn};
+use std::time::SystemTime;
+use url::Url;
+
+/// A cookie in the form browser automation protocols (e.g. the DevTools protocol's
+/// `Network.setCookie`, or WebDriver's Add Cookie) accept and report
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrowserCookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub path: String,
+    /// Whether the cookie is sent only to `domain` itself, rather than also its subdomains
+    pub host_only: bool,
+    pub secure: bool,
+    pub http_only: bool,
+    /// When the cookie expires; `None` for a session cookie
+    pub expires: Option<SystemTime>,
+}
+
+impl BrowserCookie {
+    /// The Set-Cookie header value for this cookie, or `None` if it has already expired
+    fn set_cookie(&self) -> Option<String> {
+        let mut set_cookie = format!("{}={}; Path={}", self.name, self.value, self.path);
+        if !self.host_only {
+            set_cookie.push_str(&format!("; Domain={}", self.domain));
+        }
+        if self.secure {
+            set_cookie.push_str("; Secure");
+        }
+        if self.http_only {
+            set_cookie.push_str("; HttpOnly");
+        }
+        if let Some(expires) = self.expires {
+            let max_age = expires.duration_since(SystemTime::now()).ok()?;
+            set_cookie.push_str(&format!("; Max-Age={}", max_age.as_secs()));
+        }
+        Some(set_cookie)
+    }
+}
+
+/// A headless browser driver to which a `Session` delegates URLs which respond with a JavaScript
+/// challenge; see `Session::get_with_fallback`
+pub trait BrowserFallback {
+    /// Load `url` in the browser, with `cookies` (those the session would send to `url`) set, and
+    /// wait for any challenge to complete. Returns the browser's cookies afterwards.
+    fn solve(
+        &mut self,
+        url: &Url,
+        cookies: &[BrowserCookie],
+    ) -> Result<Vec<BrowserCookie>, crate::Error>;
+}
+
+/// Responses which can be checked for signs of a JavaScript challenge, implemented for each
+/// integration's response type
+pub trait ChallengeResponse {
+    /// Whether the response appears to be a challenge rather than the requested content
+    fn is_challenge(&self) -> bool;
+}
+
+#[cfg(any(feature = "reqwest-session", feature = "wasm-session"))]
+const CHALLENGE_SERVERS: &[&str] = &["cloudflare", "ddos-guard", "sucuri"];
+
+const CHALLENGE_MARKERS: &[&str] = &[
+    "cf-browser-verification",
+    "challenge-platform",
+    "_incapsula_resource",
+    "checking your browser",
+    "just a moment...",
+    "enable javascript and cookies to continue",
+];
+
+/// Whether a status code and `Server` header value are typical of a challenge response
+#[cfg(any(feature = "reqwest-session", feature = "wasm-session"))]
+pub(crate) fn is_challenge_status(status: u16, server: Option<&str>) -> bool {
+    let server = server.map(str::to_lowercase).unwrap_or_default();
+    matches!(status, 403 | 429 | 503) && CHALLENGE_SERVERS.iter().any(|s| server.contains(s))
+}
+
+/// Whether a response body contains markers of a known JavaScript challenge page, for clients
+/// which can inspect the body before deciding to fall back
+pub fn is_challenge_body(body: &str) -> bool {
+    let body = body.to_lowercase();
+    CHALLENGE_MARKERS.iter().any(|marker| body.contains(marker))
+}
+
+#[cfg(feature = "reqwest-session")]
+impl ChallengeResponse for reqwest::blocking::Response {
+    fn is_challenge(&self) -> bool {
+        let header = |name| self.headers().get(name).and_then(|v| v.to_str().ok());
+        header("cf-mitigated") == Some("challenge")
+            || is_challenge_status(self.status().as_u16(), header("server"))
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// The unexpired cookies the store would send to `url`, for loading into a browser
+    pub fn export_browser_cookies(&self, url: &Url) -> Vec<BrowserCookie> {
+        self.store
+            .iter_unexpired()
+            .filter(|c| c.matches(url))
+            .map(|c| BrowserCookie {
+                name: c.name().to_owned(),
+                value: c.value().to_owned(),
+                domain: String::from(&c.domain),
+                path: String::from(&c.path),
+                host_only: is_host_only(&c),
+                secure: c.secure().unwrap_or(false),
+                http_only: c.http_only().unwrap_or(false),
+                expires: Expiry::of(&c).time(),
+            })
+            .collect()
+    }
+
+    /// Store `cookies` taken from a browser. Returns the number stored; cookies the store
+    /// refuses are logged and skipped.
+    pub fn import_browser_cookies(&mut self, cookies: &[BrowserCookie]) -> usize {
+        let mut imported = 0;
+        for cookie in cookies {
+            let set_cookie = match cookie.set_cookie() {
+                Some(set_cookie) => set_cookie,
+                None => continue,
+            };
+            let url = Url::parse(&format!("https://{}{}", cookie.domain, cookie.path));
+            let inserted = url.map_err(|e| e.to_string()).and_then(|url| {
+                let raw_cookie = RawCookie::parse(set_cookie).map_err(|e| e.to_string())?;
+                self.store
+                    .insert_raw(&raw_cookie, &url)
+                    .map_err(|e| e.to_string())
+            });
+            match inserted {
+                Ok(()) => imported += 1,
+                Err(e) => warn!("unable to import browser cookie {:?}: {}", cookie, e),
+            }
+        }
+        imported
+    }
+
+    /// GET `url` as with `get_with`; if the response is a challenge, have `fallback` solve it in
+    /// a browser, import the browser's cookies, and GET `url` once more. If the browser fails, the
+    /// failure is logged and the challenge response returned.
+    pub fn get_with_fallback<U, P, B>(
+        &mut self,
+        url: U,
+        prepare: P,
+        fallback: &mut B,
+    ) -> Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+    where
+        U: IntoUrl,
+        P: Fn(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+        B: BrowserFallback,
+        <C as SessionClient>::Response: ChallengeResponse,
+    {
+        let url = self.request_url(url.into_url()?);
+        let request = self.client.get_request(&url);
+        let response = self.run_request(request, "GET", &url, &prepare)?;
+        if !response.is_challenge() {
+            return Ok(response);
+        }
+        debug!("challenge response for {}; falling back to browser", url);
+        match fallback.solve(&url, &self.export_browser_cookies(&url)) {
+            Ok(cookies) => {
+                self.import_browser_cookies(&cookies);
+                let request = self.client.get_request(&url);
+                self.run_request(request, "GET", &url, prepare)
+            }
+            Err(e) => {
+                warn!("browser fallback for {} failed: {}", url, e);
+                Ok(response)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_challenge_body;
+
+    #[test]
+    fn heuristics() {
+        assert!(is_challenge_body(
+            "<title>Just a moment...</title><script src=\"/cdn-cgi/challenge-platform/\">"
+        ));
+        assert!(!is_challenge_body("<title>Article</title>"));
+    }
+
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn challenge_responses() {
+        use super::ChallengeResponse;
+
+        let response = |status, server| {
+            reqwest::blocking::Response::from(
+                http::Response::builder()
+                    .status(status)
+                    .header("server", server)
+                    .body("")
+                    .unwrap(),
+            )
+        };
+        assert!(response(503, "cloudflare").is_challenge());
+        assert!(!response(200, "cloudflare").is_challenge());
+        assert!(!response(503, "nginx").is_challenge());
+    }
+
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn browser_cookies() {
+        use super::BrowserCookie;
+        use crate::ReqwestSession;
+        use std::time::{Duration, SystemTime};
+        use url::Url;
+
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new());
+        let url = Url::parse("https://www.example.com/a/").unwrap();
+        let expires = SystemTime::now() + Duration::from_secs(3600);
+        let cookies = [
+            BrowserCookie {
+                name: "cf_clearance".to_owned(),
+                value: "c1".to_owned(),
+                domain: "example.com".to_owned(),
+                path: "/".to_owned(),
+                host_only: false,
+                secure: true,
+                http_only: true,
+                expires: Some(expires),
+            },
+            BrowserCookie {
+                name: "local".to_owned(),
+                value: "l1".to_owned(),
+                domain: "www.example.com".to_owned(),
+                path: "/a".to_owned(),
+                host_only: true,
+                secure: false,
+                http_only: false,
+                expires: None,
+            },
+            BrowserCookie {
+                name: "stale".to_owned(),
+                value: "s1".to_owned(),
+                domain: "www.example.com".to_owned(),
+                path: "/".to_owned(),
+                host_only: true,
+                secure: false,
+                http_only: false,
+                expires: Some(SystemTime::now() - Duration::from_secs(60)),
+            },
+        ];
+        assert_eq!(2, session.import_browser_cookies(&cookies));
+
+        let mut exported = session.export_browser_cookies(&url);
+        exported.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(2, exported.len());
+        assert_eq!(cookies[1], exported[1]);
+        let clearance = &exported[0];
+        assert_eq!(
+            ("cf_clearance", "c1"),
+            (&*clearance.name, &*clearance.value)
+        );
+        assert!(!clearance.host_only && clearance.secure && clearance.http_only);
+        let drift = clearance
+            .expires
+            .unwrap()
+            .duration_since(expires)
+            .unwrap_or_else(|e| e.duration());
+        assert!(drift < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,269 @@
+use crate::public_suffix::PublicSuffixes;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use url::Url;
+
+/// The mode of a request, as sent in its `Sec-Fetch-Mode` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FetchMode {
+    /// A navigation to a new document, as made by `Session::navigate`
+    Navigate,
+    /// A request made by a script of the current document, e.g. with `fetch()` (the default)
+    #[default]
+    Cors,
+    /// A request for a subresource, e.g. an image, which is not read by scripts
+    NoCors,
+    SameOrigin,
+}
+
+impl FetchMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FetchMode::Navigate => "navigate",
+            FetchMode::Cors => "cors",
+            FetchMode::NoCors => "no-cors",
+            FetchMode::SameOrigin => "same-origin",
+        }
+    }
+}
+
+/// The destination of a request, as sent in its `Sec-Fetch-Dest` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FetchDest {
+    Document,
+    /// A response read by a script, e.g. with `fetch()` (the default)
+    #[default]
+    Empty,
+    Iframe,
+    Image,
+    Script,
+    Style,
+    Font,
+}
+
+impl FetchDest {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FetchDest::Document => "document",
+            FetchDest::Empty => "empty",
+            FetchDest::Iframe => "iframe",
+            FetchDest::Image => "image",
+            FetchDest::Script => "script",
+            FetchDest::Style => "style",
+            FetchDest::Font => "font",
+        }
+    }
+}
+
+/// How a `Session` emits the `Origin` and `Sec-Fetch-*` headers a browser would send, and the
+/// document its requests are made from
+#[derive(Debug, Default)]
+pub(crate) struct FetchMetadata {
+    enabled: bool,
+    /// The document last navigated to, which initiates all other requests
+    document: Option<Url>,
+    /// The mode and destination of the next request, if not `Cors` and `Empty`
+    next: Option<(FetchMode, FetchDest)>,
+}
+
+/// Whether `method` is one a browser sends no `Origin` for when same-origin
+fn is_safe(method: &str) -> bool {
+    method.eq_ignore_ascii_case("GET") || method.eq_ignore_ascii_case("HEAD")
+}
+
+/// The `Sec-Fetch-Site` of a request for `url` initiated by `document`, or by the user if none,
+/// with sites per `suffixes`
+pub(crate) fn fetch_site(
+    suffixes: &PublicSuffixes,
+    document: Option<&Url>,
+    url: &Url,
+) -> &'static str {
+    match document {
+        None => "none",
+        Some(document) if document.origin() == url.origin() => "same-origin",
+        Some(document)
+            if document.scheme() == url.scheme()
+                && suffixes.site(document) == suffixes.site(url) =>
+        {
+            "same-site"
+        }
+        Some(_) => "cross-site",
+    }
+}
+
+impl FetchMetadata {
+    /// The mode of the next request, which is reset to the default by this call
+    pub(crate) fn take_mode(&mut self) -> (FetchMode, FetchDest) {
+        self.next.take().unwrap_or_default()
+    }
+
+    /// The headers to send with a `method` request for `url` (as it is known to the store) in
+    /// `mode`, as a browser would from the current document, with sites per `suffixes`.
+    /// `Sec-Fetch-*` headers are only sent to secure contexts.
+    pub(crate) fn headers(
+        &self,
+        suffixes: &PublicSuffixes,
+        method: &str,
+        url: &Url,
+        (mode, dest): (FetchMode, FetchDest),
+    ) -> Vec<(&'static str, String)> {
+        if !self.enabled {
+            return vec![];
+        }
+        let document = self.document.as_ref();
+        let mut headers = vec![];
+        let origin = match document {
+            Some(document) => document.origin().ascii_serialization(),
+            None => "null".to_owned(),
+        };
+        let cross_origin = document.is_some_and(|document| document.origin() != url.origin());
+        let sends_origin = match mode {
+            FetchMode::Navigate => !is_safe(method),
+            _ => document.is_some() && (cross_origin || !is_safe(method)),
+        };
+        if sends_origin {
+            headers.push(("Origin", origin));
+        }
+        if url.scheme() == "https" {
+            headers.push((
+                "Sec-Fetch-Site",
+                fetch_site(suffixes, document, url).to_owned(),
+            ));
+            headers.push(("Sec-Fetch-Mode", mode.as_str().to_owned()));
+            headers.push(("Sec-Fetch-Dest", dest.as_str().to_owned()));
+            if mode == FetchMode::Navigate {
+                headers.push(("Sec-Fetch-User", "?1".to_owned()));
+            }
+        }
+        headers
+    }
+
+    /// Make the next request one for a subresource of type `dest` of `page`, returning the
+    /// current document, to be restored with `end_subresource` once it is sent
+    pub(crate) fn begin_subresource(&mut self, page: &Url, dest: FetchDest) -> Option<Url> {
+        self.next = Some((FetchMode::NoCors, dest));
+        self.document.replace(page.clone())
+    }
+
+    pub(crate) fn end_subresource(&mut self, document: Option<Url>) {
+        self.next = None;
+        self.document = document;
+    }
+
+    /// Make `url`, just navigated to in `mode`, the current document
+    pub(crate) fn navigated(&mut self, mode: FetchMode, url: &Url) {
+        if mode == FetchMode::Navigate {
+            self.document = Some(url.clone());
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Send the `Origin` and `Sec-Fetch-Site`, `-Mode`, `-Dest`, and `-User` headers with each
+    /// request, as a browser would: requests made with `navigate` are user navigations to a new
+    /// document, and all other requests are made by scripts of the document last navigated to,
+    /// so are `same-origin`, `same-site`, or `cross-site` relative to it. Requires the request to
+    /// implement `SessionRequest::add_header`.
+    pub fn with_fetch_metadata(mut self) -> Self {
+        self.fetch_metadata.enabled = true;
+        self
+    }
+
+    /// Navigate to `url`, as if the user followed a link to it: the request is sent as a
+    /// navigation (see `with_fetch_metadata`), and the document it is served from becomes the
+    /// initiator of subsequent requests
+    pub fn navigate<U: IntoUrl>(
+        &mut self,
+        url: U,
+    ) -> Result<<C as SessionClient>::Response, <C as SessionClient>::SendError> {
+        self.fetch_metadata.next = Some((FetchMode::Navigate, FetchDest::Document));
+        let result = self.get(url);
+        self.fetch_metadata.next = None;
+        result
+    }
+
+    /// The document last navigated to with `navigate`, if any
+    pub fn current_document(&self) -> Option<&Url> {
+        self.fetch_metadata.document.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fetch_site, FetchDest, FetchMetadata, FetchMode};
+    use crate::public_suffix::PublicSuffixes;
+    use url::Url;
+
+    #[test]
+    fn fetch_metadata() {
+        let url = |s| Url::parse(s).unwrap();
+        let navigation = (FetchMode::Navigate, FetchDest::Document);
+        let suffixes = PublicSuffixes::default();
+        let mut metadata = FetchMetadata::default();
+        let home = url("https://www.example.com/");
+        assert!(metadata
+            .headers(&suffixes, "GET", &home, navigation)
+            .is_empty());
+
+        metadata.enabled = true;
+        let headers = metadata.headers(&suffixes, "GET", &home, navigation);
+        assert_eq!(
+            vec![
+                ("Sec-Fetch-Site", "none".to_owned()),
+                ("Sec-Fetch-Mode", "navigate".to_owned()),
+                ("Sec-Fetch-Dest", "document".to_owned()),
+                ("Sec-Fetch-User", "?1".to_owned()),
+            ],
+            headers
+        );
+        metadata.navigated(navigation.0, &url("https://www.example.com/app"));
+        metadata.navigated(FetchMode::Cors, &url("https://api.example.org/"));
+        assert_eq!(
+            Some(&url("https://www.example.com/app")),
+            metadata.document.as_ref()
+        );
+
+        let site = |method, u| {
+            let headers = metadata.headers(&suffixes, method, &url(u), Default::default());
+            let origin = headers.iter().find(|(name, _)| *name == "Origin");
+            let site = headers.iter().find(|(name, _)| *name == "Sec-Fetch-Site");
+            (
+                origin.map(|(_, value)| value.clone()),
+                site.map(|(_, value)| value.clone()),
+            )
+        };
+        let origin = Some("https://www.example.com".to_owned());
+        let some = |s: &str| Some(s.to_owned());
+        assert_eq!(
+            (None, some("same-origin")),
+            site("GET", "https://www.example.com/data")
+        );
+        assert_eq!(
+            (origin.clone(), some("same-origin")),
+            site("POST", "https://www.example.com/data")
+        );
+        assert_eq!(
+            (origin.clone(), some("same-site")),
+            site("GET", "https://api.example.com/data")
+        );
+        assert_eq!(
+            (origin.clone(), some("cross-site")),
+            site("GET", "https://api.example.org/data")
+        );
+        assert_eq!((origin, None), site("GET", "http://api.example.org/data"));
+    }
+
+    #[test]
+    fn public_suffix_sites() {
+        let url = |s| Url::parse(s).unwrap();
+        let page = url("https://a.co.uk/");
+        let suffixes = PublicSuffixes::new(|domain| domain == "co.uk");
+        let fetch_site = |suffixes, u| fetch_site(suffixes, Some(&page), &url(u));
+        assert_eq!("cross-site", fetch_site(&suffixes, "https://b.co.uk/"));
+        assert_eq!("same-site", fetch_site(&suffixes, "https://www.a.co.uk/"));
+        // by the last two labels, without a list
+        let heuristic = PublicSuffixes::default();
+        assert_eq!("same-site", fetch_site(&heuristic, "https://b.co.uk/"));
+    }
+}
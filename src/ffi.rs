@@ -0,0 +1,304 @@
+//! A C ABI over `ReqwestSession`, for sharing cookie jars and matching logic with non-Rust
+//! tooling. Build a shared library with e.g.
+//! `cargo rustc --release --features ffi -- --crate-type cdylib`.
+//!
+//! Sessions are opaque `UaSession` pointers, created by `ua_session_new`/`ua_session_load_json`
+//! and released with `ua_session_free`. Strings passed in are NUL-terminated UTF-8; strings
+//! returned are owned by the caller and must be released with `ua_string_free`. On failure,
+//! functions return `NULL` or a negative value, and `ua_last_error` describes the failure;
+//! panics are caught and reported as failures, rather than unwinding into the caller. The
+//! declarations are in `include/user_agent.h`.
+use crate::reqwest_session::ReqwestSession;
+use cookie::Cookie as RawCookie;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::BufReader;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use url::Url;
+
+/// Opaque handle to a `ReqwestSession`
+pub struct UaSession(ReqwestSession);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error<E: std::fmt::Display>(e: E) {
+    let message =
+        CString::new(e.to_string().replace('\0', "")).expect("NUL bytes have been removed");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run `f`, returning `failed` and setting the last error if it fails or panics; unwinding
+/// across the C ABI is undefined behavior
+fn guard<T>(failed: T, f: impl FnOnce() -> Result<T, crate::Error>) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => {
+            set_last_error(e);
+            failed
+        }
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            set_last_error(format!("panicked: {}", message));
+            failed
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err("unexpected NULL string argument".to_owned());
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|e| format!("invalid UTF-8 in string argument: {}", e))
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    match CString::new(s) {
+        Ok(s) => s.into_raw(),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The message for the last failure on this thread, or `NULL` if none. The returned string is
+/// owned by the library and valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn ua_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map(|message| message.as_ptr())
+            .unwrap_or(ptr::null())
+    })
+}
+
+/// Create a new session with an empty cookie store
+#[no_mangle]
+pub extern "C" fn ua_session_new() -> *mut UaSession {
+    guard(ptr::null_mut(), || {
+        Ok(Box::into_raw(Box::new(UaSession(ReqwestSession::new(
+            reqwest::blocking::Client::new(),
+        )))))
+    })
+}
+
+/// Create a new session with cookies loaded from the `save_json` formatted file at `path`
+///
+/// # Safety
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ua_session_load_json(path: *const c_char) -> *mut UaSession {
+    guard(ptr::null_mut(), || {
+        let file = File::open(str_arg(path)?)?;
+        let session =
+            ReqwestSession::load_json(reqwest::blocking::Client::new(), BufReader::new(file))?;
+        Ok(Box::into_raw(Box::new(UaSession(session))))
+    })
+}
+
+/// Save the persistent cookies of `session` to the file at `path`, in `save_json` format.
+/// Returns 0 on success.
+///
+/// # Safety
+/// `session` must be a live pointer from `ua_session_new`/`ua_session_load_json`, and `path` a
+/// valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ua_session_save_json(
+    session: *const UaSession,
+    path: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        let mut file = File::create(str_arg(path)?)?;
+        (*session).0.save_json(&mut file)?;
+        Ok(0)
+    })
+}
+
+/// Release `session`. Passing `NULL` is a no-op.
+///
+/// # Safety
+/// `session` must be `NULL` or a live pointer from `ua_session_new`/`ua_session_load_json`, and
+/// must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ua_session_free(session: *mut UaSession) {
+    if !session.is_null() {
+        guard((), || {
+            drop(Box::from_raw(session));
+            Ok(())
+        })
+    }
+}
+
+/// Store `set_cookie` (a Set-Cookie header value) as if received in a response from `url`.
+/// Returns 0 on success.
+///
+/// # Safety
+/// `session` must be a live session pointer, and `set_cookie` and `url` valid NUL-terminated
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn ua_session_add_cookie(
+    session: *mut UaSession,
+    set_cookie: *const c_char,
+    url: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        let url = Url::parse(str_arg(url)?)?;
+        let cookie = RawCookie::parse(str_arg(set_cookie)?.to_owned())?;
+        (*session).0.store.insert_raw(&cookie, &url)?;
+        Ok(0)
+    })
+}
+
+/// The Cookie header value (e.g. `a=1; b=2`) `session` would send in a request to `url`; an
+/// empty string if no cookies match.
+///
+/// # Safety
+/// `session` must be a live session pointer, and `url` a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ua_session_cookies_for_url(
+    session: *const UaSession,
+    url: *const c_char,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let url = Url::parse(str_arg(url)?)?;
+        let cookies = (*session)
+            .0
+            .store
+            .get_request_cookies(&url)
+            .map(|c| c.encoded().to_string())
+            .collect::<Vec<_>>()
+            .join("; ");
+        Ok(into_c_string(cookies))
+    })
+}
+
+/// Send a `method` (e.g. GET, or an extension method such as PROPFIND) request to `url` through
+/// `session`, with the `body_len` bytes at `body` as the request body (`body` may be `NULL` for no
+/// body). Cookies are attached and stored as for any session request. On success, returns the
+/// response body (with any invalid UTF-8 replaced) and writes the status code to `status`, if
+/// non-`NULL`.
+///
+/// # Safety
+/// `session` must be a live session pointer, `method` and `url` valid NUL-terminated strings,
+/// `body` either `NULL` or valid for reads of `body_len` bytes, and `status` either `NULL` or
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ua_session_request(
+    session: *mut UaSession,
+    method: *const c_char,
+    url: *const c_char,
+    body: *const u8,
+    body_len: usize,
+    status: *mut u16,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let session = &mut (*session).0;
+        let url = str_arg(url)?;
+        let body = if body.is_null() {
+            None
+        } else {
+            Some(std::slice::from_raw_parts(body, body_len).to_vec())
+        };
+        let prepare = |r: reqwest::blocking::RequestBuilder| match body {
+            Some(body) => r.body(body),
+            None => r,
+        };
+        let method = http::Method::from_bytes(str_arg(method)?.as_bytes())?;
+        let response = session.request_with(&method, url, prepare)?;
+        let code = response.status().as_u16();
+        let bytes = response.bytes()?;
+        if !status.is_null() {
+            *status = code;
+        }
+        let body = String::from_utf8_lossy(&bytes).replace('\0', "\u{fffd}");
+        Ok(into_c_string(body))
+    })
+}
+
+/// Release a string returned by this library. Passing `NULL` is a no-op.
+///
+/// # Safety
+/// `s` must be `NULL` or a string returned by this library, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ua_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        guard((), || {
+            drop(CString::from_raw(s));
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cookies() {
+        let c = |s: &str| CString::new(s).unwrap();
+        unsafe {
+            let session = ua_session_new();
+            let url = c("http://www.example.com/");
+            assert_eq!(
+                0,
+                ua_session_add_cookie(session, c("1=a; Max-Age=120").as_ptr(), url.as_ptr())
+            );
+            assert_eq!(
+                0,
+                ua_session_add_cookie(session, c("2=b").as_ptr(), url.as_ptr())
+            );
+            assert_eq!(
+                -1,
+                ua_session_add_cookie(session, c("3=c").as_ptr(), c("not a url").as_ptr())
+            );
+            assert!(!ua_last_error().is_null());
+
+            let cookies = ua_session_cookies_for_url(session, url.as_ptr());
+            let mut sent = CStr::from_ptr(cookies)
+                .to_str()
+                .unwrap()
+                .split("; ")
+                .collect::<Vec<_>>();
+            sent.sort();
+            assert_eq!(vec!["1=a", "2=b"], sent);
+            ua_string_free(cookies);
+
+            let path =
+                std::env::temp_dir().join(format!("user_agent_ffi_{}.json", std::process::id()));
+            let path = c(path.to_str().unwrap());
+            assert_eq!(0, ua_session_save_json(session, path.as_ptr()));
+            ua_session_free(session);
+
+            let session = ua_session_load_json(path.as_ptr());
+            assert!(!session.is_null());
+            let cookies = ua_session_cookies_for_url(session, url.as_ptr());
+            assert_eq!("1=a", CStr::from_ptr(cookies).to_str().unwrap()); // only persistent cookies saved
+            ua_string_free(cookies);
+            ua_session_free(session);
+            std::fs::remove_file(path.to_str().unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn panics() {
+        assert_eq!(-1, guard(-1, || panic!("cookie jar on fire")));
+        let error = unsafe { CStr::from_ptr(ua_last_error()) };
+        assert_eq!("panicked: cookie jar on fire", error.to_str().unwrap());
+        let session: *mut UaSession = guard(ptr::null_mut(), || panic!("{}", 42));
+        assert!(session.is_null());
+        let error = unsafe { CStr::from_ptr(ua_last_error()) };
+        assert_eq!("panicked: 42", error.to_str().unwrap());
+    }
+}
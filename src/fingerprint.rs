@@ -0,0 +1,215 @@
+//! Advisory checks of the headers an agent is configured to send, for inconsistencies which
+//! commonly mark a client as a bot: e.g. a Chrome User-Agent without the client hint headers
+//! Chrome sends, or no Accept-Language. The checks are heuristic; an empty result does not mean
+//! the agent is indistinguishable from a browser.
+use std::fmt;
+
+/// An inconsistency found by `check_headers`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FingerprintIssue {
+    /// No User-Agent header is sent
+    MissingUserAgent,
+    /// The User-Agent identifies an HTTP library (e.g. `curl` or `reqwest`) rather than a browser
+    LibraryUserAgent(String),
+    /// A header the browser named by the User-Agent always sends is missing
+    MissingHeader(&'static str),
+    /// A header is sent which the browser named by the User-Agent never sends
+    UnexpectedHeader(&'static str),
+    /// The major version in the User-Agent differs from that in the `sec-ch-ua` client hint
+    VersionMismatch {
+        user_agent: String,
+        client_hint: String,
+    },
+}
+
+impl fmt::Display for FingerprintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FingerprintIssue::MissingUserAgent => write!(f, "no User-Agent header"),
+            FingerprintIssue::LibraryUserAgent(library) => {
+                write!(f, "User-Agent identifies the {} library", library)
+            }
+            FingerprintIssue::MissingHeader(name) => {
+                write!(f, "{} header expected for the User-Agent is missing", name)
+            }
+            FingerprintIssue::UnexpectedHeader(name) => {
+                write!(f, "{} header is not sent by the User-Agent's browser", name)
+            }
+            FingerprintIssue::VersionMismatch {
+                user_agent,
+                client_hint,
+            } => write!(
+                f,
+                "User-Agent version {} does not match sec-ch-ua version {}",
+                user_agent, client_hint
+            ),
+        }
+    }
+}
+
+const LIBRARIES: &[&str] = &[
+    "curl",
+    "wget",
+    "python-requests",
+    "python-urllib",
+    "reqwest",
+    "go-http-client",
+    "okhttp",
+    "java",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Browser {
+    /// Chromium-based; sends client hints
+    Chromium,
+    Firefox,
+    Safari,
+}
+
+pub(crate) fn browser(user_agent: &str) -> Option<Browser> {
+    if user_agent.contains("Firefox/") {
+        Some(Browser::Firefox)
+    } else if user_agent.contains("Chrome/") || user_agent.contains("Chromium/") {
+        Some(Browser::Chromium)
+    } else if user_agent.contains("Safari/") && user_agent.contains("Version/") {
+        Some(Browser::Safari)
+    } else {
+        None
+    }
+}
+
+/// The major version following `marker` in `s`, e.g. `"80"` for `Chrome/` in `... Chrome/80.0 ...`
+fn major_version<'a>(s: &'a str, marker: &str) -> Option<&'a str> {
+    let rest = &s[s.find(marker)? + marker.len()..];
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    Some(&rest[..end]).filter(|v| !v.is_empty())
+}
+
+/// Check the headers (as name/value pairs, including any User-Agent) an agent sends with every
+/// request for inconsistencies with a real browser
+pub fn check_headers<'a, I>(headers: I) -> Vec<FingerprintIssue>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let headers = headers
+        .into_iter()
+        .map(|(name, value)| (name.to_lowercase(), value))
+        .collect::<Vec<_>>();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| *value)
+    };
+    let mut issues = vec![];
+    for &name in &["Accept", "Accept-Language"] {
+        if header(&name.to_lowercase()).is_none() {
+            issues.push(FingerprintIssue::MissingHeader(name));
+        }
+    }
+    let user_agent = match header("user-agent") {
+        Some(user_agent) => user_agent,
+        None => {
+            issues.insert(0, FingerprintIssue::MissingUserAgent);
+            return issues;
+        }
+    };
+    let lower = user_agent.to_lowercase();
+    if let Some(library) = LIBRARIES.iter().find(|l| lower.contains(*l)) {
+        issues.push(FingerprintIssue::LibraryUserAgent((*library).to_owned()));
+    }
+    let client_hints = ["sec-ch-ua", "sec-ch-ua-mobile", "sec-ch-ua-platform"];
+    match browser(user_agent) {
+        Some(Browser::Chromium) => {
+            for &name in &client_hints {
+                if header(name).is_none() {
+                    issues.push(FingerprintIssue::MissingHeader(name));
+                }
+            }
+            let versions = (
+                major_version(user_agent, "Chrome/"),
+                header("sec-ch-ua").and_then(|hint| major_version(hint, "v=\"")),
+            );
+            if let (Some(ua), Some(hint)) = versions {
+                if ua != hint {
+                    issues.push(FingerprintIssue::VersionMismatch {
+                        user_agent: ua.to_owned(),
+                        client_hint: hint.to_owned(),
+                    });
+                }
+            }
+        }
+        Some(Browser::Firefox) | Some(Browser::Safari) => {
+            for &name in &client_hints {
+                if header(name).is_some() {
+                    issues.push(FingerprintIssue::UnexpectedHeader(name));
+                }
+            }
+        }
+        None => {}
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_headers, FingerprintIssue};
+
+    const CHROME: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+                          (KHTML, like Gecko) Chrome/80.0.3987.132 Safari/537.36";
+    const FIREFOX: &str =
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:73.0) Gecko/20100101 Firefox/73.0";
+
+    #[test]
+    fn issues() {
+        assert_eq!(
+            vec![
+                FingerprintIssue::MissingUserAgent,
+                FingerprintIssue::MissingHeader("Accept"),
+                FingerprintIssue::MissingHeader("Accept-Language"),
+            ],
+            check_headers(vec![])
+        );
+
+        let accept = vec![("Accept", "text/html"), ("Accept-Language", "en-US")];
+        let with = |extra: Vec<(&'static str, &'static str)>| {
+            let mut headers = accept.clone();
+            headers.extend(extra);
+            check_headers(headers)
+        };
+        assert_eq!(
+            vec![FingerprintIssue::LibraryUserAgent("curl".to_owned())],
+            with(vec![("User-Agent", "curl/7.68.0")])
+        );
+        assert_eq!(
+            vec![
+                FingerprintIssue::MissingHeader("sec-ch-ua-mobile"),
+                FingerprintIssue::MissingHeader("sec-ch-ua-platform"),
+                FingerprintIssue::VersionMismatch {
+                    user_agent: "80".to_owned(),
+                    client_hint: "79".to_owned(),
+                },
+            ],
+            with(vec![
+                ("user-agent", CHROME),
+                ("sec-ch-ua", "\"Chromium\";v=\"79\""),
+            ])
+        );
+        assert!(with(vec![
+            ("User-Agent", CHROME),
+            ("sec-ch-ua", "\"Chromium\";v=\"80\""),
+            ("sec-ch-ua-mobile", "?0"),
+            ("sec-ch-ua-platform", "\"Windows\""),
+        ])
+        .is_empty());
+        assert_eq!(
+            vec![FingerprintIssue::UnexpectedHeader("sec-ch-ua")],
+            with(vec![
+                ("User-Agent", FIREFOX),
+                ("sec-ch-ua", "\"Firefox\";v=\"73\""),
+            ])
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use crate::attribution::{domain_site, site};
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use log::warn;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Limits on the cookies a `Session` holds for each site, past which a `GrowthAlert` is raised
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GrowthLimits {
+    /// The most unexpired cookies a site may have in the store
+    pub cookies: Option<usize>,
+    /// The most bytes of `name=value` pairs a site may have in the store, e.g. to stay clear of
+    /// servers' Cookie header size limits
+    pub bytes: Option<usize>,
+    /// The most cookies a site may add to the store per minute
+    pub per_minute: Option<f64>,
+}
+
+impl GrowthLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cookies(mut self, cookies: usize) -> Self {
+        self.cookies = Some(cookies);
+        self
+    }
+
+    pub fn with_bytes(mut self, bytes: usize) -> Self {
+        self.bytes = Some(bytes);
+        self
+    }
+
+    pub fn with_per_minute(mut self, per_minute: f64) -> Self {
+        self.per_minute = Some(per_minute);
+        self
+    }
+}
+
+/// Which of the `GrowthLimits` a site crossed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GrowthLimit {
+    Cookies,
+    Bytes,
+    PerMinute,
+}
+
+/// The cookies a site has in a `Session`'s store, and how fast they are growing, as last
+/// measured
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiteGrowth {
+    pub site: String,
+    pub cookies: usize,
+    /// The bytes of the `name=value` pairs of the cookies
+    pub bytes: usize,
+    /// The cookies added per minute over the measurement window (averaged over at least a
+    /// minute)
+    pub per_minute: f64,
+    /// How long until the site reaches its cookie or byte limit at its current rate, if it is
+    /// growing and not there already
+    pub forecast: Option<Duration>,
+}
+
+/// A site crossed one of the `GrowthLimits` of a `Session`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrowthAlert {
+    pub limit: GrowthLimit,
+    pub growth: SiteGrowth,
+}
+
+type GrowthHandler = Box<dyn FnMut(&GrowthAlert) + Send>;
+
+/// Tracks the growth of a `Session`'s store per site, raising `GrowthAlert`s
+pub(crate) struct JarGrowth {
+    limits: GrowthLimits,
+    /// How far back growth rates are measured
+    window: Duration,
+    /// The cookie counts of each site measured within the window
+    samples: HashMap<String, VecDeque<(Instant, usize)>>,
+    latest: HashMap<String, SiteGrowth>,
+    /// The limits each site is over, which are alerted once until the site is back under them
+    over: HashSet<(String, GrowthLimit)>,
+    handler: GrowthHandler,
+}
+
+/// The number of unexpired cookies `store` has for `site`, and the bytes of their pairs
+fn measure<S: CookieStorage>(store: &S, site: &str) -> (usize, usize) {
+    store
+        .iter_unexpired()
+        .filter(|cookie| domain_site(&String::from(&cookie.domain)) == site)
+        .fold((0, 0), |(cookies, bytes), cookie| {
+            (
+                cookies + 1,
+                bytes + cookie.name().len() + cookie.value().len() + 1,
+            )
+        })
+}
+
+impl JarGrowth {
+    fn new(limits: GrowthLimits, handler: GrowthHandler) -> Self {
+        JarGrowth {
+            limits,
+            window: Duration::from_secs(10 * 60),
+            samples: HashMap::new(),
+            latest: HashMap::new(),
+            over: HashSet::new(),
+            handler,
+        }
+    }
+
+    /// Measure the cookies `store` holds for the site of `url`, just stored to
+    pub(crate) fn observe_store<S: CookieStorage>(&mut self, store: &S, url: &Url) {
+        if let Some(site) = site(url) {
+            let (cookies, bytes) = measure(store, &site);
+            self.observe(site, cookies, bytes, Instant::now());
+        }
+    }
+
+    /// Record that `site` has `cookies` cookies of `bytes` at `now`, alerting of any limits it
+    /// has newly crossed
+    fn observe(&mut self, site: String, cookies: usize, bytes: usize, now: Instant) {
+        let samples = self.samples.entry(site.clone()).or_default();
+        while let Some(&(at, _)) = samples.front() {
+            if now.duration_since(at) <= self.window {
+                break;
+            }
+            samples.pop_front();
+        }
+        samples.push_back((now, cookies));
+        let (since, initially) = samples[0];
+        let minutes = now.duration_since(since).as_secs_f64().max(60.0) / 60.0;
+        let per_minute = cookies.saturating_sub(initially) as f64 / minutes;
+
+        let limits = self.limits;
+        // bytes are taken to grow with cookies, at their average size so far
+        let bytes_per_cookie = bytes as f64 / cookies.max(1) as f64;
+        let forecast = [
+            limits.cookies.map(|limit| (cookies, limit, 1.0)),
+            limits.bytes.map(|limit| (bytes, limit, bytes_per_cookie)),
+        ];
+        let forecast = forecast
+            .iter()
+            .flatten()
+            .filter(|&&(current, limit, _)| per_minute > 0.0 && current < limit)
+            .map(|&(current, limit, per_cookie)| {
+                let minutes = (limit - current) as f64 / (per_minute * per_cookie);
+                Duration::from_secs_f64(minutes * 60.0)
+            })
+            .min();
+        let growth = SiteGrowth {
+            site: site.clone(),
+            cookies,
+            bytes,
+            per_minute,
+            forecast,
+        };
+
+        let crossed = [
+            (GrowthLimit::Cookies, limits.cookies.map(|l| cookies > l)),
+            (GrowthLimit::Bytes, limits.bytes.map(|l| bytes > l)),
+            (
+                GrowthLimit::PerMinute,
+                limits.per_minute.map(|l| per_minute > l),
+            ),
+        ];
+        for (limit, over) in crossed.iter() {
+            let key = (site.clone(), *limit);
+            if *over != Some(true) {
+                self.over.remove(&key);
+            } else if self.over.insert(key) {
+                warn!("cookies of {} over {:?} limit: {:?}", site, limit, growth);
+                (self.handler)(&GrowthAlert {
+                    limit: *limit,
+                    growth: growth.clone(),
+                });
+            }
+        }
+        self.latest.insert(site, growth);
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Track the growth of the store per site (see `jar_growth`), calling `handler` when a site
+    /// crosses one of `limits`, e.g. as a misbehaving site sets a unique cookie per request. Each
+    /// limit is alerted once, until the site is back under it.
+    pub fn with_growth_alerts<F>(mut self, limits: GrowthLimits, handler: F) -> Self
+    where
+        F: FnMut(&GrowthAlert) + Send + 'static,
+    {
+        self.growth = Some(JarGrowth::new(limits, Box::new(handler)));
+        self
+    }
+
+    /// The growth of each site's cookies, as last measured on storing cookies from it, by site;
+    /// empty unless configured with `with_growth_alerts`
+    pub fn jar_growth(&self) -> Vec<SiteGrowth> {
+        let mut growth = self
+            .growth
+            .iter()
+            .flat_map(|growth| growth.latest.values().cloned())
+            .collect::<Vec<_>>();
+        growth.sort_by(|a, b| a.site.cmp(&b.site));
+        growth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GrowthAlert, GrowthLimit, GrowthLimits, JarGrowth};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn growth_alerts() {
+        let alerts = Arc::new(Mutex::new(vec![]));
+        let recorded = alerts.clone();
+        let limits = GrowthLimits::new().with_cookies(50).with_per_minute(5.0);
+        let mut growth = JarGrowth::new(
+            limits,
+            Box::new(move |alert: &GrowthAlert| recorded.lock().unwrap().push(alert.clone())),
+        );
+        let start = Instant::now();
+        let minutes = |m: u64| start + Duration::from_secs(m * 60);
+        let site = || "example.com".to_owned();
+
+        growth.observe(site(), 10, 100, minutes(0));
+        growth.observe(site(), 30, 300, minutes(2));
+        let latest = &growth.latest["example.com"];
+        assert_eq!(10.0, latest.per_minute);
+        assert_eq!(Some(Duration::from_secs(2 * 60)), latest.forecast);
+        growth.observe(site(), 40, 400, minutes(3));
+        growth.observe(site(), 60, 600, minutes(4));
+        let limits = alerts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|alert| alert.limit)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![GrowthLimit::PerMinute, GrowthLimit::Cookies], limits);
+
+        // the growth falls out of the window, and the count back under the limit
+        growth.observe(site(), 45, 450, minutes(30));
+        assert_eq!(0.0, growth.latest["example.com"].per_minute);
+        assert!(growth.over.is_empty());
+        growth.observe(site(), 51, 510, minutes(32));
+        assert_eq!(3, alerts.lock().unwrap().len());
+    }
+}
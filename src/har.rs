@@ -0,0 +1,284 @@
+use crate::session::{Session, SessionClient, SessionResponse};
+use crate::storage::CookieStorage;
+#[cfg(feature = "persistence")]
+use crate::utils::iso8601;
+use cookie::Cookie as RawCookie;
+#[cfg(feature = "persistence")]
+use serde_json::{json, Value};
+#[cfg(feature = "persistence")]
+use std::io::Write;
+use std::time::{Duration, Instant, SystemTime};
+use url::Url;
+
+/// A request sent by a `Session` recording HAR (see `Session::with_har_recording`), and its
+/// response
+#[derive(Debug, Clone, PartialEq)]
+pub struct HarEntry {
+    pub started: SystemTime,
+    pub method: String,
+    pub url: Url,
+    /// The headers the session added to the request: its `Cookie` header, and any fetch metadata
+    /// headers. Headers added by the client, or in preparing the request, are not known to the
+    /// session.
+    pub request_headers: Vec<(String, String)>,
+    pub request_cookies: Vec<RawCookie<'static>>,
+    /// The status of the response; `None` if the request failed, or the client does not report
+    /// statuses
+    pub status: Option<u16>,
+    /// The headers of the response known to the session: `Set-Cookie`, `Location`, `Date`,
+    /// `Retry-After`, and `Clear-Site-Data`
+    pub response_headers: Vec<(String, String)>,
+    pub response_cookies: Vec<RawCookie<'static>>,
+    /// How long the client took to send the request and receive the response (or fail)
+    pub time: Duration,
+}
+
+/// The requests of a `Session` recorded for HAR export
+#[derive(Debug, Default)]
+pub(crate) struct HarRecorder {
+    entries: Vec<HarEntry>,
+    /// The request being sent, and when it was
+    sending: Option<(HarEntry, Instant)>,
+}
+
+impl HarRecorder {
+    /// Record the request being sent to `url`, with the `cookies` and `headers` the session added
+    pub(crate) fn request(
+        &mut self,
+        method: &str,
+        url: &Url,
+        cookies: &[RawCookie<'static>],
+        headers: &[(&str, String)],
+    ) {
+        let mut request_headers = vec![];
+        if !cookies.is_empty() {
+            let pairs = cookies
+                .iter()
+                .map(|c| format!("{}={}", c.name(), c.value()))
+                .collect::<Vec<_>>();
+            request_headers.push(("Cookie".to_owned(), pairs.join("; ")));
+        }
+        request_headers.extend(headers.iter().map(|(n, v)| ((*n).to_owned(), v.clone())));
+        let entry = HarEntry {
+            started: SystemTime::now(),
+            method: method.to_owned(),
+            url: url.clone(),
+            request_headers,
+            request_cookies: cookies.to_vec(),
+            status: None,
+            response_headers: vec![],
+            response_cookies: vec![],
+            time: Duration::default(),
+        };
+        self.sending = Some((entry, Instant::now()));
+    }
+
+    /// Record the response to the request being sent; `None` if it failed
+    pub(crate) fn response<R: SessionResponse>(&mut self, response: Option<&R>) {
+        let (mut entry, sent) = match self.sending.take() {
+            Some(sending) => sending,
+            None => return,
+        };
+        entry.time = sent.elapsed();
+        if let Some(response) = response {
+            entry.status = response.status_code();
+            let set_cookie = response.raw_set_cookie();
+            let set_cookie = if set_cookie.is_empty() {
+                let cookies = response.parse_set_cookie();
+                cookies.iter().map(|c| c.to_string()).collect()
+            } else {
+                set_cookie
+            };
+            let headers = &mut entry.response_headers;
+            headers.extend(set_cookie.into_iter().map(|v| ("Set-Cookie".to_owned(), v)));
+            let known = vec![
+                ("Location", response.location()),
+                ("Date", response.date()),
+                ("Retry-After", response.retry_after()),
+                ("Clear-Site-Data", response.clear_site_data()),
+            ];
+            for (name, value) in known {
+                if let Some(value) = value {
+                    headers.push((name.to_owned(), value));
+                }
+            }
+            entry.response_cookies = response.parse_set_cookie();
+        }
+        self.entries.push(entry);
+    }
+}
+
+#[cfg(feature = "persistence")]
+fn name_values(pairs: &[(String, String)]) -> Vec<Value> {
+    pairs
+        .iter()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect()
+}
+
+#[cfg(feature = "persistence")]
+fn cookie_json(cookie: &RawCookie<'static>) -> Value {
+    let mut json = json!({
+        "name": cookie.name(),
+        "value": cookie.value(),
+        "httpOnly": cookie.http_only() == Some(true),
+        "secure": cookie.secure() == Some(true),
+    });
+    if let Some(path) = cookie.path() {
+        json["path"] = path.into();
+    }
+    if let Some(domain) = cookie.domain() {
+        json["domain"] = domain.into();
+    }
+    json
+}
+
+/// `entry` as an entry of a HAR 1.2 log
+#[cfg(feature = "persistence")]
+fn entry_json(entry: &HarEntry) -> Value {
+    let millis = entry.time.as_secs_f64() * 1_000.0;
+    let query = entry
+        .url
+        .query_pairs()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect::<Vec<_>>();
+    let location = entry
+        .response_headers
+        .iter()
+        .find(|(name, _)| name == "Location")
+        .map_or("", |(_, value)| value.as_str());
+    json!({
+        "startedDateTime": iso8601(entry.started),
+        "time": millis,
+        "request": {
+            "method": entry.method,
+            "url": entry.url.as_str(),
+            "httpVersion": "",
+            "cookies": entry.request_cookies.iter().map(cookie_json).collect::<Vec<_>>(),
+            "headers": name_values(&entry.request_headers),
+            "queryString": query,
+            "headersSize": -1,
+            "bodySize": -1,
+        },
+        "response": {
+            // browsers record failed requests with a status of 0
+            "status": entry.status.unwrap_or(0),
+            "statusText": "",
+            "httpVersion": "",
+            "cookies": entry.response_cookies.iter().map(cookie_json).collect::<Vec<_>>(),
+            "headers": name_values(&entry.response_headers),
+            "content": { "size": -1, "mimeType": "" },
+            "redirectURL": location,
+            "headersSize": -1,
+            "bodySize": -1,
+        },
+        "cache": {},
+        "timings": { "send": 0, "wait": millis, "receive": 0 },
+    })
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Record each request the session sends, and its response, for export as a HAR (HTTP
+    /// Archive) with `write_har`, e.g. to debug a scraping flow, or to share a reproduction of
+    /// it. As every request is kept, this is intended for debugging rather than long-running use.
+    pub fn with_har_recording(mut self) -> Self {
+        self.har = Some(HarRecorder::default());
+        self
+    }
+
+    /// The requests recorded since `with_har_recording` was called, in the order sent
+    pub fn har_entries(&self) -> &[HarEntry] {
+        self.har.as_ref().map_or(&[], |har| &har.entries[..])
+    }
+
+    /// The recorded requests as a HAR 1.2 log. Bodies are not recorded, nor are headers not
+    /// known to the session (see `HarEntry`).
+    #[cfg(feature = "persistence")]
+    pub fn har(&self) -> Value {
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": { "name": "user_agent", "version": env!("CARGO_PKG_VERSION") },
+                "entries": self.har_entries().iter().map(entry_json).collect::<Vec<_>>(),
+            }
+        })
+    }
+
+    /// Write the recorded requests to `writer` as a HAR 1.2 log, i.e. a `.har` file
+    #[cfg(feature = "persistence")]
+    pub fn write_har<W: Write>(&self, writer: W) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(writer, &self.har())
+    }
+}
+
+#[cfg(all(test, feature = "persistence", feature = "reqwest-session"))]
+mod tests {
+    use crate::ReqwestSession;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn write_har() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let responses = [
+                "HTTP/1.1 302 Found\r\nConnection: close\r\nSet-Cookie: id=1; Path=/; HttpOnly\r\n\
+                 Location: /home\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok",
+            ];
+            for response in responses.iter() {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let client = reqwest::blocking::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let mut session = ReqwestSession::new(client).with_har_recording();
+        let base = format!("http://{}", addr);
+        session.get(&format!("{}/login?user=a", base)).unwrap();
+        session.get(&format!("{}/home", base)).unwrap();
+        server.join().unwrap();
+
+        assert_eq!(2, session.har_entries().len());
+        let mut har = vec![];
+        session.write_har(&mut har).unwrap();
+        let har: serde_json::Value = serde_json::from_slice(&har).unwrap();
+        let entries = &har["log"]["entries"];
+        assert_eq!("1.2", har["log"]["version"]);
+        assert_eq!("GET", entries[0]["request"]["method"]);
+        assert_eq!("user", entries[0]["request"]["queryString"][0]["name"]);
+        assert_eq!(302, entries[0]["response"]["status"]);
+        assert_eq!("/home", entries[0]["response"]["redirectURL"]);
+        let set_cookie = &entries[0]["response"]["cookies"][0];
+        assert_eq!(
+            ("id", true),
+            (
+                set_cookie["name"].as_str().unwrap(),
+                set_cookie["httpOnly"] == true
+            )
+        );
+        assert_eq!("id", entries[1]["request"]["cookies"][0]["name"]);
+        let cookie_header = &entries[1]["request"]["headers"][0];
+        assert_eq!(
+            ("Cookie", "id=1"),
+            (
+                cookie_header["name"].as_str().unwrap(),
+                cookie_header["value"].as_str().unwrap()
+            )
+        );
+        assert_eq!(200, entries[1]["response"]["status"]);
+        assert!(entries[1]["startedDateTime"]
+            .as_str()
+            .unwrap()
+            .ends_with('Z'));
+    }
+}
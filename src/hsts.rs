@@ -0,0 +1,307 @@
+#[cfg(feature = "persistence")]
+use crate::error::SessionError;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::unix_secs;
+use log::debug;
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(feature = "persistence")]
+use std::ffi::OsString;
+#[cfg(feature = "persistence")]
+use std::io::{BufRead, Write};
+#[cfg(feature = "persistence")]
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use url::Url;
+
+/// The HSTS policy of a host, as set by its `Strict-Transport-Security` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+struct KnownHost {
+    /// When the policy expires, in seconds since the Unix epoch
+    expires: i64,
+    include_subdomains: bool,
+}
+
+/// The hosts known to a `Session` to require HTTPS (see `Session::with_hsts`)
+#[derive(Debug, Default)]
+pub(crate) struct Hsts {
+    hosts: BTreeMap<String, KnownHost>,
+}
+
+/// The `max-age` and `includeSubDomains` directives of the `Strict-Transport-Security` header
+/// `value`, if it is valid (it has exactly one `max-age`, and no directive is repeated)
+fn parse(value: &str) -> Option<(u64, bool)> {
+    let mut max_age = None;
+    let mut include_subdomains = false;
+    let mut seen = vec![];
+    for directive in value.split(';').map(str::trim).filter(|d| !d.is_empty()) {
+        let (name, value) = match directive.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+            None => (directive, None),
+        };
+        let name = name.to_ascii_lowercase();
+        if seen.contains(&name) {
+            return None;
+        }
+        match (name.as_str(), value) {
+            ("max-age", Some(value)) => max_age = Some(value.parse().ok()?),
+            ("max-age", None) => return None,
+            ("includesubdomains", _) => include_subdomains = true,
+            _ => {}
+        }
+        seen.push(name);
+    }
+    Some((max_age?, include_subdomains))
+}
+
+impl Hsts {
+    /// Record the `Strict-Transport-Security` header `value` of a response from `url` at `now`
+    /// (in seconds since the Unix epoch). The header is honored only over HTTPS, and from hosts
+    /// which are domain names rather than IP addresses.
+    fn record_at(&mut self, url: &Url, value: &str, now: i64) {
+        let host = match url.domain() {
+            Some(host) if url.scheme() == "https" => host.to_ascii_lowercase(),
+            _ => return,
+        };
+        match parse(value) {
+            Some((0, _)) => {
+                debug!("removing HSTS host {}", host);
+                self.hosts.remove(&host);
+            }
+            Some((max_age, include_subdomains)) => {
+                let expires = now.saturating_add(max_age.min(i64::MAX as u64) as i64);
+                let known = KnownHost {
+                    expires,
+                    include_subdomains,
+                };
+                self.hosts.insert(host, known);
+            }
+            None => debug!("ignoring invalid Strict-Transport-Security '{}'", value),
+        }
+    }
+
+    pub(crate) fn record(&mut self, url: &Url, value: &str) {
+        self.record_at(url, value, unix_secs(SystemTime::now()))
+    }
+
+    /// Whether requests to `host` must be made over HTTPS at `now`: it or (for a policy which
+    /// includes subdomains) any domain it is a subdomain of is known, and unexpired
+    fn is_known_at(&self, host: &str, now: i64) -> bool {
+        let host = host.to_ascii_lowercase();
+        let mut domain = host.as_str();
+        loop {
+            match self.hosts.get(domain) {
+                Some(known)
+                    if known.expires > now && (domain == host || known.include_subdomains) =>
+                {
+                    return true
+                }
+                _ => {}
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// `url`, upgraded to HTTPS if its host is known to require it
+    pub(crate) fn upgrade(&self, mut url: Url) -> Url {
+        let known = match url.domain() {
+            Some(host) if url.scheme() == "http" => {
+                self.is_known_at(host, unix_secs(SystemTime::now()))
+            }
+            _ => false,
+        };
+        if known {
+            debug!("upgrading {} to HTTPS", url);
+            // the default port of http is dropped, so becomes that of https
+            let _ = url.set_scheme("https");
+        }
+        url
+    }
+}
+
+/// The file the HSTS hosts of a session with the jar `jar` are saved to, beside it
+#[cfg(feature = "persistence")]
+fn hsts_path(jar: &Path) -> PathBuf {
+    let mut path = OsString::from(jar.as_os_str());
+    path.push(".hsts");
+    PathBuf::from(path)
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Honor the `Strict-Transport-Security` headers of HTTPS responses, making later requests
+    /// (and redirects followed by the session) to the hosts which sent them over HTTPS, including
+    /// to their subdomains when the header has `includeSubDomains`. The known hosts are saved with
+    /// `save_hsts`, and, for a session with a jar (see `with_jar`), beside the jar as
+    /// `{jar}.hsts` whenever the jar is saved; they can be loaded back with `load_hsts`.
+    pub fn with_hsts(mut self) -> Self {
+        self.hsts.get_or_insert_with(Hsts::default);
+        self
+    }
+
+    /// Whether requests to `host` are made over HTTPS, per the `Strict-Transport-Security`
+    /// headers received (see `with_hsts`)
+    pub fn is_hsts_host(&self, host: &str) -> bool {
+        let now = unix_secs(SystemTime::now());
+        matches!(self.hsts, Some(ref hsts) if hsts.is_known_at(host, now))
+    }
+
+    /// Write the unexpired HSTS hosts of the session to `writer`, as JSON
+    #[cfg(feature = "persistence")]
+    pub fn save_hsts<W: Write>(&self, writer: W) -> Result<(), SessionError> {
+        let now = unix_secs(SystemTime::now());
+        let hosts = self
+            .hsts
+            .iter()
+            .flat_map(|hsts| hsts.hosts.iter())
+            .filter(|(_, known)| known.expires > now)
+            .collect::<BTreeMap<_, _>>();
+        serde_json::to_writer(writer, &hosts)?;
+        Ok(())
+    }
+
+    /// Add the unexpired HSTS hosts written by `save_hsts` to the session, enabling HSTS (see
+    /// `with_hsts`), returning the number loaded
+    #[cfg(feature = "persistence")]
+    pub fn load_hsts<R: BufRead>(&mut self, reader: R) -> Result<usize, SessionError> {
+        let hosts: BTreeMap<String, KnownHost> = serde_json::from_reader(reader)?;
+        let now = unix_secs(SystemTime::now());
+        let unexpired = hosts
+            .into_iter()
+            .filter(|(_, known)| known.expires > now)
+            .collect::<Vec<_>>();
+        let loaded = unexpired.len();
+        let hsts = self.hsts.get_or_insert_with(Hsts::default);
+        hsts.hosts.extend(unexpired);
+        Ok(loaded)
+    }
+
+    /// Save the HSTS hosts beside `jar`, if HSTS is enabled
+    #[cfg(feature = "persistence")]
+    pub(crate) fn save_hsts_beside(&self, jar: &Path) -> Result<(), SessionError> {
+        if self.hsts.is_none() {
+            return Ok(());
+        }
+        let path = hsts_path(jar);
+        let mut hosts = vec![];
+        self.save_hsts(&mut hosts)?;
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        std::fs::write(&temp, hosts)?;
+        std::fs::rename(&temp, &path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Hsts};
+    use url::Url;
+
+    #[test]
+    fn strict_transport_security() {
+        assert_eq!(Some((31536000, false)), parse("max-age=31536000"));
+        assert_eq!(
+            Some((60, true)),
+            parse("max-age=\"60\"; includeSubDomains; preload")
+        );
+        assert_eq!(Some((0, false)), parse(" Max-Age = 0 "));
+        assert_eq!(None, parse("includeSubDomains"));
+        assert_eq!(None, parse("max-age=60; max-age=120"));
+        assert_eq!(None, parse("max-age=soon"));
+
+        let now = 784_111_777;
+        let mut hsts = Hsts::default();
+        let url = |url| Url::parse(url).unwrap();
+        hsts.record_at(
+            &url("https://example.com/"),
+            "max-age=60; includeSubDomains",
+            now,
+        );
+        hsts.record_at(&url("https://www.example.org/"), "max-age=60", now);
+        // not over HTTPS, or from an IP address
+        hsts.record_at(&url("http://example.net/"), "max-age=60", now);
+        hsts.record_at(&url("https://127.0.0.1/"), "max-age=60", now);
+        assert!(hsts.is_known_at("example.com", now));
+        assert!(hsts.is_known_at("a.b.EXAMPLE.com", now));
+        assert!(hsts.is_known_at("www.example.org", now));
+        assert!(!hsts.is_known_at("sub.www.example.org", now));
+        assert!(!hsts.is_known_at("example.org", now));
+        assert!(!hsts.is_known_at("example.net", now));
+        assert!(!hsts.is_known_at("example.com", now + 60));
+        assert_eq!(2, hsts.hosts.len());
+        hsts.record_at(&url("https://www.example.org/"), "max-age=0", now);
+        assert!(!hsts.is_known_at("www.example.org", now));
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn jar_path() {
+        use super::hsts_path;
+        use std::path::Path;
+
+        assert_eq!(
+            Path::new("/tmp/jar.json.hsts"),
+            hsts_path(Path::new("/tmp/jar.json"))
+        );
+    }
+
+    #[test]
+    fn upgrade() {
+        let mut hsts = Hsts::default();
+        let url = |url| Url::parse(url).unwrap();
+        hsts.record(
+            &url("https://example.com/"),
+            "max-age=60; includeSubDomains",
+        );
+        let upgrade = |u| hsts.upgrade(url(u)).to_string();
+        assert_eq!(
+            "https://www.example.com/a?b",
+            upgrade("http://www.example.com/a?b")
+        );
+        assert_eq!("https://example.com/", upgrade("http://example.com:80/"));
+        assert_eq!(
+            "https://example.com:8080/",
+            upgrade("http://example.com:8080/")
+        );
+        assert_eq!("http://example.org/", upgrade("http://example.org/"));
+        assert_eq!("ws://example.com/", upgrade("ws://example.com/"));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn with_hsts() {
+        use crate::testing::{MockClient, MockResponse};
+        use crate::{Poll, Session};
+        use std::time::Duration;
+
+        let sts = "max-age=3600; includeSubDomains";
+        let client = MockClient::new()
+            .with_response(MockResponse::new(200).with_header("strict-transport-security", sts))
+            .with_response(MockResponse::new(200))
+            .with_response(MockResponse::new(200));
+        let mut session = Session::new(client).with_hsts();
+        session.get("https://example.com/").unwrap();
+        session.get("http://www.example.com/page").unwrap();
+        let last = session.client.last_request().unwrap();
+        assert_eq!("https://www.example.com/page", last.url.as_str());
+        // as are the requests of the other entry points
+        session
+            .poll_with("http://example.com/feed", Duration::ZERO, |_| Poll::Stop)
+            .unwrap();
+        let last = session.client.last_request().unwrap();
+        assert_eq!("https://example.com/feed", last.url.as_str());
+
+        let mut hosts = vec![];
+        session.save_hsts(&mut hosts).unwrap();
+        let mut loaded = Session::new(MockClient::new());
+        assert!(!loaded.is_hsts_host("example.com"));
+        assert_eq!(1, loaded.load_hsts(&hosts[..]).unwrap());
+        assert!(loaded.is_hsts_host("www.example.com"));
+    }
+}
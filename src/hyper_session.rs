@@ -0,0 +1,459 @@
+//! A `Session` over [hyper](https://crates.io/crates/hyper) 1.x, via the `hyper-util` client.
+//!
+//! As `Session` is blocking, a `HyperClient` drives its `hyper_util` client on a tokio runtime of
+//! its own, and so must not be used from within an async context. hyper does not follow
+//! redirects, so each response's `final_url` is the URL it was requested from. The client is
+//! generic over its connector; `HyperClient::new` makes plain HTTP connections, and
+//! `HyperClient::from_client` takes a client with any other (e.g. a `hyper-rustls` or
+//! `hyper-tls` connector for HTTPS).
+use crate::content::{BodyChunks, ContentResponse};
+use crate::paginate::HeaderResponse;
+use crate::session::{
+    ClassifySendError, SendErrorKind, Session, SessionClient, SessionRequest, SessionResponse,
+};
+use crate::spec::{SpecRequest, StatusResponse};
+use crate::utils::parse_set_cookie_values;
+use cookie::Cookie as RawCookie;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{CONTENT_TYPE, COOKIE, DATE, SET_COOKIE};
+use hyper::http::request::Builder;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use log::debug;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use url::Url;
+
+/// A `SessionClient` sending requests with a `hyper_util` client, on a runtime of its own
+#[derive(Clone)]
+pub struct HyperClient<C = HttpConnector> {
+    client: Client<C, Full<Bytes>>,
+    runtime: Arc<Runtime>,
+    timeout: Option<Duration>,
+}
+
+impl HyperClient {
+    /// A client making plain HTTP connections
+    pub fn new() -> Result<Self, HyperSessionError> {
+        Self::from_client(Client::builder(TokioExecutor::new()).build_http())
+    }
+}
+
+impl<C> HyperClient<C> {
+    /// A client sending requests with `client`
+    pub fn from_client(client: Client<C, Full<Bytes>>) -> Result<Self, HyperSessionError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(HyperSessionError::Runtime)?;
+        Ok(HyperClient {
+            client,
+            runtime: Arc::new(runtime),
+            timeout: None,
+        })
+    }
+
+    /// Fail requests for which no response (up to its headers) is received within `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// A request to be sent by a `HyperClient`
+#[derive(Debug)]
+pub struct HyperRequest {
+    pub builder: Builder,
+    pub body: Bytes,
+}
+
+impl HyperRequest {
+    fn new(method: Method, url: &Url) -> Self {
+        HyperRequest {
+            builder: Request::builder().method(method).uri(url.as_str()),
+            body: Bytes::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.builder = self.builder.header(name, value);
+        self
+    }
+
+    pub fn body<B: Into<Bytes>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+impl SessionRequest for HyperRequest {
+    fn add_cookies(self, cookies: Vec<&RawCookie<'static>>) -> Self {
+        if cookies.is_empty() {
+            debug!("no cookies to add to request");
+            return self;
+        }
+        let pairs = cookies
+            .iter()
+            .map(|rc| rc.encoded().to_string())
+            .collect::<Vec<_>>();
+        self.add_cookie_pairs(pairs)
+    }
+
+    fn add_cookie_pairs(self, pairs: Vec<String>) -> Self {
+        // HTTP/2 permits several Cookie headers, but HTTP/1.1 servers expect a single one
+        if pairs.is_empty() {
+            self
+        } else {
+            self.header(COOKIE.as_str(), &pairs.join("; "))
+        }
+    }
+
+    fn add_header(self, name: &str, value: &str) -> Self {
+        self.header(name, value)
+    }
+}
+
+impl SpecRequest for HyperRequest {
+    fn header(self, name: &str, value: &str) -> Self {
+        HyperRequest::header(self, name, value)
+    }
+
+    fn body(self, body: String) -> Self {
+        HyperRequest::body(self, body)
+    }
+}
+
+/// A response received by a `HyperClient`, whose body is read as it is consumed
+#[derive(Debug)]
+pub struct HyperResponse {
+    response: Response<Incoming>,
+    url: Url,
+    runtime: Arc<Runtime>,
+}
+
+impl HyperResponse {
+    pub fn status(&self) -> StatusCode {
+        self.response.status()
+    }
+
+    pub fn headers(&self) -> &hyper::HeaderMap {
+        self.response.headers()
+    }
+
+    /// The URL the response was requested from
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    fn header_strs(&self, name: &str) -> impl Iterator<Item = &str> {
+        self.headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+    }
+}
+
+impl SessionResponse for HyperResponse {
+    type Url = Url;
+
+    fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+        parse_set_cookie_values(
+            self.headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .map(|set_cookie| set_cookie.as_bytes()),
+        )
+    }
+
+    fn final_url(&self) -> Option<&Url> {
+        Some(&self.url)
+    }
+
+    fn redirect_locations(&self) -> Vec<String> {
+        if self.status().is_redirection() {
+            self.header_strs("location").map(str::to_owned).collect()
+        } else {
+            vec![]
+        }
+    }
+
+    fn date(&self) -> Option<String> {
+        self.header_strs(DATE.as_str()).next().map(str::to_owned)
+    }
+
+    fn raw_set_cookie(&self) -> Vec<String> {
+        let headers = self.headers();
+        headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .chain(headers.get_all("set-cookie2").iter())
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+            .collect()
+    }
+
+    fn clear_site_data(&self) -> Option<String> {
+        let values = self.header_strs("clear-site-data").collect::<Vec<_>>();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    fn is_proxy_response(&self) -> bool {
+        self.status() == StatusCode::PROXY_AUTHENTICATION_REQUIRED
+    }
+
+    fn status_code(&self) -> Option<u16> {
+        Some(self.status().as_u16())
+    }
+
+    fn retry_after(&self) -> Option<String> {
+        self.header_strs("retry-after").next().map(str::to_owned)
+    }
+
+    fn location(&self) -> Option<String> {
+        self.header_strs("location").next().map(str::to_owned)
+    }
+
+    fn strict_transport_security(&self) -> Option<String> {
+        self.header_strs("strict-transport-security")
+            .next()
+            .map(str::to_owned)
+    }
+
+    fn www_authenticate(&self) -> Vec<String> {
+        self.header_strs("www-authenticate")
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+impl StatusResponse for HyperResponse {
+    fn status(&self) -> u16 {
+        HyperResponse::status(self).as_u16()
+    }
+}
+
+impl HeaderResponse for HyperResponse {
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.header_strs(name).collect()
+    }
+
+    fn header_names(&self) -> Vec<String> {
+        self.headers().keys().map(|k| k.as_str().to_owned()).collect()
+    }
+}
+
+/// The data frames of a response body, each read as it is pulled
+struct Frames {
+    body: Incoming,
+    runtime: Arc<Runtime>,
+}
+
+impl Iterator for Frames {
+    type Item = Result<Vec<u8>, HyperSessionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = match self.runtime.block_on(self.body.frame())? {
+                Ok(frame) => frame,
+                Err(e) => return Some(Err(HyperSessionError::Body(e))),
+            };
+            // trailers are skipped
+            if let Ok(data) = frame.into_data() {
+                return Some(Ok(data.to_vec()));
+            }
+        }
+    }
+}
+
+impl ContentResponse for HyperResponse {
+    type BodyError = HyperSessionError;
+
+    fn content_type(&self) -> Option<&str> {
+        self.header_strs(CONTENT_TYPE.as_str()).next()
+    }
+
+    fn into_body(self) -> Result<Vec<u8>, Self::BodyError> {
+        let body = self.response.into_body();
+        let collected = self
+            .runtime
+            .block_on(body.collect())
+            .map_err(HyperSessionError::Body)?;
+        Ok(collected.to_bytes().to_vec())
+    }
+
+    fn into_chunks(self) -> BodyChunks<Self::BodyError> {
+        Box::new(Frames {
+            body: self.response.into_body(),
+            runtime: self.runtime,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum HyperSessionError {
+    ParseUrlError(url::ParseError),
+    /// The request could not be built, e.g. for an invalid header
+    Http(hyper::http::Error),
+    /// The request timed out (see `HyperClient::with_timeout`)
+    Timeout,
+    /// Sending the request failed, e.g. as a connection could not be established
+    Client(hyper_util::client::legacy::Error),
+    /// Reading a response body failed
+    Body(hyper::Error),
+    /// The client's runtime could not be started
+    Runtime(std::io::Error),
+}
+
+impl std::fmt::Display for HyperSessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HyperSessionError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            HyperSessionError::Http(e) => write!(f, "HTTP error: {}", e),
+            HyperSessionError::Timeout => write!(f, "Timeout error"),
+            HyperSessionError::Client(e) => write!(f, "Client error: {}", e),
+            HyperSessionError::Body(e) => write!(f, "Body error: {}", e),
+            HyperSessionError::Runtime(e) => write!(f, "Runtime error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HyperSessionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HyperSessionError::ParseUrlError(e) => Some(e),
+            HyperSessionError::Http(e) => Some(e),
+            HyperSessionError::Timeout => None,
+            HyperSessionError::Client(e) => Some(e),
+            HyperSessionError::Body(e) => Some(e),
+            HyperSessionError::Runtime(e) => Some(e),
+        }
+    }
+}
+
+impl ClassifySendError for HyperSessionError {
+    fn kind(&self) -> SendErrorKind {
+        match self {
+            HyperSessionError::ParseUrlError(_) => SendErrorKind::Url,
+            HyperSessionError::Timeout => SendErrorKind::Timeout,
+            HyperSessionError::Client(e) if e.is_connect() => SendErrorKind::Connect,
+            HyperSessionError::Body(e) if e.is_timeout() => SendErrorKind::Timeout,
+            _ => SendErrorKind::Other,
+        }
+    }
+}
+
+impl From<url::ParseError> for HyperSessionError {
+    fn from(e: url::ParseError) -> Self {
+        HyperSessionError::ParseUrlError(e)
+    }
+}
+
+pub type HyperSession<C = HttpConnector> = Session<HyperClient<C>>;
+
+impl<C> SessionClient for HyperClient<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    type Request = HyperRequest;
+    type Response = HyperResponse;
+    type SendError = HyperSessionError;
+
+    fn request(&self, method: &http::Method, url: &Url) -> Self::Request {
+        // hyper 1.x uses http 1.x, whose `Method` is a distinct type
+        let method = Method::from_bytes(method.as_str().as_bytes())
+            .expect("methods valid in http 0.2 are valid in http 1.x");
+        HyperRequest::new(method, url)
+    }
+
+    fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let request = request
+            .builder
+            .body(Full::new(request.body))
+            .map_err(HyperSessionError::Http)?;
+        let url = Url::parse(&request.uri().to_string())?;
+        let sent = self.client.request(request);
+        let response = match self.timeout {
+            Some(timeout) => self
+                .runtime
+                .block_on(async { tokio::time::timeout(timeout, sent).await })
+                .map_err(|_| HyperSessionError::Timeout)?,
+            None => self.runtime.block_on(sent),
+        }
+        .map_err(HyperSessionError::Client)?;
+        Ok(HyperResponse {
+            response,
+            url,
+            runtime: self.runtime.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HyperClient, HyperSession};
+    use crate::{ClassifySendError, ContentResponse, Method, SendErrorKind};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve `responses` in turn, one per connection, returning the requests' headers
+    fn serve(responses: Vec<&'static str>) -> (u16, thread::JoinHandle<Vec<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let reader = BufReader::new(stream.try_clone().unwrap());
+                    let headers = reader
+                        .lines()
+                        .map(Result::unwrap)
+                        .take_while(|line| !line.is_empty())
+                        .collect::<Vec<_>>();
+                    (&stream).write_all(response.as_bytes()).unwrap();
+                    headers
+                })
+                .collect()
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn hyper_session() {
+        let (port, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nSet-Cookie: id=1\r\nSet-Cookie: theme=dark\r\n\
+             Content-Length: 5\r\nConnection: close\r\n\r\nhello",
+            "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 207 Multi-Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let url = format!("http://127.0.0.1:{}/", port);
+        let mut session = HyperSession::new(HyperClient::new().unwrap());
+        let response = session.get(&url).unwrap();
+        assert_eq!(b"hello".to_vec(), response.into_body().unwrap());
+        session
+            .get_with(&url, |r| r.header("x-probe", "1"))
+            .unwrap();
+        let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+        session.request_with(&propfind, &url, |r| r).unwrap();
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0]
+            .iter()
+            .any(|h| h.to_lowercase().starts_with("cookie:")));
+        assert!(requests[1].contains(&"x-probe: 1".to_owned()));
+        assert!(requests[1].contains(&"cookie: id=1; theme=dark".to_owned()));
+        assert_eq!("PROPFIND / HTTP/1.1", requests[2][0]);
+        assert!(requests[2].contains(&"cookie: id=1; theme=dark".to_owned()));
+
+        let refused = session.get(&url).err().unwrap();
+        assert_eq!(SendErrorKind::Connect, refused.kind());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::clear_site_data::expiring;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use cookie_store::Cookie;
+use log::debug;
+use url::Url;
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// The cookies a request to `url` would carry (before any are withheld from a cross-site
+    /// subresource request)
+    pub fn cookies_for_url(&self, url: &Url) -> Vec<RawCookie<'static>> {
+        let canonical = self.origin_aliases.canonical(url);
+        let cookie_url = canonical.as_ref().unwrap_or(url);
+        let cookies = self.store.get_request_cookies(cookie_url);
+        self.accounts.outgoing(cookie_url, cookies)
+    }
+
+    /// The cookie identified by `domain`, `path`, and `name`, if it is in the store and unexpired
+    pub fn get_cookie(&self, domain: &str, path: &str, name: &str) -> Option<Cookie<'static>> {
+        self.store.iter_unexpired().find(|c| {
+            String::from(&c.domain) == domain && String::from(&c.path) == path && c.name() == name
+        })
+    }
+
+    /// Remove the cookie identified by `domain`, `path`, and `name` from the store, returning it
+    /// if it was there and unexpired. The removal is journaled (see `Session::with_journal`) as
+    /// the Set-Cookie which would expire the cookie.
+    pub fn remove_cookie(
+        &mut self,
+        domain: &str,
+        path: &str,
+        name: &str,
+    ) -> Option<Cookie<'static>> {
+        let removed = self.store.remove(domain, path, name)?;
+        self.limiter.forget(&removed);
+        if let (Some(journal), Ok((expired, url))) = (self.journal.as_mut(), expiring(&removed)) {
+            journal.record(&expired, &url);
+        }
+        Some(removed)
+    }
+
+    /// Remove the expired cookies the store retains, returning the number removed (see
+    /// `CookieStorage::remove_expired`)
+    pub fn clear_expired(&mut self) -> usize {
+        let removed = self.store.remove_expired();
+        debug!("removed {} expired cookies", removed);
+        removed
+    }
+
+    /// Remove every cookie from the store, returning the number of unexpired cookies removed
+    pub fn clear_all(&mut self) -> usize {
+        let cookies = self.store.iter_unexpired().collect::<Vec<_>>();
+        let removed = cookies
+            .iter()
+            .filter_map(|c| {
+                let (domain, path) = (String::from(&c.domain), String::from(&c.path));
+                self.remove_cookie(&domain, &path, c.name())
+            })
+            .count();
+        self.clear_expired();
+        removed
+    }
+}
+
+#[cfg(all(test, feature = "reqwest-session"))]
+mod tests {
+    use crate::{CookieStorage, LruCookieStore, ReqwestSession, ShardedCookieStore};
+    use cookie::Cookie as RawCookie;
+    use url::Url;
+
+    #[test]
+    fn inspect() {
+        let url = Url::parse("https://www.example.com/account").unwrap();
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new());
+        session.store.parse("id=1; Path=/account", &url).unwrap();
+        session
+            .store
+            .parse("pref=2; Domain=example.com; Path=/", &url)
+            .unwrap();
+        let other = Url::parse("https://www.example.org/").unwrap();
+        session.store.parse("id=4", &other).unwrap();
+
+        let names = |session: &ReqwestSession, url: &Url| {
+            let mut names = session
+                .cookies_for_url(url)
+                .iter()
+                .map(|c| c.name().to_owned())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(vec!["id", "pref"], names(&session, &url));
+        let pref = session.get_cookie("example.com", "/", "pref").unwrap();
+        assert_eq!("2", pref.value());
+        assert!(session.get_cookie("www.example.com", "/", "pref").is_none());
+
+        assert!(session
+            .remove_cookie("www.example.com", "/account", "id")
+            .is_some());
+        assert!(session
+            .remove_cookie("www.example.com", "/account", "id")
+            .is_none());
+        assert_eq!(vec!["pref"], names(&session, &url));
+        session.store.parse("old=3", &url).unwrap();
+        session.store.parse("old=; Max-Age=0", &url).unwrap();
+        assert_eq!(1, session.clear_expired());
+        assert_eq!(0, session.clear_expired());
+        assert_eq!(2, session.clear_all());
+        assert_eq!(0, session.store.iter_any().count());
+
+        let mut session = session.with_store(ShardedCookieStore::new(4));
+        session
+            .store
+            .insert_raw(&RawCookie::parse("a=1").unwrap(), &url)
+            .unwrap();
+        assert!(session.remove_cookie("www.example.com", "/", "a").is_some());
+        assert!(session.cookies_for_url(&url).is_empty());
+
+        let mut session = session.with_store(LruCookieStore::new(10));
+        session
+            .store
+            .insert_raw(&RawCookie::parse("a=1").unwrap(), &url)
+            .unwrap();
+        session
+            .store
+            .insert_raw(&RawCookie::parse("b=2").unwrap(), &other)
+            .unwrap();
+        assert_eq!(2, session.clear_all());
+        assert!(session.store.is_empty());
+    }
+}
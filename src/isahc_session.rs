@@ -0,0 +1,386 @@
+//! A `Session` over [isahc](https://crates.io/crates/isahc) 0.9, and so libcurl.
+//!
+//! Cookies are kept by the session rather than by curl's cookie engine, so they can be
+//! inspected, persisted and shared like those of any other `Session`; the `isahc` dependency is
+//! built without its `cookies` feature. isahc does not follow redirects unless its client is
+//! configured to, in which case each response's `final_url` is its effective URI.
+use crate::content::{BodyChunks, ContentResponse};
+use crate::paginate::HeaderResponse;
+use crate::session::{
+    ClassifySendError, SendErrorKind, Session, SessionClient, SessionRequest, SessionResponse,
+};
+use crate::spec::{SpecRequest, StatusResponse};
+use crate::utils::parse_set_cookie_values;
+use cookie::Cookie as RawCookie;
+use isahc::http::header::{CONTENT_TYPE, COOKIE, DATE, SET_COOKIE};
+use isahc::http::request::Builder;
+use isahc::http::{Method, Request, Response, StatusCode};
+use isahc::{Body, HttpClient, ResponseExt};
+use log::debug;
+use std::io::Read;
+use url::Url;
+
+/// A request to be sent by an `isahc::HttpClient`
+#[derive(Debug)]
+pub struct IsahcRequest {
+    pub builder: Builder,
+    pub body: Vec<u8>,
+}
+
+impl IsahcRequest {
+    fn new(method: Method, url: &Url) -> Self {
+        IsahcRequest {
+            builder: Request::builder().method(method).uri(url.as_str()),
+            body: vec![],
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.builder = self.builder.header(name, value);
+        self
+    }
+
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+impl SessionRequest for IsahcRequest {
+    fn add_cookies(self, cookies: Vec<&RawCookie<'static>>) -> Self {
+        if cookies.is_empty() {
+            debug!("no cookies to add to request");
+            return self;
+        }
+        let pairs = cookies
+            .iter()
+            .map(|rc| rc.encoded().to_string())
+            .collect::<Vec<_>>();
+        self.add_cookie_pairs(pairs)
+    }
+
+    fn add_cookie_pairs(self, pairs: Vec<String>) -> Self {
+        if pairs.is_empty() {
+            self
+        } else {
+            self.header(COOKIE.as_str(), &pairs.join("; "))
+        }
+    }
+
+    fn add_header(self, name: &str, value: &str) -> Self {
+        self.header(name, value)
+    }
+}
+
+impl SpecRequest for IsahcRequest {
+    fn header(self, name: &str, value: &str) -> Self {
+        IsahcRequest::header(self, name, value)
+    }
+
+    fn body(self, body: String) -> Self {
+        IsahcRequest::body(self, body)
+    }
+}
+
+/// A response received by an `isahc::HttpClient`, whose body is read as it is consumed
+#[derive(Debug)]
+pub struct IsahcResponse {
+    response: Response<Body>,
+    url: Url,
+}
+
+impl IsahcResponse {
+    pub fn status(&self) -> StatusCode {
+        self.response.status()
+    }
+
+    pub fn headers(&self) -> &isahc::http::HeaderMap {
+        self.response.headers()
+    }
+
+    /// The URL the response was served from
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn into_inner(self) -> Response<Body> {
+        self.response
+    }
+
+    fn header_strs(&self, name: &str) -> impl Iterator<Item = &str> {
+        self.headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+    }
+}
+
+impl SessionResponse for IsahcResponse {
+    type Url = Url;
+
+    fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+        parse_set_cookie_values(
+            self.headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .map(|set_cookie| set_cookie.as_bytes()),
+        )
+    }
+
+    fn final_url(&self) -> Option<&Url> {
+        Some(&self.url)
+    }
+
+    fn date(&self) -> Option<String> {
+        self.header_strs(DATE.as_str()).next().map(str::to_owned)
+    }
+
+    fn raw_set_cookie(&self) -> Vec<String> {
+        let headers = self.headers();
+        headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .chain(headers.get_all("set-cookie2").iter())
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+            .collect()
+    }
+
+    fn clear_site_data(&self) -> Option<String> {
+        let values = self.header_strs("clear-site-data").collect::<Vec<_>>();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    fn is_proxy_response(&self) -> bool {
+        self.status() == StatusCode::PROXY_AUTHENTICATION_REQUIRED
+    }
+
+    fn status_code(&self) -> Option<u16> {
+        Some(self.status().as_u16())
+    }
+
+    fn retry_after(&self) -> Option<String> {
+        self.header_strs("retry-after").next().map(str::to_owned)
+    }
+
+    fn location(&self) -> Option<String> {
+        self.header_strs("location").next().map(str::to_owned)
+    }
+
+    fn strict_transport_security(&self) -> Option<String> {
+        self.header_strs("strict-transport-security")
+            .next()
+            .map(str::to_owned)
+    }
+
+    fn www_authenticate(&self) -> Vec<String> {
+        self.header_strs("www-authenticate")
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+impl StatusResponse for IsahcResponse {
+    fn status(&self) -> u16 {
+        IsahcResponse::status(self).as_u16()
+    }
+}
+
+impl HeaderResponse for IsahcResponse {
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.header_strs(name).collect()
+    }
+
+    fn header_names(&self) -> Vec<String> {
+        self.headers().keys().map(|k| k.as_str().to_owned()).collect()
+    }
+}
+
+impl ContentResponse for IsahcResponse {
+    type BodyError = IsahcSessionError;
+
+    fn content_type(&self) -> Option<&str> {
+        self.header_strs(CONTENT_TYPE.as_str()).next()
+    }
+
+    fn into_body(self) -> Result<Vec<u8>, Self::BodyError> {
+        let mut body = vec![];
+        self.response
+            .into_body()
+            .read_to_end(&mut body)
+            .map_err(IsahcSessionError::Body)?;
+        Ok(body)
+    }
+
+    fn into_chunks(self) -> BodyChunks<Self::BodyError> {
+        let mut body = self.response.into_body();
+        let mut buffer = [0; 8192];
+        Box::new(std::iter::from_fn(move || match body.read(&mut buffer) {
+            Ok(0) => None,
+            Ok(read) => Some(Ok(buffer[..read].to_vec())),
+            Err(e) => Some(Err(IsahcSessionError::Body(e))),
+        }))
+    }
+}
+
+#[derive(Debug)]
+pub enum IsahcSessionError {
+    ParseUrlError(url::ParseError),
+    /// The request could not be built, e.g. for an invalid header
+    Http(isahc::http::Error),
+    /// Sending the request failed
+    Isahc(isahc::Error),
+    /// Reading a response body failed
+    Body(std::io::Error),
+}
+
+impl std::fmt::Display for IsahcSessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IsahcSessionError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            IsahcSessionError::Http(e) => write!(f, "HTTP error: {}", e),
+            IsahcSessionError::Isahc(e) => write!(f, "isahc error: {}", e),
+            IsahcSessionError::Body(e) => write!(f, "Body error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IsahcSessionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IsahcSessionError::ParseUrlError(e) => Some(e),
+            IsahcSessionError::Http(e) => Some(e),
+            IsahcSessionError::Isahc(e) => Some(e),
+            IsahcSessionError::Body(e) => Some(e),
+        }
+    }
+}
+
+impl ClassifySendError for IsahcSessionError {
+    fn kind(&self) -> SendErrorKind {
+        use isahc::Error;
+        match self {
+            IsahcSessionError::ParseUrlError(_) => SendErrorKind::Url,
+            IsahcSessionError::Isahc(e) => match e {
+                Error::Timeout => SendErrorKind::Timeout,
+                Error::ConnectFailed | Error::CouldntResolveHost | Error::CouldntResolveProxy => {
+                    SendErrorKind::Connect
+                }
+                Error::BadClientCertificate(_)
+                | Error::BadServerCertificate(_)
+                | Error::SSLConnectFailed(_)
+                | Error::SSLEngineError(_) => SendErrorKind::Tls,
+                Error::InvalidContentEncoding(_) => SendErrorKind::Decode,
+                _ => SendErrorKind::Other,
+            },
+            _ => SendErrorKind::Other,
+        }
+    }
+}
+
+impl From<url::ParseError> for IsahcSessionError {
+    fn from(e: url::ParseError) -> Self {
+        IsahcSessionError::ParseUrlError(e)
+    }
+}
+
+pub type IsahcSession = Session<HttpClient>;
+
+impl SessionClient for HttpClient {
+    type Request = IsahcRequest;
+    type Response = IsahcResponse;
+    type SendError = IsahcSessionError;
+
+    fn request(&self, method: &http::Method, url: &Url) -> Self::Request {
+        IsahcRequest::new(method.clone(), url)
+    }
+
+    fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let request = request
+            .builder
+            .body(request.body)
+            .map_err(IsahcSessionError::Http)?;
+        let requested = Url::parse(&request.uri().to_string())?;
+        let response = HttpClient::send(self, request).map_err(IsahcSessionError::Isahc)?;
+        let url = match response.effective_uri() {
+            Some(uri) => requested.join(&uri.to_string())?,
+            None => requested,
+        };
+        Ok(IsahcResponse { response, url })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IsahcSession;
+    use crate::{ClassifySendError, ContentResponse, Method, SendErrorKind};
+    use isahc::HttpClient;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve `responses` in turn, one per connection, returning the requests' headers
+    fn serve(responses: Vec<&'static str>) -> (u16, thread::JoinHandle<Vec<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let reader = BufReader::new(stream.try_clone().unwrap());
+                    let headers = reader
+                        .lines()
+                        .map(Result::unwrap)
+                        .take_while(|line| !line.is_empty())
+                        .collect::<Vec<_>>();
+                    (&stream).write_all(response.as_bytes()).unwrap();
+                    headers
+                })
+                .collect()
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn isahc_session() {
+        let (port, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nSet-Cookie: id=1\r\nSet-Cookie: theme=dark\r\n\
+             Content-Length: 5\r\nConnection: close\r\n\r\nhello",
+            "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 207 Multi-Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let url = format!("http://127.0.0.1:{}/", port);
+        let mut session = IsahcSession::new(HttpClient::new().unwrap());
+        let response = session.get(&url).unwrap();
+        assert_eq!(b"hello".to_vec(), response.into_body().unwrap());
+        session
+            .get_with(&url, |r| r.header("x-probe", "1"))
+            .unwrap();
+        let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+        session.request_with(&propfind, &url, |r| r).unwrap();
+
+        let requests = server.join().unwrap();
+        // curl sends headers without a space after the colon
+        let has = |headers: &[String], name: &str, value: &str| {
+            headers.iter().any(|h| {
+                let mut parts = h.splitn(2, ':');
+                parts.next().map(|n| n.eq_ignore_ascii_case(name)) == Some(true)
+                    && parts.next().map(str::trim) == Some(value)
+            })
+        };
+        assert!(!requests[0]
+            .iter()
+            .any(|h| h.to_lowercase().starts_with("cookie:")));
+        assert!(has(&requests[1], "x-probe", "1"));
+        assert!(has(&requests[1], "cookie", "id=1; theme=dark"));
+        assert_eq!("PROPFIND / HTTP/1.1", requests[2][0]);
+        assert!(has(&requests[2], "cookie", "id=1; theme=dark"));
+
+        let refused = session.get(&url).err().unwrap();
+        assert_eq!(SendErrorKind::Connect, refused.kind());
+    }
+}
@@ -0,0 +1,366 @@
+//! Checks of cookie jars in the `Session::save_json` format, for ops tooling to run over a jar
+//! before deploying it to agents, and loading of jars which may be damaged.
+use crate::error::SessionError;
+use crate::extensions::FIELD as EXTENSIONS_FIELD;
+use crate::utils::is_sensitive_name;
+use cookie_store::CookieStore;
+use cookie_store::{Cookie, CookieError};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::BufRead;
+use url::Url;
+
+/// The largest name plus value, in bytes, browsers are required to accept (RFC 6265, section 6.1)
+const MAX_COOKIE_SIZE: usize = 4096;
+
+const KNOWN_FIELDS: &[&str] = &["raw_cookie", "path", "domain", "expires", "extensions"];
+
+/// What `lint` found wrong with a cookie
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintKind {
+    /// The line could not be read or parsed as a cookie
+    Invalid(String),
+    /// The cookie has a field not written by `save_json`, which is ignored on load
+    UnknownField(String),
+    /// The cookie has the same domain, path, and name as the cookie on an earlier line, which it
+    /// replaces on load
+    Duplicate { first_line: usize },
+    /// The cookie has expired, and is skipped on load
+    Expired,
+    /// The cookie's domain is a public suffix (e.g. `com`), so it would be sent to every site
+    /// beneath it
+    PublicSuffix(String),
+    /// The cookie's name and value total the given number of bytes, more than the 4096 browsers
+    /// are required to accept
+    Oversized(usize),
+    /// The cookie's name suggests it holds a credential, but it lacks the Secure attribute
+    InsecureSensitive,
+}
+
+/// A problem found by `lint`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintFinding {
+    /// The (1-based) line of the input holding the cookie
+    pub line: usize,
+    pub kind: LintKind,
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            LintKind::Invalid(ref e) => write!(f, "invalid cookie: {}", e),
+            LintKind::UnknownField(ref field) => write!(f, "unknown field `{}`", field),
+            LintKind::Duplicate { first_line } => {
+                write!(f, "duplicate of the cookie on line {}", first_line)
+            }
+            LintKind::Expired => write!(f, "expired cookie"),
+            LintKind::PublicSuffix(ref domain) => {
+                write!(f, "cookie for public suffix domain `{}`", domain)
+            }
+            LintKind::Oversized(size) => write!(f, "oversized cookie ({} bytes)", size),
+            LintKind::InsecureSensitive => write!(f, "sensitive-looking cookie is not Secure"),
+        }
+    }
+}
+
+/// A cookie of a jar which could not be loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadError {
+    /// The (1-based) line of the input holding the cookie
+    pub line: usize,
+    /// The offset in bytes into the input at which the error was found
+    pub offset: usize,
+    /// The (0-based) index of the cookie among those of the jar, not counting blank lines
+    pub index: usize,
+    pub message: String,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unable to load cookie {} (line {}, byte {}): {}",
+            self.index, self.line, self.offset, self.message
+        )
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// A jar loaded by `load_json_partial`
+#[derive(Debug)]
+pub struct PartialLoad {
+    /// The cookies which could be loaded
+    pub store: CookieStore,
+    /// The cookies which could not be loaded, in order
+    pub errors: Vec<LoadError>,
+    /// The lines of the cookies loaded with extension attributes, as read
+    pub(crate) json: Vec<u8>,
+}
+
+/// Insert `cookie`, as loaded from a jar, into `store`, as if received from its domain
+pub(crate) fn insert_loaded(
+    store: &mut CookieStore,
+    cookie: Cookie<'static>,
+) -> Result<(), CookieError> {
+    let url = Url::parse(&format!("https://{}/", String::from(&cookie.domain)))
+        .map_err(|_| CookieError::UnspecifiedDomain)?;
+    store.insert(cookie, &url).map(|_| ())
+}
+
+/// Load the jar read from `reader`, in the `Session::save_json` format, salvaging what cookies
+/// can be loaded from one which is damaged (e.g. by a partial write) and reporting where each of
+/// those which cannot be went wrong. Only a failure to read `reader` is an error.
+pub fn load_json_partial<R: BufRead>(mut reader: R) -> Result<PartialLoad, SessionError> {
+    let mut store = CookieStore::default();
+    let mut json = vec![];
+    let mut errors = vec![];
+    let extended = format!("\"{}\"", EXTENSIONS_FIELD);
+    let (mut line, mut offset, mut index) = (0, 0, 0);
+    let mut buf = vec![];
+    loop {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+        line += 1;
+        let start = offset;
+        offset += read;
+        let text = match std::str::from_utf8(&buf) {
+            Ok(text) => text,
+            Err(e) => {
+                errors.push(LoadError {
+                    line,
+                    offset: start + e.valid_up_to(),
+                    index,
+                    message: e.to_string(),
+                });
+                index += 1;
+                continue;
+            }
+        };
+        let text = text.trim_end();
+        if text.trim_start().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Cookie<'static>>(text) {
+            // skipped, as by `CookieStore::load_json`
+            Ok(cookie) if cookie.is_expired() => {}
+            Ok(cookie) => match insert_loaded(&mut store, cookie) {
+                Ok(()) if text.contains(&extended) => {
+                    json.extend_from_slice(text.as_bytes());
+                    json.push(b'\n');
+                }
+                Ok(()) => {}
+                Err(e) => errors.push(LoadError {
+                    line,
+                    offset: start,
+                    index,
+                    message: e.to_string(),
+                }),
+            },
+            Err(e) => errors.push(LoadError {
+                line,
+                offset: start + e.column().saturating_sub(1),
+                index,
+                message: e.to_string(),
+            }),
+        }
+        index += 1;
+    }
+    Ok(PartialLoad {
+        store,
+        errors,
+        json,
+    })
+}
+
+/// Load the jar read from `reader`, in the `Session::save_json` format, failing with a
+/// `LoadError` locating the first cookie which cannot be loaded, if any
+pub fn load_json<R: BufRead>(reader: R) -> Result<CookieStore, SessionError> {
+    let loaded = load_json_partial(reader)?;
+    match loaded.errors.into_iter().next() {
+        Some(e) => Err(e.into()),
+        None => Ok(loaded.store),
+    }
+}
+
+/// Lint the jar read from `reader`, treating single-label domains (such as `com` or `localhost`)
+/// as public suffixes; see `lint_with` to use a full public suffix list.
+pub fn lint<R: BufRead>(reader: R) -> Vec<LintFinding> {
+    lint_with(reader, |domain| !domain.contains('.'))
+}
+
+/// Lint the jar read from `reader`, with `is_public_suffix` deciding whether a cookie's domain is
+/// a public suffix
+pub fn lint_with<R, P>(reader: R, is_public_suffix: P) -> Vec<LintFinding>
+where
+    R: BufRead,
+    P: Fn(&str) -> bool,
+{
+    let mut findings = vec![];
+    let mut seen = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let mut finding = |kind| findings.push(LintFinding { line: i + 1, kind });
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                finding(LintKind::Invalid(format!("unable to read line: {}", e)));
+                break;
+            }
+        };
+        let value = match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(value) => value,
+            Err(e) => {
+                finding(LintKind::Invalid(e.to_string()));
+                continue;
+            }
+        };
+        if let Some(fields) = value.as_object() {
+            for field in fields.keys() {
+                if !KNOWN_FIELDS.contains(&field.as_str()) {
+                    finding(LintKind::UnknownField(field.clone()));
+                }
+            }
+        }
+        let cookie = match serde_json::from_value::<Cookie<'static>>(value) {
+            Ok(cookie) => cookie,
+            Err(e) => {
+                finding(LintKind::Invalid(e.to_string()));
+                continue;
+            }
+        };
+
+        let domain = String::from(&cookie.domain);
+        let key = (
+            domain.clone(),
+            String::from(&cookie.path),
+            cookie.name().to_owned(),
+        );
+        match seen.get(&key) {
+            Some(&first_line) => finding(LintKind::Duplicate { first_line }),
+            None => {
+                seen.insert(key, i + 1);
+            }
+        }
+        if cookie.is_expired() {
+            finding(LintKind::Expired);
+        }
+        if !domain.is_empty() && is_public_suffix(&domain) {
+            finding(LintKind::PublicSuffix(domain));
+        }
+        let size = cookie.name().len() + cookie.value().len();
+        if size > MAX_COOKIE_SIZE {
+            finding(LintKind::Oversized(size));
+        }
+        if !cookie.secure().unwrap_or(false) && is_sensitive_name(cookie.name()) {
+            finding(LintKind::InsecureSensitive);
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, load_json, load_json_partial, LintFinding, LintKind, LoadError};
+    use crate::error::SessionError;
+
+    #[test]
+    fn findings() {
+        let cookie = |raw: &str, domain: &str, expires: &str| {
+            format!(
+                r#"{{"raw_cookie":"{}","path":["/",true],"domain":{{"Suffix":"{}"}},"expires":{}}}"#,
+                raw, domain, expires
+            )
+        };
+        let future = r#"{"AtUtc":"2999-01-01T00:00:00Z"}"#;
+        let jar = [
+            cookie("a=1", "example.com", future),
+            cookie("a=2", "example.com", future),
+            cookie("b=1", "example.com", r#"{"AtUtc":"2000-01-01T00:00:00Z"}"#),
+            cookie("c=1", "com", future),
+            cookie(&format!("d={}", "x".repeat(4096)), "example.com", future),
+            cookie("session_id=1", "example.com", future),
+            cookie("session_id=1; Secure", "example.org", future),
+            cookie("e=1", "example.com", future).replace("}}", "}, \"comment\": \"x\"}"),
+            "not json".to_owned(),
+        ]
+        .join("\n");
+
+        let kinds = lint(jar.as_bytes())
+            .into_iter()
+            .map(|LintFinding { line, kind }| (line, kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (2, LintKind::Duplicate { first_line: 1 }),
+                (3, LintKind::Expired),
+                (4, LintKind::PublicSuffix("com".to_owned())),
+                (5, LintKind::Oversized(4097)),
+                (6, LintKind::InsecureSensitive),
+                (8, LintKind::UnknownField("comment".to_owned())),
+            ],
+            kinds[..6].to_vec()
+        );
+        assert_eq!(7, kinds.len());
+        match kinds[6] {
+            (9, LintKind::Invalid(_)) => {}
+            ref other => panic!("unexpected finding {:?}", other),
+        }
+    }
+
+    #[test]
+    fn partial_load() {
+        let good = |name: &str| {
+            format!(
+                r#"{{"raw_cookie":"{}=1","path":["/",true],"domain":{{"HostOnly":"example.com"}},"expires":"SessionEnd"}}"#,
+                name
+            )
+        };
+        let truncated = good("b");
+        let truncated = &truncated[..truncated.len() - 20];
+        let jar = format!("{}\n\n{}\n{}\n", good("a"), truncated, good("c"));
+        let loaded = load_json_partial(jar.as_bytes()).unwrap();
+        let mut names = loaded
+            .store
+            .iter_any()
+            .map(|c| c.name().to_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(vec!["a", "c"], names);
+        assert_eq!(1, loaded.errors.len());
+        let LoadError {
+            line,
+            offset,
+            index,
+            ..
+        } = loaded.errors[0];
+        assert_eq!((3, 1), (line, index));
+        // at the last byte of the truncated line, where its input ran out
+        assert_eq!(good("a").len() + 2 + truncated.len() - 1, offset);
+
+        let e = match load_json(jar.as_bytes()).unwrap_err() {
+            SessionError::CookieParse(e) => e,
+            e => panic!("expected a cookie parse error, got {:?}", e),
+        };
+        let e = e.downcast_ref::<LoadError>().unwrap();
+        assert_eq!(3, e.line);
+        assert!(load_json(good("a").as_bytes()).is_ok());
+
+        let domainless = good("d").replace(r#"{"HostOnly":"example.com"}"#, r#""NotPresent""#);
+        let expired = good("e").replace(r#""SessionEnd""#, r#"{"AtUtc":"2000-01-01T00:00:00Z"}"#);
+        let jar = format!("{}\n{}\n{}\n", good("a"), domainless, expired);
+        let loaded = load_json_partial(jar.as_bytes()).unwrap();
+        assert_eq!(1, loaded.store.iter_any().count());
+        assert_eq!(
+            vec![(2, good("a").len() + 1)],
+            loaded
+                .errors
+                .iter()
+                .map(|e| (e.line, e.offset))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! A journal of the changes made to a `Session`'s cookie store, from which the store can be
+//! rebuilt as it was at any past time: e.g. to see which cookies an agent held when a request
+//! failed. Requires the `persistence` feature.
+use crate::error::SessionError;
+use crate::expiry::Expiry;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::unix_secs;
+use cookie::Cookie as RawCookie;
+use cookie_store::{Cookie, CookieStore};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::time::SystemTime;
+use url::Url;
+
+/// A line of a journal: a Set-Cookie stored from `url` at `at`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// When the cookie was stored, in seconds since the Unix epoch
+    pub at: i64,
+    pub url: String,
+    pub set_cookie: String,
+    /// When the cookie expires, in seconds since the Unix epoch; `None` at the end of the session
+    pub expires: Option<i64>,
+}
+
+/// Where a `Session` writes its journal
+pub(crate) struct Journal {
+    writer: Box<dyn Write + Send>,
+}
+
+impl Journal {
+    /// Record that `cookie` was stored as received from `url`. Failures to write are logged, so
+    /// that a full disk does not fail requests.
+    pub(crate) fn record(&mut self, cookie: &RawCookie<'static>, url: &Url) {
+        let expires = Cookie::try_from_raw_cookie(cookie, url)
+            .ok()
+            .and_then(|stored| Expiry::of(&stored).time())
+            .map(unix_secs);
+        let entry = JournalEntry {
+            at: unix_secs(SystemTime::now()),
+            url: url.to_string(),
+            set_cookie: cookie.to_string(),
+            expires,
+        };
+        let written = serde_json::to_string(&entry)
+            .map_err(SessionError::from)
+            .and_then(|line| Ok(writeln!(self.writer, "{}", line)?));
+        if let Err(e) = written {
+            warn!("unable to write journal entry {:?}: {}", entry, e);
+        }
+    }
+}
+
+/// `entry`'s Set-Cookie as if it were stored at `at`: its `Expires` and `Max-Age` replaced by a
+/// `Max-Age` of the time it had left to live at `at`
+fn as_of(entry: &JournalEntry, at: i64) -> String {
+    let mut set_cookie = entry
+        .set_cookie
+        .split(';')
+        .filter(|attribute| {
+            let name = attribute.split('=').next().unwrap_or("").trim();
+            !name.eq_ignore_ascii_case("max-age") && !name.eq_ignore_ascii_case("expires")
+        })
+        .collect::<Vec<_>>()
+        .join(";");
+    if let Some(expires) = entry.expires {
+        set_cookie.push_str(&format!("; Max-Age={}", (expires - at).max(0)));
+    }
+    set_cookie
+}
+
+/// Rebuild the store of the session which wrote `journal` as it was at `at`, by replaying its
+/// entries up to then. The times of expiry of the rebuilt cookies are shifted by the time since
+/// `at`, so that the store holds exactly the cookies which were unexpired at `at`, each with as
+/// long left to live as it had then. Cookies a session stored by other means than responses
+/// (e.g. `pin_cookie`, or loading a jar) are not journaled.
+pub fn rebuild_at<R: BufRead>(journal: R, at: SystemTime) -> Result<CookieStore, SessionError> {
+    let at = unix_secs(at);
+    let mut store = CookieStore::default();
+    for line in journal.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str::<JournalEntry>(&line)?;
+        if entry.at > at {
+            break;
+        }
+        let url = Url::parse(&entry.url)?;
+        let cookie = RawCookie::parse(as_of(&entry, at))?;
+        if let Err(e) = CookieStorage::insert_raw(&mut store, &cookie, &url) {
+            warn!("unable to replay journal entry {:?}: {}", entry, e);
+        }
+    }
+    Ok(store)
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Journal each Set-Cookie stored (including those expiring or clearing a cookie) to
+    /// `writer`, one `JournalEntry` per line, as JSON, for `rebuild_at`
+    pub fn with_journal<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.journal = Some(Journal {
+            writer: Box::new(writer),
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rebuild_at, JournalEntry};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn rebuild() {
+        let entry = |at, set_cookie: &str, expires| JournalEntry {
+            at,
+            url: "https://www.example.com/".to_owned(),
+            set_cookie: set_cookie.to_owned(),
+            expires,
+        };
+        let journal = [
+            entry(1_000, "id=1; Max-Age=3600; Secure", Some(4_600)),
+            entry(1_000, "tmp=1", None),
+            entry(2_000, "short=1; Max-Age=60", Some(2_060)),
+            entry(3_000, "id=2; Max-Age=3600; Secure", Some(6_600)),
+            entry(4_000, "tmp=; Max-Age=0", Some(4_000)),
+        ]
+        .iter()
+        .map(|e| serde_json::to_string(e).unwrap() + "\n")
+        .collect::<String>();
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let cookies = |secs| {
+            let store = rebuild_at(journal.as_bytes(), at(secs)).unwrap();
+            let mut cookies = store
+                .iter_unexpired()
+                .map(|c| format!("{}={}", c.name(), c.value()))
+                .collect::<Vec<_>>();
+            cookies.sort();
+            cookies
+        };
+        assert!(cookies(999).is_empty());
+        assert_eq!(vec!["id=1", "short=1", "tmp=1"], cookies(2_030));
+        assert_eq!(vec!["id=1", "tmp=1"], cookies(2_060));
+        assert_eq!(vec!["id=2", "tmp=1"], cookies(3_500));
+        assert_eq!(vec!["id=2"], cookies(5_000));
+    }
+}
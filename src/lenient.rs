@@ -0,0 +1,158 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::{cookie_date, fixdate};
+use cookie::Cookie as RawCookie;
+use log::debug;
+
+/// How strictly a `Session` parses the cookies of responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    /// Parse each Set-Cookie header as a single cookie, per RFC 6265 (the default)
+    #[default]
+    Strict,
+    /// Accept what legacy servers send and browsers tolerate: several comma-separated cookies in
+    /// one Set-Cookie header, RFC 2965 Set-Cookie2 headers, quoted attribute values, the
+    /// obsolete `Version`, `Comment`, `CommentURL`, `Discard`, and `Port` attributes (which are
+    /// dropped), and `Expires` dates in any format browsers accept. Only takes effect for
+    /// responses providing their `SessionResponse::raw_set_cookie` headers; others are parsed
+    /// strictly.
+    Lenient,
+}
+
+/// The attributes of RFC 2965 (and RFC 2109) with no meaning under RFC 6265
+const OBSOLETE_ATTRIBUTES: &[&str] = &["version", "comment", "commenturl", "discard", "port"];
+
+/// The cookies of the Set-Cookie (or Set-Cookie2) header `line`, split at each comma followed by
+/// a cookie name and `=`, so that commas within `Expires` dates are not split at
+pub(crate) fn split_cookies(line: &str) -> Vec<&str> {
+    let mut cookies = vec![];
+    let mut start = 0;
+    for (i, _) in line.match_indices(',') {
+        let next = line[i + 1..].split(';').next().unwrap_or("");
+        let starts_cookie = match next.find('=') {
+            Some(eq) => {
+                let name = next[..eq].trim();
+                !name.is_empty() && !name.contains(char::is_whitespace)
+            }
+            None => false,
+        };
+        if starts_cookie {
+            cookies.push(line[start..i].trim());
+            start = i + 1;
+        }
+    }
+    cookies.push(line[start..].trim());
+    cookies.retain(|cookie| !cookie.is_empty());
+    cookies
+}
+
+/// The last cookie named `name` in the raw Set-Cookie (or Set-Cookie2) headers `raw`
+pub(crate) fn find_raw_cookie<'r>(raw: &'r [String], name: &str) -> Option<&'r str> {
+    raw.iter()
+        .flat_map(|line| split_cookies(line))
+        .rfind(|cookie| cookie.split('=').next().map(str::trim) == Some(name))
+}
+
+/// `value`, trimmed, without any surrounding double quotes
+pub(crate) fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+/// `cookie` in RFC 6265 form: obsolete attributes dropped, `Path`, `Domain`, and `Max-Age`
+/// unquoted, and `Expires` reformatted as an IMF-fixdate (or dropped if it cannot be parsed).
+/// The cookie's value is left as is.
+fn normalize(cookie: &str) -> String {
+    let mut parts = cookie.split(';');
+    let mut normalized = parts.next().unwrap_or("").trim().to_owned();
+    for attribute in parts {
+        let mut attribute = attribute.splitn(2, '=');
+        let name = attribute.next().unwrap_or("").trim();
+        let value = attribute.next();
+        let lowercase = name.to_lowercase();
+        let normalized_value = match (lowercase.as_str(), value) {
+            ("", _) => continue,
+            (obsolete, _) if OBSOLETE_ATTRIBUTES.contains(&obsolete) => continue,
+            ("expires", Some(value)) => match cookie_date(unquote(value)) {
+                Some(expires) => Some(fixdate(expires)),
+                None => {
+                    debug!("dropping unparsable Expires {}", value);
+                    continue;
+                }
+            },
+            ("path", Some(value)) | ("domain", Some(value)) | ("max-age", Some(value)) => {
+                Some(unquote(value).to_owned())
+            }
+            (_, value) => value.map(|v| v.trim().to_owned()),
+        };
+        normalized.push_str("; ");
+        normalized.push_str(name);
+        if let Some(value) = normalized_value {
+            normalized.push('=');
+            normalized.push_str(&value);
+        }
+    }
+    normalized
+}
+
+/// Parse the raw values of Set-Cookie and Set-Cookie2 headers per `Strictness::Lenient`,
+/// skipping (and logging) any cookies which still fail to parse
+pub(crate) fn parse_lenient(values: &[String]) -> Vec<RawCookie<'static>> {
+    values
+        .iter()
+        .flat_map(|line| split_cookies(line))
+        .map(normalize)
+        .filter_map(|set_cookie| match RawCookie::parse(set_cookie.clone()) {
+            Ok(cookie) => Some(cookie),
+            Err(e) => {
+                debug!("error parsing Set-Cookie {:?}: {:?}", set_cookie, e);
+                None
+            }
+        })
+        .collect()
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Set how strictly the cookies of responses are parsed
+    pub fn with_strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, parse_lenient, split_cookies};
+
+    #[test]
+    fn lenient() {
+        assert_eq!(
+            vec![
+                "a=1; Expires=Wed, 04-Mar-20 05:06:07 GMT",
+                "b=2; Path=/",
+                "c=x,y"
+            ],
+            split_cookies("a=1; Expires=Wed, 04-Mar-20 05:06:07 GMT, b=2; Path=/, c=x,y")
+        );
+        assert_eq!(
+            "a=1; Path=/acme; Expires=Wed, 04 Mar 2020 05:06:07 GMT; Secure",
+            normalize(
+                "a=1; Version=\"1\"; Path=\"/acme\"; Expires=Wednesday, 04-Mar-20 05:06:07 GMT; \
+                 Discard; Secure"
+            )
+        );
+        assert_eq!("a=1", normalize("a=1; Expires=someday; Comment=\"hi\""));
+
+        let cookies = parse_lenient(&[
+            "a=1; Path=/, b=2; Max-Age=\"60\"".to_owned(),
+            "c=3; Version=\"1\"; Port=\"80,8080\"".to_owned(),
+        ]);
+        let names = cookies.iter().map(|c| c.name()).collect::<Vec<_>>();
+        assert_eq!(vec!["a", "b", "c"], names);
+        assert_eq!(Some("/"), cookies[0].path());
+    }
+}
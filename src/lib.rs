@@ -1,8 +1,236 @@
 type Error = Box<dyn std::error::Error + Send + Sync>;
 #[macro_use]
 mod session;
+mod accounts;
+#[cfg(feature = "attohttpc-session")]
+mod attohttpc_session;
+mod attribution;
+mod auth;
+mod backoff;
+#[cfg(feature = "browser-import")]
+mod browser_import;
+mod cache;
+#[cfg(feature = "testing")]
+mod cassette;
+mod change;
+#[cfg(feature = "test-util")]
+mod chaos;
+mod clear_site_data;
+mod connections;
+#[cfg(feature = "consent")]
+mod consent;
+mod content;
+mod cookie_policy;
+#[cfg(feature = "credentials")]
+mod credentials;
+mod delta;
+mod domains;
+mod duplicates;
+mod environment;
+mod error;
+mod events;
+mod expiry;
+mod extensions;
+pub mod fallback;
+mod fetch_metadata;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fingerprint;
+mod growth;
+mod har;
+mod hsts;
+#[cfg(feature = "hyper-session")]
+mod hyper_session;
+mod inspect;
+#[cfg(feature = "isahc-session")]
+mod isahc_session;
+#[cfg(feature = "persistence")]
+pub mod jar;
+#[cfg(feature = "persistence")]
+pub mod journal;
+/// Without the `persistence` feature, sessions cannot be given a journal
+#[cfg(not(feature = "persistence"))]
+mod journal {
+    pub(crate) enum Journal {}
+
+    impl Journal {
+        pub(crate) fn record(&mut self, _: &cookie::Cookie<'static>, _: &url::Url) {
+            match *self {}
+        }
+    }
+}
+mod lenient;
+mod limits;
+mod login;
+mod lru_store;
+mod memo;
+mod merge;
+mod metrics;
+mod middleware;
+mod opts;
+mod origin;
+mod owned;
+mod paginate;
+#[cfg(feature = "persistence")]
+mod persistent;
+mod pin;
+mod policy_log;
+mod poll;
+mod preflight;
+pub mod prelude;
+#[cfg(feature = "profile")]
+mod profile;
+mod public_suffix;
+mod quoting;
+mod rate_limit;
+mod redirect;
+#[cfg(feature = "redis-store")]
+mod redis_store;
+#[cfg(feature = "reqwest-session")]
 mod reqwest_session;
+mod retry;
+mod robots;
+#[cfg(feature = "scenario")]
+mod scenario;
+#[cfg(feature = "schema")]
+pub mod schema;
+mod sent;
+mod sharded_store;
+mod shutdown;
+#[cfg(feature = "sitemap")]
+mod sitemap;
+mod skew;
+mod spec;
+#[cfg(feature = "spool")]
+mod spool;
+#[cfg(feature = "sqlite-store")]
+mod sqlite_store;
+#[cfg(feature = "reqwest-session")]
+mod sse;
+mod status;
+mod storage;
+mod subresource;
+#[cfg(feature = "surf-session")]
+mod surf_session;
+mod tenant;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod trace;
+mod transaction;
+mod transfer;
+#[cfg(feature = "ureq-session")]
+mod ureq_session;
 mod utils;
+#[cfg(feature = "wasm-session")]
+mod wasm_session;
+#[cfg(feature = "websocket")]
+mod websocket;
+pub use crate::accounts::AccountSession;
+#[cfg(feature = "attohttpc-session")]
+pub use crate::attohttpc_session::{
+    AttohttpcRequest, AttohttpcResponse, AttohttpcSession, AttohttpcSessionError,
+};
+pub use crate::attribution::{CrossSiteRedirect, UnknownFinalUrl};
+pub use crate::auth::{AuthManager, Credential};
+pub use crate::backoff::HostBackoff;
+#[cfg(feature = "browser-import")]
+pub use crate::browser_import::ImportReport;
+pub use crate::cache::{CacheStatus, CachedResponse, MemoryResponseCache, ResponseCache};
+pub use crate::change::{CookieChange, CookieChangeKind};
+#[cfg(feature = "test-util")]
+pub use crate::chaos::{ChaosClient, ChaosError, ChaosResponse};
+pub use crate::clear_site_data::ClearSiteData;
+pub use crate::connections::{ConnectionStats, SessionStats};
+#[cfg(feature = "consent")]
+pub use crate::consent::{ConsentPost, ConsentRecipe, ConsentRecipes};
+pub use crate::content::{BodyChunks, ContentError, ContentResponse};
+pub use crate::cookie_policy::{CookiePolicy, RejectedCookie};
+#[cfg(feature = "credentials")]
+pub use crate::credentials::{CredentialBundle, CredentialError, CredentialRecipes};
+pub use crate::delta::CookieDelta;
+pub use crate::domains::DomainMap;
+pub use crate::duplicates::DuplicateSetCookies;
+pub use crate::environment::{Environment, UnknownEnvironment};
+pub use crate::error::SessionError;
+pub use crate::events::{RejectionReason, StoreEvent, StoreEventSink};
+pub use crate::expiry::Expiry;
+pub use crate::fetch_metadata::{FetchDest, FetchMode};
+pub use crate::growth::{GrowthAlert, GrowthLimit, GrowthLimits, SiteGrowth};
+pub use crate::har::HarEntry;
+#[cfg(feature = "hyper-session")]
+pub use crate::hyper_session::{
+    HyperClient, HyperRequest, HyperResponse, HyperSession, HyperSessionError,
+};
+#[cfg(feature = "isahc-session")]
+pub use crate::isahc_session::{IsahcRequest, IsahcResponse, IsahcSession, IsahcSessionError};
+pub use crate::lenient::Strictness;
+pub use crate::limits::CookieLimits;
+pub use crate::login::{default_jar_path, LoginError};
+pub use crate::lru_store::LruCookieStore;
+pub use crate::memo::PrimingCache;
+pub use crate::merge::MergePolicy;
+pub use crate::metrics::SessionMetrics;
+pub use crate::middleware::{MiddlewareClient, SessionMiddleware};
+pub use crate::opts::RequestOpts;
+pub use crate::owned::SessionResponseOwned;
+pub use crate::paginate::{HeaderResponse, Pages, PaginationRule};
+#[cfg(feature = "persistence")]
+pub use crate::persistent::{JarFormat, JsonFormat, PersistentSession, PersistentStore, SavePolicy};
+pub use crate::policy_log::{PolicyAction, PolicyDecision, PolicyRule};
+pub use crate::poll::Poll;
+pub use crate::preflight::{CheckOutcome, Preflight, PreflightReport};
+#[cfg(feature = "profile")]
+pub use crate::profile::{
+    CookiePolicyProfile, PinnedCookieProfile, ProxyCredentials, RetryProfile, SessionProfile,
+    TlsProfile,
+};
+pub use crate::quoting::ValueQuoting;
+pub use crate::rate_limit::RateLimiter;
+pub use crate::redirect::RedirectMode;
+#[cfg(feature = "redis-store")]
+pub use crate::redis_store::RedisCookieStore;
+#[cfg(feature = "reqwest-session")]
 pub use crate::reqwest_session::{ReqwestSession, ReqwestSessionError};
-pub use crate::session::{Session, SessionClient, SessionRequest, SessionResponse};
+pub use crate::retry::RetryPolicy;
+pub use crate::robots::{RobotsError, RobotsPolicy};
+#[cfg(feature = "scenario")]
+pub use crate::scenario::{Assertion, Extractor, Scenario, ScenarioError, ScenarioStep};
+pub use crate::session::{
+    ClassifySendError, SendErrorKind, Session, SessionClient, SessionRequest, SessionResponse,
+};
+pub use crate::sharded_store::ShardedCookieStore;
+pub use crate::shutdown::ShutdownReport;
+#[cfg(feature = "sitemap")]
+pub use crate::sitemap::{SitemapEntries, SitemapEntry, SitemapError};
+pub use crate::spec::{RequestSpec, SpecError, SpecMethod, SpecRequest, StatusResponse};
+#[cfg(feature = "spool")]
+pub use crate::spool::{BodyHandle, SpoolPolicy};
+#[cfg(feature = "sqlite-store")]
+pub use crate::sqlite_store::SqliteCookieStore;
+#[cfg(feature = "reqwest-session")]
+pub use crate::sse::{EventSource, ServerSentEvent};
+pub use crate::status::{EnsureStatus, StatusError};
+pub use crate::storage::CookieStorage;
+pub use crate::subresource::SubresourceFetch;
+#[cfg(feature = "surf-session")]
+pub use crate::surf_session::{SurfRequest, SurfResponse, SurfSession, SurfSessionError};
+pub use crate::tenant::{InvalidTenantId, TenantId};
+#[cfg(feature = "persistence")]
+pub use crate::tenant::TenantSessions;
+pub use crate::transaction::TransactionError;
+pub use crate::transfer::TransferStats;
+#[cfg(feature = "ureq-session")]
+pub use crate::ureq_session::{UreqRequest, UreqResponse, UreqSession, UreqSessionError};
+#[cfg(feature = "wasm-session")]
+pub use crate::wasm_session::{
+    WasmClient, WasmRequest, WasmResponse, WasmSession, WasmSessionError,
+};
+#[cfg(feature = "websocket")]
+pub use crate::websocket::WebSocketSessionError;
 pub use cookie_store::CookieError;
+pub use http::Method;
+
+// the crates whose types appear in the public API, for version-matched use downstream
+pub use cookie;
+pub use cookie_store;
+pub use url;
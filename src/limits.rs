@@ -0,0 +1,289 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use cookie_store::Cookie;
+use log::debug;
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+/// Limits on the cookies a `Session` stores from responses, so that long-running sessions (such
+/// as crawls) do not grow their store unboundedly. Cookies too large are not stored (and are
+/// reported as `RejectionReason::TooLarge`); when a limit on the number of cookies is exceeded,
+/// expired cookies are removed first, then those least recently stored or sent, as browsers do
+/// (and are reported as `StoreEvent::Evicted`). By default there are no limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "persistence", serde(default))]
+pub struct CookieLimits {
+    /// The most cookies stored in all
+    pub max_cookies: Option<usize>,
+    /// The most cookies stored for any one domain
+    pub max_cookies_per_domain: Option<usize>,
+    /// The most bytes of the name and value of a cookie
+    pub max_cookie_bytes: Option<usize>,
+}
+
+impl CookieLimits {
+    /// No limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The limits of common browsers: 3000 cookies, 180 per domain, and 4096 bytes per cookie
+    pub fn browser() -> Self {
+        CookieLimits {
+            max_cookies: Some(3000),
+            max_cookies_per_domain: Some(180),
+            max_cookie_bytes: Some(4096),
+        }
+    }
+
+    pub fn with_max_cookies(mut self, max: usize) -> Self {
+        self.max_cookies = Some(max);
+        self
+    }
+
+    pub fn with_max_cookies_per_domain(mut self, max: usize) -> Self {
+        self.max_cookies_per_domain = Some(max);
+        self
+    }
+
+    pub fn with_max_cookie_bytes(mut self, max: usize) -> Self {
+        self.max_cookie_bytes = Some(max);
+        self
+    }
+}
+
+/// The domain, path and name of a cookie of the store
+type CookieKey = (String, String, String);
+
+fn key(cookie: &Cookie<'_>) -> CookieKey {
+    (
+        String::from(&cookie.domain),
+        String::from(&cookie.path),
+        cookie.name().to_owned(),
+    )
+}
+
+/// The `CookieLimits` of a `Session`, and when each cookie of its store was last used
+#[derive(Debug, Default)]
+pub(crate) struct CookieLimiter {
+    limits: CookieLimits,
+    tick: u64,
+    used: HashMap<CookieKey, u64>,
+}
+
+impl CookieLimiter {
+    /// Whether the number of cookies stored is limited, so that their use must be tracked
+    pub(crate) fn limits_count(&self) -> bool {
+        self.limits.max_cookies.is_some() || self.limits.max_cookies_per_domain.is_some()
+    }
+
+    /// `cookies` split into those within the size limit, and those too large to store
+    pub(crate) fn filter_size(
+        &self,
+        cookies: Vec<RawCookie<'static>>,
+    ) -> (Vec<RawCookie<'static>>, Vec<RawCookie<'static>>) {
+        match self.limits.max_cookie_bytes {
+            Some(max) => cookies
+                .into_iter()
+                .partition(|c| c.name().len() + c.value().len() <= max),
+            None => (cookies, vec![]),
+        }
+    }
+
+    /// Mark `cookie` as most recently used
+    pub(crate) fn touch(&mut self, cookie: &Cookie<'_>) {
+        self.tick += 1;
+        self.used.insert(key(cookie), self.tick);
+    }
+
+    /// Stop tracking the use of `cookie`, removed from the store
+    pub(crate) fn forget(&mut self, cookie: &Cookie<'_>) {
+        self.used.remove(&key(cookie));
+    }
+
+    /// Mark the cookies of `store` sent with a request to `url`, as `sent` (from its
+    /// `get_request_cookies`), as most recently used
+    pub(crate) fn touch_sent<S: CookieStorage>(
+        &mut self,
+        store: &S,
+        url: &Url,
+        sent: &[RawCookie<'static>],
+    ) {
+        if !self.limits_count() || sent.is_empty() {
+            return;
+        }
+        for cookie in store.iter_unexpired() {
+            if cookie.matches(url) && sent.contains(&*cookie) {
+                self.touch(&cookie);
+            }
+        }
+    }
+
+    /// Remove cookies from `store` until it is within the limits, having stored a cookie for
+    /// `domain`: expired cookies first, then the least recently used of `domain`, then the least
+    /// recently used of all. Returns the unexpired cookies removed.
+    pub(crate) fn evict<S: CookieStorage>(
+        &mut self,
+        store: &mut S,
+        domain: &str,
+    ) -> Vec<Cookie<'static>> {
+        if !self.limits_count() {
+            return vec![];
+        }
+        let cookies = store.iter_unexpired().collect::<Vec<_>>();
+        // cookies removed or expired since they were used are no longer tracked
+        let stored = cookies.iter().map(key).collect::<HashSet<_>>();
+        self.used.retain(|k, _| stored.contains(k));
+        let in_domain = cookies
+            .iter()
+            .filter(|c| String::from(&c.domain) == domain)
+            .count();
+        let over =
+            |count: usize, max: Option<usize>| max.map_or(0, |max| count.saturating_sub(max));
+        let over_domain = over(in_domain, self.limits.max_cookies_per_domain);
+        let over_total = over(cookies.len(), self.limits.max_cookies);
+        if over_domain == 0 && over_total == 0 {
+            return vec![];
+        }
+        store.remove_expired();
+        let used = &self.used;
+        let last_used = |c: &Cookie<'_>| used.get(&key(c)).copied().unwrap_or(0);
+        let mut by_use = cookies;
+        by_use.sort_by_key(|c| last_used(c));
+        let mut victims = by_use
+            .iter()
+            .filter(|c| String::from(&c.domain) == domain)
+            .take(over_domain)
+            .map(key)
+            .collect::<Vec<_>>();
+        let remaining = over_total.saturating_sub(victims.len());
+        let others = by_use
+            .iter()
+            .map(key)
+            .filter(|k| !victims.contains(k))
+            .take(remaining)
+            .collect::<Vec<_>>();
+        victims.extend(others);
+        let evicted = victims
+            .into_iter()
+            .filter_map(|(domain, path, name)| store.remove(&domain, &path, &name))
+            .collect::<Vec<_>>();
+        store.remove_expired();
+        for cookie in &evicted {
+            debug!(
+                "evicting {} for {}",
+                cookie.name(),
+                String::from(&cookie.domain)
+            );
+            self.forget(cookie);
+        }
+        evicted
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Limit the cookies the session stores from responses to `limits`
+    pub fn with_cookie_limits(mut self, limits: CookieLimits) -> Self {
+        self.limiter.limits = limits;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn limits() {
+        use super::CookieLimits;
+        use crate::events::{RejectionReason, StoreEvent};
+        use crate::ReqwestSession;
+        use cookie::Cookie as RawCookie;
+        use std::sync::{Arc, Mutex};
+        use url::Url;
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let recorded = events.clone();
+        let limits = CookieLimits::new()
+            .with_max_cookies(3)
+            .with_max_cookies_per_domain(2)
+            .with_max_cookie_bytes(16);
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new())
+            .with_cookie_limits(limits)
+            .with_event_sink(move |event: &StoreEvent<'_>| {
+                let event = match event {
+                    StoreEvent::Evicted { cookie } => format!("evicted {}", cookie.name()),
+                    StoreEvent::Rejected {
+                        cookie,
+                        reason: RejectionReason::TooLarge,
+                        ..
+                    } => format!("too large {}", cookie.name()),
+                    _ => return,
+                };
+                recorded.lock().unwrap().push(event);
+            });
+        let a = Url::parse("https://a.example.com/").unwrap();
+        let b = Url::parse("https://b.example.com/").unwrap();
+        let set_cookie = |s: &str| vec![RawCookie::parse(s.to_owned()).unwrap()];
+        session.store_response_cookies(set_cookie("big=0123456789abcdef"), &a);
+        session.store_response_cookies(set_cookie("a1=1"), &a);
+        session.store_response_cookies(set_cookie("a2=1"), &a);
+        session
+            .limiter
+            .touch_sent(&session.store, &a, &set_cookie("a1=1"));
+        session.store_response_cookies(set_cookie("a3=1"), &a);
+        session.store_response_cookies(set_cookie("b1=1"), &b);
+        session.store_response_cookies(set_cookie("b2=1"), &b);
+        assert_eq!(
+            vec!["too large big", "evicted a2", "evicted a1"],
+            *events.lock().unwrap()
+        );
+        let mut names = session
+            .store
+            .iter_unexpired()
+            .map(|c| c.name().to_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(vec!["a3", "b1", "b2"], names);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn sent_by_account() {
+        use super::CookieLimits;
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let client = MockClient::new()
+            .with_response(
+                MockResponse::new(200)
+                    .with_set_cookie("a1=1")
+                    .with_set_cookie("a2=1; Path=/other"),
+            )
+            .with_response(MockResponse::new(200).with_set_cookie("a3=1"));
+        let limits = CookieLimits::new().with_max_cookies_per_domain(2);
+        let mut session = Session::new(client)
+            .with_cookie_limits(limits)
+            .with_accounts();
+        let url = "https://example.com/";
+        session.as_account("alice").get(url).unwrap();
+        // sends (and so uses) only alice~a1, leaving alice~a2 to be evicted for alice~a3
+        session.as_account("alice").get(url).unwrap();
+        let mut names = session
+            .store
+            .iter_unexpired()
+            .map(|c| c.name().to_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(vec!["alice~a1", "alice~a3"], names);
+
+        assert_eq!(2, session.limiter.used.len());
+        session
+            .remove_cookie("example.com", "/", "alice~a1")
+            .unwrap();
+        assert_eq!(1, session.limiter.used.len());
+    }
+}
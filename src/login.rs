@@ -0,0 +1,182 @@
+//! Helpers for the common "persistent logged-in command-line tool": a jar in the user's config
+//! directory, loaded when the tool starts, logged in to only when it holds no login, and saved
+//! when the tool exits
+#[cfg(feature = "persistence")]
+use crate::error::SessionError;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use log::debug;
+#[cfg(feature = "persistence")]
+use log::warn;
+use std::ffi::OsString;
+#[cfg(feature = "persistence")]
+use std::fs::File;
+#[cfg(feature = "persistence")]
+use std::io::BufReader;
+use std::path::PathBuf;
+use url::Url;
+
+/// The user's config directory, per `var` looking up environment variables: `%APPDATA%` on
+/// Windows, `~/Library/Application Support` on macOS, and `$XDG_CONFIG_HOME` (or `~/.config`)
+/// elsewhere
+fn config_dir<F: Fn(&str) -> Option<OsString>>(var: F) -> Option<PathBuf> {
+    let absolute = |name: &str| {
+        var(name)
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+    };
+    if cfg!(windows) {
+        absolute("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        absolute("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        absolute("XDG_CONFIG_HOME").or_else(|| absolute("HOME").map(|home| home.join(".config")))
+    }
+}
+
+/// The path of the jar of the tool `app`, as `cookies.json` in the directory `app` of the user's
+/// config directory (`%APPDATA%` on Windows, `~/Library/Application Support` on macOS, and
+/// `$XDG_CONFIG_HOME` or `~/.config` elsewhere), or `None` if the config directory cannot be
+/// determined. The directory need not exist; `Session::shutdown` creates it.
+pub fn default_jar_path(app: &str) -> Option<PathBuf> {
+    config_dir(|name| std::env::var_os(name)).map(|dir| dir.join(app).join("cookies.json"))
+}
+
+/// The login given to `Session::ensure_login` did not store the cookie carrying the login
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginError {
+    pub url: Url,
+    pub cookie: String,
+}
+
+impl std::fmt::Display for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "logging in did not store cookie {} for {}",
+            self.cookie, self.url
+        )
+    }
+}
+
+impl std::error::Error for LoginError {}
+
+#[cfg(feature = "persistence")]
+impl<C: SessionClient> Session<C> {
+    /// Load a session from the jar at `path` (e.g. from `default_jar_path`) if there is one, or
+    /// else start with an empty store, and save it back to `path` on `shutdown` (see
+    /// `with_jar`). A damaged jar is salvaged: cookies which cannot be loaded are logged and
+    /// dropped, rather than failing the tool on every run until the jar is deleted.
+    pub fn open_jar<P: Into<PathBuf>>(client: C, path: P) -> Result<Session<C>, SessionError> {
+        let path = path.into();
+        let session = if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            let (session, errors) = Self::load_json_partial(client, reader)?;
+            for e in errors {
+                warn!("dropping cookie of jar {}: {}", path.display(), e);
+            }
+            session
+        } else {
+            debug!("no jar at {}, starting empty", path.display());
+            Self::new(client)
+        };
+        Ok(session.with_jar(path))
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Whether the store holds a cookie named `cookie` to send to `url`
+    fn holds_cookie(&self, url: &Url, cookie: &str) -> bool {
+        self.store
+            .get_request_cookies(url)
+            .iter()
+            .any(|c| c.name() == cookie)
+    }
+
+    /// Log in with `login`, unless the store already holds the cookie named `cookie` carrying a
+    /// login to `url` (e.g. as loaded with `open_jar`), returning whether `login` was called.
+    /// Fails with a `LoginError` if `login` succeeds without storing the cookie.
+    pub fn ensure_login<F>(
+        &mut self,
+        url: &Url,
+        cookie: &str,
+        login: F,
+    ) -> Result<bool, crate::Error>
+    where
+        F: FnOnce(&mut Self) -> Result<(), crate::Error>,
+    {
+        if self.holds_cookie(url, cookie) {
+            debug!("already logged in to {}", url);
+            return Ok(false);
+        }
+        login(self)?;
+        if !self.holds_cookie(url, cookie) {
+            return Err(LoginError {
+                url: url.clone(),
+                cookie: cookie.to_owned(),
+            }
+            .into());
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::config_dir;
+    use std::path::PathBuf;
+
+    #[test]
+    fn config_dir_of_env() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.into())
+            }
+        };
+        if cfg!(windows) || cfg!(target_os = "macos") {
+            return;
+        }
+        assert_eq!(
+            Some(PathBuf::from("/home/u/.config")),
+            config_dir(env(&[("HOME", "/home/u")]))
+        );
+        assert_eq!(
+            Some(PathBuf::from("/xdg")),
+            config_dir(env(&[("HOME", "/home/u"), ("XDG_CONFIG_HOME", "/xdg")]))
+        );
+        assert_eq!(None, config_dir(env(&[("HOME", "relative")])));
+    }
+
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn ensure_login() {
+        use super::LoginError;
+        use crate::ReqwestSession;
+        use url::Url;
+
+        let url = Url::parse("https://www.example.com/account").unwrap();
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new());
+        let logged_in = session
+            .ensure_login(&url, "sid", |session| {
+                session.store.parse("sid=1; Path=/", &url)?;
+                Ok(())
+            })
+            .unwrap();
+        assert!(logged_in);
+        let logged_in = session
+            .ensure_login(&url, "sid", |_| panic!("logged in again"))
+            .unwrap();
+        assert!(!logged_in);
+
+        let e = session.ensure_login(&url, "token", |_| Ok(())).unwrap_err();
+        assert_eq!(
+            Some(&LoginError {
+                url: url.clone(),
+                cookie: "token".to_owned(),
+            }),
+            e.downcast_ref()
+        );
+    }
+}
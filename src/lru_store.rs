@@ -0,0 +1,244 @@
+#[cfg(feature = "persistence")]
+use crate::error::SessionError;
+use crate::storage::CookieStorage;
+use crate::utils::request_domains;
+use cookie::Cookie as RawCookie;
+use cookie_store::{Cookie, CookieError, CookieStore};
+use log::debug;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "persistence")]
+use std::io::{BufRead, Write};
+use url::Url;
+
+/// A bounded, in-memory `CookieStorage` for high-volume, throwaway sessions such as crawls.
+/// Cookies are sharded by domain; once more than `capacity` cookies are held, the shards of the
+/// least recently used domains (by request or received cookie) are evicted whole. Lookups only
+/// consult the shards for the request's domain and its parents, and do not lock or update
+/// recency. The store cannot be saved or loaded.
+#[derive(Debug)]
+pub struct LruCookieStore {
+    capacity: usize,
+    len: usize,
+    tick: u64,
+    /// Each domain's cookies, and when the domain was last used
+    shards: HashMap<String, (u64, CookieStore)>,
+    /// Domains by when they were last used
+    recency: BTreeMap<u64, String>,
+}
+
+impl LruCookieStore {
+    /// Create an empty store holding at most `capacity` cookies
+    pub fn new(capacity: usize) -> Self {
+        LruCookieStore {
+            capacity,
+            len: 0,
+            tick: 0,
+            shards: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    /// The number of cookies held
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the store holds no cookies
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Mark `domain` as most recently used, if it has a shard
+    fn touch(&mut self, domain: &str) {
+        if let Some((used, _)) = self.shards.get_mut(domain) {
+            self.recency.remove(used);
+            self.tick += 1;
+            *used = self.tick;
+            self.recency.insert(self.tick, domain.to_owned());
+        }
+    }
+
+    /// Evict the least recently used shards (other than `keep`) until within capacity
+    fn evict(&mut self, keep: &str) {
+        while self.len > self.capacity {
+            let oldest = self
+                .recency
+                .values()
+                .find(|domain| *domain != keep)
+                .cloned();
+            let domain = match oldest {
+                Some(domain) => domain,
+                None => break,
+            };
+            if let Some((used, shard)) = self.shards.remove(&domain) {
+                self.recency.remove(&used);
+                let evicted = shard.iter_any().count();
+                debug!("evicting {} cookies for {}", evicted, domain);
+                self.len -= evicted;
+            }
+        }
+    }
+}
+
+impl CookieStorage for LruCookieStore {
+    fn refresh(&mut self, url: &Url) {
+        for domain in request_domains(url) {
+            self.touch(&domain);
+        }
+    }
+
+    fn get_request_cookies(&self, url: &Url) -> Vec<RawCookie<'static>> {
+        request_domains(url)
+            .into_iter()
+            .filter_map(|domain| self.shards.get(&domain))
+            .flat_map(|(_, shard)| shard.get_request_cookies(url).cloned())
+            .collect()
+    }
+
+    fn insert_raw(&mut self, cookie: &RawCookie<'static>, url: &Url) -> Result<(), CookieError> {
+        let domain = String::from(&Cookie::try_from_raw_cookie(cookie, url)?.domain);
+        if !self.shards.contains_key(&domain) {
+            self.shards
+                .insert(domain.clone(), (0, CookieStore::default()));
+        }
+        let shard = &mut self.shards.get_mut(&domain).expect("shard inserted").1;
+        let before = shard.iter_any().count();
+        let result = shard.insert_raw(cookie, url).map(|_| ());
+        // expiring a cookie retains it in the store; purge it, so that it does not count
+        let expired = shard
+            .iter_any()
+            .filter(|c| c.is_expired())
+            .map(|c| (String::from(&c.path), c.name().to_owned()))
+            .collect::<Vec<_>>();
+        for (path, name) in expired {
+            shard.remove(&domain, &path, &name);
+        }
+        let after = shard.iter_any().count();
+        self.len = self.len + after - before;
+        if after == 0 {
+            if let Some((used, _)) = self.shards.remove(&domain) {
+                self.recency.remove(&used);
+            }
+        } else {
+            self.touch(&domain);
+            self.evict(&domain);
+        }
+        result
+    }
+
+    fn iter_any(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_> {
+        Box::new(
+            self.shards
+                .values()
+                .flat_map(|(_, shard)| shard.iter_any().cloned()),
+        )
+    }
+
+    fn remove(&mut self, domain: &str, path: &str, name: &str) -> Option<Cookie<'static>> {
+        let shard = &mut self.shards.get_mut(domain)?.1;
+        let removed = shard.remove(domain, path, name)?;
+        let empty = shard.iter_any().next().is_none();
+        self.len -= 1;
+        if empty {
+            if let Some((used, _)) = self.shards.remove(domain) {
+                self.recency.remove(&used);
+            }
+        }
+        Some(removed).filter(|c| !c.is_expired())
+    }
+
+    fn remove_expired(&mut self) -> usize {
+        let expired = self
+            .iter_any()
+            .filter(|c| c.is_expired())
+            .map(|c| (String::from(&c.domain), String::from(&c.path), c.name().to_owned()))
+            .collect::<Vec<_>>();
+        for (domain, path, name) in &expired {
+            self.remove(domain, path, name);
+        }
+        expired.len()
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save<W, E, F>(&self, _: &mut W, _: F) -> Result<(), SessionError>
+    where
+        W: Write,
+        F: Fn(&Cookie<'_>) -> Result<String, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Err("an LruCookieStore cannot be saved".into())
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save_json<W: Write>(&self, _: &mut W) -> Result<(), SessionError> {
+        Err("an LruCookieStore cannot be saved".into())
+    }
+
+    #[cfg(feature = "persistence")]
+    fn load<R, E, F>(_: R, _: F) -> Result<Self, SessionError>
+    where
+        R: BufRead,
+        F: Fn(&str) -> Result<Cookie<'static>, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Err("an LruCookieStore cannot be loaded".into())
+    }
+
+    #[cfg(feature = "persistence")]
+    fn load_json<R: BufRead>(_: R) -> Result<Self, SessionError> {
+        Err("an LruCookieStore cannot be loaded".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LruCookieStore;
+    use crate::storage::CookieStorage;
+    use cookie::Cookie as RawCookie;
+    use url::Url;
+
+    #[test]
+    fn eviction() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        let set = |store: &mut LruCookieStore, c: &str, u: &str| {
+            store
+                .insert_raw(&RawCookie::parse(c.to_owned()).unwrap(), &url(u))
+                .unwrap()
+        };
+        let sent = |store: &LruCookieStore, u: &str| {
+            let mut sent = store
+                .get_request_cookies(&url(u))
+                .iter()
+                .map(|c| c.name().to_owned())
+                .collect::<Vec<_>>();
+            sent.sort();
+            sent
+        };
+
+        let mut store = LruCookieStore::new(3);
+        set(&mut store, "a=1", "http://a.example.com/");
+        set(
+            &mut store,
+            "parent=1; Domain=example.com",
+            "http://a.example.com/",
+        );
+        set(&mut store, "b=1", "http://b.example.com/");
+        assert_eq!(3, store.len());
+        assert_eq!(vec!["a", "parent"], sent(&store, "http://a.example.com/"));
+
+        // a.example.com (and its parent, example.com) are used, leaving b.example.com as the
+        // least recently used domain when c.example.com sets a cookie
+        store.refresh(&url("http://a.example.com/"));
+        set(&mut store, "c=1", "http://c.example.com/");
+        assert_eq!(3, store.len());
+        assert_eq!(vec!["a", "parent"], sent(&store, "http://a.example.com/"));
+        assert_eq!(vec!["parent"], sent(&store, "http://b.example.com/"));
+
+        // expiring a cookie frees its space
+        set(&mut store, "a=1; Max-Age=0", "http://a.example.com/");
+        assert_eq!(2, store.len());
+        assert_eq!(vec!["parent"], sent(&store, "http://a.example.com/"));
+        #[cfg(feature = "persistence")]
+        assert!(store.save_json(&mut vec![]).is_err());
+    }
+}
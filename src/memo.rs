@@ -0,0 +1,125 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use log::debug;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// A cache of values extracted from idempotent "priming" requests (e.g. the CSRF token from a
+/// login page), for use with `Session::get_memoized_with`. Entries are keyed by the request URL
+/// and the state of the cookies which would be sent to it, so a change in the relevant cookies
+/// (such as logging out) causes the page to be fetched again. Expired entries are pruned as new
+/// ones are added.
+#[derive(Debug)]
+pub struct PrimingCache<T> {
+    ttl: Duration,
+    entries: HashMap<(Url, u64), (Instant, T)>,
+}
+
+impl<T: Clone> PrimingCache<T> {
+    /// Create an empty cache whose entries are considered fresh for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        PrimingCache {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Remove all entries for `url`, regardless of cookie state
+    pub fn invalidate(&mut self, url: &Url) {
+        self.entries.retain(|(u, _), _| u != url);
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn get(&self, key: &(Url, u64)) -> Option<T> {
+        self.entries
+            .get(key)
+            .filter(|(at, _)| at.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Add `value` for `key`, first pruning the entries which are no longer fresh
+    fn insert(&mut self, key: (Url, u64), value: T) {
+        let ttl = self.ttl;
+        self.entries.retain(|_, (at, _)| at.elapsed() < ttl);
+        self.entries.insert(key, (Instant::now(), value));
+    }
+}
+
+/// Hash the name/value pairs of the cookies `store` would send to `url`
+fn cookie_state<S: CookieStorage>(store: &S, url: &Url) -> u64 {
+    let cookies = store.get_request_cookies(url);
+    let mut cookies = cookies
+        .iter()
+        .map(|c| (c.name(), c.value()))
+        .collect::<Vec<_>>();
+    cookies.sort();
+    let mut hasher = DefaultHasher::new();
+    cookies.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// GET `url` as with `get_with`, returning the value `extract`ed from the response, unless
+    /// `cache` holds a fresh value for `url` with the current cookie state, in which case that
+    /// value is returned without sending a request. Useful for auth-priming requests which would
+    /// otherwise be repeated every time a login is retried.
+    pub fn get_memoized_with<U, P, F, T>(
+        &mut self,
+        cache: &mut PrimingCache<T>,
+        url: U,
+        prepare: P,
+        extract: F,
+    ) -> Result<T, <C as SessionClient>::SendError>
+    where
+        U: IntoUrl,
+        P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+        F: FnOnce(<C as SessionClient>::Response) -> T,
+        T: Clone,
+    {
+        let url = self.request_url(url.into_url()?);
+        let key = (url.clone(), cookie_state(&self.store, &url));
+        if let Some(value) = cache.get(&key) {
+            debug!("using memoized response for {}", url);
+            return Ok(value);
+        }
+        let request = self.client.get_request(&url);
+        let value = extract(self.run_request(request, "GET", &url, prepare)?);
+        // key on the cookie state *after* the response, so that an immediate repeat (with any
+        // cookies the priming request set) is a hit
+        let key = (url.clone(), cookie_state(&self.store, &url));
+        cache.insert(key, value.clone());
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrimingCache;
+    use std::time::Duration;
+    use url::Url;
+
+    #[test]
+    fn prune() {
+        let url = Url::parse("https://example.com/login").unwrap();
+        let mut cache = PrimingCache::new(Duration::from_secs(60));
+        cache.insert((url.clone(), 1), "a");
+        cache.insert((url.clone(), 2), "b");
+        assert_eq!(2, cache.entries.len());
+        assert_eq!(Some("a"), cache.get(&(url.clone(), 1)));
+
+        let mut expiring = PrimingCache::new(Duration::from_secs(0));
+        for state in 0..10 {
+            expiring.insert((url.clone(), state), state);
+        }
+        assert_eq!(1, expiring.entries.len());
+        assert_eq!(None, expiring.get(&(url, 9)));
+    }
+}
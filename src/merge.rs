@@ -0,0 +1,148 @@
+use crate::delta::{same, CookieDelta, StoreSnapshot};
+use crate::expiry::Expiry;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::transaction::restoring;
+use cookie_store::Cookie;
+use log::{debug, warn};
+
+/// How `Session::merge_from` reconciles a cookie of another store with the cookie of the same
+/// domain, path, and name in the session's store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// The other store's cookie replaces the session's, e.g. to merge a fresh login into a
+    /// long-lived jar
+    Overwrite,
+    /// The session's cookie is kept; only the cookies the session lacks are added
+    KeepExisting,
+    /// Whichever cookie expires later is kept (a session cookie expiring before any other), the
+    /// session's if they expire together
+    LatestExpiry,
+}
+
+impl MergePolicy {
+    /// Whether `theirs` replaces `ours`
+    fn replaces(self, ours: &Cookie<'_>, theirs: &Cookie<'_>) -> bool {
+        match self {
+            MergePolicy::Overwrite => true,
+            MergePolicy::KeepExisting => false,
+            MergePolicy::LatestExpiry => Expiry::of(theirs).time() > Expiry::of(ours).time(),
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Store the unexpired cookies of `other` (e.g. the store of another session, or of another
+    /// machine), those also in the store being reconciled by `policy`; cookies of the store not
+    /// in `other` are kept. Cookies are stored with their expiry as it is. Returns the changes
+    /// made to the store.
+    pub fn merge_from<O: CookieStorage>(&mut self, other: &O, policy: MergePolicy) -> CookieDelta {
+        let snapshot = StoreSnapshot::take(&self.store);
+        for theirs in other.iter_unexpired() {
+            match snapshot.before(&theirs) {
+                Some(ours) if same(ours, &theirs) || !policy.replaces(ours, &theirs) => continue,
+                _ => {}
+            }
+            let merged = restoring(&theirs).and_then(|(set_cookie, url)| {
+                debug!("merging Set-Cookie '{}'", set_cookie);
+                self.store
+                    .insert_raw(&set_cookie, &url)
+                    .map_err(|e| e.to_string())?;
+                if let Some(ref mut journal) = self.journal {
+                    journal.record(&set_cookie, &url);
+                }
+                Ok(())
+            });
+            if let Err(e) = merged {
+                warn!("unable to merge cookie {}: {}", theirs.name(), e);
+            }
+        }
+        snapshot.delta(&self.store)
+    }
+
+    /// The changes from the unexpired cookies of the store to those of `other`, by domain, path,
+    /// and name: the cookies only `other` has are `added`, those which differ are `updated` (as
+    /// they are in `other`), and those only the store has are `expired`
+    pub fn diff<O: CookieStorage>(&self, other: &O) -> CookieDelta {
+        StoreSnapshot::take(&self.store).diff(&StoreSnapshot::take(other))
+    }
+}
+
+#[cfg(all(test, feature = "reqwest-session"))]
+mod tests {
+    use super::MergePolicy;
+    use crate::expiry::Expiry;
+    use crate::ReqwestSession;
+    use cookie_store::CookieStore;
+    use url::Url;
+
+    #[test]
+    fn merge_from() {
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let session = || {
+            let mut session = ReqwestSession::new(reqwest::blocking::Client::new());
+            for set_cookie in &["kept=1", "id=old; Max-Age=60", "mine=1"] {
+                session.store.parse(set_cookie, &url).unwrap();
+            }
+            session
+        };
+        let mut other = CookieStore::default();
+        for set_cookie in &[
+            "kept=1",
+            "id=new; Max-Age=3600",
+            "theirs=1; Domain=example.com",
+        ] {
+            other.parse(set_cookie, &url).unwrap();
+        }
+
+        let diff = session().diff(&other);
+        assert!(diff.added("theirs").is_some());
+        assert_eq!("new", diff.updated("id").unwrap().value());
+        assert!(diff.expired("mine").is_some());
+        assert_eq!(
+            (1, 1, 1),
+            (diff.added.len(), diff.updated.len(), diff.expired.len())
+        );
+
+        let mut keeping = session();
+        let delta = keeping.merge_from(&other, MergePolicy::KeepExisting);
+        assert!(delta.added("theirs").is_some());
+        assert!(delta.updated.is_empty() && delta.expired.is_empty());
+        let value = |session: &ReqwestSession, domain: &str, name: &str| {
+            session
+                .get_cookie(domain, "/", name)
+                .map(|c| c.value().to_owned())
+        };
+        assert_eq!(
+            Some("old".to_owned()),
+            value(&keeping, "www.example.com", "id")
+        );
+        assert_eq!(
+            Some("1".to_owned()),
+            value(&keeping, "example.com", "theirs")
+        );
+
+        for policy in &[MergePolicy::Overwrite, MergePolicy::LatestExpiry] {
+            let mut merging = session();
+            let delta = merging.merge_from(&other, *policy);
+            assert_eq!("new", delta.updated("id").unwrap().value());
+            assert_eq!(
+                Some("1".to_owned()),
+                value(&merging, "www.example.com", "mine")
+            );
+            let merged = merging.store.iter_unexpired().find(|c| c.name() == "id");
+            let original = other.iter_unexpired().find(|c| c.name() == "id");
+            assert_eq!(Expiry::of(original.unwrap()), Expiry::of(merged.unwrap()));
+            assert!(merging.diff(&other).added.is_empty());
+        }
+
+        let mut latest = session();
+        latest.store.parse("id=latest; Max-Age=7200", &url).unwrap();
+        let delta = latest.merge_from(&other, MergePolicy::LatestExpiry);
+        assert!(delta.updated.is_empty());
+        assert_eq!(
+            Some("latest".to_owned()),
+            value(&latest, "www.example.com", "id")
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use crate::growth::SiteGrowth;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use std::fmt::Write;
+use std::time::Duration;
+use url::Url;
+
+/// Hooks called by a `Session` as it sends requests, reads and sends bodies, and stores cookies,
+/// registered with `Session::with_metrics`, e.g. to feed Prometheus or statsd from a long-running
+/// crawler, and monitor its error rates and cookie churn. Each hook does nothing unless
+/// implemented. (For a snapshot of the session's own counters, see `metrics_prometheus`.)
+pub trait SessionMetrics {
+    /// A request to `url` is about to be sent (each attempt of a retried request, and each
+    /// redirect followed by the session, is sent as a request)
+    fn request_started(&mut self, _method: &str, _url: &Url) {}
+
+    /// A request to `url` was answered with `status`, `elapsed` after it was started; `status` is
+    /// `None` if the request failed, or the client does not report statuses
+    fn request_finished(
+        &mut self,
+        _method: &str,
+        _url: &Url,
+        _status: Option<u16>,
+        _elapsed: Duration,
+    ) {
+    }
+
+    /// Of the Set-Cookies of a response from `url`, `stored` were stored, and `rejected` were not
+    fn cookies_received(&mut self, _url: &Url, _stored: u64, _rejected: u64) {}
+
+    /// `bytes` of a response body were read by the session (see `TransferStats`)
+    fn bytes_downloaded(&mut self, _bytes: u64) {}
+
+    /// `bytes` of a request body were attached by the session (see `TransferStats`)
+    fn bytes_uploaded(&mut self, _bytes: u64) {}
+}
+
+pub(crate) type MetricsHooks = Vec<Box<dyn SessionMetrics + Send>>;
+
+/// The counts of the requests a `Session` has sent, and the cookies it has stored, for
+/// `Session::metrics_prometheus` (and the `tracing` spans of requests)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Counters {
+    pub(crate) requests: u64,
+    pub(crate) send_errors: u64,
+    pub(crate) redirects: u64,
+    pub(crate) cookies_sent: u64,
+    pub(crate) cookies_stored: u64,
+    pub(crate) cookies_rejected: u64,
+}
+
+/// A label value escaped per the Prometheus text exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Write the metric `name` of type `kind`, with a sample for each of `samples`, labelled by
+/// `label` with its value if given
+fn metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    label: &str,
+    samples: &[(Option<String>, f64)],
+) {
+    let _ = writeln!(out, "# HELP user_agent_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE user_agent_{} {}", name, kind);
+    for (value, sample) in samples {
+        let _ = match value {
+            Some(value) => writeln!(
+                out,
+                "user_agent_{}{{{}=\"{}\"}} {}",
+                name,
+                label,
+                escape(value),
+                sample
+            ),
+            None => writeln!(out, "user_agent_{} {}", name, sample),
+        };
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Add `metrics`, to be called as the session sends requests, transfers bodies, and stores
+    /// cookies
+    pub fn with_metrics<M>(mut self, metrics: M) -> Self
+    where
+        M: SessionMetrics + Send + 'static,
+    {
+        self.metrics_hooks.push(Box::new(metrics));
+        self
+    }
+
+    /// A snapshot of the session's counters and gauges, in the Prometheus text exposition
+    /// format, for serving from a service's own metrics endpoint. The metrics are prefixed
+    /// `user_agent_`; those by site require `with_growth_alerts`, and those by origin are of the
+    /// clock skews seen (see `clock_skews`).
+    pub fn metrics_prometheus(&self) -> String {
+        let mut out = String::new();
+        let counters = self.counters;
+        let transfer = self.transfer_stats();
+        let counter = |out: &mut String, name, help, value: u64| {
+            metric(out, name, "counter", help, "", &[(None, value as f64)]);
+        };
+        counter(
+            &mut out,
+            "requests_total",
+            "Requests sent.",
+            counters.requests,
+        );
+        counter(
+            &mut out,
+            "send_errors_total",
+            "Requests which failed to be sent.",
+            counters.send_errors,
+        );
+        counter(
+            &mut out,
+            "cookies_stored_total",
+            "Set-Cookies stored from responses.",
+            counters.cookies_stored,
+        );
+        counter(
+            &mut out,
+            "cookies_rejected_total",
+            "Set-Cookies from responses which were not stored.",
+            counters.cookies_rejected,
+        );
+        counter(
+            &mut out,
+            "downloaded_bytes_total",
+            "Bytes of response bodies read by the session.",
+            transfer.downloaded,
+        );
+        counter(
+            &mut out,
+            "uploaded_bytes_total",
+            "Bytes of request bodies attached by the session.",
+            transfer.uploaded,
+        );
+
+        let (unexpired, expired) = self.store.iter_any().fold((0, 0), |(u, e), cookie| {
+            if cookie.is_expired() {
+                (u, e + 1)
+            } else {
+                (u + 1, e)
+            }
+        });
+        metric(
+            &mut out,
+            "cookies",
+            "gauge",
+            "Unexpired cookies in the store.",
+            "",
+            &[(None, unexpired as f64)],
+        );
+        metric(
+            &mut out,
+            "expired_cookies",
+            "gauge",
+            "Expired cookies still held by the store.",
+            "",
+            &[(None, expired as f64)],
+        );
+
+        let growth = self.jar_growth();
+        let by_site = |f: fn(&SiteGrowth) -> f64| {
+            growth
+                .iter()
+                .map(|growth| (Some(growth.site.clone()), f(growth)))
+                .collect::<Vec<_>>()
+        };
+        metric(
+            &mut out,
+            "site_cookies",
+            "gauge",
+            "Unexpired cookies in the store, by site.",
+            "site",
+            &by_site(|growth| growth.cookies as f64),
+        );
+        metric(
+            &mut out,
+            "site_cookie_bytes",
+            "gauge",
+            "Bytes of the name=value pairs of the cookies in the store, by site.",
+            "site",
+            &by_site(|growth| growth.bytes as f64),
+        );
+        metric(
+            &mut out,
+            "site_cookies_per_minute",
+            "gauge",
+            "Cookies added to the store per minute, by site.",
+            "site",
+            &by_site(|growth| growth.per_minute),
+        );
+
+        let mut skews = self
+            .clock_skews()
+            .map(|(origin, skew)| (Some(origin), skew as f64))
+            .collect::<Vec<_>>();
+        skews.sort_by(|a, b| a.0.cmp(&b.0));
+        metric(
+            &mut out,
+            "clock_skew_seconds",
+            "gauge",
+            "The skew of servers' clocks from the local clock, by origin.",
+            "origin",
+            &skews,
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn metrics_prometheus() {
+        use crate::ReqwestSession;
+        use url::Url;
+
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new());
+        let url = Url::parse("https://www.example.com/").unwrap();
+        session.store.parse("id=1; Max-Age=3600", &url).unwrap();
+        session.counters.requests = 2;
+        let metrics = session.metrics_prometheus();
+        assert!(metrics.contains("# TYPE user_agent_requests_total counter\n"));
+        assert!(metrics.contains("\nuser_agent_requests_total 2\n"));
+        assert!(metrics.contains("\nuser_agent_cookies 1\n"));
+        assert!(metrics.contains("\nuser_agent_expired_cookies 0\n"));
+        assert!(!metrics.contains("user_agent_site_cookies{"));
+        assert_eq!("a\\\"b\\\\c\\n", super::escape("a\"b\\c\n"));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn session_metrics() {
+        use super::SessionMetrics;
+        use crate::testing::{MockClient, MockResponse};
+        use crate::{SendErrorKind, Session};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+        use url::Url;
+
+        #[derive(Default)]
+        struct Recorded(Arc<Mutex<Vec<String>>>);
+
+        impl SessionMetrics for Recorded {
+            fn request_started(&mut self, method: &str, url: &Url) {
+                let event = format!("started {} {}", method, url);
+                self.0.lock().unwrap().push(event);
+            }
+            fn request_finished(&mut self, _: &str, _: &Url, status: Option<u16>, _: Duration) {
+                let event = format!("finished {:?}", status);
+                self.0.lock().unwrap().push(event);
+            }
+            fn cookies_received(&mut self, _: &Url, stored: u64, rejected: u64) {
+                let event = format!("cookies {} {}", stored, rejected);
+                self.0.lock().unwrap().push(event);
+            }
+            fn bytes_downloaded(&mut self, bytes: u64) {
+                self.0.lock().unwrap().push(format!("downloaded {}", bytes));
+            }
+        }
+
+        let client = MockClient::new()
+            .with_response(
+                MockResponse::new(200)
+                    .with_set_cookie("id=1")
+                    .with_set_cookie("other=1; Domain=example.org")
+                    .with_body("hello"),
+            )
+            .with_error(SendErrorKind::Connect);
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut session = Session::new(client).with_metrics(Recorded(events.clone()));
+        let body = session.get_bytes("https://www.example.com/").unwrap();
+        assert_eq!(b"hello", &body[..]);
+        assert!(session.get("https://www.example.com/").is_err());
+        assert_eq!(
+            vec![
+                "started GET https://www.example.com/",
+                "finished Some(200)",
+                "cookies 1 1",
+                "downloaded 5",
+                "started GET https://www.example.com/",
+                "finished None",
+            ],
+            *events.lock().unwrap()
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use crate::session::SessionClient;
+use http::Method;
+use std::cell::RefCell;
+use url::Url;
+
+/// Hooks into each request sent through a `MiddlewareClient`, e.g. to add auth headers, or to log
+/// or measure requests, without wrapping every call to the session.
+pub trait SessionMiddleware<C: SessionClient> {
+    /// Called with each request to `url` as it is about to be sent: after its cookies and any
+    /// headers of the session are added, and it is prepared by the caller. Returns the request to
+    /// send.
+    fn before_request(&mut self, request: C::Request, _url: &Url) -> C::Request {
+        request
+    }
+
+    /// Called with each response to a request to `url`, before its cookies are stored
+    fn after_response(&mut self, _response: &C::Response, _url: &Url) {}
+}
+
+/// A `SessionClient` wrapping another, which calls `middleware` around each request it sends.
+/// Wrap a `MiddlewareClient` in another to add further middleware, the outermost called first
+/// with each request.
+///
+/// ```
+/// # #[cfg(feature = "reqwest-session")]
+/// # {
+/// use url::Url;
+/// use user_agent::{MiddlewareClient, Session, SessionMiddleware};
+///
+/// struct Auth;
+/// impl SessionMiddleware<reqwest::blocking::Client> for Auth {
+///     fn before_request(
+///         &mut self,
+///         request: reqwest::blocking::RequestBuilder,
+///         _: &Url,
+///     ) -> reqwest::blocking::RequestBuilder {
+///         request.bearer_auth("secret")
+///     }
+/// }
+///
+/// let session = Session::new(MiddlewareClient::new(reqwest::blocking::Client::new(), Auth));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MiddlewareClient<C, M> {
+    inner: C,
+    middleware: RefCell<M>,
+    // requests do not carry their URLs, so that of the last made is passed to the middleware when
+    // one is sent: a session sends each request it makes (and any retries, copied from it) before
+    // making another
+    url: RefCell<Option<Url>>,
+}
+
+impl<C: SessionClient, M: SessionMiddleware<C>> MiddlewareClient<C, M> {
+    /// Wrap `inner`, calling `middleware` around each request it sends
+    pub fn new(inner: C, middleware: M) -> Self {
+        MiddlewareClient {
+            inner,
+            middleware: RefCell::new(middleware),
+            url: RefCell::new(None),
+        }
+    }
+
+    /// The wrapped client
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// The middleware, e.g. to read state it has collected
+    pub fn middleware(&mut self) -> &mut M {
+        self.middleware.get_mut()
+    }
+
+    /// Note `url` as that of the request being made
+    fn made(&self, url: &Url) -> &C {
+        *self.url.borrow_mut() = Some(url.clone());
+        &self.inner
+    }
+}
+
+impl<C: SessionClient, M: SessionMiddleware<C>> SessionClient for MiddlewareClient<C, M> {
+    type Request = C::Request;
+    type Response = C::Response;
+    type SendError = C::SendError;
+
+    fn get_request(&self, url: &Url) -> Self::Request {
+        self.made(url).get_request(url)
+    }
+    fn put_request(&self, url: &Url) -> Self::Request {
+        self.made(url).put_request(url)
+    }
+    fn head_request(&self, url: &Url) -> Self::Request {
+        self.made(url).head_request(url)
+    }
+    fn delete_request(&self, url: &Url) -> Self::Request {
+        self.made(url).delete_request(url)
+    }
+    fn post_request(&self, url: &Url) -> Self::Request {
+        self.made(url).post_request(url)
+    }
+    fn patch_request(&self, url: &Url) -> Self::Request {
+        self.made(url).patch_request(url)
+    }
+    fn options_request(&self, url: &Url) -> Self::Request {
+        self.made(url).options_request(url)
+    }
+    fn request(&self, method: &Method, url: &Url) -> Self::Request {
+        self.made(url).request(method, url)
+    }
+
+    fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let url = self.url.borrow().clone();
+        let url = match url {
+            Some(url) => url,
+            None => return self.inner.send(request),
+        };
+        let request = self.middleware.borrow_mut().before_request(request, &url);
+        let response = self.inner.send(request)?;
+        self.middleware.borrow_mut().after_response(&response, &url);
+        Ok(response)
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::{MiddlewareClient, SessionMiddleware};
+    use crate::testing::{MockClient, MockRequest, MockResponse};
+    use crate::{RetryPolicy, Session, SessionResponse};
+    use std::time::Duration;
+    use url::Url;
+
+    #[derive(Default)]
+    struct Auth(Vec<String>);
+    impl SessionMiddleware<MockClient> for Auth {
+        fn before_request(&mut self, request: MockRequest, _: &Url) -> MockRequest {
+            request.header("authorization", "Bearer t")
+        }
+        fn after_response(&mut self, response: &MockResponse, url: &Url) {
+            self.0.push(format!("{} {:?}", url, response.status_code()));
+        }
+    }
+
+    #[test]
+    fn middleware() {
+        let client = MockClient::new()
+            .with_response(MockResponse::new(503))
+            .with_response(MockResponse::new(200));
+        let policy =
+            RetryPolicy::new().with_backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let mut session =
+            Session::new(MiddlewareClient::new(client, Auth::default())).with_retry_policy(policy);
+        session
+            .get_with("https://www.example.com/", |r| r.header("x-probe", "1"))
+            .unwrap();
+        let requests = session.client.inner().take_requests();
+        assert_eq!(2, requests.len());
+        for request in &requests {
+            assert_eq!(Some("Bearer t"), request.header_value("authorization"));
+            assert_eq!(Some("1"), request.header_value("x-probe"));
+        }
+        assert_eq!(
+            vec![
+                "https://www.example.com/ Some(503)",
+                "https://www.example.com/ Some(200)"
+            ],
+            session.client.middleware().0
+        );
+    }
+}
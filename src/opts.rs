@@ -0,0 +1,156 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use cookie::Cookie as RawCookie;
+use http::Method;
+
+/// Options for a single request sent through a `Session` with `get_with_opts` and the like, e.g.
+/// for a login probe which must not carry a stale session cookie
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestOpts {
+    /// Whether to send the cookies of the store with the request (the default)
+    pub send_cookies: bool,
+    /// Cookies to send with the request only, replacing any of the store with the same name.
+    /// They are not stored, and are not sent with requests to redirect targets followed by the
+    /// session (see `RedirectMode::Session`).
+    pub extra_cookies: Vec<RawCookie<'static>>,
+}
+
+impl Default for RequestOpts {
+    fn default() -> Self {
+        RequestOpts {
+            send_cookies: true,
+            extra_cookies: vec![],
+        }
+    }
+}
+
+impl RequestOpts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Do not send the cookies of the store with the request
+    pub fn without_cookies(mut self) -> Self {
+        self.send_cookies = false;
+        self
+    }
+
+    /// Send `cookie` with the request
+    pub fn with_cookie(mut self, cookie: RawCookie<'static>) -> Self {
+        self.extra_cookies.push(cookie);
+        self
+    }
+
+    /// The cookies to send with a request with these options, given `stored`, those of the store
+    pub(crate) fn outgoing(&self, stored: Vec<RawCookie<'static>>) -> Vec<RawCookie<'static>> {
+        let mut cookies = stored;
+        if !self.send_cookies {
+            cookies.clear();
+        }
+        cookies.retain(|c| !self.extra_cookies.iter().any(|e| e.name() == c.name()));
+        cookies.extend(self.extra_cookies.iter().cloned());
+        cookies
+    }
+
+    /// The options of requests to the redirect targets of a request with these options
+    pub(crate) fn for_redirect(&self) -> Self {
+        RequestOpts {
+            send_cookies: self.send_cookies,
+            extra_cookies: vec![],
+        }
+    }
+}
+
+macro_rules! define_with_opts_fn {
+    ($with_opts_fn: ident, $with_fn: ident) => {
+        /// As the method without `_opts`, with `opts` applying to the request
+        pub fn $with_opts_fn<U, P>(
+            &mut self,
+            url: U,
+            opts: RequestOpts,
+            prepare: P,
+        ) -> ::std::result::Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+        where
+            P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+            U: IntoUrl,
+        {
+            self.request_opts = Some(opts);
+            let result = self.$with_fn(url, prepare);
+            self.request_opts = None;
+            result
+        }
+    };
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    define_with_opts_fn!(get_with_opts, get_with);
+    define_with_opts_fn!(put_with_opts, put_with);
+    define_with_opts_fn!(head_with_opts, head_with);
+    define_with_opts_fn!(delete_with_opts, delete_with);
+    define_with_opts_fn!(post_with_opts, post_with);
+    define_with_opts_fn!(patch_with_opts, patch_with);
+    define_with_opts_fn!(options_with_opts, options_with);
+
+    /// As `request_with`, with `opts` applying to the request
+    pub fn request_with_opts<U, P>(
+        &mut self,
+        method: &Method,
+        url: U,
+        opts: RequestOpts,
+        prepare: P,
+    ) -> Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+    where
+        P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+        U: IntoUrl,
+    {
+        self.request_opts = Some(opts);
+        let result = self.request_with(method, url, prepare);
+        self.request_opts = None;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestOpts;
+    use cookie::Cookie as RawCookie;
+
+    #[test]
+    fn outgoing() {
+        let stored = vec![RawCookie::new("id", "1"), RawCookie::new("theme", "dark")];
+        let names = |cookies: Vec<RawCookie<'static>>| {
+            cookies
+                .iter()
+                .map(|c| format!("{}={}", c.name(), c.value()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["id=1", "theme=dark"],
+            names(RequestOpts::new().outgoing(stored.clone()))
+        );
+        let opts = RequestOpts::new().with_cookie(RawCookie::new("id", "2"));
+        assert_eq!(
+            vec!["theme=dark", "id=2"],
+            names(opts.outgoing(stored.clone()))
+        );
+        let opts = opts.without_cookies();
+        assert_eq!(vec!["id=2"], names(opts.outgoing(stored.clone())));
+        assert_eq!(
+            Vec::<String>::new(),
+            names(opts.for_redirect().outgoing(stored))
+        );
+    }
+
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn invalid_url() {
+        use crate::ReqwestSession;
+
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new());
+        assert!(session
+            .get_with_opts("not a url", RequestOpts::new(), |r| r)
+            .is_err());
+        assert!(session.request_opts.is_none());
+    }
+}
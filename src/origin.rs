@@ -0,0 +1,134 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use log::{debug, warn};
+use std::collections::HashMap;
+use url::{Host, Origin, Url};
+
+/// The origins a `Session` treats as another origin for cookie purposes, each mapped to the URL
+/// of that (canonical) origin
+#[derive(Debug, Default)]
+pub(crate) struct OriginAliases {
+    aliases: HashMap<Origin, Url>,
+    /// Whether `http` URLs of local hosts are treated as `https`
+    pub(crate) dev_mode: bool,
+}
+
+/// Whether `url` is of a loopback address, `localhost` (or a subdomain of it), or a `.local`
+/// (mDNS) host
+fn is_local(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost") || domain.ends_with(".local")
+        }
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    }
+}
+
+impl OriginAliases {
+    /// `url`, with its scheme, host, and port replaced by those of its canonical origin, if its
+    /// origin is an alias, or with its scheme replaced by `https` if it is a local `http` URL in
+    /// dev mode
+    pub(crate) fn canonical(&self, url: &Url) -> Option<Url> {
+        let canonical = match self.aliases.get(&url.origin()) {
+            Some(canonical) => canonical,
+            None if self.dev_mode && url.scheme() == "http" && is_local(url) => {
+                let mut secure = url.clone();
+                secure.set_scheme("https").ok()?;
+                return Some(secure);
+            }
+            None => return None,
+        };
+        let mut mapped = url.clone();
+        mapped.set_scheme(canonical.scheme()).ok()?;
+        mapped.set_host(canonical.host_str()).ok()?;
+        mapped.set_port(canonical.port()).ok()?;
+        debug!("treating {} as {}", url, mapped);
+        Some(mapped)
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Treat the origin (scheme, host, and port) of `alias` as that of `canonical` for cookie
+    /// purposes: requests to `alias`'s origin carry the cookies `canonical`'s origin would, and
+    /// cookies set by responses from it are stored as if set by `canonical`'s origin. E.g. for a
+    /// staging server on `https://example.com:8443` which scopes its cookies to
+    /// `https://example.com`. Only URLs of exactly `alias`'s origin are affected (paths are
+    /// ignored), and the equivalence is one-way: requests to `canonical` are unaffected. An alias
+    /// on `http` of an `https` origin sends that origin's Secure cookies over `http`.
+    ///
+    /// Origins without a scheme, host, and port (e.g. `data:` URLs) cannot be aliased, and are
+    /// ignored with a warning.
+    pub fn with_equivalent_origin(mut self, alias: &Url, canonical: &Url) -> Self {
+        let alias_origin = alias.origin();
+        if !alias_origin.is_tuple() || !canonical.origin().is_tuple() {
+            warn!("unable to treat {} as {}: opaque origin", alias, canonical);
+            return self;
+        }
+        let mut canonical = canonical.clone();
+        canonical.set_path("/");
+        canonical.set_query(None);
+        canonical.set_fragment(None);
+        self.origin_aliases.aliases.insert(alias_origin, canonical);
+        self
+    }
+
+    /// Treat `http` URLs of local hosts (`localhost` and its subdomains, loopback addresses, and
+    /// `.local` hosts) as secure contexts, as browsers do for `localhost`: Secure cookies may be
+    /// set by and sent to them, so that integration tests of Secure cookies can run against a
+    /// local server without TLS. Cookies are still stored per host. For development only; by
+    /// default, Secure cookies are only set and sent over `https`.
+    pub fn with_dev_mode(mut self) -> Self {
+        self.origin_aliases.dev_mode = true;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OriginAliases;
+    use url::Url;
+
+    #[test]
+    fn canonical() {
+        let url = |s| Url::parse(s).unwrap();
+        let mut aliases = OriginAliases::default();
+        aliases.aliases.insert(
+            url("https://example.com:8443/").origin(),
+            url("https://example.com/"),
+        );
+        aliases.aliases.insert(
+            url("http://localhost:3000/").origin(),
+            url("https://app.example.com:444/"),
+        );
+        assert_eq!(
+            Some(url("https://example.com/login?next=/a")),
+            aliases.canonical(&url("https://example.com:8443/login?next=/a"))
+        );
+        assert_eq!(
+            Some(url("https://app.example.com:444/api")),
+            aliases.canonical(&url("http://localhost:3000/api"))
+        );
+        assert_eq!(None, aliases.canonical(&url("http://example.com:8443/")));
+        assert_eq!(None, aliases.canonical(&url("https://example.com/")));
+        assert_eq!(None, aliases.canonical(&url("http://localhost:3001/")));
+
+        aliases.dev_mode = true;
+        for local in &[
+            "http://localhost:3001/a",
+            "http://api.localhost/a",
+            "http://127.0.0.1:8080/a",
+            "http://[::1]/a",
+            "http://devbox.local/a",
+        ] {
+            let secure = aliases.canonical(&url(local)).unwrap();
+            assert_eq!("https", secure.scheme());
+            assert_eq!(url(local).host(), secure.host());
+            assert_eq!(url(local).port(), secure.port());
+        }
+        assert_eq!(None, aliases.canonical(&url("http://www.example.com/")));
+        assert_eq!(None, aliases.canonical(&url("http://10.0.0.1/")));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::content::ContentResponse;
+use crate::paginate::HeaderResponse;
+use crate::session::{Session, SessionClient, SessionResponse};
+use crate::spec::StatusResponse;
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use http::Method;
+use url::Url;
+
+/// A response read whole, from `Session::get_owned` and the like, for callers with no need to
+/// prepare requests or stream bodies
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionResponseOwned {
+    pub status: u16,
+    /// Each header value, with its name, grouped by name (see `HeaderResponse::header_names`)
+    pub headers: Vec<(String, String)>,
+    /// The URL the response was served from, after any redirects, if known
+    pub url: Option<Url>,
+    pub body: Vec<u8>,
+}
+
+impl SessionResponseOwned {
+    /// The first value of the `name` header, if any
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The body as UTF-8 text, if it is
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.body).ok()
+    }
+}
+
+macro_rules! define_owned_fn {
+    ($owned_fn: ident, $send_fn: ident) => {
+        /// As the method without `_owned`, reading the response whole
+        pub fn $owned_fn<U>(
+            &mut self,
+            url: U,
+        ) -> ::std::result::Result<SessionResponseOwned, <C as SessionClient>::SendError>
+        where
+            U: IntoUrl,
+            <C as SessionClient>::Response: ContentResponse + StatusResponse + HeaderResponse,
+            <C as SessionClient>::SendError:
+                From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+        {
+            let response = self.$send_fn(url)?;
+            self.read_owned(response)
+        }
+    };
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    define_owned_fn!(get_owned, get);
+    define_owned_fn!(put_owned, put);
+    define_owned_fn!(head_owned, head);
+    define_owned_fn!(delete_owned, delete);
+    define_owned_fn!(post_owned, post);
+    define_owned_fn!(patch_owned, patch);
+    define_owned_fn!(options_owned, options);
+
+    /// As `request_with` with no preparation, reading the response whole
+    pub fn request_owned<U>(
+        &mut self,
+        method: &Method,
+        url: U,
+    ) -> Result<SessionResponseOwned, <C as SessionClient>::SendError>
+    where
+        U: IntoUrl,
+        <C as SessionClient>::Response: ContentResponse + StatusResponse + HeaderResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let response = self.request_with(method, url, |request| request)?;
+        self.read_owned(response)
+    }
+
+    /// Read `response` whole, its body subject to any `with_download_limit`
+    pub(crate) fn read_owned(
+        &mut self,
+        response: <C as SessionClient>::Response,
+    ) -> Result<SessionResponseOwned, <C as SessionClient>::SendError>
+    where
+        <C as SessionClient>::Response: ContentResponse + StatusResponse + HeaderResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let status = response.status();
+        let headers = response
+            .header_names()
+            .into_iter()
+            .flat_map(|name| {
+                let values = response.header_values(&name);
+                let values = values.into_iter().map(str::to_owned).collect::<Vec<_>>();
+                values.into_iter().map(move |value| (name.clone(), value))
+            })
+            .collect();
+        let url = match response.final_url() {
+            Some(url) => Some(url.clone().into_url()?),
+            None => None,
+        };
+        let body = response.read_body(&mut |n| self.downloaded(n))?;
+        Ok(SessionResponseOwned {
+            status,
+            headers,
+            url,
+            body,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use crate::testing::{MockClient, MockResponse};
+    use crate::Session;
+    use url::Url;
+
+    #[test]
+    fn get_owned() {
+        let response = MockResponse::new(200)
+            .with_header("content-type", "text/plain")
+            .with_set_cookie("id=1")
+            .with_body("hello");
+        let mut session = Session::new(MockClient::new().with_response(response));
+        let owned = session.get_owned("https://www.example.com/").unwrap();
+        assert_eq!(200, owned.status);
+        assert_eq!(Some("text/plain"), owned.header("Content-Type"));
+        assert_eq!(Some("id=1"), owned.header("set-cookie"));
+        assert_eq!(Some("hello"), owned.text());
+        assert_eq!(
+            Some(Url::parse("https://www.example.com/").unwrap()),
+            owned.url
+        );
+        assert!(session.get_cookie("www.example.com", "/", "id").is_some());
+    }
+}
@@ -0,0 +1,275 @@
+use crate::session::{Session, SessionClient, SessionResponse};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use log::debug;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Responses whose headers can be read by name, implemented for each integration's response type
+pub trait HeaderResponse: SessionResponse {
+    /// The values of every `name` header, in order
+    fn header_values(&self, name: &str) -> Vec<&str>;
+    /// The names of the headers present, each once (see `SessionResponseOwned`). By default none
+    /// are reported.
+    fn header_names(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(feature = "reqwest-session")]
+impl HeaderResponse for reqwest::blocking::Response {
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect()
+    }
+
+    fn header_names(&self) -> Vec<String> {
+        self.headers().keys().map(|k| k.as_str().to_owned()).collect()
+    }
+}
+
+/// A function finding the URL of the page after a response
+type NextUrl<R> = Box<dyn FnMut(&R) -> Option<Url>>;
+
+/// How `Session::paginate` finds the URL of the page after a response
+pub enum PaginationRule<R> {
+    /// The target of the response's `Link` header with `rel="next"`
+    LinkHeader,
+    /// The URL returned by the given function, e.g. one built from a cursor header
+    Extractor(NextUrl<R>),
+}
+
+impl<R> PaginationRule<R> {
+    pub fn extractor<F: FnMut(&R) -> Option<Url> + 'static>(f: F) -> Self {
+        PaginationRule::Extractor(Box::new(f))
+    }
+}
+
+impl<R> std::fmt::Debug for PaginationRule<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PaginationRule::LinkHeader => write!(f, "LinkHeader"),
+            PaginationRule::Extractor(_) => write!(f, "Extractor(..)"),
+        }
+    }
+}
+
+/// The target of the first link with relation `next` in the `Link` header value `header`,
+/// resolved against `base`
+fn next_link(header: &str, base: &Url) -> Option<Url> {
+    let mut rest = header;
+    while let Some(start) = rest.find('<') {
+        let end = start + rest[start..].find('>')?;
+        let target = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+        let params = &rest[..rest.find('<').unwrap_or(rest.len())];
+        let is_next = params.split(';').any(|param| {
+            let mut param = param.splitn(2, '=');
+            let name = param.next().unwrap_or("").trim();
+            let value = param.next().unwrap_or("");
+            name.eq_ignore_ascii_case("rel")
+                && value
+                    .trim_matches(|c: char| c == '"' || c == ',' || c.is_whitespace())
+                    .split_whitespace()
+                    .any(|rel| rel.eq_ignore_ascii_case("next"))
+        });
+        if is_next {
+            return base.join(target).ok();
+        }
+    }
+    None
+}
+
+/// An iterator over the pages of a paginated resource, from `Session::paginate`. Each page is
+/// fetched when the iterator is advanced; iteration ends after the last page, after the first
+/// failed request, or on reaching a page already fetched.
+pub struct Pages<'s, C: SessionClient, S: CookieStorage> {
+    session: &'s mut Session<C, S>,
+    rule: PaginationRule<<C as SessionClient>::Response>,
+    next: Option<Result<Url, <C as SessionClient>::SendError>>,
+    visited: HashSet<Url>,
+    interval: Option<Duration>,
+    last: Option<Instant>,
+}
+
+impl<'s, C: SessionClient, S: CookieStorage> Pages<'s, C, S> {
+    /// Wait at least `interval` between the requests for successive pages
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+}
+
+impl<'s, C, S> Iterator for Pages<'s, C, S>
+where
+    C: SessionClient,
+    S: CookieStorage,
+    <C as SessionClient>::Response: HeaderResponse,
+{
+    type Item = Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let url = match self.next.take()? {
+            Ok(url) => url,
+            Err(e) => return Some(Err(e)),
+        };
+        if !self.visited.insert(url.clone()) {
+            debug!("pagination revisited {}, stopping", url);
+            return None;
+        }
+        if let (Some(interval), Some(last)) = (self.interval, self.last) {
+            if let Some(wait) = interval.checked_sub(last.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        self.last = Some(Instant::now());
+        let response = match self.session.get(url.clone()) {
+            Ok(response) => response,
+            Err(e) => return Some(Err(e)),
+        };
+        let base = match response.final_url() {
+            Some(u) => u.clone().into_url().unwrap_or(url),
+            None => url,
+        };
+        let next = match self.rule {
+            PaginationRule::LinkHeader => response
+                .header_values("link")
+                .into_iter()
+                .find_map(|header| next_link(header, &base)),
+            PaginationRule::Extractor(ref mut extract) => extract(&response),
+        };
+        debug!("next page: {:?}", next.as_ref().map(Url::as_str));
+        self.next = next.map(Ok);
+        Some(Ok(response))
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Iterate over the pages of a paginated resource starting at `url`, finding each following
+    /// page with `rule`. Every page is requested through the session, so it carries the cookies
+    /// set by the pages before it.
+    pub fn paginate<U: IntoUrl>(
+        &mut self,
+        url: U,
+        rule: PaginationRule<<C as SessionClient>::Response>,
+    ) -> Pages<'_, C, S> {
+        Pages {
+            session: self,
+            rule,
+            next: Some(url.into_url().map_err(Into::into)),
+            visited: HashSet::new(),
+            interval: None,
+            last: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_link, HeaderResponse, PaginationRule};
+    use crate::session::{
+        ClassifySendError, Session, SessionClient, SessionRequest, SessionResponse,
+    };
+    use cookie::Cookie as RawCookie;
+    use http::Method;
+    use url::{ParseError as ParseUrlError, Url};
+
+    #[test]
+    fn link_header() {
+        let base = Url::parse("https://api.example.com/items?page=1").unwrap();
+        let header = r#"<https://api.example.com/items?page=1>; rel="prev first",
+            <https://api.example.com/items?page=3,4>; rel="next", </items?page=9>; rel=last"#;
+        assert_eq!(
+            "https://api.example.com/items?page=3,4",
+            next_link(header, &base).unwrap().as_str()
+        );
+        assert_eq!(
+            "https://api.example.com/items?page=2",
+            next_link("</items?page=2>; rel=next", &base)
+                .unwrap()
+                .as_str()
+        );
+        assert_eq!(None, next_link(r#"</items?page=9>; rel="last""#, &base));
+        assert_eq!(None, next_link("<broken; rel=next", &base));
+    }
+
+    struct Client;
+    struct Response(Url, Option<String>);
+    #[derive(Debug)]
+    struct Error;
+
+    impl SessionResponse for Response {
+        type Url = Url;
+        fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+            vec![]
+        }
+        fn final_url(&self) -> Option<&Url> {
+            Some(&self.0)
+        }
+    }
+    impl HeaderResponse for Response {
+        fn header_values(&self, name: &str) -> Vec<&str> {
+            match self.1 {
+                Some(ref link) if name == "link" => vec![link],
+                _ => vec![],
+            }
+        }
+    }
+    impl From<ParseUrlError> for Error {
+        fn from(_: ParseUrlError) -> Self {
+            Error
+        }
+    }
+    impl ClassifySendError for Error {}
+    impl SessionClient for Client {
+        type Request = Url;
+        type Response = Response;
+        type SendError = Error;
+        fn request(&self, _: &Method, url: &Url) -> Url {
+            url.clone()
+        }
+        // pages 1 to 3, the last linking back to the first
+        fn send(&self, url: Url) -> Result<Response, Error> {
+            let page = url
+                .query()
+                .unwrap_or("1")
+                .parse::<u8>()
+                .map_err(|_| Error)?;
+            let next = if page < 3 { page + 1 } else { 1 };
+            Ok(Response(url, Some(format!("<?{}>; rel=next", next))))
+        }
+    }
+    impl SessionRequest for Url {
+        fn add_cookies(self, _: Vec<&RawCookie<'static>>) -> Self {
+            self
+        }
+    }
+
+    #[test]
+    fn pages() {
+        let mut session = Session::new(Client);
+        let pages = session
+            .paginate("http://example.com/items?1", PaginationRule::LinkHeader)
+            .map(|page| page.unwrap().0.query().map(str::to_owned))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Some("1".to_owned()),
+                Some("2".to_owned()),
+                Some("3".to_owned())
+            ],
+            pages
+        );
+
+        let mut pages = session.paginate(
+            "http://example.com/items?x",
+            PaginationRule::extractor(|_: &Response| None),
+        );
+        assert!(pages.next().unwrap().is_err());
+        assert!(pages.next().is_none());
+    }
+}
@@ -0,0 +1,306 @@
+use crate::error::SessionError;
+use crate::session::{Session, SessionClient};
+use crate::shutdown::write_jar_with;
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use cookie_store::{Cookie, CookieError, CookieStore};
+use log::{debug, warn};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// When a `PersistentStore` writes its store to its jar. Whatever the policy, unsaved changes are
+/// written when the store is flushed (e.g. by `Session::shutdown`) or dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavePolicy {
+    /// After every cookie stored
+    OnMutation,
+    /// After a cookie is stored, or before a request is made, if the jar was last written at
+    /// least the given interval before; there is no background thread, so an idle session does
+    /// not save
+    Every(Duration),
+    /// Only when flushed or dropped
+    OnDrop,
+}
+
+/// How a `PersistentStore` writes and reads its jar
+pub trait JarFormat {
+    /// Write the unexpired, persistent cookies of `store` to `writer`
+    fn save<S: CookieStorage, W: Write>(
+        &self,
+        store: &S,
+        writer: &mut W,
+    ) -> Result<(), SessionError>;
+
+    /// Read a store written by `save` from `reader`
+    fn load<S: CookieStorage, R: BufRead>(&self, reader: R) -> Result<S, SessionError>;
+}
+
+/// The `Session::save_json` format
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormat;
+
+impl JarFormat for JsonFormat {
+    fn save<S: CookieStorage, W: Write>(
+        &self,
+        store: &S,
+        writer: &mut W,
+    ) -> Result<(), SessionError> {
+        store.save_json(writer)
+    }
+
+    fn load<S: CookieStorage, R: BufRead>(&self, reader: R) -> Result<S, SessionError> {
+        S::load_json(reader)
+    }
+}
+
+/// A `CookieStorage` writing the store it wraps to a jar, per its `SavePolicy`, so that
+/// programs need not load and save their sessions around every run. Jars are written via a
+/// temporary file renamed into place, so an interrupted write leaves the previous jar intact;
+/// failures to write are logged (and retried at the next save), so that a full disk does not
+/// fail requests.
+#[derive(Debug)]
+pub struct PersistentStore<S: CookieStorage = CookieStore, F: JarFormat = JsonFormat> {
+    store: S,
+    path: PathBuf,
+    policy: SavePolicy,
+    format: F,
+    /// Whether the store has changed since the jar was last written
+    dirty: bool,
+    last_saved: Instant,
+}
+
+impl<S: CookieStorage + Default> PersistentStore<S> {
+    /// Load the store from the `save_json` jar at `path`, if there is one, or else start empty
+    pub fn open<P: Into<PathBuf>>(path: P, policy: SavePolicy) -> Result<Self, SessionError> {
+        Self::open_with_format(path, policy, JsonFormat)
+    }
+}
+
+impl<S: CookieStorage + Default, F: JarFormat> PersistentStore<S, F> {
+    /// Load the store from the jar in `format` at `path`, if there is one, or else start empty
+    pub fn open_with_format<P: Into<PathBuf>>(
+        path: P,
+        policy: SavePolicy,
+        format: F,
+    ) -> Result<Self, SessionError> {
+        let path = path.into();
+        let store = if path.exists() {
+            format.load(BufReader::new(File::open(&path)?))?
+        } else {
+            debug!("no jar at {}, starting empty", path.display());
+            S::default()
+        };
+        Ok(PersistentStore {
+            store,
+            path,
+            policy,
+            format,
+            dirty: false,
+            last_saved: Instant::now(),
+        })
+    }
+}
+
+impl<S: CookieStorage, F: JarFormat> PersistentStore<S, F> {
+    /// The jar the store is written to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn policy(&self) -> SavePolicy {
+        self.policy
+    }
+
+    /// The store written to the jar
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Whether the store has changed since the jar was last written
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Write the store to the jar now, returning the number of cookies written
+    pub fn save(&mut self) -> Result<usize, SessionError> {
+        let format = &self.format;
+        let saved = write_jar_with(&self.store, &self.path, |store, writer| {
+            format.save(store, writer)
+        })?;
+        self.dirty = false;
+        self.last_saved = Instant::now();
+        Ok(saved)
+    }
+
+    /// Save if the store has changed and the policy is due
+    fn save_if_due(&mut self) {
+        let due = match self.policy {
+            SavePolicy::OnMutation => true,
+            SavePolicy::Every(interval) => self.last_saved.elapsed() >= interval,
+            SavePolicy::OnDrop => false,
+        };
+        if self.dirty && due {
+            if let Err(e) = self.save() {
+                warn!("unable to save jar {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+impl<S: CookieStorage, F: JarFormat> CookieStorage for PersistentStore<S, F> {
+    fn refresh(&mut self, url: &Url) {
+        self.store.refresh(url);
+        self.save_if_due();
+    }
+
+    fn get_request_cookies(&self, url: &Url) -> Vec<RawCookie<'static>> {
+        self.store.get_request_cookies(url)
+    }
+
+    fn insert_raw(&mut self, cookie: &RawCookie<'static>, url: &Url) -> Result<(), CookieError> {
+        self.store.insert_raw(cookie, url)?;
+        self.dirty = true;
+        self.save_if_due();
+        Ok(())
+    }
+
+    fn iter_any(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_> {
+        self.store.iter_any()
+    }
+
+    fn iter_unexpired(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_> {
+        self.store.iter_unexpired()
+    }
+
+    fn remove(&mut self, domain: &str, path: &str, name: &str) -> Option<Cookie<'static>> {
+        let removed = self.store.remove(domain, path, name)?;
+        self.dirty = true;
+        self.save_if_due();
+        Some(removed)
+    }
+
+    fn remove_expired(&mut self) -> usize {
+        self.store.remove_expired()
+    }
+
+    fn save<W, E, G>(&self, writer: &mut W, cookie_to_string: G) -> Result<(), SessionError>
+    where
+        W: Write,
+        G: Fn(&Cookie<'_>) -> Result<String, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.store.save(writer, cookie_to_string)
+    }
+
+    fn save_json<W: Write>(&self, writer: &mut W) -> Result<(), SessionError> {
+        self.store.save_json(writer)
+    }
+
+    fn flush(&mut self) -> Result<(), SessionError> {
+        self.store.flush()?;
+        if self.dirty {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn load<R, E, G>(_: R, _: G) -> Result<Self, SessionError>
+    where
+        R: BufRead,
+        G: Fn(&str) -> Result<Cookie<'static>, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Err("a PersistentStore is loaded with PersistentStore::open".into())
+    }
+
+    fn load_json<R: BufRead>(_: R) -> Result<Self, SessionError> {
+        Err("a PersistentStore is loaded with PersistentStore::open".into())
+    }
+}
+
+impl<S: CookieStorage, F: JarFormat> Drop for PersistentStore<S, F> {
+    fn drop(&mut self) {
+        if self.dirty {
+            if let Err(e) = self.save() {
+                warn!("unable to save jar {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// A `Session` whose store is written to a jar automatically (see `PersistentStore`)
+pub type PersistentSession<C, S = CookieStore, F = JsonFormat> = Session<C, PersistentStore<S, F>>;
+
+impl<C: SessionClient> Session<C, PersistentStore> {
+    /// Create a session with the store of the `save_json` jar at `path`, if there is one, which
+    /// is written back to the jar per `policy`
+    pub fn open_persistent<P: Into<PathBuf>>(
+        client: C,
+        path: P,
+        policy: SavePolicy,
+    ) -> Result<Self, SessionError> {
+        Ok(Self::from_store(
+            client,
+            PersistentStore::open(path, policy)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PersistentStore, SavePolicy};
+    use crate::storage::CookieStorage;
+    use cookie::Cookie as RawCookie;
+    use cookie_store::CookieStore;
+    use std::time::Duration;
+    use url::Url;
+
+    #[test]
+    fn save_policies() {
+        let dir =
+            std::env::temp_dir().join(format!("user_agent-persistent-{}", std::process::id()));
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let cookie = |s: &str| RawCookie::parse(s.to_owned()).unwrap();
+        let open = |name: &str, policy| {
+            PersistentStore::<CookieStore>::open(dir.join(name), policy).unwrap()
+        };
+        let saved = |name: &str| {
+            let store = open(name, SavePolicy::OnDrop);
+            store
+                .iter_unexpired()
+                .map(|c| c.name().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let mut store = open("mutation.json", SavePolicy::OnMutation);
+        store
+            .insert_raw(&cookie("id=1; Max-Age=3600"), &url)
+            .unwrap();
+        assert!(!store.is_dirty());
+        assert_eq!(vec!["id"], saved("mutation.json"));
+
+        let mut store = open(
+            "interval.json",
+            SavePolicy::Every(Duration::from_secs(3600)),
+        );
+        store
+            .insert_raw(&cookie("id=1; Max-Age=3600"), &url)
+            .unwrap();
+        assert!(store.is_dirty());
+        assert!(!dir.join("interval.json").exists());
+        store.flush().unwrap();
+        assert_eq!(vec!["id"], saved("interval.json"));
+
+        let mut store = open("drop.json", SavePolicy::OnDrop);
+        store
+            .insert_raw(&cookie("id=1; Max-Age=3600"), &url)
+            .unwrap();
+        assert!(!dir.join("drop.json").exists());
+        drop(store);
+        assert_eq!(vec!["id"], saved("drop.json"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use cookie_store::{Cookie, CookieError};
+use log::debug;
+use std::collections::HashSet;
+use url::Url;
+
+/// The (domain, path, name) identifying a cookie in the store
+type CookieKey = (String, String, String);
+
+fn cookie_key(cookie: &Cookie<'_>) -> CookieKey {
+    (
+        String::from(&cookie.domain),
+        String::from(&cookie.path),
+        cookie.name().to_owned(),
+    )
+}
+
+/// Cookies whose values have been pinned, and which responses may not update
+#[derive(Debug, Default)]
+pub(crate) struct PinnedCookies {
+    pins: HashSet<CookieKey>,
+    suppressed: Vec<RawCookie<'static>>,
+}
+
+impl PinnedCookies {
+    /// Remove any of `cookies`, received from `url`, which would update a pinned cookie,
+    /// recording them as suppressed. The suppressed set from any previous response is replaced.
+    pub(crate) fn filter_response_cookies(
+        &mut self,
+        cookies: Vec<RawCookie<'static>>,
+        url: &Url,
+    ) -> Vec<RawCookie<'static>> {
+        self.suppressed.clear();
+        if self.pins.is_empty() {
+            return cookies;
+        }
+        let (suppressed, cookies) = cookies.into_iter().partition(|raw_cookie| {
+            Cookie::try_from_raw_cookie(raw_cookie, url)
+                .map(|cookie| self.pins.contains(&cookie_key(&cookie)))
+                .unwrap_or(false)
+        });
+        self.suppressed = suppressed;
+        for raw_cookie in &self.suppressed {
+            debug!("suppressing update to pinned cookie {:?}", raw_cookie);
+        }
+        cookies
+    }
+
+    pub(crate) fn suppressed(&self) -> &[RawCookie<'static>] {
+        &self.suppressed
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Store `cookie` as if received from `url`, and pin it: any later Set-Cookie which would
+    /// update or expire it is ignored, and reported by `suppressed_cookies`. Useful for holding a
+    /// site in a fixed A/B test or feature-flag bucket.
+    pub fn pin_cookie(&mut self, cookie: RawCookie<'static>, url: &Url) -> Result<(), CookieError> {
+        let key = cookie_key(&Cookie::try_from_raw_cookie(&cookie, url)?);
+        self.store.insert_raw(&cookie, url)?;
+        self.pinned.pins.insert(key);
+        Ok(())
+    }
+
+    /// Allow responses to update the cookie identified by `domain`, `path`, and `name` again.
+    /// Returns `false` if the cookie was not pinned.
+    pub fn unpin_cookie(&mut self, domain: &str, path: &str, name: &str) -> bool {
+        self.pinned
+            .pins
+            .remove(&(domain.to_owned(), path.to_owned(), name.to_owned()))
+    }
+
+    /// The Set-Cookies from the last response which were ignored because they would have updated a
+    /// pinned cookie
+    pub fn suppressed_cookies(&self) -> &[RawCookie<'static>] {
+        self.pinned.suppressed()
+    }
+}
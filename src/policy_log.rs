@@ -0,0 +1,251 @@
+use crate::events::RejectionReason;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+#[cfg(feature = "persistence")]
+use crate::utils::unix_secs;
+use cookie::Cookie as RawCookie;
+#[cfg(feature = "persistence")]
+use log::warn;
+#[cfg(feature = "persistence")]
+use serde_json::json;
+#[cfg(feature = "persistence")]
+use std::io::Write;
+use std::time::SystemTime;
+use url::Url;
+
+/// The rule of a session's policy under which a `PolicyDecision` was made
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PolicyRule {
+    /// The cookie store refused a cookie (`RejectionReason::Invalid`)
+    InvalidCookie,
+    /// A cookie would have updated a pinned cookie (`RejectionReason::Pinned`)
+    PinnedCookie,
+    /// The URL a response was served from was unknown (`RejectionReason::UnknownUrl`)
+    UnknownUrl,
+    /// A response was from a proxy (`RejectionReason::ProxyResponse`)
+    ProxyResponse,
+    /// A cross-site subresource response set a cookie which was not `SameSite=None; Secure`
+    /// (`RejectionReason::SameSite`)
+    SameSiteSet,
+    /// A cookie which was not `SameSite=None; Secure` was withheld from a cross-site subresource
+    /// request
+    SameSiteSend,
+    /// A response set a cookie more than once, and this Set-Cookie was not the one stored
+    /// (`RejectionReason::Duplicate`)
+    DuplicateCookie,
+    /// A cookie was for a public suffix domain (`RejectionReason::PublicSuffix`)
+    PublicSuffix,
+    /// A cookie was not accepted under the session's `CookiePolicy` (`RejectionReason::Policy`)
+    CookiePolicy,
+    /// A cookie was larger than the session's `CookieLimits` allow (`RejectionReason::TooLarge`)
+    TooLarge,
+}
+
+impl PolicyRule {
+    /// The stable identifier of the rule, as written to the policy log
+    pub fn id(self) -> &'static str {
+        match self {
+            PolicyRule::InvalidCookie => "cookie.invalid",
+            PolicyRule::PinnedCookie => "cookie.pinned",
+            PolicyRule::UnknownUrl => "cookie.unknown-url",
+            PolicyRule::ProxyResponse => "cookie.proxy-response",
+            PolicyRule::SameSiteSet => "cookie.same-site.set",
+            PolicyRule::SameSiteSend => "cookie.same-site.send",
+            PolicyRule::DuplicateCookie => "cookie.duplicate",
+            PolicyRule::PublicSuffix => "cookie.public-suffix",
+            PolicyRule::CookiePolicy => "cookie.policy",
+            PolicyRule::TooLarge => "cookie.too-large",
+        }
+    }
+
+    fn of(reason: &RejectionReason) -> Self {
+        match reason {
+            RejectionReason::Invalid(_) => PolicyRule::InvalidCookie,
+            RejectionReason::Pinned => PolicyRule::PinnedCookie,
+            RejectionReason::UnknownUrl => PolicyRule::UnknownUrl,
+            RejectionReason::ProxyResponse => PolicyRule::ProxyResponse,
+            RejectionReason::SameSite => PolicyRule::SameSiteSet,
+            RejectionReason::Duplicate => PolicyRule::DuplicateCookie,
+            RejectionReason::PublicSuffix => PolicyRule::PublicSuffix,
+            RejectionReason::Policy(_) => PolicyRule::CookiePolicy,
+            RejectionReason::TooLarge => PolicyRule::TooLarge,
+        }
+    }
+}
+
+/// What a session did with a cookie under a `PolicyRule`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyAction {
+    /// A cookie set by a response was not stored
+    Block,
+    /// A cookie in the store was not sent with a request
+    Withhold,
+}
+
+impl PolicyAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PolicyAction::Block => "block",
+            PolicyAction::Withhold => "withhold",
+        }
+    }
+}
+
+/// A decision a session made in enforcing its policy, for audits of its enforcement
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyDecision {
+    pub at: SystemTime,
+    pub rule: PolicyRule,
+    pub action: PolicyAction,
+    /// The URL of the request or response the decision was made for
+    pub url: Url,
+    /// The name of the cookie the decision was made for
+    pub cookie: String,
+    /// Further detail, e.g. the reason the store refused a cookie
+    pub detail: Option<String>,
+}
+
+impl PolicyDecision {
+    /// The decision as a line of the policy log: a JSON object with `at` (in seconds since the
+    /// Unix epoch), `rule` (its `PolicyRule::id`), `action`, `url`, `cookie`, and `detail`
+    #[cfg(feature = "persistence")]
+    pub fn to_json(&self) -> String {
+        json!({
+            "at": unix_secs(self.at),
+            "rule": self.rule.id(),
+            "action": self.action.as_str(),
+            "url": self.url.as_str(),
+            "cookie": self.cookie,
+            "detail": self.detail,
+        })
+        .to_string()
+    }
+}
+
+type PolicyHandler = Box<dyn FnMut(&PolicyDecision) + Send>;
+
+/// Where a `Session` reports its `PolicyDecision`s
+#[derive(Default)]
+pub(crate) struct PolicyLog {
+    handlers: Vec<PolicyHandler>,
+}
+
+impl PolicyLog {
+    pub(crate) fn decide(
+        &mut self,
+        rule: PolicyRule,
+        action: PolicyAction,
+        url: &Url,
+        cookie: &RawCookie<'_>,
+        detail: Option<String>,
+    ) {
+        if self.handlers.is_empty() {
+            return;
+        }
+        let decision = PolicyDecision {
+            at: SystemTime::now(),
+            rule,
+            action,
+            url: url.clone(),
+            cookie: cookie.name().to_owned(),
+            detail,
+        };
+        for handler in self.handlers.iter_mut() {
+            handler(&decision);
+        }
+    }
+
+    /// Report that `cookie`, received from `url`, was not stored for `reason`
+    pub(crate) fn rejected(&mut self, cookie: &RawCookie<'_>, url: &Url, reason: &RejectionReason) {
+        let detail = match reason {
+            RejectionReason::Invalid(e) => Some(e.to_string()),
+            RejectionReason::Policy(reason) => Some(reason.clone()),
+            _ => None,
+        };
+        self.decide(
+            PolicyRule::of(reason),
+            PolicyAction::Block,
+            url,
+            cookie,
+            detail,
+        );
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Call `handler` with each `PolicyDecision` the session makes
+    pub fn with_policy_handler<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&PolicyDecision) + Send + 'static,
+    {
+        self.policy_log.handlers.push(Box::new(handler));
+        self
+    }
+
+    /// Write each `PolicyDecision` the session makes to `writer`, one per line, as JSON (see
+    /// `PolicyDecision::to_json`), so audits can show the configured policy was enforced.
+    /// Failures to write are logged, so that a full disk does not fail requests.
+    #[cfg(feature = "persistence")]
+    pub fn with_policy_log<W: Write + Send + 'static>(self, mut writer: W) -> Self {
+        self.with_policy_handler(move |decision| {
+            if let Err(e) = writeln!(writer, "{}", decision.to_json()) {
+                warn!("unable to write policy decision {:?}: {}", decision, e);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PolicyAction, PolicyDecision, PolicyLog, PolicyRule};
+    use crate::events::RejectionReason;
+    use cookie::Cookie as RawCookie;
+    use std::sync::{Arc, Mutex};
+    use url::Url;
+
+    #[test]
+    fn policy_log() {
+        let url = Url::parse("https://pixel.tracker.net/p.gif").unwrap();
+        let decisions = Arc::new(Mutex::new(vec![]));
+        let mut log = PolicyLog::default();
+        let recorded = decisions.clone();
+        log.handlers.push(Box::new(move |d: &PolicyDecision| {
+            recorded.lock().unwrap().push(d.clone())
+        }));
+        let cookie = RawCookie::new("uid", "1");
+        log.rejected(&cookie, &url, &RejectionReason::SameSite);
+        log.decide(
+            PolicyRule::SameSiteSend,
+            PolicyAction::Withhold,
+            &url,
+            &cookie,
+            None,
+        );
+        let decisions = decisions.lock().unwrap();
+        assert_eq!(
+            vec![PolicyRule::SameSiteSet, PolicyRule::SameSiteSend],
+            decisions.iter().map(|d| d.rule).collect::<Vec<_>>()
+        );
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn to_json() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let decision = PolicyDecision {
+            at: UNIX_EPOCH + Duration::from_secs(60),
+            rule: PolicyRule::SameSiteSet,
+            action: PolicyAction::Block,
+            url: Url::parse("https://pixel.tracker.net/p.gif").unwrap(),
+            cookie: "uid".to_owned(),
+            detail: None,
+        };
+        let json = serde_json::from_str::<serde_json::Value>(&decision.to_json()).unwrap();
+        assert_eq!(60, json["at"]);
+        assert_eq!("cookie.same-site.set", json["rule"]);
+        assert_eq!("block", json["action"]);
+        assert_eq!("uid", json["cookie"]);
+        assert!(json["detail"].is_null());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use log::debug;
+use std::time::Duration;
+
+/// The maximum factor by which `Session::poll_with` will stretch its interval after consecutive
+/// failed requests
+const MAX_BACKOFF_FACTOR: u32 = 32;
+
+/// Returned by a `Session::poll_with` handler to indicate whether polling should continue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Poll {
+    Continue,
+    Stop,
+}
+
+/// The wait before the next poll, `interval` stretched by `backoff`, saturating rather than
+/// overflowing
+fn wait(interval: Duration, backoff: u32) -> Duration {
+    interval.checked_mul(backoff).unwrap_or(Duration::MAX)
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Repeatedly GET `url`, waiting `interval` between requests, until `handler` returns
+    /// `Poll::Stop`. Each request is sent through the session as with `get`, so it carries the
+    /// cookies in the store at the time it is sent (including those set by earlier polls), and is
+    /// retried per any `RetryPolicy`. `handler` is passed the result of every request; after a
+    /// failed request the wait is doubled for each consecutive failure, up to 32x `interval`, and
+    /// reset on the next success.
+    pub fn poll_with<U, H>(
+        &mut self,
+        url: U,
+        interval: Duration,
+        mut handler: H,
+    ) -> Result<(), <C as SessionClient>::SendError>
+    where
+        U: IntoUrl,
+        H: FnMut(Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>) -> Poll,
+    {
+        let url = self.request_url(url.into_url()?);
+        let mut backoff = 1;
+        loop {
+            let request = self.client.get_request(&url);
+            let result = self.run_request(request, "GET", &url, |request| request);
+            backoff = if result.is_ok() {
+                1
+            } else {
+                (backoff * 2).min(MAX_BACKOFF_FACTOR)
+            };
+            if handler(result) == Poll::Stop {
+                return Ok(());
+            }
+            let wait = wait(interval, backoff);
+            debug!("polling {} again in {:?}", url, wait);
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{wait, MAX_BACKOFF_FACTOR};
+    use std::time::Duration;
+
+    #[test]
+    fn waits() {
+        let second = Duration::from_secs(1);
+        assert_eq!(second, wait(second, 1));
+        assert_eq!(32 * second, wait(second, MAX_BACKOFF_FACTOR));
+        let long = Duration::from_secs(u64::MAX / 4);
+        assert_eq!(Duration::MAX, wait(long, MAX_BACKOFF_FACTOR));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn poll_with() {
+        use super::Poll;
+        use crate::session::{ClassifySendError, SendErrorKind, SessionResponse};
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let client = MockClient::new()
+            .with_response(MockResponse::new(200).with_set_cookie("session=1"))
+            .with_error(SendErrorKind::Connect)
+            .with_response(MockResponse::new(204));
+        let mut session = Session::new(client);
+        let mut results = vec![];
+        session
+            .poll_with(
+                "https://example.com/status",
+                Duration::from_millis(0),
+                |r| {
+                    results.push(r.map(|r| r.status_code()).map_err(|e| e.kind()));
+                    match results.len() {
+                        3 => Poll::Stop,
+                        _ => Poll::Continue,
+                    }
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            vec![Ok(Some(200)), Err(SendErrorKind::Connect), Ok(Some(204))],
+            results
+        );
+        let requests = session.client.requests();
+        assert_eq!(vec!["session"], requests[2].cookie_names());
+    }
+}
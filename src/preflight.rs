@@ -0,0 +1,238 @@
+use crate::paginate::HeaderResponse;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::http_date;
+use log::debug;
+use std::fs::OpenOptions;
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// The checks `Session::preflight` performs; each is skipped unless configured
+#[derive(Debug, Clone)]
+pub struct Preflight {
+    probe: Option<Url>,
+    max_clock_skew: Duration,
+    proxy: Option<Url>,
+    timeout: Duration,
+}
+
+impl Default for Preflight {
+    fn default() -> Self {
+        Preflight {
+            probe: None,
+            max_clock_skew: Duration::from_secs(60),
+            proxy: None,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Preflight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check the local clock against the `Date` header of a GET of `url`, sent through the
+    /// session, failing if they differ by more than `max_skew`
+    pub fn with_clock_check(mut self, url: Url, max_skew: Duration) -> Self {
+        self.probe = Some(url);
+        self.max_clock_skew = max_skew;
+        self
+    }
+
+    /// Check that a TCP connection can be made to the proxy at `proxy` within `timeout`
+    pub fn with_proxy_check(mut self, proxy: Url, timeout: Duration) -> Self {
+        self.proxy = Some(proxy);
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// The outcome of one of the checks of a `Session::preflight`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOutcome {
+    Passed,
+    /// The check failed, for the given reason
+    Failed(String),
+    /// The check was not performed, for the given reason
+    Skipped(String),
+}
+
+/// The outcomes of the checks of a `Session::preflight`, by name: `jar`, `clock`, `proxy`, and
+/// `public_suffix_list`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightReport {
+    pub checks: Vec<(&'static str, CheckOutcome)>,
+    /// The server's clock less the local clock, in seconds, if the clock was checked
+    pub clock_skew: Option<i64>,
+}
+
+impl PreflightReport {
+    /// Whether no check failed
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// The names of the checks which failed, with their reasons
+    pub fn failures(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.checks
+            .iter()
+            .filter_map(|(name, outcome)| match outcome {
+                CheckOutcome::Failed(reason) => Some((*name, reason.as_str())),
+                _ => None,
+            })
+    }
+}
+
+/// Whether a file can be created alongside `jar`, creating its directory if need be
+fn check_jar(jar: &Path) -> CheckOutcome {
+    let mut probe = jar.as_os_str().to_owned();
+    probe.push(".preflight");
+    let writable = jar
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| OpenOptions::new().write(true).create_new(true).open(&probe))
+        .and_then(|_| std::fs::remove_file(&probe));
+    match writable {
+        Ok(()) => CheckOutcome::Passed,
+        Err(e) => CheckOutcome::Failed(format!("{} is not writable: {}", jar.display(), e)),
+    }
+}
+
+fn check_proxy(proxy: &Url, timeout: Duration) -> CheckOutcome {
+    let addrs = match proxy.socket_addrs(|| None) {
+        Ok(addrs) => addrs,
+        Err(e) => return CheckOutcome::Failed(format!("unable to resolve {}: {}", proxy, e)),
+    };
+    let mut error = format!("{} has no addresses", proxy);
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(_) => return CheckOutcome::Passed,
+            Err(e) => error = format!("unable to connect to {} ({}): {}", proxy, addr, e),
+        }
+    }
+    CheckOutcome::Failed(error)
+}
+
+/// The skew of a server's clock, given the `Date` header `date` of its response, relative to
+/// `now` (in seconds since the Unix epoch)
+fn clock_skew(date: Option<&str>, now: i64) -> Result<i64, String> {
+    let date = date.ok_or_else(|| "the probe response has no Date header".to_owned())?;
+    let server = http_date(date).ok_or_else(|| format!("unable to parse Date {}", date))?;
+    Ok(server - now)
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Check the session's environment, so that a misconfigured agent can fail fast: that its
+    /// jar (see `with_jar`) is writable, and the checks configured by `checks`. The public suffix
+    /// list check is always skipped, as the session does not use one.
+    pub fn preflight(&mut self, checks: &Preflight) -> PreflightReport
+    where
+        <C as SessionClient>::Response: HeaderResponse,
+        <C as SessionClient>::SendError: std::fmt::Display,
+    {
+        let mut report = PreflightReport {
+            checks: vec![],
+            clock_skew: None,
+        };
+        let jar = match self.jar {
+            Some(ref jar) => check_jar(jar),
+            None => CheckOutcome::Skipped("the session has no jar".to_owned()),
+        };
+        report.checks.push(("jar", jar));
+
+        let clock = match checks.probe {
+            Some(ref probe) => {
+                let skew = self
+                    .get(probe.clone())
+                    .map_err(|e| format!("probe request failed: {}", e))
+                    .and_then(|response| {
+                        let now = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map_err(|e| e.to_string())?;
+                        clock_skew(
+                            response.header_values("date").first().cloned(),
+                            now.as_secs() as i64,
+                        )
+                    });
+                match skew {
+                    Ok(skew) => {
+                        debug!("clock skew against {}: {}s", probe, skew);
+                        report.clock_skew = Some(skew);
+                        if skew.unsigned_abs() > checks.max_clock_skew.as_secs() {
+                            CheckOutcome::Failed(format!(
+                                "the local clock is {}s {} {}",
+                                skew.abs(),
+                                if skew > 0 { "behind" } else { "ahead of" },
+                                probe
+                            ))
+                        } else {
+                            CheckOutcome::Passed
+                        }
+                    }
+                    Err(reason) => CheckOutcome::Failed(reason),
+                }
+            }
+            None => CheckOutcome::Skipped("no probe URL".to_owned()),
+        };
+        report.checks.push(("clock", clock));
+
+        let proxy = match checks.proxy {
+            Some(ref proxy) => check_proxy(proxy, checks.timeout),
+            None => CheckOutcome::Skipped("no proxy".to_owned()),
+        };
+        report.checks.push(("proxy", proxy));
+        let public_suffix_list = match (
+            self.public_suffixes.is_public_suffix("com"),
+            self.public_suffixes.is_public_suffix("example.com"),
+        ) {
+            (None, _) => CheckOutcome::Skipped("no public suffix list is in use".to_owned()),
+            (Some(true), Some(false)) => CheckOutcome::Passed,
+            _ => CheckOutcome::Failed("the public suffix list does not list `com`".to_owned()),
+        };
+        report.checks.push(("public_suffix_list", public_suffix_list));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_jar, check_proxy, clock_skew, CheckOutcome};
+    use std::time::Duration;
+    use url::Url;
+
+    #[test]
+    fn checks() {
+        let date = "Wed, 04 Mar 2020 05:06:07 GMT";
+        assert_eq!(Ok(-3), clock_skew(Some(date), 1_583_298_370));
+        assert!(clock_skew(None, 0).is_err());
+        assert!(clock_skew(Some("yesterday"), 0).is_err());
+
+        let dir = std::env::temp_dir().join(format!("user_agent-preflight-{}", std::process::id()));
+        assert_eq!(CheckOutcome::Passed, check_jar(&dir.join("a/jar.json")));
+        assert!(!dir.join("a/jar.json.preflight").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // nothing listens on the discard port of localhost
+        let proxy = Url::parse("http://127.0.0.1:9/").unwrap();
+        assert!(matches!(
+            check_proxy(&proxy, Duration::from_secs(1)),
+            CheckOutcome::Failed(_)
+        ));
+    }
+
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn preflight() {
+        use super::Preflight;
+        use crate::ReqwestSession;
+
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new());
+        let report = session.preflight(&Preflight::new());
+        assert!(report.is_ok());
+        assert_eq!(4, report.checks.len());
+        assert_eq!(None, report.clock_skew);
+    }
+}
@@ -0,0 +1,17 @@
+//! The commonly used traits and types of this crate, together with the `cookie`, `cookie_store`,
+//! and `url` types its APIs expose, at the versions it is built against:
+//!
+//! ```
+//! use user_agent::prelude::*;
+//! ```
+pub use crate::events::StoreEventSink;
+#[cfg(feature = "reqwest-session")]
+pub use crate::reqwest_session::ReqwestSession;
+pub use crate::session::{
+    ClassifySendError, Session, SessionClient, SessionRequest, SessionResponse,
+};
+pub use crate::status::EnsureStatus;
+pub use crate::storage::CookieStorage;
+pub use cookie::Cookie as RawCookie;
+pub use cookie_store::{Cookie, CookieError, CookieStore};
+pub use url::Url;
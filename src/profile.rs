@@ -0,0 +1,449 @@
+use crate::cookie_policy::CookiePolicy;
+use crate::error::SessionError;
+use crate::fingerprint::{browser, check_headers, Browser, FingerprintIssue};
+use crate::limits::CookieLimits;
+use crate::redirect::RedirectMode;
+use crate::retry::RetryPolicy;
+use crate::session::{Session, SessionClient};
+use cookie::Cookie as RawCookie;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::Duration;
+use url::Url;
+
+/// A cookie to pin (see `Session::pin_cookie`) when instantiating a `Session` from a
+/// `SessionProfile`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PinnedCookieProfile {
+    /// The URL the cookie is set as if received from
+    pub url: String,
+    /// The cookie, in Set-Cookie header format
+    pub cookie: String,
+}
+
+/// The credentials a `SessionProfile`'s client authenticates to its proxy with. They are sent
+/// only to the proxy, in `Proxy-Authorization` (on the `CONNECT` request of a tunnel to an
+/// `https` URL, which carries none of the session's cookies), never to origins.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for ProxyCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyCredentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// The TLS client hello a `SessionProfile`'s client presents. Requires the `rustls-tls` feature;
+/// rustls can only approximate a browser (matching its cipher suite preference and ALPN, but not
+/// e.g. its extension order or GREASE values), so targets fingerprinting TLS strictly may still
+/// distinguish the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsProfile {
+    /// Match the browser named by the profile's `user_agent`, if any
+    Auto,
+    Chrome,
+    Firefox,
+    Safari,
+}
+
+#[cfg(feature = "rustls-tls")]
+impl TlsProfile {
+    /// A rustls `ClientConfig` approximating this profile; `Auto` is treated as `Chrome`
+    pub fn rustls_config(self) -> rustls::ClientConfig {
+        use rustls::ciphersuite::*;
+        let ciphersuites = match self {
+            TlsProfile::Auto | TlsProfile::Chrome => [
+                &TLS13_AES_128_GCM_SHA256,
+                &TLS13_AES_256_GCM_SHA384,
+                &TLS13_CHACHA20_POLY1305_SHA256,
+                &TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+                &TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+                &TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+                &TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+                &TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+                &TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+            ],
+            TlsProfile::Firefox => [
+                &TLS13_AES_128_GCM_SHA256,
+                &TLS13_CHACHA20_POLY1305_SHA256,
+                &TLS13_AES_256_GCM_SHA384,
+                &TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+                &TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+                &TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+                &TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+                &TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+                &TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+            ],
+            TlsProfile::Safari => [
+                &TLS13_AES_128_GCM_SHA256,
+                &TLS13_AES_256_GCM_SHA384,
+                &TLS13_CHACHA20_POLY1305_SHA256,
+                &TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+                &TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+                &TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+                &TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+                &TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+                &TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+            ],
+        };
+        let mut config = rustls::ClientConfig::new();
+        config.ciphersuites = ciphersuites.to_vec();
+        config
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+        config
+    }
+}
+
+/// The `CookiePolicy` of a `SessionProfile`'s session; `Custom` policies cannot be configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CookiePolicyProfile {
+    Permissive,
+    Rfc6265Strict,
+    Rfc6265bis,
+}
+
+impl From<CookiePolicyProfile> for CookiePolicy {
+    fn from(profile: CookiePolicyProfile) -> Self {
+        match profile {
+            CookiePolicyProfile::Permissive => CookiePolicy::Permissive,
+            CookiePolicyProfile::Rfc6265Strict => CookiePolicy::Rfc6265Strict,
+            CookiePolicyProfile::Rfc6265bis => CookiePolicy::Rfc6265bis,
+        }
+    }
+}
+
+/// The `RetryPolicy` of a `SessionProfile`'s session; settings left unset are those of
+/// `RetryPolicy::new`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryProfile {
+    /// The most attempts at each request, including the first
+    pub max_attempts: Option<u32>,
+    /// The wait before the first retry, in milliseconds
+    pub initial_backoff_ms: Option<u64>,
+    /// The longest wait before a retry, in milliseconds
+    pub max_backoff_ms: Option<u64>,
+    /// The statuses of responses to retry
+    pub retry_statuses: Option<Vec<u16>>,
+    /// Whether to wait as long as a response's `Retry-After` header asks
+    pub honor_retry_after: Option<bool>,
+}
+
+impl RetryProfile {
+    /// The `RetryPolicy` configured
+    pub fn retry_policy(&self) -> RetryPolicy {
+        let mut policy = RetryPolicy::new();
+        let initial = self.initial_backoff_ms.map(Duration::from_millis);
+        let max = self.max_backoff_ms.map(Duration::from_millis);
+        let (initial, max) = (
+            initial.unwrap_or(policy.initial_backoff),
+            max.unwrap_or(policy.max_backoff),
+        );
+        policy = policy.with_backoff(initial, max);
+        if let Some(max_attempts) = self.max_attempts {
+            policy = policy.with_max_attempts(max_attempts);
+        }
+        if let Some(ref statuses) = self.retry_statuses {
+            policy = policy.with_retry_statuses(statuses.iter().copied());
+        }
+        if let Some(honor_retry_after) = self.honor_retry_after {
+            policy = policy.with_honor_retry_after(honor_retry_after);
+        }
+        policy
+    }
+}
+
+/// The configuration of a `Session`, (de)serializable so that agents can be instantiated from
+/// configuration files
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionProfile {
+    /// The User-Agent header to send
+    pub user_agent: Option<String>,
+    /// Additional headers to send with every request
+    pub headers: BTreeMap<String, String>,
+    /// URL of a proxy to send all requests through
+    pub proxy: Option<String>,
+    /// Credentials for the `proxy`, if it requires authentication
+    pub proxy_credentials: Option<ProxyCredentials>,
+    /// A `save_json` formatted cookie jar to load the store from, if it exists
+    pub jar: Option<PathBuf>,
+    /// Cookies to pin in the store
+    pub pinned_cookies: Vec<PinnedCookieProfile>,
+    /// The TLS client hello to present; if unset, the client's default is used
+    pub tls: Option<TlsProfile>,
+    /// Which Set-Cookies are stored (see `Session::with_cookie_policy`)
+    pub cookie_policy: Option<CookiePolicyProfile>,
+    /// Limits on the cookies stored (see `Session::with_cookie_limits`)
+    pub cookie_limits: Option<CookieLimits>,
+    /// Who follows redirects (see `Session::with_redirect_mode`); the client built by
+    /// `reqwest_client` follows none itself if the session does
+    pub redirect_mode: Option<RedirectMode>,
+    /// How requests failing transiently are retried (see `Session::with_retry_policy`); if
+    /// unset, they are not
+    pub retry: Option<RetryProfile>,
+}
+
+impl SessionProfile {
+    /// The TLS profile to present, with `TlsProfile::Auto` resolved against the user agent.
+    /// `None` if no profile is configured, or `Auto` is configured and the user agent names no
+    /// known browser.
+    pub fn tls_profile(&self) -> Option<TlsProfile> {
+        match self.tls? {
+            TlsProfile::Auto => match browser(self.user_agent.as_ref()?) {
+                Some(Browser::Chromium) => Some(TlsProfile::Chrome),
+                Some(Browser::Firefox) => Some(TlsProfile::Firefox),
+                Some(Browser::Safari) => Some(TlsProfile::Safari),
+                None => None,
+            },
+            tls => Some(tls),
+        }
+    }
+
+    /// Build a `reqwest::blocking::Client` configured with the user agent, headers, proxy, and
+    /// TLS profile of this profile, for use with `Session::from_profile`
+    pub fn reqwest_client(&self) -> Result<reqwest::blocking::Client, crate::Error> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        let mut builder = reqwest::blocking::Client::builder().default_headers(headers);
+        if let Some(ref user_agent) = self.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        if let Some(RedirectMode::Session { .. }) = self.redirect_mode {
+            builder = builder.redirect(reqwest::redirect::Policy::none());
+        }
+        if let Some(ref proxy) = self.proxy {
+            let mut proxy = reqwest::Proxy::all(proxy.as_str())?;
+            if let Some(ref credentials) = self.proxy_credentials {
+                proxy = proxy.basic_auth(&credentials.username, &credentials.password);
+            }
+            builder = builder.proxy(proxy);
+        }
+        if let Some(tls) = self.tls_profile() {
+            #[cfg(feature = "rustls-tls")]
+            {
+                builder = builder.use_preconfigured_tls(tls.rustls_config());
+            }
+            #[cfg(not(feature = "rustls-tls"))]
+            log::warn!(
+                "TLS profiles require the rustls-tls feature; ignoring {:?}",
+                tls
+            );
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Advisory checks of the profile's user agent and headers; see `fingerprint::check_headers`
+    pub fn fingerprint_issues(&self) -> Vec<FingerprintIssue> {
+        let user_agent = self
+            .user_agent
+            .as_ref()
+            .map(|ua| ("User-Agent", ua.as_str()));
+        let headers = self.headers.iter().map(|(n, v)| (n.as_str(), v.as_str()));
+        check_headers(user_agent.into_iter().chain(headers))
+    }
+}
+
+impl<C: SessionClient> Session<C> {
+    /// Create a `Session` using `client` (see `SessionProfile::reqwest_client`), with its store
+    /// loaded from (and saved on `shutdown` to) the profile's `jar` if that file exists, the
+    /// profile's pinned cookies applied, and its cookie policy, cookie limits, redirect mode, and
+    /// retry policy set.
+    pub fn from_profile(profile: &SessionProfile, client: C) -> Result<Session<C>, SessionError> {
+        let mut session = match profile.jar {
+            Some(ref jar) if jar.exists() => {
+                Session::load_json(client, BufReader::new(File::open(jar)?))?
+            }
+            _ => Session::new(client),
+        };
+        if let Some(ref jar) = profile.jar {
+            session = session.with_jar(jar);
+        }
+        for pinned in &profile.pinned_cookies {
+            let url = Url::parse(&pinned.url)?;
+            let cookie = RawCookie::parse(pinned.cookie.clone())?;
+            session.pin_cookie(cookie, &url)?;
+        }
+        if let Some(policy) = profile.cookie_policy {
+            session = session.with_cookie_policy(policy.into());
+        }
+        if let Some(limits) = profile.cookie_limits {
+            session = session.with_cookie_limits(limits);
+        }
+        if let Some(mode) = profile.redirect_mode {
+            session = session.with_redirect_mode(mode);
+        }
+        if let Some(ref retry) = profile.retry {
+            session = session.with_retry_policy(retry.retry_policy());
+        }
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProxyCredentials, SessionProfile, TlsProfile};
+    use crate::fingerprint::FingerprintIssue;
+    use crate::ReqwestSession;
+
+    #[test]
+    fn from_profile() {
+        let profile: SessionProfile = serde_json::from_str(
+            r#"{
+                "user_agent": "agent/1.0",
+                "headers": { "Accept-Language": "en-US" },
+                "jar": "/nonexistent/jar.json",
+                "pinned_cookies": [
+                    { "url": "https://www.example.com/", "cookie": "bucket=b; Max-Age=3600" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(None, profile.proxy);
+        assert_eq!(
+            vec![FingerprintIssue::MissingHeader("Accept")],
+            profile.fingerprint_issues()
+        );
+
+        let session =
+            ReqwestSession::from_profile(&profile, profile.reqwest_client().unwrap()).unwrap();
+        assert_eq!(
+            "b",
+            session
+                .store
+                .get("www.example.com", "/", "bucket")
+                .unwrap()
+                .value()
+        );
+    }
+
+    #[test]
+    fn tls_profile() {
+        let mut profile = SessionProfile {
+            user_agent: Some("Mozilla/5.0 (X11; Linux x86_64; rv:73.0) Firefox/73.0".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(None, profile.tls_profile());
+        profile.tls = Some(TlsProfile::Auto);
+        assert_eq!(Some(TlsProfile::Firefox), profile.tls_profile());
+        profile.user_agent = Some("agent/1.0".to_owned());
+        assert_eq!(None, profile.tls_profile());
+        profile.tls = Some(TlsProfile::Safari);
+        assert_eq!(Some(TlsProfile::Safari), profile.tls_profile());
+        assert!(profile.reqwest_client().is_ok());
+
+        let profile: SessionProfile = serde_json::from_str(r#"{ "tls": "chrome" }"#).unwrap();
+        assert_eq!(Some(TlsProfile::Chrome), profile.tls);
+    }
+
+    #[test]
+    fn policies() {
+        use super::CookiePolicyProfile;
+        use crate::{CookieLimits, RedirectMode, RetryPolicy};
+        use std::time::Duration;
+
+        let profile: SessionProfile = serde_json::from_str(
+            r#"{
+                "cookie_policy": "rfc6265bis",
+                "cookie_limits": { "max_cookies_per_domain": 2 },
+                "redirect_mode": { "Session": { "max_redirects": 3 } },
+                "retry": { "max_attempts": 2, "initial_backoff_ms": 10 }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(Some(CookiePolicyProfile::Rfc6265bis), profile.cookie_policy);
+        assert_eq!(
+            Some(CookieLimits::new().with_max_cookies_per_domain(2)),
+            profile.cookie_limits
+        );
+        let retry = RetryPolicy::new()
+            .with_max_attempts(2)
+            .with_backoff(Duration::from_millis(10), Duration::from_secs(30));
+        assert_eq!(retry, profile.retry.as_ref().unwrap().retry_policy());
+
+        let session =
+            ReqwestSession::from_profile(&profile, profile.reqwest_client().unwrap()).unwrap();
+        assert_eq!("Rfc6265bis", format!("{:?}", session.cookie_policy));
+        assert_eq!(
+            RedirectMode::Session { max_redirects: 3 },
+            session.redirect_mode
+        );
+        assert_eq!(Some(retry), session.retry);
+    }
+
+    #[test]
+    fn proxy_credentials() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+        use url::Url;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let profile = SessionProfile {
+            proxy: Some(format!("http://{}", listener.local_addr().unwrap())),
+            proxy_credentials: Some(ProxyCredentials {
+                username: "user".to_owned(),
+                password: "pass".to_owned(),
+            }),
+            ..Default::default()
+        };
+        assert!(!format!("{:?}", profile).contains("pass\""));
+        let proxy = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut head = vec![];
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                head.push(line.trim().to_owned());
+            }
+            stream
+                .write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            head
+        });
+
+        let mut session = ReqwestSession::new(profile.reqwest_client().unwrap());
+        let url = Url::parse("https://www.example.com/").unwrap();
+        session.store.parse("id=1; Max-Age=3600", &url).unwrap();
+        assert!(session.get(url).is_err());
+        let head = proxy.join().unwrap();
+        assert!(head[0].starts_with("CONNECT www.example.com:443 "));
+        let header = |name: &str| {
+            head.iter().find_map(|line| {
+                let (found, value) = line.split_once(':')?;
+                if found.eq_ignore_ascii_case(name) {
+                    Some(value.trim().to_owned())
+                } else {
+                    None
+                }
+            })
+        };
+        assert_eq!(
+            Some("Basic dXNlcjpwYXNz".to_owned()),
+            header("proxy-authorization")
+        );
+        assert_eq!(None, header("cookie"));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::attribution;
+#[cfg(feature = "publicsuffix")]
+use crate::session::{Session, SessionClient};
+#[cfg(feature = "publicsuffix")]
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use log::debug;
+use url::{Host, Url};
+
+type IsPublicSuffix = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// The public suffix list a `Session` checks the `Domain` attributes of Set-Cookies against, so
+/// that "supercookies" (e.g. with `Domain=co.uk`), sent to every site under a public suffix, are
+/// not stored
+#[derive(Default)]
+pub(crate) struct PublicSuffixes {
+    is_public_suffix: Option<IsPublicSuffix>,
+}
+
+impl PublicSuffixes {
+    #[cfg_attr(not(feature = "publicsuffix"), allow(dead_code))]
+    pub(crate) fn new<F>(is_public_suffix: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        PublicSuffixes {
+            is_public_suffix: Some(Box::new(is_public_suffix)),
+        }
+    }
+
+    /// Whether `domain` is a public suffix, or `None` if no list is in use
+    pub(crate) fn is_public_suffix(&self, domain: &str) -> Option<bool> {
+        self.is_public_suffix
+            .as_ref()
+            .map(|is_public_suffix| is_public_suffix(domain))
+    }
+
+    /// The site of `url`: its registrable domain (see `domain_site`), or its IP address
+    pub(crate) fn site(&self, url: &Url) -> Option<String> {
+        match url.host()? {
+            Host::Domain(domain) => Some(self.domain_site(domain)),
+            host => Some(host.to_string()),
+        }
+    }
+
+    /// The site of `domain`: the public suffix it is under and the label before it, per the list
+    /// (where a top-level domain is always a public suffix), or its last two labels if no list is
+    /// in use. A domain which is itself a public suffix is its own site.
+    pub(crate) fn domain_site(&self, domain: &str) -> String {
+        if self.is_public_suffix.is_none() {
+            return attribution::domain_site(domain);
+        }
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        let labels = domain.split('.').collect::<Vec<_>>();
+        for start in (0..labels.len().saturating_sub(1)).rev() {
+            let suffix = labels[start..].join(".");
+            if self.is_public_suffix(&suffix) != Some(true) {
+                return suffix;
+            }
+        }
+        domain
+    }
+
+    /// `cookies`, received from `url`, split into those to store, and those with a `Domain` of a
+    /// public suffix. A `Domain` of the host of `url` itself is allowed, as browsers do, so that
+    /// sites whose domain is listed (e.g. `github.io`) can still set cookies.
+    pub(crate) fn filter(
+        &self,
+        url: &Url,
+        cookies: Vec<RawCookie<'static>>,
+    ) -> (Vec<RawCookie<'static>>, Vec<RawCookie<'static>>) {
+        if self.is_public_suffix.is_none() {
+            return (cookies, vec![]);
+        }
+        cookies.into_iter().partition(|cookie| {
+            let domain = match cookie.domain() {
+                Some(domain) => domain.trim_start_matches('.').to_lowercase(),
+                None => return true,
+            };
+            if url.host_str() == Some(domain.as_str()) {
+                return true;
+            }
+            let allowed = self.is_public_suffix(&domain) != Some(true);
+            if !allowed {
+                debug!("not storing {} for public suffix {}", cookie.name(), domain);
+            }
+            allowed
+        })
+    }
+}
+
+#[cfg(feature = "publicsuffix")]
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Check the `Domain` attributes of Set-Cookies against `list` (e.g. loaded with
+    /// `publicsuffix::List::from_path`, or embedded with `List::from_str`), not storing cookies
+    /// for a public suffix (such as `Domain=co.uk`) other than the host of the response itself.
+    /// Such cookies are reported to any `StoreEventSink` as `RejectionReason::PublicSuffix`.
+    pub fn with_public_suffix_list(mut self, list: publicsuffix::List) -> Self {
+        self.public_suffixes = PublicSuffixes::new(move |domain| {
+            list.parse_domain(domain)
+                .map(|domain| domain.root().is_none())
+                .unwrap_or(false)
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PublicSuffixes;
+    use cookie::Cookie as RawCookie;
+    use url::Url;
+
+    fn names<'c>(cookies: &'c [RawCookie<'static>]) -> Vec<&'c str> {
+        cookies.iter().map(|c| c.name()).collect()
+    }
+
+    #[test]
+    fn filter() {
+        let cookies = || {
+            vec![
+                RawCookie::parse("host=1").unwrap(),
+                RawCookie::parse("site=1; Domain=.example.co.uk").unwrap(),
+                RawCookie::parse("super=1; Domain=CO.UK").unwrap(),
+                RawCookie::parse("self=1; Domain=co.uk").unwrap(),
+            ]
+        };
+        let url = Url::parse("https://www.example.co.uk/").unwrap();
+        let (stored, blocked) = PublicSuffixes::default().filter(&url, cookies());
+        assert_eq!(4, stored.len());
+        assert!(blocked.is_empty());
+
+        let suffixes = PublicSuffixes::new(|domain| domain == "co.uk" || domain == "uk");
+        let (stored, blocked) = suffixes.filter(&url, cookies());
+        assert_eq!(vec!["host", "site"], names(&stored));
+        assert_eq!(vec!["super", "self"], names(&blocked));
+
+        let url = Url::parse("https://co.uk/").unwrap();
+        let (_, blocked) = suffixes.filter(&url, cookies());
+        assert!(blocked.is_empty());
+    }
+
+    #[test]
+    fn site() {
+        let url = |url| Url::parse(url).unwrap();
+        let suffixes = PublicSuffixes::new(|domain| domain == "co.uk" || domain == "github.io");
+        let site = |u| suffixes.site(&url(u));
+        assert_eq!(Some("a.co.uk".to_owned()), site("https://www.A.co.uk./"));
+        assert_ne!(site("https://a.co.uk/"), site("https://b.co.uk/"));
+        assert_eq!(
+            Some("me.github.io".to_owned()),
+            site("https://x.me.github.io/")
+        );
+        assert_eq!(
+            Some("example.com".to_owned()),
+            site("https://www.example.com/")
+        );
+        assert_eq!(Some("co.uk".to_owned()), site("https://co.uk/"));
+        assert_eq!(Some("localhost".to_owned()), site("http://localhost/"));
+        assert_eq!(Some("127.0.0.1".to_owned()), site("http://127.0.0.1/"));
+        // without a list, by the last two labels
+        let url = url("https://a.co.uk/");
+        assert_eq!(
+            Some("co.uk".to_owned()),
+            PublicSuffixes::default().site(&url)
+        );
+    }
+
+    #[cfg(feature = "publicsuffix")]
+    #[test]
+    fn with_public_suffix_list() {
+        use crate::events::{RejectionReason, StoreEvent};
+        use crate::ReqwestSession;
+        use std::sync::{Arc, Mutex};
+
+        let list =
+            publicsuffix::List::from_str("// ===BEGIN ICANN DOMAINS===\nuk\nco.uk\n").unwrap();
+        let rejected = Arc::new(Mutex::new(vec![]));
+        let recorded = rejected.clone();
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new())
+            .with_public_suffix_list(list)
+            .with_event_sink(move |event: &StoreEvent<'_>| {
+                if let StoreEvent::Rejected { cookie, reason, .. } = event {
+                    recorded
+                        .lock()
+                        .unwrap()
+                        .push((cookie.name().to_owned(), reason.clone()));
+                }
+            });
+        let url = Url::parse("https://www.example.co.uk/").unwrap();
+        session.store_response_cookies(
+            vec![
+                RawCookie::parse("super=1; Domain=co.uk").unwrap(),
+                RawCookie::parse("site=1; Domain=example.co.uk").unwrap(),
+            ],
+            &url,
+        );
+        assert!(session.store.contains("example.co.uk", "/", "site"));
+        assert_eq!(
+            vec![("super".to_owned(), RejectionReason::PublicSuffix)],
+            *rejected.lock().unwrap()
+        );
+    }
+}
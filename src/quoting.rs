@@ -0,0 +1,145 @@
+use crate::domains::DomainMap;
+use crate::lenient::{find_raw_cookie, unquote};
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use log::debug;
+use url::Url;
+
+/// How a `Session` handles cookie values which are quoted, or contain characters not permitted
+/// in cookie values (e.g. `,`, `;`, `\`, and spaces) as some servers set them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValueQuoting {
+    /// Store values as parsed by `cookie`, and percent-encode them when sent (the default)
+    #[default]
+    Normalize,
+    /// Send values exactly as the server set them, quotes and all. Requires the response to
+    /// provide its `SessionResponse::raw_set_cookie` headers, and the request to implement
+    /// `SessionRequest::add_cookie_pairs`.
+    Preserve,
+    /// As `Preserve`, but with any surrounding double quotes removed
+    Strip,
+}
+
+/// The `ValueQuoting` of a `Session`, by domain
+#[derive(Debug, Default)]
+pub(crate) struct ValueQuotingRules {
+    rules: DomainMap<ValueQuoting>,
+}
+
+/// The value of the last cookie named `name` in the raw Set-Cookie headers `raw`, as set: up to
+/// the first `;`, or if quoted, up to its closing quote
+fn raw_value<'r>(raw: &'r [String], name: &str) -> Option<&'r str> {
+    let value = find_raw_cookie(raw, name)?.split_once('=')?.1.trim_start();
+    let end = match value.strip_prefix('"') {
+        Some(quoted) => quoted.find('"').map(|i| i + 2),
+        None => None,
+    };
+    let end = end.or_else(|| value.find(';')).unwrap_or(value.len());
+    Some(value[..end].trim_end())
+}
+
+impl ValueQuotingRules {
+    /// The `ValueQuoting` for cookies exchanged with `url`: that of its host, or failing that, of
+    /// its closest parent domain with one
+    pub(crate) fn for_url(&self, url: &Url) -> ValueQuoting {
+        self.rules.for_url(url).cloned().unwrap_or_default()
+    }
+
+    /// `cookies` received from `url`, with their values restored from the raw Set-Cookie headers
+    /// `raw` as set, per the `ValueQuoting` of `url`
+    pub(crate) fn restore(
+        &self,
+        url: &Url,
+        mut cookies: Vec<RawCookie<'static>>,
+        raw: &[String],
+    ) -> Vec<RawCookie<'static>> {
+        let quoting = self.for_url(url);
+        if quoting == ValueQuoting::Normalize {
+            return cookies;
+        }
+        for cookie in cookies.iter_mut() {
+            let value = match raw_value(raw, cookie.name()) {
+                Some(value) if quoting == ValueQuoting::Strip => unquote(value),
+                Some(value) => value,
+                None => continue,
+            };
+            if value != cookie.value() {
+                debug!("restoring value of {} as set: {}", cookie.name(), value);
+                cookie.set_value(value.to_owned());
+            }
+        }
+        cookies
+    }
+
+    /// The `name=value` pairs to send `cookies` to `url` as, if its `ValueQuoting` is not
+    /// `Normalize`
+    pub(crate) fn pairs(&self, url: &Url, cookies: &[RawCookie<'static>]) -> Option<Vec<String>> {
+        let quoting = self.for_url(url);
+        if quoting == ValueQuoting::Normalize {
+            return None;
+        }
+        let pairs = cookies.iter().map(|cookie| {
+            let value = match quoting {
+                ValueQuoting::Strip => unquote(cookie.value()),
+                _ => cookie.value(),
+            };
+            format!("{}={}", cookie.name(), value)
+        });
+        Some(pairs.collect())
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Set the handling of quoted and special-character values of cookies set by, and sent to,
+    /// `domain` and its subdomains; the setting for the closest domain of a URL applies
+    pub fn with_value_quoting(mut self, domain: &str, quoting: ValueQuoting) -> Self {
+        self.value_quoting.rules.insert(domain, quoting);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{raw_value, ValueQuoting, ValueQuotingRules};
+    use cookie::Cookie as RawCookie;
+    use url::Url;
+
+    #[test]
+    fn value_quoting() {
+        let raw = vec![
+            "a=\"x;y\"; Path=/".to_owned(),
+            "b=1,2; Secure".to_owned(),
+            "a=\"v1\"".to_owned(),
+        ];
+        assert_eq!(Some("\"v1\""), raw_value(&raw, "a"));
+        assert_eq!(Some("1,2"), raw_value(&raw, "b"));
+        assert_eq!(Some("\"x;y\""), raw_value(&raw[..1], "a"));
+        assert_eq!(None, raw_value(&raw, "c"));
+
+        let mut rules = ValueQuotingRules::default();
+        rules.rules.insert("example.com", ValueQuoting::Preserve);
+        rules
+            .rules
+            .insert("legacy.example.com", ValueQuoting::Strip);
+        let preserved = Url::parse("https://www.example.com/").unwrap();
+        let stripped = Url::parse("https://app.legacy.example.com/").unwrap();
+        let other = Url::parse("https://www.example.org/").unwrap();
+        assert_eq!(ValueQuoting::Normalize, rules.for_url(&other));
+
+        let parsed = || vec![RawCookie::parse("a=\"x;y\"; Path=/").unwrap()];
+        let cookies = rules.restore(&preserved, parsed(), &raw[..1]);
+        assert_eq!("\"x;y\"", cookies[0].value());
+        assert_eq!(
+            Some(vec!["a=\"x;y\"".to_owned()]),
+            rules.pairs(&preserved, &cookies)
+        );
+        let cookies = rules.restore(&stripped, parsed(), &raw[..1]);
+        assert_eq!("x;y", cookies[0].value());
+        assert_eq!(
+            Some(vec!["a=x;y".to_owned()]),
+            rules.pairs(&stripped, &cookies)
+        );
+        assert_eq!(None, rules.pairs(&other, &cookies));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use log::debug;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Limits the rate a `Session` sends requests at, per host and overall, set with
+/// `Session::with_rate_limiter`. Requests are spaced evenly: the session sleeps before sending a
+/// request until it would keep within each limit. Every request the session sends is limited,
+/// including each attempt of a retried request, and each redirect followed by the session.
+///
+/// ```
+/// # #[cfg(feature = "reqwest-session")]
+/// # {
+/// use user_agent::{RateLimiter, ReqwestSession};
+///
+/// let limiter = RateLimiter::new().with_per_host(2.0).with_global(10.0);
+/// let session = ReqwestSession::new(reqwest::blocking::Client::new()).with_rate_limiter(limiter);
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    per_host: Option<Duration>,
+    global: Option<Duration>,
+    /// When each host may next be sent a request, for hosts not yet free to be
+    hosts: HashMap<String, Instant>,
+    /// When any request may next be sent
+    next: Option<Instant>,
+}
+
+/// The interval between requests at `requests_per_sec`
+fn interval(requests_per_sec: f64) -> Duration {
+    Duration::from_secs_f64(1.0 / requests_per_sec.max(f64::EPSILON))
+}
+
+impl RateLimiter {
+    /// A limiter with no limits, until configured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send at most `requests_per_sec` requests per second to each host (which may be fractional,
+    /// e.g. 0.5 for a request every 2 seconds)
+    pub fn with_per_host(mut self, requests_per_sec: f64) -> Self {
+        self.per_host = Some(interval(requests_per_sec));
+        self
+    }
+
+    /// Send at most `requests_per_sec` requests per second in all
+    pub fn with_global(mut self, requests_per_sec: f64) -> Self {
+        self.global = Some(interval(requests_per_sec));
+        self
+    }
+
+    /// Reserve the earliest time from `now` a request to `host` may be sent at within the limits
+    fn reserve(&mut self, host: &str, now: Instant) -> Instant {
+        let mut at = now;
+        if let Some(next) = self.next {
+            at = at.max(next);
+        }
+        if let Some(&next) = self.hosts.get(host) {
+            at = at.max(next);
+        }
+        if let Some(interval) = self.global {
+            self.next = Some(at + interval);
+        }
+        if let Some(interval) = self.per_host {
+            // hosts free to be sent a request need not be remembered
+            self.hosts.retain(|_, next| *next > now);
+            self.hosts.insert(host.to_owned(), at + interval);
+        }
+        at
+    }
+
+    /// Sleep until a request to `url` may be sent within the limits
+    pub(crate) fn wait(&mut self, url: &Url) {
+        let now = Instant::now();
+        let at = self.reserve(url.host_str().unwrap_or_default(), now);
+        if at > now {
+            debug!("rate limited; waiting {:?} to send to {}", at - now, url);
+            std::thread::sleep(at - now);
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Limit the rate the session sends requests at per `limiter`
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn reserve() {
+        let start = Instant::now();
+        let secs = |secs: f64| start + Duration::from_secs_f64(secs);
+
+        let mut limiter = RateLimiter::new().with_per_host(2.0);
+        assert_eq!(start, limiter.reserve("a.example", start));
+        assert_eq!(secs(0.5), limiter.reserve("a.example", start));
+        assert_eq!(start, limiter.reserve("b.example", start));
+        assert_eq!(secs(1.0), limiter.reserve("a.example", secs(0.2)));
+        // a host's slot passing frees it
+        assert_eq!(secs(3.0), limiter.reserve("a.example", secs(3.0)));
+        assert_eq!(1, limiter.hosts.len());
+
+        let mut limiter = RateLimiter::new().with_per_host(1.0).with_global(4.0);
+        assert_eq!(start, limiter.reserve("a.example", start));
+        assert_eq!(secs(0.25), limiter.reserve("b.example", start));
+        assert_eq!(secs(1.0), limiter.reserve("a.example", start));
+        assert_eq!(secs(1.25), limiter.reserve("c.example", start));
+
+        let mut unlimited = RateLimiter::new();
+        assert_eq!(start, unlimited.reserve("a.example", start));
+        assert_eq!(start, unlimited.reserve("a.example", start));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::session::{Exchange, Session, SessionClient, SessionResponse};
+use crate::storage::CookieStorage;
+use http::Method;
+use log::{debug, warn};
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+
+/// Who follows the redirects of requests made through a `Session`, set with
+/// `Session::with_redirect_mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub enum RedirectMode {
+    /// The client, if it does (the default). Cookies set by the intermediate redirect responses
+    /// of a request are then not seen by the session, as most clients do not expose them.
+    #[default]
+    Client,
+    /// The session, following at most `max_redirects` redirects of each request, and storing the
+    /// cookies of every response along the way; each request to a redirect target carries the
+    /// cookies in the store at the time it is sent. The client must not follow redirects itself,
+    /// e.g. a reqwest client built with `redirect(reqwest::redirect::Policy::none())` (see
+    /// `ReqwestSession::following_redirects`).
+    ///
+    /// Requests to redirect targets are created anew by the client, so do not carry the headers
+    /// added to the original request by its `prepare` function. As in browsers, a 303 (or a 301
+    /// or 302 of a POST) is followed with a GET; other redirects are followed only for GET and
+    /// HEAD requests, as a request body cannot be re-sent, and are otherwise returned as is (as
+    /// is the last redirect response, when `max_redirects` is exceeded).
+    Session { max_redirects: usize },
+}
+
+/// The method with which to follow a redirect with `status` of a request with `method`, if the
+/// session can follow it
+fn redirect_method(status: u16, method: &str) -> Option<Method> {
+    let bodiless = method == "GET" || method == "HEAD";
+    match status {
+        303 if method == "HEAD" => Some(Method::HEAD),
+        303 => Some(Method::GET),
+        301 | 302 if method == "POST" => Some(Method::GET),
+        301 | 302 | 307 | 308 if bodiless => Method::from_bytes(method.as_bytes()).ok(),
+        _ => None,
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Follow redirects with the client or the session, per `mode`
+    pub fn with_redirect_mode(mut self, mode: RedirectMode) -> Self {
+        self.redirect_mode = mode;
+        self
+    }
+
+    /// Follow the redirects of `response`, received for `exchange`, per `RedirectMode::Session`,
+    /// returning the response they lead to
+    pub(crate) fn follow_redirects(
+        &mut self,
+        mut response: <C as SessionClient>::Response,
+        exchange: &Exchange<'_>,
+    ) -> ::std::result::Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+    {
+        let max_redirects = match self.redirect_mode {
+            RedirectMode::Client => return Ok(response),
+            RedirectMode::Session { max_redirects } => max_redirects,
+        };
+        let mut method = Method::from_bytes(exchange.method.as_bytes()).unwrap_or(Method::GET);
+        let mut url = exchange.url.clone();
+        for redirects in 0.. {
+            let status = match response.status_code() {
+                Some(status) if (300..400).contains(&status) => status,
+                _ => break,
+            };
+            let next_method = match redirect_method(status, method.as_str()) {
+                Some(next_method) => next_method,
+                None => break,
+            };
+            let location = match response.location().and_then(|l| url.join(&l).ok()) {
+                Some(location) => self.request_url(location),
+                None => break,
+            };
+            if redirects == max_redirects {
+                warn!(
+                    "not following redirect of {} to {}: {} redirects followed",
+                    url, location, max_redirects
+                );
+                break;
+            }
+            debug!("following {} redirect of {} to {}", status, url, location);
+            self.counters.redirects += 1;
+            method = next_method;
+            url = location;
+            let canonical = self.origin_aliases.canonical(&url);
+            let hop = Exchange {
+                method: method.as_str(),
+                url: &url,
+                cookie_url: canonical.as_ref().unwrap_or(&url),
+                mode: exchange.mode,
+                initiator: exchange.initiator.clone(),
+                opts: exchange.opts.for_redirect(),
+            };
+            let request = self.client.request(&method, &url);
+            response = self.exchange(request, &hop, |request| request)?;
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redirect_method, RedirectMode};
+    use crate::opts::RequestOpts;
+    use crate::session::{
+        ClassifySendError, Session, SessionClient, SessionRequest, SessionResponse,
+    };
+    use cookie::Cookie as RawCookie;
+    use http::Method;
+    use std::cell::RefCell;
+    use url::{ParseError as ParseUrlError, Url};
+
+    /// Redirects `/login` to `/next`, and `/next` to `/home`, setting a cookie on each
+    #[derive(Default)]
+    struct Client {
+        sent: RefCell<Vec<(Method, String, Vec<String>)>>,
+    }
+    struct Request(Method, Url, Vec<String>);
+    struct Response(Url);
+    #[derive(Debug)]
+    struct Error;
+
+    impl SessionRequest for Request {
+        fn add_cookies(mut self, cookies: Vec<&RawCookie<'static>>) -> Self {
+            self.2 = cookies.iter().map(|c| c.name().to_owned()).collect();
+            // in the order stored only with `preserve_order`
+            self.2.sort();
+            self
+        }
+    }
+    impl SessionResponse for Response {
+        type Url = Url;
+        fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+            let name = self.0.path().trim_start_matches('/').to_owned();
+            vec![RawCookie::new(name, "1")]
+        }
+        fn final_url(&self) -> Option<&Url> {
+            Some(&self.0)
+        }
+        fn status_code(&self) -> Option<u16> {
+            Some(if self.location().is_some() { 302 } else { 200 })
+        }
+        fn location(&self) -> Option<String> {
+            match self.0.path() {
+                "/login" => Some("/next".to_owned()),
+                "/next" => Some("https://www.example.com/home".to_owned()),
+                _ => None,
+            }
+        }
+    }
+    impl From<ParseUrlError> for Error {
+        fn from(_: ParseUrlError) -> Self {
+            Error
+        }
+    }
+    impl ClassifySendError for Error {}
+    impl SessionClient for &Client {
+        type Request = Request;
+        type Response = Response;
+        type SendError = Error;
+        fn request(&self, method: &Method, url: &Url) -> Request {
+            Request(method.clone(), url.clone(), vec![])
+        }
+        fn send(&self, request: Request) -> Result<Response, Error> {
+            let Request(method, url, cookies) = request;
+            let sent = (method, url.path().to_owned(), cookies);
+            self.sent.borrow_mut().push(sent);
+            Ok(Response(url))
+        }
+    }
+
+    #[test]
+    fn follow_redirects() {
+        let url = "https://www.example.com/login";
+        let client = Client::default();
+        let mut session =
+            Session::new(&client).with_redirect_mode(RedirectMode::Session { max_redirects: 5 });
+        let response = session.post(url).unwrap();
+        assert_eq!("/home", response.0.path());
+        assert_eq!(
+            vec![
+                (Method::POST, "/login".to_owned(), vec![]),
+                (Method::GET, "/next".to_owned(), vec!["login".to_owned()]),
+                (
+                    Method::GET,
+                    "/home".to_owned(),
+                    vec!["login".to_owned(), "next".to_owned()]
+                ),
+            ],
+            client.sent.borrow_mut().split_off(0)
+        );
+        for name in &["login", "next", "home"] {
+            assert!(session.store.contains("www.example.com", "/", name));
+        }
+
+        let mut session =
+            Session::new(&client).with_redirect_mode(RedirectMode::Session { max_redirects: 1 });
+        assert_eq!("/next", session.get(url).unwrap().0.path());
+        assert_eq!(2, client.sent.borrow().len());
+
+        let mut session = Session::new(&client);
+        assert_eq!("/login", session.get(url).unwrap().0.path());
+
+        // one-off cookies are not sent to redirect targets
+        let opts = RequestOpts::new()
+            .without_cookies()
+            .with_cookie(RawCookie::new("probe", "1"));
+        let mut session =
+            Session::new(&client).with_redirect_mode(RedirectMode::Session { max_redirects: 5 });
+        client.sent.borrow_mut().clear();
+        session.get(url).unwrap();
+        client.sent.borrow_mut().clear();
+        session.get_with_opts(url, opts, |r| r).unwrap();
+        let sent = client.sent.borrow_mut().split_off(0);
+        let cookies = sent.into_iter().map(|(_, _, c)| c).collect::<Vec<_>>();
+        assert_eq!(vec![vec!["probe".to_owned()], vec![], vec![]], cookies);
+    }
+
+    #[test]
+    fn methods() {
+        assert_eq!(Some(Method::GET), redirect_method(303, "PUT"));
+        assert_eq!(Some(Method::HEAD), redirect_method(303, "HEAD"));
+        assert_eq!(Some(Method::GET), redirect_method(302, "POST"));
+        assert_eq!(Some(Method::HEAD), redirect_method(308, "HEAD"));
+        assert_eq!(None, redirect_method(307, "POST"));
+        assert_eq!(None, redirect_method(301, "DELETE"));
+        assert_eq!(None, redirect_method(304, "GET"));
+    }
+}
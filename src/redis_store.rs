@@ -0,0 +1,288 @@
+use crate::error::SessionError;
+use crate::jar::insert_loaded;
+use crate::storage::CookieStorage;
+use crate::utils::{request_domains, unix_time};
+use cookie::Cookie as RawCookie;
+use cookie_store::{Cookie, CookieError, CookieStore};
+use log::{debug, warn};
+use redis::{Commands, Connection, PipelineCommands, RedisResult};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{BufRead, Write};
+use url::Url;
+
+/// A `CookieStorage` kept in a shared Redis instance, so that several workers may act as one
+/// session. Each domain's cookies are held in a Redis hash at `{prefix}:{domain}` (mapping
+/// `{path};{name}` to the cookie in `save_json` format), expiring with the last of its cookies.
+///
+/// The cookies for a URL are reloaded from Redis before each request to it (and those of a domain
+/// before the first cookie received for it), updating only the cookies which changed, and
+/// received cookies are written through immediately; should Redis be unavailable, the locally cached cookies are
+/// used, and writes which failed are retried on `flush`. Concurrent updates to the same cookie
+/// are last-write-wins.
+pub struct RedisCookieStore {
+    connection: Connection,
+    prefix: String,
+    mirror: Mirror,
+    /// The (domain, path, name) of each cookie which could not be written to Redis
+    unsaved: BTreeSet<(String, String, String)>,
+}
+
+/// The cookies of the domains loaded from Redis, both as loaded and in a store for matching
+#[derive(Default)]
+struct Mirror {
+    /// The `save_json` formatted cookies of each domain loaded, by field
+    domains: HashMap<String, BTreeMap<String, String>>,
+    cache: CookieStore,
+}
+
+impl Mirror {
+    /// Replace the cookies of `domain` with `fields`, as loaded from Redis, updating in the cache
+    /// only those cookies which changed
+    fn sync(&mut self, domain: &str, fields: BTreeMap<String, String>) {
+        let Mirror { domains, cache } = self;
+        let previous = domains
+            .insert(domain.to_owned(), fields)
+            .unwrap_or_default();
+        let current = &domains[domain];
+        for field in previous.keys().filter(|f| !current.contains_key(*f)) {
+            if let Some((path, name)) = field.split_once(';') {
+                cache.remove(domain, path, name);
+            }
+        }
+        for (field, cookie) in current {
+            if previous.get(field) == Some(cookie) {
+                continue;
+            }
+            let inserted = match serde_json::from_str::<Cookie<'static>>(cookie) {
+                Ok(cookie) => insert_loaded(cache, cookie).map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = inserted {
+                debug!("not loading cookie {} of {}: {}", field, domain, e);
+            }
+        }
+    }
+}
+
+impl RedisCookieStore {
+    /// Connect to the Redis instance at `url` (e.g. `redis://127.0.0.1/`), storing cookies under
+    /// keys beginning with `prefix`
+    pub fn open(url: &str, prefix: &str) -> RedisResult<Self> {
+        let connection = redis::Client::open(url)?.get_connection()?;
+        Ok(Self::from_connection(connection, prefix))
+    }
+
+    /// Store cookies through an existing `connection`, under keys beginning with `prefix`
+    pub fn from_connection(connection: Connection, prefix: &str) -> Self {
+        RedisCookieStore {
+            connection,
+            prefix: prefix.to_owned(),
+            mirror: Mirror::default(),
+            unsaved: BTreeSet::new(),
+        }
+    }
+
+    fn key(&self, domain: &str) -> String {
+        format!("{}:{}", self.prefix, domain)
+    }
+
+    fn load_domain(&mut self, domain: &str) {
+        let key = self.key(domain);
+        match self.connection.hgetall::<_, BTreeMap<String, String>>(&key) {
+            Ok(fields) => {
+                debug!("loaded {} cookies from {}", fields.len(), key);
+                self.mirror.sync(domain, fields);
+            }
+            Err(e) => warn!("unable to load cookies for {} from Redis: {}", domain, e),
+        }
+    }
+
+    /// Write the cookie `field` of `domain` as now held in the cache, and update the key's expiry
+    fn save_field(&mut self, domain: &str, path: &str, name: &str) -> RedisResult<()> {
+        let key = self.key(domain);
+        let field = format!("{};{}", path, name);
+        let cookie = match self.mirror.cache.get(domain, path, name) {
+            Some(cookie) => Some(
+                serde_json::to_string(cookie)
+                    .map_err(|e| (redis::ErrorKind::TypeError, "", e.to_string()))?,
+            ),
+            None => None,
+        };
+        let fields = self.mirror.domains.entry(domain.to_owned()).or_default();
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        match cookie {
+            Some(cookie) => {
+                pipe.hset(&key, &field, &cookie).ignore();
+                fields.insert(field, cookie);
+            }
+            None => {
+                pipe.hdel(&key, &field).ignore();
+                fields.remove(&field);
+            }
+        }
+        match expires_at(fields.values()) {
+            Some(at) => pipe.expire_at(&key, at).ignore(),
+            None => pipe.persist(&key).ignore(),
+        };
+        pipe.query(&mut self.connection)
+    }
+}
+
+/// The time (in seconds since the Unix epoch) at which the last of `cookies` (in `save_json`
+/// format) expires, or `None` if any lasts for the session
+fn expires_at<'a, I: Iterator<Item = &'a String>>(cookies: I) -> Option<usize> {
+    let mut last = 0;
+    for cookie in cookies {
+        let value = serde_json::from_str::<serde_json::Value>(cookie).ok()?;
+        let at = value["expires"]["AtUtc"].as_str().and_then(unix_time)?;
+        last = last.max(at);
+    }
+    Some(last as usize)
+}
+
+impl CookieStorage for RedisCookieStore {
+    fn refresh(&mut self, url: &Url) {
+        for domain in request_domains(url) {
+            self.load_domain(&domain);
+        }
+    }
+
+    fn get_request_cookies(&self, url: &Url) -> Vec<RawCookie<'static>> {
+        CookieStorage::get_request_cookies(&self.mirror.cache, url)
+    }
+
+    fn insert_raw(&mut self, cookie: &RawCookie<'static>, url: &Url) -> Result<(), CookieError> {
+        let parsed = Cookie::try_from_raw_cookie(cookie, url)?;
+        let (domain, path) = (String::from(&parsed.domain), String::from(&parsed.path));
+        if !self.mirror.domains.contains_key(&domain) {
+            self.load_domain(&domain);
+        }
+        CookieStorage::insert_raw(&mut self.mirror.cache, cookie, url)?;
+        if let Err(e) = self.save_field(&domain, &path, cookie.name()) {
+            warn!("unable to save cookie {} to Redis: {}", cookie.name(), e);
+            self.unsaved
+                .insert((domain, path, cookie.name().to_owned()));
+        }
+        Ok(())
+    }
+
+    fn iter_any(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_> {
+        CookieStorage::iter_any(&self.mirror.cache)
+    }
+
+    fn remove(&mut self, domain: &str, path: &str, name: &str) -> Option<Cookie<'static>> {
+        let removed = self.mirror.cache.remove(domain, path, name)?;
+        if let Err(e) = self.save_field(domain, path, name) {
+            warn!("unable to remove cookie {} from Redis: {}", name, e);
+            let cookie = (domain.to_owned(), path.to_owned(), name.to_owned());
+            self.unsaved.insert(cookie);
+        }
+        Some(removed).filter(|c| !c.is_expired())
+    }
+
+    fn save<W, E, F>(&self, writer: &mut W, cookie_to_string: F) -> Result<(), SessionError>
+    where
+        W: Write,
+        F: Fn(&Cookie<'_>) -> Result<String, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        CookieStorage::save(&self.mirror.cache, writer, cookie_to_string)
+    }
+
+    fn save_json<W: Write>(&self, writer: &mut W) -> Result<(), SessionError> {
+        CookieStorage::save_json(&self.mirror.cache, writer)
+    }
+
+    fn flush(&mut self) -> Result<(), SessionError> {
+        let unsaved = std::mem::take(&mut self.unsaved);
+        for (domain, path, name) in unsaved {
+            if let Err(e) = self.save_field(&domain, &path, &name) {
+                self.unsaved.insert((domain, path, name));
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn load<R, E, F>(_: R, _: F) -> Result<Self, SessionError>
+    where
+        R: BufRead,
+        F: Fn(&str) -> Result<Cookie<'static>, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Err("a RedisCookieStore must be created with RedisCookieStore::open".into())
+    }
+
+    fn load_json<R: BufRead>(_: R) -> Result<Self, SessionError> {
+        Err("a RedisCookieStore must be created with RedisCookieStore::open".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expires_at, Mirror};
+    use std::collections::BTreeMap;
+    use url::Url;
+
+    #[test]
+    fn mirror() {
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let cookie = |name: &str, value: &str| {
+            let json = format!(
+                r#"{{"raw_cookie":"{}={}","path":["/",false],"domain":{{"HostOnly":"www.example.com"}},"expires":"SessionEnd"}}"#,
+                name, value
+            );
+            (format!("/;{}", name), json)
+        };
+        let sent = |mirror: &Mirror| {
+            let mut sent = mirror
+                .cache
+                .get_request_cookies(&url)
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>();
+            sent.sort();
+            sent
+        };
+        let mut mirror = Mirror::default();
+        mirror.sync(
+            "www.example.com",
+            vec![cookie("a", "1"), cookie("b", "2")]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(vec!["a=1", "b=2"], sent(&mirror));
+
+        // inserted locally, then reloaded with another worker's update and removal
+        mirror.cache.parse("c=3", &url).unwrap();
+        let (field, json) = cookie("c", "3");
+        mirror
+            .domains
+            .get_mut("www.example.com")
+            .unwrap()
+            .insert(field, json);
+        mirror.sync(
+            "www.example.com",
+            vec![cookie("a", "10"), cookie("c", "3")]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(vec!["a=10", "c=3"], sent(&mirror));
+
+        mirror.sync("www.example.com", BTreeMap::new());
+        assert!(sent(&mirror).is_empty());
+        assert!(mirror.domains["www.example.com"].is_empty());
+    }
+
+    #[test]
+    fn expiry() {
+        let persistent = r#"{"raw_cookie":"1=a","path":["/",false],"domain":{"HostOnly":"www.example.com"},"expires":{"AtUtc":"2020-03-04T05:06:07Z"}}"#.to_owned();
+        let later = persistent.replace("2020", "2021");
+        let session = r#"{"raw_cookie":"2=b","path":["/",false],"domain":{"HostOnly":"www.example.com"},"expires":"SessionEnd"}"#.to_owned();
+        assert_eq!(
+            Some(1_614_834_367),
+            expires_at(vec![&persistent, &later].into_iter())
+        );
+        assert_eq!(None, expires_at(vec![&persistent, &session].into_iter()));
+    }
+}
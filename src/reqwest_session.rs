@@ -1,43 +1,99 @@
-use crate::session::{Session, SessionClient, SessionRequest, SessionResponse};
+use crate::redirect::RedirectMode;
+use crate::session::{
+    ClassifySendError, SendErrorKind, Session, SessionClient, SessionRequest, SessionResponse,
+};
+use crate::utils::{parse_set_cookie_values, IntoUrl};
 use cookie::Cookie as RawCookie;
 use log::debug;
 use reqwest;
+use reqwest::blocking::{RequestBuilder, Response};
 use reqwest::header::{COOKIE, SET_COOKIE};
+#[cfg(feature = "persistence")]
+use serde::Serialize;
 use url::Url;
 
 impl SessionResponse for reqwest::blocking::Response {
     type Url = url::Url;
     fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+        parse_set_cookie_values(
+            self.headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .map(|set_cookie| set_cookie.as_bytes()),
+        )
+    }
+
+    fn final_url(&self) -> Option<&url::Url> {
+        Some(&self.url())
+    }
+
+    fn is_proxy_response(&self) -> bool {
+        self.status() == reqwest::StatusCode::PROXY_AUTHENTICATION_REQUIRED
+    }
+
+    fn date(&self) -> Option<String> {
         self.headers()
+            .get(reqwest::header::DATE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned)
+    }
+
+    fn raw_set_cookie(&self) -> Vec<String> {
+        let headers = self.headers();
+        headers
             .get_all(SET_COOKIE)
             .iter()
-            .filter_map(|set_cookie| {
-                set_cookie
-                    .to_str()
-                    .map_err(|e| {
-                        debug!(
-                            "error parsing Set-Cookie to String {:?}: {:?}",
-                            set_cookie, e
-                        );
-                        e
-                    })
-                    .ok()
-                    .and_then(|sc| match RawCookie::parse(sc.to_owned()) {
-                        Ok(raw_cookie) => Some(raw_cookie),
-                        Err(e) => {
-                            debug!(
-                                "error parsing Set-Cookie to RawCookie {:?}: {:?}",
-                                set_cookie, e
-                            );
-                            None
-                        }
-                    })
-            })
-            .collect::<Vec<_>>()
+            .chain(headers.get_all("set-cookie2").iter())
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+            .collect()
     }
 
-    fn final_url(&self) -> Option<&url::Url> {
-        Some(&self.url())
+    fn clear_site_data(&self) -> Option<String> {
+        let values = self
+            .headers()
+            .get_all("clear-site-data")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    fn status_code(&self) -> Option<u16> {
+        Some(self.status().as_u16())
+    }
+
+    fn retry_after(&self) -> Option<String> {
+        self.headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned)
+    }
+
+    fn location(&self) -> Option<String> {
+        self.headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned)
+    }
+
+    fn strict_transport_security(&self) -> Option<String> {
+        self.headers()
+            .get(reqwest::header::STRICT_TRANSPORT_SECURITY)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned)
+    }
+
+    fn www_authenticate(&self) -> Vec<String> {
+        self.headers()
+            .get_all(reqwest::header::WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .map(str::to_owned)
+            .collect()
     }
 }
 
@@ -55,24 +111,136 @@ impl SessionRequest for reqwest::blocking::RequestBuilder {
             out
         }
     }
+
+    fn add_cookie_pairs(self, pairs: Vec<String>) -> Self {
+        let mut out = self;
+        for pair in pairs {
+            out = out.header(COOKIE, pair);
+        }
+        out
+    }
+
+    fn add_header(self, name: &str, value: &str) -> Self {
+        self.header(name, value)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        reqwest::blocking::RequestBuilder::try_clone(self)
+    }
 }
 
 #[derive(Debug)]
 pub enum ReqwestSessionError {
     ParseUrlError(url::ParseError),
+    /// The request or connection timed out
+    Timeout(reqwest::Error),
+    /// A connection to the server could not be established
+    Connect(reqwest::Error),
+    /// The TLS handshake failed
+    Tls(reqwest::Error),
+    /// The response body could not be decoded
+    Decode(reqwest::Error),
+    /// An error status, as from `reqwest::blocking::Response::error_for_status`
+    Status(reqwest::Error),
     ReqwestError(reqwest::Error),
+    /// Reading a response body failed
+    Io(std::io::Error),
+}
+
+impl ReqwestSessionError {
+    /// The underlying `reqwest::Error`, if any
+    pub fn reqwest_error(&self) -> Option<&reqwest::Error> {
+        match self {
+            ReqwestSessionError::ParseUrlError(_) | ReqwestSessionError::Io(_) => None,
+            ReqwestSessionError::Timeout(e)
+            | ReqwestSessionError::Connect(e)
+            | ReqwestSessionError::Tls(e)
+            | ReqwestSessionError::Decode(e)
+            | ReqwestSessionError::Status(e)
+            | ReqwestSessionError::ReqwestError(e) => Some(e),
+        }
+    }
+
+    /// The response status, for `Status` errors
+    pub fn status(&self) -> Option<reqwest::StatusCode> {
+        match self {
+            ReqwestSessionError::Status(e) => e.status(),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for ReqwestSessionError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             ReqwestSessionError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            ReqwestSessionError::Timeout(e) => write!(f, "Timeout error: {}", e),
+            ReqwestSessionError::Connect(e) => write!(f, "Connect error: {}", e),
+            ReqwestSessionError::Tls(e) => write!(f, "TLS error: {}", e),
+            ReqwestSessionError::Decode(e) => write!(f, "Decode error: {}", e),
+            ReqwestSessionError::Status(e) => write!(f, "Status error: {}", e),
             ReqwestSessionError::ReqwestError(e) => write!(f, "Reqwest error: {}", e),
+            ReqwestSessionError::Io(e) => write!(f, "I/O error: {}", e),
         }
     }
 }
 
-impl std::error::Error for ReqwestSessionError {}
+impl std::error::Error for ReqwestSessionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReqwestSessionError::ParseUrlError(e) => Some(e),
+            ReqwestSessionError::Io(e) => Some(e),
+            _ => self.reqwest_error().map(|e| e as _),
+        }
+    }
+}
+
+impl ClassifySendError for ReqwestSessionError {
+    fn kind(&self) -> SendErrorKind {
+        match self {
+            ReqwestSessionError::ParseUrlError(_) => SendErrorKind::Url,
+            ReqwestSessionError::Timeout(_) => SendErrorKind::Timeout,
+            ReqwestSessionError::Connect(_) => SendErrorKind::Connect,
+            ReqwestSessionError::Tls(_) => SendErrorKind::Tls,
+            ReqwestSessionError::Decode(_) => SendErrorKind::Decode,
+            ReqwestSessionError::Status(e) => match e.status() {
+                Some(status) => SendErrorKind::Status(status.as_u16()),
+                None => SendErrorKind::Other,
+            },
+            ReqwestSessionError::ReqwestError(_) => SendErrorKind::Other,
+            ReqwestSessionError::Io(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                SendErrorKind::Timeout
+            }
+            ReqwestSessionError::Io(_) => SendErrorKind::Other,
+        }
+    }
+}
+
+/// Whether the cause of `e` is a failure of the TLS backend in use
+fn is_tls_error(e: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        #[cfg(feature = "default-tls")]
+        {
+            if cause.is::<native_tls::Error>() {
+                return true;
+            }
+        }
+        #[cfg(feature = "rustls-tls")]
+        {
+            // tokio-rustls reports handshake failures as I/O errors wrapping the rustls error
+            let inner = cause
+                .downcast_ref::<std::io::Error>()
+                .and_then(|io| io.get_ref())
+                .map(|inner| inner.is::<rustls::TLSError>());
+            if cause.is::<rustls::TLSError>() || inner == Some(true) {
+                return true;
+            }
+        }
+        source = cause.source();
+    }
+    false
+}
 
 impl From<url::ParseError> for ReqwestSessionError {
     fn from(e: url::ParseError) -> Self {
@@ -82,12 +250,87 @@ impl From<url::ParseError> for ReqwestSessionError {
 
 impl From<reqwest::Error> for ReqwestSessionError {
     fn from(e: reqwest::Error) -> Self {
-        ReqwestSessionError::ReqwestError(e)
+        if is_tls_error(&e) {
+            ReqwestSessionError::Tls(e)
+        } else if e.is_timeout() {
+            ReqwestSessionError::Timeout(e)
+        } else if e.is_connect() {
+            ReqwestSessionError::Connect(e)
+        } else if e.is_decode() {
+            ReqwestSessionError::Decode(e)
+        } else if e.is_status() {
+            ReqwestSessionError::Status(e)
+        } else {
+            ReqwestSessionError::ReqwestError(e)
+        }
+    }
+}
+
+impl From<std::io::Error> for ReqwestSessionError {
+    fn from(e: std::io::Error) -> Self {
+        // reading a blocking response's body reports reqwest's errors wrapped in I/O errors
+        let kind = e.kind();
+        match e.get_ref() {
+            Some(inner) if inner.is::<reqwest::Error>() => {
+                match e.into_inner().map(|inner| inner.downcast::<reqwest::Error>()) {
+                    Some(Ok(inner)) => ReqwestSessionError::from(*inner),
+                    Some(Err(inner)) => ReqwestSessionError::Io(std::io::Error::new(kind, inner)),
+                    None => ReqwestSessionError::Io(kind.into()),
+                }
+            }
+            _ => ReqwestSessionError::Io(e),
+        }
     }
 }
 
 pub type ReqwestSession = Session<reqwest::blocking::Client>;
 
+impl ReqwestSession {
+    /// Create a session with a client built by `builder`, with its redirect policy disabled, so
+    /// that the session follows up to `max_redirects` redirects itself, storing the cookies of
+    /// each (see `RedirectMode::Session`)
+    pub fn following_redirects(
+        builder: reqwest::blocking::ClientBuilder,
+        max_redirects: usize,
+    ) -> Result<Self, ReqwestSessionError> {
+        let client = builder.redirect(reqwest::redirect::Policy::none()).build()?;
+        Ok(Session::new(client).with_redirect_mode(RedirectMode::Session { max_redirects }))
+    }
+
+    /// POST `form` to `url` as an `application/x-www-form-urlencoded` body, preparing the request
+    /// further with `prepare`. Requires the `persistence` feature, for `serde`.
+    #[cfg(feature = "persistence")]
+    pub fn post_form_with<U, T, P>(
+        &mut self,
+        url: U,
+        form: &T,
+        prepare: P,
+    ) -> Result<Response, ReqwestSessionError>
+    where
+        U: IntoUrl,
+        T: Serialize + ?Sized,
+        P: FnOnce(RequestBuilder) -> RequestBuilder,
+    {
+        self.post_with(url, |request| prepare(request.form(form)))
+    }
+
+    /// POST `multipart` to `url` as a `multipart/form-data` body, preparing the request further
+    /// with `prepare`. As the body cannot be cloned, the request is not retried (see
+    /// `Session::with_retry`).
+    pub fn post_multipart_with<U, P>(
+        &mut self,
+        url: U,
+        multipart: reqwest::blocking::multipart::Form,
+        prepare: P,
+    ) -> Result<Response, ReqwestSessionError>
+    where
+        U: IntoUrl,
+        P: FnOnce(RequestBuilder) -> RequestBuilder,
+    {
+        self.post_with(url, |request| prepare(request.multipart(multipart)))
+    }
+}
+
 impl SessionClient for reqwest::blocking::Client {
     type Request = reqwest::blocking::RequestBuilder;
     type Response = reqwest::blocking::Response;
@@ -108,19 +351,46 @@ impl SessionClient for reqwest::blocking::Client {
     fn post_request(&self, url: &Url) -> Self::Request {
         self.post(url.clone())
     }
+    fn patch_request(&self, url: &Url) -> Self::Request {
+        self.patch(url.clone())
+    }
+    fn options_request(&self, url: &Url) -> Self::Request {
+        self.request(reqwest::Method::OPTIONS, url.clone())
+    }
+    fn request(&self, method: &reqwest::Method, url: &Url) -> Self::Request {
+        reqwest::blocking::Client::request(self, method.clone(), url.clone())
+    }
 
     fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
-        request.send().map_err(ReqwestSessionError::from)
+        let response = request.send().map_err(ReqwestSessionError::from);
+        #[cfg(feature = "tracing")]
+        match response {
+            Ok(ref response) => tracing::trace!(
+                status = response.status().as_u16(),
+                version = ?response.version(),
+                url = %response.url(),
+                "reqwest response"
+            ),
+            Err(ref e) => tracing::trace!(error = %e, "reqwest request failed"),
+        }
+        response
     }
 }
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "persistence")]
     use env_logger;
     use reqwest;
 
-    use super::ReqwestSession;
+    use super::{ReqwestSession, ReqwestSessionError};
+    use crate::{ClassifySendError, SendErrorKind};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use url::Url;
 
+    #[cfg(feature = "persistence")]
     macro_rules! dump {
         ($e: expr, $i: ident) => {{
             use serde_json;
@@ -145,6 +415,7 @@ mod tests {
         }};
     }
 
+    #[cfg(feature = "persistence")]
     fn assert_cookies_count(session: &mut ReqwestSession, url: &str, add: bool) {
         let cookies_count_origin = session.store.iter_unexpired().count();
         session
@@ -167,6 +438,103 @@ mod tests {
         assert_eq!(cookies_count, cookies_count_expected);
     }
 
+    #[test]
+    fn error_kinds() {
+        let mut s = ReqwestSession::new(reqwest::blocking::Client::new());
+        let e = s.get("not a url").unwrap_err();
+        assert_eq!(SendErrorKind::Url, e.kind());
+        assert!(!e.is_retryable());
+
+        // nothing listens on port 1, so the connection is refused
+        let e = s.get("http://127.0.0.1:1/").unwrap_err();
+        assert_eq!(SendErrorKind::Connect, e.kind());
+        assert!(e.is_retryable());
+
+        let status = |code: u16| {
+            let response = http::Response::builder().status(code).body("").unwrap();
+            ReqwestSessionError::from(
+                reqwest::Response::from(response)
+                    .error_for_status()
+                    .unwrap_err(),
+            )
+        };
+        assert_eq!(SendErrorKind::Status(503), status(503).kind());
+        assert!(status(503).is_retryable());
+        assert!(status(429).is_retryable());
+        assert!(!status(404).is_retryable());
+        assert_eq!(Some(reqwest::StatusCode::NOT_FOUND), status(404).status());
+    }
+
+    /// Serve `response` to one request, returning the request's head and body
+    fn serve(response: &'static str) -> (u16, thread::JoinHandle<(Vec<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_owned();
+                if line.is_empty() {
+                    break;
+                }
+                head.push(line);
+            }
+            let length = head
+                .iter()
+                .find_map(|h| {
+                    let h = h.to_lowercase();
+                    h.strip_prefix("content-length:")?.trim().parse().ok()
+                })
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            (&stream).write_all(response.as_bytes()).unwrap();
+            (head, String::from_utf8_lossy(&body).into_owned())
+        });
+        (port, server)
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn post_form_with() {
+        let (port, server) =
+            serve("HTTP/1.1 200 OK\r\nSet-Cookie: id=2\r\nContent-Length: 0\r\n\r\n");
+        let url = Url::parse(&format!("http://127.0.0.1:{}/login", port)).unwrap();
+        let mut s = ReqwestSession::new(reqwest::blocking::Client::new());
+        s.store.parse("id=1", &url).unwrap();
+        let form = [("user", "jo"), ("note", "a b")];
+        let response = s
+            .post_form_with(url.clone(), &form, |r| r.header("x-probe", "1"))
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+        let (head, body) = server.join().unwrap();
+        let head = head.join("\n").to_lowercase();
+        assert!(head.contains("content-type: application/x-www-form-urlencoded"));
+        assert!(head.contains("cookie: id=1"));
+        assert!(head.contains("x-probe: 1"));
+        assert_eq!("user=jo&note=a+b", body);
+        assert_eq!("2", s.get_cookie("127.0.0.1", "/", "id").unwrap().value());
+    }
+
+    #[test]
+    fn post_multipart_with() {
+        let (port, server) = serve("HTTP/1.1 204 No Content\r\n\r\n");
+        let url = Url::parse(&format!("http://127.0.0.1:{}/upload", port)).unwrap();
+        let mut s = ReqwestSession::new(reqwest::blocking::Client::new());
+        s.store.parse("id=1", &url).unwrap();
+        let form = reqwest::blocking::multipart::Form::new().text("title", "notes");
+        s.post_multipart_with(url.clone(), form, |r| r).unwrap();
+        let (head, body) = server.join().unwrap();
+        let head = head.join("\n").to_lowercase();
+        assert!(head.contains("content-type: multipart/form-data; boundary="));
+        assert!(head.contains("cookie: id=1"));
+        assert!(body.contains("name=\"title\"\r\n\r\nnotes"));
+    }
+
+    #[cfg(feature = "persistence")]
     #[test]
     fn test_gets() {
         env_logger::init();
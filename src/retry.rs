@@ -0,0 +1,229 @@
+use crate::session::{
+    ClassifySendError, Exchange, Session, SessionClient, SessionRequest, SessionResponse,
+};
+use crate::storage::CookieStorage;
+use crate::utils::{http_date, unix_secs};
+use log::debug;
+use std::time::{Duration, SystemTime};
+
+/// How a `Session` retries requests which fail transiently, set with `Session::with_retry_policy`.
+///
+/// Only idempotent requests (GET, HEAD, PUT, DELETE, OPTIONS and TRACE) are retried, when sending
+/// them fails with a retryable error (see `ClassifySendError::is_retryable`) or the response has
+/// one of the retry statuses. Each attempt carries the cookies in the store at the time it is
+/// sent, so cookies set by a failed attempt's response are sent with the next. (The `prepare`
+/// function of a retried request is called once, before any cookies are added to it; see
+/// `SessionRequest::try_clone`.)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    retry_statuses: Vec<u16>,
+    honor_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    /// 3 attempts, backing off from 500ms up to 30s, retrying on 429, 502 and 503 and honoring
+    /// `Retry-After`
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            retry_statuses: vec![429, 502, 503],
+            honor_retry_after: true,
+        }
+    }
+}
+
+/// How long the `Retry-After` header `value` asks to wait, at `now` (in seconds since the Unix
+/// epoch): a number of seconds, or an HTTP date
+pub(crate) fn parse_retry_after(value: &str, now: i64) -> Option<Duration> {
+    let value = value.trim();
+    value
+        .parse::<u64>()
+        .ok()
+        .or_else(|| http_date(value).map(|at| (at - now).max(0) as u64))
+        .map(Duration::from_secs)
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make at most `max_attempts` attempts at each request (including the first)
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Wait `initial_backoff` before the first retry, doubling the wait before each further
+    /// retry up to `max_backoff`
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff.max(initial_backoff);
+        self
+    }
+
+    /// Retry responses with any of `statuses`, rather than 429, 502 and 503
+    pub fn with_retry_statuses<I: IntoIterator<Item = u16>>(mut self, statuses: I) -> Self {
+        self.retry_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Whether to wait as long as a response's `Retry-After` header asks (up to the maximum
+    /// backoff) before retrying it, rather than backing off as usual
+    pub fn with_honor_retry_after(mut self, honor_retry_after: bool) -> Self {
+        self.honor_retry_after = honor_retry_after;
+        self
+    }
+
+    /// Whether requests with `method` may be retried
+    pub(crate) fn applies_to(&self, method: &str) -> bool {
+        self.max_attempts > 1
+            && ["GET", "HEAD", "PUT", "DELETE", "OPTIONS", "TRACE"]
+                .iter()
+                .any(|m| m.eq_ignore_ascii_case(method))
+    }
+
+    /// How long to wait after failed attempt number `attempt` (from 1), whose response asked
+    /// to be retried after `retry_after`, if it had one, at `now` (in seconds since the Unix
+    /// epoch)
+    fn delay(&self, attempt: u32, retry_after: Option<&str>, now: i64) -> Duration {
+        let requested = retry_after
+            .filter(|_| self.honor_retry_after)
+            .and_then(|value| parse_retry_after(value, now));
+        let delay = requested.unwrap_or_else(|| {
+            self.initial_backoff
+                .checked_mul(1 << (attempt - 1).min(31))
+                .unwrap_or(self.max_backoff)
+        });
+        delay.min(self.max_backoff)
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Retry idempotent requests per `policy`
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Send `request` for `exchange`, retrying it per `policy`. The request is prepared once,
+    /// and copied for each attempt before the cookies of the store are added to it; requests
+    /// which cannot be copied are sent only once.
+    pub(crate) fn run_with_retries<P>(
+        &mut self,
+        request: <C as SessionClient>::Request,
+        exchange: &Exchange<'_>,
+        prepare: P,
+        policy: &RetryPolicy,
+    ) -> ::std::result::Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+    where
+        P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+    {
+        let mut template = prepare(request);
+        let mut attempt = 1;
+        loop {
+            let copy = if attempt < policy.max_attempts {
+                template.try_clone()
+            } else {
+                None
+            };
+            let (request, next) = match copy {
+                Some(copy) => (copy, Some(template)),
+                None => (template, None),
+            };
+            let request = self.add_request_cookies(request, exchange);
+            let result = self.send_request(request, exchange);
+            let delay = match result {
+                Ok(ref response) => {
+                    self.receive_response(response, exchange)?;
+                    response
+                        .status_code()
+                        .filter(|status| policy.retry_statuses.contains(status))
+                        .map(|_| {
+                            let retry_after = response.retry_after();
+                            let now = unix_secs(SystemTime::now());
+                            policy.delay(attempt, retry_after.as_deref(), now)
+                        })
+                }
+                Err(ref e) if e.is_retryable() => Some(policy.delay(attempt, None, 0)),
+                Err(_) => None,
+            };
+            match (delay, next) {
+                (Some(delay), Some(next)) => {
+                    debug!(
+                        "retrying {} {} in {:?} (attempt {} failed)",
+                        exchange.method, exchange.url, delay, attempt
+                    );
+                    std::thread::sleep(delay);
+                    template = next;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use std::time::Duration;
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn prepare_once() {
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let client = MockClient::new()
+            .with_response(MockResponse::new(503).with_set_cookie("challenge=1"))
+            .with_response(MockResponse::new(200));
+        let policy =
+            RetryPolicy::new().with_backoff(Duration::from_millis(0), Duration::from_millis(0));
+        let mut session = Session::new(client).with_retry_policy(policy);
+        let mut prepared = 0;
+        session
+            .get_with("https://example.com/", |r| {
+                prepared += 1;
+                r.header("x-trace", "1")
+            })
+            .unwrap();
+        assert_eq!(1, prepared);
+        let requests = session.client.requests();
+        assert_eq!(2, requests.len());
+        for request in &requests {
+            // prepared before the cookies were added
+            assert_eq!("x-trace", request.headers[0].0);
+        }
+        assert!(requests[0].cookie_names().is_empty());
+        assert_eq!(vec!["challenge"], requests[1].cookie_names());
+    }
+
+    #[test]
+    fn delay() {
+        let policy = RetryPolicy::new()
+            .with_backoff(Duration::from_secs(1), Duration::from_secs(10))
+            .with_max_attempts(5);
+        let secs = |attempt, retry_after| policy.delay(attempt, retry_after, 784_111_777).as_secs();
+        assert_eq!(1, secs(1, None));
+        assert_eq!(4, secs(3, None));
+        assert_eq!(10, secs(5, None));
+        assert_eq!(7, secs(1, Some("7")));
+        assert_eq!(10, secs(1, Some("120")));
+        // 784111777 is Sun, 06 Nov 1994 08:49:37 GMT
+        assert_eq!(3, secs(1, Some("Sun, 06 Nov 1994 08:49:40 GMT")));
+        assert_eq!(0, secs(1, Some("Sun, 06 Nov 1994 08:49:00 GMT")));
+        assert_eq!(2, secs(2, Some("soon")));
+        let ignoring = policy.clone().with_honor_retry_after(false);
+        assert_eq!(Duration::from_secs(1), ignoring.delay(1, Some("7"), 0));
+
+        assert!(policy.applies_to("get") && policy.applies_to("PUT"));
+        assert!(!policy.applies_to("POST") && !policy.applies_to("PATCH"));
+        assert!(!RetryPolicy::new().with_max_attempts(1).applies_to("GET"));
+    }
+}
@@ -0,0 +1,381 @@
+use crate::content::{ContentError, ContentResponse};
+use crate::session::{Session, SessionClient, SessionResponse};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use log::debug;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// An `Allow` or `Disallow` rule of a robots.txt group
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    /// The path pattern, in which `*` matches any characters, and a final `$` the end of the path
+    pattern: String,
+}
+
+/// The rules applying to `user_agent` in the robots.txt `body`: those of the groups naming it
+/// (compared case-insensitively), or if there are none, those of the `*` groups
+fn parse(body: &str, user_agent: &str) -> Vec<Rule> {
+    let (mut named, mut any) = (vec![], vec![]);
+    let mut named_group = false;
+    // the agents of the current group, and whether its rules have begun
+    let mut agents: Vec<&str> = vec![];
+    let mut in_rules = false;
+    for line in body.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+        let allow = match key.as_str() {
+            "user-agent" => {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                named_group |= value.eq_ignore_ascii_case(user_agent);
+                agents.push(value);
+                continue;
+            }
+            "allow" => true,
+            "disallow" => false,
+            _ => continue,
+        };
+        in_rules = true;
+        // an empty rule allows everything, as no rule would
+        if value.is_empty() {
+            continue;
+        }
+        let rule = Rule {
+            allow,
+            pattern: value.to_owned(),
+        };
+        if agents
+            .iter()
+            .any(|agent| agent.eq_ignore_ascii_case(user_agent))
+        {
+            named.push(rule.clone());
+        }
+        if agents.contains(&"*") {
+            any.push(rule);
+        }
+    }
+    if named_group {
+        named
+    } else {
+        any
+    }
+}
+
+/// Whether `path` matches `pattern`, from its start
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match path.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts = parts.collect::<Vec<_>>();
+    for (i, part) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        // an anchored final part must match the end of the path
+        let found = if last && anchored {
+            rest.ends_with(part).then(|| rest.len() - part.len())
+        } else {
+            rest.find(part)
+        };
+        match found {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Whether `rules` allow `path`: the rule with the longest pattern matching it decides, an
+/// `Allow` rule winning a tie; a path no rule matches is allowed
+fn allows(rules: &[Rule], path: &str) -> bool {
+    rules
+        .iter()
+        .filter(|rule| matches(&rule.pattern, path))
+        .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+        .map(|rule| rule.allow)
+        .unwrap_or(true)
+}
+
+/// The path and query of `url`, as robots.txt rules are matched against
+fn robots_path(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    }
+}
+
+/// Honors the robots.txt of each site a `Session` crawls, set with
+/// `Session::with_robots_policy`, for requests made with `Session::get_allowed` (and
+/// `get_allowed_with`). Each origin's `/robots.txt` is fetched through the session, so carries
+/// its cookies, and is cached for the maximum age (24 hours, unless configured).
+///
+/// As RFC 9309 specifies, the rules of the groups naming the user-agent token (or if there are
+/// none, of the `*` groups) apply, the longest matching rule deciding. A robots.txt which is
+/// missing (a 4xx status, or an unfollowed redirect) allows every URL, and one whose server
+/// fails (a 5xx status) disallows every URL until it is fetched again.
+#[derive(Debug, Clone)]
+pub struct RobotsPolicy {
+    user_agent: String,
+    max_age: Duration,
+    /// The rules of each origin's robots.txt, and when they were fetched
+    origins: HashMap<String, (Vec<Rule>, Instant)>,
+}
+
+impl RobotsPolicy {
+    /// Honor the rules for the crawler named by the `user_agent` token (e.g. `mybot`)
+    pub fn new(user_agent: &str) -> Self {
+        RobotsPolicy {
+            user_agent: user_agent.to_owned(),
+            max_age: Duration::from_secs(24 * 60 * 60),
+            origins: HashMap::new(),
+        }
+    }
+
+    /// Fetch each origin's robots.txt again once it is older than `max_age`
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Whether the cached robots.txt of the origin of `url` allows it, if it is fresh
+    fn cached(&self, url: &Url) -> Option<bool> {
+        let (rules, fetched) = self.origins.get(&url.origin().ascii_serialization())?;
+        if fetched.elapsed() > self.max_age {
+            return None;
+        }
+        Some(allows(rules, &robots_path(url)))
+    }
+}
+
+/// The failure of a `Session::get_allowed` request
+#[derive(Debug)]
+pub enum RobotsError<E> {
+    /// The robots.txt of the URL's site disallows it
+    Disallowed(Url),
+    /// Fetching the robots.txt of the URL's site failed
+    Robots(ContentError<E>),
+    /// Sending the (allowed) request failed
+    Send(E),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for RobotsError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RobotsError::Disallowed(url) => write!(f, "{} is disallowed by robots.txt", url),
+            RobotsError::Robots(e) => write!(f, "fetching robots.txt failed: {}", e),
+            RobotsError::Send(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RobotsError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RobotsError::Disallowed(_) => None,
+            RobotsError::Robots(e) => Some(e),
+            RobotsError::Send(e) => Some(e),
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Honor robots.txt per `policy` for requests made with `get_allowed`
+    pub fn with_robots_policy(mut self, policy: RobotsPolicy) -> Self {
+        self.robots = Some(policy);
+        self
+    }
+
+    /// Whether the robots.txt of the site of `url` allows it, fetching the robots.txt if it is
+    /// not cached; always `true` without a `RobotsPolicy`
+    pub fn robots_allowed(
+        &mut self,
+        url: &Url,
+    ) -> Result<bool, ContentError<<C as SessionClient>::SendError>>
+    where
+        <C as SessionClient>::Response: ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let user_agent = match self.robots {
+            Some(ref policy) => match policy.cached(url) {
+                Some(allowed) => return Ok(allowed),
+                None => policy.user_agent.clone(),
+            },
+            None => return Ok(true),
+        };
+        if url.path() == "/robots.txt" {
+            return Ok(true);
+        }
+        let robots_url = url
+            .join("/robots.txt")
+            .map_err(|e| ContentError::Send(e.into()))?;
+        debug!("fetching {}", robots_url);
+        let response = self.get(robots_url).map_err(ContentError::Send)?;
+        let rules = match response.status_code() {
+            Some(status) if status >= 500 => vec![Rule {
+                allow: false,
+                pattern: "/".to_owned(),
+            }],
+            Some(status) if status >= 300 => vec![],
+            _ => {
+                let body = self.read_content(response, None)?;
+                parse(&String::from_utf8_lossy(&body), &user_agent)
+            }
+        };
+        let allowed = allows(&rules, &robots_path(url));
+        if let Some(ref mut policy) = self.robots {
+            let origin = url.origin().ascii_serialization();
+            policy.origins.insert(origin, (rules, Instant::now()));
+        }
+        Ok(allowed)
+    }
+
+    /// GET `url` as `get_with` does, if its site's robots.txt allows it (see `RobotsPolicy`)
+    pub fn get_allowed_with<U, P>(
+        &mut self,
+        url: U,
+        prepare: P,
+    ) -> Result<<C as SessionClient>::Response, RobotsError<<C as SessionClient>::SendError>>
+    where
+        U: IntoUrl,
+        P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+        <C as SessionClient>::Response: ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let url = url.into_url().map_err(|e| RobotsError::Send(e.into()))?;
+        if !self.robots_allowed(&url).map_err(RobotsError::Robots)? {
+            debug!("{} is disallowed by robots.txt", url);
+            return Err(RobotsError::Disallowed(url));
+        }
+        self.get_with(url, prepare).map_err(RobotsError::Send)
+    }
+
+    /// GET `url`, if its site's robots.txt allows it (see `RobotsPolicy`)
+    pub fn get_allowed<U>(
+        &mut self,
+        url: U,
+    ) -> Result<<C as SessionClient>::Response, RobotsError<<C as SessionClient>::SendError>>
+    where
+        U: IntoUrl,
+        <C as SessionClient>::Response: ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        self.get_allowed_with(url, |request| request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{allows, matches, parse, Rule};
+
+    #[test]
+    fn rules() {
+        let robots = "# a comment\n\
+            User-agent: *\n\
+            Disallow: /private\n\
+            \n\
+            User-agent: OtherBot\n\
+            User-agent: MyBot\n\
+            Disallow: /\n\
+            Allow: /pub # inline comment\n\
+            Disallow: /*.pdf$\n\
+            Disallow:\n\
+            Sitemap: https://www.example.com/sitemap.xml\n\
+            \n\
+            User-agent: ThirdBot\n\
+            Allow: /\n";
+        let rule = |allow, pattern: &str| Rule {
+            allow,
+            pattern: pattern.to_owned(),
+        };
+        let mine = parse(robots, "mybot");
+        assert_eq!(
+            vec![rule(false, "/"), rule(true, "/pub"), rule(false, "/*.pdf$")],
+            mine
+        );
+        assert!(allows(&mine, "/public/page"));
+        assert!(!allows(&mine, "/public/paper.pdf"));
+        assert!(allows(&mine, "/public/paper.pdf?download=1"));
+        assert!(!allows(&mine, "/about"));
+        let any = parse(robots, "somebot");
+        assert_eq!(vec![rule(false, "/private")], any);
+        assert!(allows(&any, "/about") && !allows(&any, "/private/1"));
+        assert!(parse("", "mybot").is_empty());
+
+        // an Allow rule wins a tie
+        let tie = vec![rule(false, "/page"), rule(true, "/page")];
+        assert!(allows(&tie, "/page"));
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(matches("/", "/anything"));
+        assert!(matches("/fish", "/fish.html"));
+        assert!(!matches("/fish", "/Fish"));
+        assert!(matches("/fish*.php", "/fish/salmon.php?id=1"));
+        assert!(matches("/*.php$", "/index.php"));
+        assert!(!matches("/*.php$", "/index.php?id=1"));
+        assert!(matches("/a$", "/a"));
+        assert!(!matches("/a$", "/ab"));
+        assert!(matches("/*a*b", "/xaxb"));
+        assert!(!matches("/*a*b", "/xbxa"));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn get_allowed() {
+        use super::{RobotsError, RobotsPolicy};
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let robots = "User-agent: *\nDisallow: /private\n";
+        let client = MockClient::new()
+            .with_response(MockResponse::new(200).with_body(robots))
+            .with_response(MockResponse::new(200))
+            .with_response(MockResponse::new(503))
+            .with_response(MockResponse::new(404))
+            .with_response(MockResponse::new(200));
+        let mut session = Session::new(client).with_robots_policy(RobotsPolicy::new("mybot"));
+        match session.get_allowed("https://www.example.com/private/1") {
+            Err(RobotsError::Disallowed(url)) => assert_eq!("/private/1", url.path()),
+            _ => panic!("expected /private/1 to be disallowed"),
+        }
+        session
+            .get_allowed("https://www.example.com/public")
+            .unwrap();
+        let disallowed = session.get_allowed("https://www.example.org/");
+        assert!(matches!(disallowed, Err(RobotsError::Disallowed(_))));
+        session.get_allowed("https://www.example.net/").unwrap();
+        let urls = session
+            .client
+            .requests()
+            .iter()
+            .map(|request| request.url.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "https://www.example.com/robots.txt",
+                "https://www.example.com/public",
+                "https://www.example.org/robots.txt",
+                "https://www.example.net/robots.txt",
+                "https://www.example.net/",
+            ],
+            urls
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use crate::content::ContentResponse;
+use crate::session::{Session, SessionClient};
+use crate::spec::{render, RequestSpec, SpecError, SpecRequest, StatusResponse};
+use crate::storage::CookieStorage;
+use log::debug;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use url::Url;
+
+/// How a `ScenarioStep` extracts a variable from its response body
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Extractor {
+    /// The value at a path into a JSON body, e.g. `data.items[0].id`
+    JsonPath(String),
+    /// The first capture group (or, if it has none, the whole match) of a regex over the body
+    Regex(String),
+}
+
+/// A check of a `ScenarioStep`'s response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Assertion {
+    /// The body contains the (rendered) string
+    BodyContains(String),
+    /// The value at a path into a JSON body equals `value`
+    JsonEquals { path: String, value: Value },
+    /// After the response, the store holds a cookie of this name for the step's URL
+    Cookie(String),
+}
+
+/// A `RequestSpec`, the variables to extract from its response for use by later steps, and the
+/// assertions its response must pass
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScenarioStep {
+    #[serde(flatten)]
+    pub request: RequestSpec,
+    #[serde(default)]
+    pub extract: BTreeMap<String, Extractor>,
+    #[serde(default)]
+    pub assert: Vec<Assertion>,
+}
+
+/// An ordered list of steps, run through a single `Session` so that cookies set by one step are
+/// sent by the next; see `Session::run_scenario`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    pub steps: Vec<ScenarioStep>,
+}
+
+/// The failure of a step of a `Session::run_scenario`; `step` is its index
+#[derive(Debug)]
+pub enum ScenarioError<E> {
+    /// The step's request could not be sent, or its response read or of the expected status
+    Request { step: usize, error: SpecError<E> },
+    /// The variable `name` could not be extracted
+    Extract { step: usize, name: String },
+    /// An `Extractor::Regex` was invalid
+    Regex { step: usize, error: regex::Error },
+    /// The response failed `assertion`
+    Assertion { step: usize, assertion: Assertion },
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for ScenarioError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ScenarioError::Request { step, error } => write!(f, "step {}: {}", step, error),
+            ScenarioError::Extract { step, name } => {
+                write!(f, "step {}: unable to extract {}", step, name)
+            }
+            ScenarioError::Regex { step, error } => write!(f, "step {}: {}", step, error),
+            ScenarioError::Assertion { step, assertion } => {
+                write!(f, "step {}: assertion failed: {:?}", step, assertion)
+            }
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for ScenarioError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScenarioError::Request { error, .. } => Some(error),
+            ScenarioError::Regex { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// The value at `path` (dot-separated keys and `[index]`es, optionally prefixed by `$.`) in
+/// `value`
+fn json_path<'v>(value: &'v Value, path: &str) -> Option<&'v Value> {
+    let path = path.trim_start_matches('$').trim_start_matches('.');
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .try_fold(value, |value, segment| {
+            let mut parts = segment.split('[');
+            let key = parts.next().unwrap_or("");
+            let value = if key.is_empty() {
+                value
+            } else {
+                value.get(key)?
+            };
+            parts.try_fold(value, |value, index| {
+                value.get(index.trim_end_matches(']').parse::<usize>().ok()?)
+            })
+        })
+}
+
+/// A JSON value as a variable: strings unquoted, other values as JSON
+fn json_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn extract(extractor: &Extractor, body: &str) -> Result<Option<String>, regex::Error> {
+    Ok(match extractor {
+        Extractor::JsonPath(path) => serde_json::from_str::<Value>(body)
+            .ok()
+            .and_then(|json| json_path(&json, path).map(json_string)),
+        Extractor::Regex(regex) => Regex::new(regex)?.captures(body).and_then(|captures| {
+            captures
+                .get(1)
+                .or_else(|| captures.get(0))
+                .map(|m| m.as_str().to_owned())
+        }),
+    })
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Run the steps of `scenario` in order, starting with the variables `vars`, and adding those
+    /// extracted by each step for use by later steps. Stops at the first failing step. Returns
+    /// the final variables.
+    pub fn run_scenario(
+        &mut self,
+        scenario: &Scenario,
+        vars: HashMap<String, String>,
+    ) -> Result<HashMap<String, String>, ScenarioError<<C as SessionClient>::SendError>>
+    where
+        <C as SessionClient>::Request: SpecRequest,
+        <C as SessionClient>::Response: StatusResponse + ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let mut vars = vars;
+        for (step, spec) in scenario.steps.iter().enumerate() {
+            debug!(
+                "scenario step {}: {:?} {}",
+                step, spec.request.method, spec.request.url
+            );
+            let request_error = |error| ScenarioError::Request { step, error };
+            let response = self
+                .execute_spec(&spec.request, &vars)
+                .map_err(request_error)?;
+            let body = response
+                .read_body(&mut |n| self.downloaded(n))
+                .map_err(|e| request_error(SpecError::Send(e.into())))?;
+            let body = String::from_utf8_lossy(&body);
+            for (name, extractor) in &spec.extract {
+                match extract(extractor, &body) {
+                    Ok(Some(value)) => {
+                        vars.insert(name.clone(), value);
+                    }
+                    Ok(None) => {
+                        return Err(ScenarioError::Extract {
+                            step,
+                            name: name.clone(),
+                        })
+                    }
+                    Err(error) => return Err(ScenarioError::Regex { step, error }),
+                }
+            }
+            for assertion in &spec.assert {
+                let passed = match assertion {
+                    Assertion::BodyContains(s) => {
+                        body.contains(&render(s, &vars).map_err(request_error)?)
+                    }
+                    Assertion::JsonEquals { path, value } => {
+                        let json = serde_json::from_str::<Value>(&body);
+                        matches!(json, Ok(ref json) if json_path(json, path) == Some(value))
+                    }
+                    Assertion::Cookie(name) => {
+                        let url = render(&spec.request.url, &vars).map_err(request_error)?;
+                        let url = Url::parse(&url)
+                            .map_err(|e| request_error(SpecError::ParseUrlError(e)))?;
+                        self.store
+                            .get_request_cookies(&url)
+                            .iter()
+                            .any(|c| c.name() == name)
+                    }
+                };
+                if !passed {
+                    return Err(ScenarioError::Assertion {
+                        step,
+                        assertion: assertion.clone(),
+                    });
+                }
+            }
+        }
+        Ok(vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract, json_path, Assertion, Extractor, Scenario};
+    use serde_json::json;
+
+    #[test]
+    fn extraction() {
+        let json = json!({ "data": { "items": [{ "id": 7 }, { "id": "x" }] } });
+        assert_eq!(Some(&json!(7)), json_path(&json, "data.items[0].id"));
+        assert_eq!(Some(&json!("x")), json_path(&json, "$.data.items[1].id"));
+        assert_eq!(None, json_path(&json, "data.items[2]"));
+
+        let body = json.to_string();
+        let path = Extractor::JsonPath("data.items[1].id".to_owned());
+        assert_eq!(Some("x".to_owned()), extract(&path, &body).unwrap());
+        let html = r#"<input name="csrf" value="abc123">"#;
+        let regex = Extractor::Regex(r#"name="csrf" value="([^"]+)""#.to_owned());
+        assert_eq!(Some("abc123".to_owned()), extract(&regex, html).unwrap());
+        assert!(extract(&Extractor::Regex("(".to_owned()), html).is_err());
+    }
+
+    #[test]
+    fn deserialize() {
+        let scenario: Scenario = serde_json::from_str(
+            r#"{ "steps": [
+                {
+                    "url": "https://example.com/login",
+                    "extract": { "csrf": { "regex": "csrf=(\\w+)" } }
+                },
+                {
+                    "method": "POST",
+                    "url": "https://example.com/login",
+                    "body": "csrf={{csrf}}",
+                    "expect_status": 200,
+                    "assert": [
+                        { "cookie": "session" },
+                        { "json_equals": { "path": "user.name", "value": "a" } }
+                    ]
+                }
+            ] }"#,
+        )
+        .unwrap();
+        assert_eq!(2, scenario.steps.len());
+        assert_eq!(Some(200), scenario.steps[1].request.expect_status);
+        assert_eq!(
+            Assertion::Cookie("session".to_owned()),
+            scenario.steps[1].assert[0]
+        );
+    }
+}
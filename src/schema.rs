@@ -0,0 +1,219 @@
+//! A [JSON Schema](https://json-schema.org/) for the format written by `Session::save_json` and
+//! read by `Session::load_json`, for tools producing or consuming cookie jars outside of Rust.
+//!
+//! A saved jar is a sequence of lines, each holding one JSON-serialized cookie matching the schema
+//! returned by `cookie_schema`.
+use cookie::Cookie as RawCookie;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{RootSchema, Schema, SchemaObject};
+use schemars::{schema_for, JsonSchema};
+use serde::Deserialize;
+use std::io::BufRead;
+
+/// A persisted cookie
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "Cookie")]
+#[allow(dead_code)]
+struct CookieRecord {
+    /// The cookie as it would appear in a Set-Cookie header, including its attributes
+    raw_cookie: String,
+    /// The path the cookie is scoped to, and whether it was set from a Path attribute (`true`) or
+    /// defaulted from the request URL (`false`)
+    path: (String, bool),
+    /// The domain the cookie is scoped to
+    domain: DomainRecord,
+    /// When the cookie expires
+    expires: ExpiresRecord,
+    /// The extension attributes of the cookie (e.g. `Priority=High`), written only by sessions
+    /// configured with `Session::with_extension_attributes_saved`
+    #[serde(default)]
+    extensions: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(rename = "CookieDomain")]
+#[allow(dead_code)]
+enum DomainRecord {
+    /// Sent only to this exact host; the cookie had no Domain attribute
+    HostOnly(String),
+    /// Sent to this domain and its subdomains, from the Domain attribute
+    Suffix(String),
+    /// No Domain attribute was present
+    NotPresent,
+    /// An empty Domain attribute was present
+    Empty,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(rename = "CookieExpiration")]
+#[allow(dead_code)]
+enum ExpiresRecord {
+    /// Expires at the given UTC time, in RFC 3339 format (e.g. `2020-03-04T05:06:07Z`)
+    AtUtc(#[schemars(schema_with = "date_time")] String),
+    /// Expires at the end of the session; such cookies are not normally persisted
+    SessionEnd,
+}
+
+/// The schema of an RFC 3339 date and time
+fn date_time(gen: &mut SchemaGenerator) -> Schema {
+    let mut schema: SchemaObject = String::json_schema(gen).into();
+    schema.format = Some("date-time".to_owned());
+    schema.into()
+}
+
+/// A problem found by `validate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaViolation {
+    /// The (1-based) line of the input containing the invalid cookie
+    pub line: usize,
+    pub message: String,
+}
+
+/// The JSON Schema for a single persisted cookie (one line of a saved jar)
+pub fn cookie_schema() -> RootSchema {
+    schema_for!(CookieRecord)
+}
+
+/// Check each line of `reader` against the persisted cookie schema, including the formats of
+/// `raw_cookie` and of `AtUtc` times, returning any violations found. Each line satisfying the
+/// schema is then loaded as a cookie, so an empty result means the jar may be loaded with
+/// `Session::load_json`.
+pub fn validate<R: BufRead>(reader: R) -> Vec<SchemaViolation> {
+    let mut violations = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let mut violation = |message: String| {
+            violations.push(SchemaViolation {
+                line: i + 1,
+                message,
+            })
+        };
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                violation(format!("unable to read line: {}", e));
+                break;
+            }
+        };
+        let record = match serde_json::from_str::<CookieRecord>(&line) {
+            Ok(record) => record,
+            Err(e) => {
+                violation(e.to_string());
+                continue;
+            }
+        };
+        let invalid_at = match record.expires {
+            ExpiresRecord::AtUtc(ref at) => {
+                let parsed = time02::OffsetDateTime::parse(at, time02::Format::Rfc3339);
+                parsed
+                    .err()
+                    .map(|e| format!("invalid AtUtc time {:?}: {}", at, e))
+            }
+            ExpiresRecord::SessionEnd => None,
+        };
+        if let Err(e) = RawCookie::parse(record.raw_cookie.as_str()) {
+            violation(format!("invalid raw_cookie: {}", e));
+        } else if let Some(message) = invalid_at {
+            violation(message);
+        } else if let Err(e) = serde_json::from_str::<cookie_store::Cookie>(&line) {
+            violation(e.to_string());
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cookie_schema, validate, SchemaViolation};
+
+    #[test]
+    fn schema() {
+        let schema = serde_json::to_value(cookie_schema()).unwrap();
+        assert_eq!("Cookie", schema["title"]);
+        assert!(schema["definitions"]["CookieDomain"].is_object());
+        let expiration = &schema["definitions"]["CookieExpiration"];
+        assert_eq!(
+            "date-time",
+            expiration["oneOf"][0]["properties"]["AtUtc"]["format"]
+        );
+    }
+
+    #[test]
+    fn validation() {
+        let jar = r#"{"raw_cookie":"1=a; Max-Age=120","path":["/",false],"domain":{"HostOnly":"www.example.com"},"expires":{"AtUtc":"2020-03-04T05:06:07Z"}}
+{"raw_cookie":"2=b","path":["/foo",true],"domain":{"Suffix":"example.com"},"expires":"SessionEnd","extra":1}
+{"raw_cookie":"","path":["/",false],"domain":"NotPresent","expires":"SessionEnd"}
+"#;
+        let violations = validate(jar.as_bytes());
+        assert_eq!(
+            vec![2, 3],
+            violations
+                .iter()
+                .map(|SchemaViolation { line, .. }| *line)
+                .collect::<Vec<_>>()
+        );
+        assert!(violations[0].message.contains("extra"));
+    }
+
+    #[test]
+    fn invalid_times() {
+        let cookie = |at: &str| {
+            format!(
+                r#"{{"raw_cookie":"1=a","path":["/",false],"domain":{{"HostOnly":"example.com"}},"expires":{{"AtUtc":"{}"}}}}"#,
+                at
+            )
+        };
+        let jar = [
+            cookie("2020-03-04T05:06:07Z"),
+            cookie("2020-03-04 05:06:07"),
+            cookie("Wed, 04 Mar 2020 05:06:07 GMT"),
+            cookie("2020-13-04T05:06:07Z"),
+        ]
+        .join("\n");
+        let violations = validate(jar.as_bytes());
+        assert_eq!(
+            vec![2, 3, 4],
+            violations.iter().map(|v| v.line).collect::<Vec<_>>()
+        );
+        assert!(violations[0].message.contains("AtUtc"));
+    }
+
+    #[test]
+    fn validate_saved() {
+        let url = url::Url::parse("http://www.example.com/foo/bar").unwrap();
+        let mut store = cookie_store::CookieStore::default();
+        store.parse("1=a; Max-Age=120", &url).unwrap();
+        store
+            .parse("2=b; Max-Age=120; Domain=example.com; Path=/", &url)
+            .unwrap();
+        let mut saved = vec![];
+        store.save_json(&mut saved).unwrap();
+        assert_eq!(Vec::<SchemaViolation>::new(), validate(&saved[..]));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn validate_saved_extensions() {
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let client = MockClient::new().with_response(
+            MockResponse::new(200)
+                .with_set_cookie("id=1; Max-Age=120; Priority=High")
+                .with_set_cookie("pref=a; Max-Age=120"),
+        );
+        let mut session = Session::new(client).with_extension_attributes_saved();
+        session.get("https://www.example.com/").unwrap();
+        let mut saved = vec![];
+        session.save_json(&mut saved).unwrap();
+        assert_eq!(
+            1,
+            String::from_utf8_lossy(&saved)
+                .matches("\"extensions\"")
+                .count()
+        );
+        assert_eq!(Vec::<SchemaViolation>::new(), validate(&saved[..]));
+        let schema = serde_json::to_value(cookie_schema()).unwrap();
+        assert!(schema["properties"]["extensions"].is_object());
+    }
+}
@@ -0,0 +1,27 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use std::collections::HashMap;
+use url::Url;
+
+/// The cookies attached to the most recent request to each URL
+pub(crate) type SentCookies = HashMap<Url, Vec<RawCookie<'static>>>;
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Record the cookies attached to each request, for retrieval with `last_sent_cookies`. As an
+    /// entry is kept for every URL requested, this is intended for testing and debugging rather
+    /// than long-running use.
+    pub fn with_sent_cookie_tracking(mut self) -> Self {
+        self.sent_cookies = Some(SentCookies::new());
+        self
+    }
+
+    /// The cookies attached to the most recent request to `url`, if tracking was enabled with
+    /// `with_sent_cookie_tracking` and `url` has been requested
+    pub fn last_sent_cookies(&self, url: &Url) -> Option<&[RawCookie<'static>]> {
+        self.sent_cookies
+            .as_ref()
+            .and_then(|sent| sent.get(url))
+            .map(|cookies| &cookies[..])
+    }
+}
@@ -1,7 +1,54 @@
+use crate::accounts::AccountRouting;
+use crate::attribution::{attribute, redirect_targets, CrossSiteRedirect, UnknownFinalUrl};
+use crate::auth::AuthManager;
+use crate::backoff::HostBackoff;
+use crate::cache::ResponseCacheBox;
+use crate::change::{stored, ChangeObservers, CookieChange};
+use crate::clear_site_data::SiteDataClearing;
+use crate::connections::ConnectionReuse;
+use crate::cookie_policy::{CookiePolicy, RejectedCookie};
+use crate::duplicates::DuplicateSetCookies;
+use crate::environment::Environments;
+#[cfg(feature = "persistence")]
+use crate::error::SessionError;
+use crate::events::{notify, EventSinks, RejectionReason, StoreEvent};
+use crate::extensions::ExtensionAttributes;
+use crate::fetch_metadata::{FetchDest, FetchMetadata, FetchMode};
+use crate::growth::JarGrowth;
+use crate::har::HarRecorder;
+use crate::hsts::Hsts;
+#[cfg(feature = "persistence")]
+use crate::jar::{load_json_partial, LoadError};
+use crate::journal::Journal;
+use crate::lenient::{parse_lenient, Strictness};
+use crate::limits::CookieLimiter;
+use crate::metrics::{Counters, MetricsHooks};
+use crate::opts::RequestOpts;
+use crate::origin::OriginAliases;
+use crate::pin::PinnedCookies;
+use crate::policy_log::{PolicyAction, PolicyLog, PolicyRule};
+use crate::public_suffix::PublicSuffixes;
+use crate::quoting::ValueQuotingRules;
+use crate::rate_limit::RateLimiter;
+use crate::redirect::RedirectMode;
+use crate::retry::RetryPolicy;
+use crate::robots::RobotsPolicy;
+use crate::sent::SentCookies;
+use crate::skew::ClockSkew;
+use crate::storage::CookieStorage;
+use crate::subresource;
+use crate::tenant::TenantId;
+use crate::trace::RequestTrace;
+use crate::transfer::Transfer;
 use crate::utils::IntoUrl;
 use cookie::Cookie as RawCookie;
 use cookie_store::{Cookie, CookieStore};
+use http::Method;
+use log::debug;
+#[cfg(feature = "persistence")]
 use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::time::Instant;
 use url::{ParseError as ParseUrlError, Url};
 
 /// Trait representing requests which can carry a Cookie header, appropriate
@@ -9,6 +56,38 @@ use url::{ParseError as ParseUrlError, Url};
 pub trait SessionRequest {
     /// Add the given set of cookies to the request
     fn add_cookies(self, _: Vec<&RawCookie<'static>>) -> Self;
+    /// Add cookies already formatted as `name=value` pairs, to be sent exactly as given (see
+    /// `ValueQuoting`). By default the pairs are parsed and added with `add_cookies`, which may
+    /// encode their values.
+    fn add_cookie_pairs(self, pairs: Vec<String>) -> Self
+    where
+        Self: Sized,
+    {
+        let cookies = pairs
+            .into_iter()
+            .filter_map(|pair| RawCookie::parse(pair).ok())
+            .collect::<Vec<_>>();
+        self.add_cookies(cookies.iter().collect())
+    }
+    /// Add the header `name` with `value` (see `Session::with_fetch_metadata`). By default the
+    /// header is not added.
+    fn add_header(self, _name: &str, _value: &str) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
+    /// Copy the request, if it can be (e.g. it has no streaming body), so that it can be re-sent
+    /// per a `RetryPolicy` or `AuthManager`. By default requests cannot be copied, so are not
+    /// retried. A request which may be re-sent is prepared (by the `prepare` function of e.g.
+    /// `Session::get_with`) once, before the cookies and headers of the session are added to it,
+    /// and copied for each attempt; other requests are prepared after them.
+    fn try_clone(&self) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 
 /// Trait representing responses which may have a Set-Cookie header, appropriate
@@ -20,10 +99,69 @@ pub trait SessionResponse {
     /// Return the final Url for the response. In cases such as redirects,
     /// such Url may differ from the Request Url. May return `None` if unavailable.
     fn final_url(&self) -> Option<&Self::Url>;
+    /// Return the `Location` header values of any redirects followed to produce this response, in
+    /// order. Implementations which cannot provide a `final_url` may provide these instead, so
+    /// that cookies are attributed to the URL the response was actually served from.
+    fn redirect_locations(&self) -> Vec<String> {
+        vec![]
+    }
+    /// Return the `Date` header value of the response, if available, so that the skew of the
+    /// server's clock can be detected
+    fn date(&self) -> Option<String> {
+        None
+    }
+    /// Return the raw values of the Set-Cookie and (obsolete) Set-Cookie2 headers of the
+    /// response, for parsing per `Strictness::Lenient`; responses which do not provide these are
+    /// parsed strictly with `parse_set_cookie`
+    fn raw_set_cookie(&self) -> Vec<String> {
+        vec![]
+    }
+    /// Return the `Clear-Site-Data` header value of the response, if available, so that the
+    /// cookies of the site may be cleared
+    fn clear_site_data(&self) -> Option<String> {
+        None
+    }
+    /// Return whether the response was received over a connection reused from an earlier
+    /// request, rather than a newly established one, if the integration can tell (see
+    /// `Session::stats`)
+    fn connection_reused(&self) -> Option<bool> {
+        None
+    }
+    /// Return whether the response is from a proxy rather than the origin (e.g. its
+    /// `407 Proxy Authentication Required`), so that none of its cookies are stored for the
+    /// origin
+    fn is_proxy_response(&self) -> bool {
+        false
+    }
+    /// Return the status code of the response, if available, so that a `RetryPolicy` can retry
+    /// on it
+    fn status_code(&self) -> Option<u16> {
+        None
+    }
+    /// Return the `Retry-After` header value of the response, if available, so that a
+    /// `RetryPolicy` can wait as long as the server asks
+    fn retry_after(&self) -> Option<String> {
+        None
+    }
+    /// Return the `Location` header value of the response, if available, so that a `Session`
+    /// can follow redirects itself (see `RedirectMode::Session`)
+    fn location(&self) -> Option<String> {
+        None
+    }
+    /// Return the `Strict-Transport-Security` header value of the response, if available, so
+    /// that a `Session` can make later requests to the host over HTTPS (see `Session::with_hsts`)
+    fn strict_transport_security(&self) -> Option<String> {
+        None
+    }
+    /// Return the `WWW-Authenticate` header values of the response, so that a `Session` can
+    /// answer its challenges (see `Session::with_auth_manager`)
+    fn www_authenticate(&self) -> Vec<String> {
+        vec![]
+    }
 }
 
 macro_rules! define_with_fn {
-    ($with_fn: ident, $request_fn: ident) => {
+    ($with_fn: ident, $request_fn: ident, $method: expr) => {
     pub fn $with_fn<U, P>(
         &mut self,
         url: U,
@@ -33,15 +171,15 @@ macro_rules! define_with_fn {
         P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
         U: IntoUrl
     {
-        let url = url.into_url()?;
+        let url = self.request_url(url.into_url()?);
         let request = self.client.$request_fn(&url);
-        self.run_request(request, &url, prepare)
+        self.run_request(request, $method, &url, prepare)
     }
     }
 }
 
 macro_rules! define_send_fn {
-    ($send_fn: ident, $request_fn: ident) => {
+    ($send_fn: ident, $request_fn: ident, $method: expr) => {
     pub fn $send_fn<U>(
         &mut self,
         url: U,
@@ -49,10 +187,57 @@ macro_rules! define_send_fn {
     where
         U: IntoUrl
     {
-        let url = url.into_url()?;
+        let url = self.request_url(url.into_url()?);
         let request = self.client.$request_fn(&url);
-        self.run_request(request, &url, |req| req)
+        self.run_request(request, $method, &url, |req| req)
+    }
+    }
+}
+
+/// The broad kind of a failure to send a request, as reported by `ClassifySendError::kind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendErrorKind {
+    /// The request URL could not be parsed
+    Url,
+    /// The request or connection timed out
+    Timeout,
+    /// A connection to the server could not be established
+    Connect,
+    /// The TLS handshake failed, e.g. for an untrusted certificate
+    Tls,
+    /// The response body could not be decoded
+    Decode,
+    /// The server responded with the given (error) status code
+    Status(u16),
+    /// Any other failure
+    Other,
+}
+
+impl SendErrorKind {
+    /// Whether a failure of this kind may succeed if the request is retried: timeouts, connection
+    /// failures, and the 408, 429, 500, 502, 503, and 504 statuses
+    pub fn is_retryable(self) -> bool {
+        match self {
+            SendErrorKind::Timeout | SendErrorKind::Connect => true,
+            SendErrorKind::Status(status) => {
+                matches!(status, 408 | 429 | 500 | 502 | 503 | 504)
+            }
+            _ => false,
+        }
     }
+}
+
+/// Classifies a `SessionClient::SendError`, so that callers (such as retry logic) can branch on
+/// the kind of failure without inspecting client specific errors
+pub trait ClassifySendError {
+    /// The kind of this failure; `SendErrorKind::Other` unless implemented
+    fn kind(&self) -> SendErrorKind {
+        SendErrorKind::Other
+    }
+
+    /// Whether the request may succeed if retried; see `SendErrorKind::is_retryable`
+    fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
     }
 }
 
@@ -61,56 +246,249 @@ macro_rules! define_send_fn {
 pub trait SessionClient {
     type Request: SessionRequest;
     type Response: SessionResponse;
-    type SendError: From<ParseUrlError>;
-
-    /// Create a `Self::Request` for a GET request
-    fn get_request(&self, url: &Url) -> Self::Request;
-    /// Create a `Self::Request` for a PUT request
-    fn put_request(&self, url: &Url) -> Self::Request;
-    /// Create a `Self::Request` for a HEAD request
-    fn head_request(&self, url: &Url) -> Self::Request;
-    /// Create a `Self::Request` for a DELETE request
-    fn delete_request(&self, url: &Url) -> Self::Request;
-    /// Create a `Self::Request` for a POST request
-    fn post_request(&self, url: &Url) -> Self::Request;
+    type SendError: From<ParseUrlError> + ClassifySendError;
+
+    /// Create a `Self::Request` for a GET request; by default, as by `request`
+    fn get_request(&self, url: &Url) -> Self::Request {
+        self.request(&Method::GET, url)
+    }
+    /// Create a `Self::Request` for a PUT request; by default, as by `request`
+    fn put_request(&self, url: &Url) -> Self::Request {
+        self.request(&Method::PUT, url)
+    }
+    /// Create a `Self::Request` for a HEAD request; by default, as by `request`
+    fn head_request(&self, url: &Url) -> Self::Request {
+        self.request(&Method::HEAD, url)
+    }
+    /// Create a `Self::Request` for a DELETE request; by default, as by `request`
+    fn delete_request(&self, url: &Url) -> Self::Request {
+        self.request(&Method::DELETE, url)
+    }
+    /// Create a `Self::Request` for a POST request; by default, as by `request`
+    fn post_request(&self, url: &Url) -> Self::Request {
+        self.request(&Method::POST, url)
+    }
+    /// Create a `Self::Request` for a PATCH request; by default, as by `request`
+    fn patch_request(&self, url: &Url) -> Self::Request {
+        self.request(&Method::PATCH, url)
+    }
+    /// Create a `Self::Request` for an OPTIONS request; by default, as by `request`
+    fn options_request(&self, url: &Url) -> Self::Request {
+        self.request(&Method::OPTIONS, url)
+    }
+    /// Create a `Self::Request` for a request of any `method`, including extension methods
+    /// (e.g. WebDAV's `PROPFIND`)
+    fn request(&self, method: &Method, url: &Url) -> Self::Request;
 
     /// Send `request` with no further preparation
     fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError>;
 }
 
-pub struct Session<C: SessionClient> {
+pub struct Session<C: SessionClient, S: CookieStorage = CookieStore> {
     pub client: C,
-    pub store: CookieStore,
+    pub store: S,
+    pub(crate) pinned: PinnedCookies,
+    pub(crate) event_sinks: EventSinks,
+    pub(crate) change_observers: ChangeObservers,
+    pub(crate) unknown_final_url: UnknownFinalUrl,
+    pub(crate) cross_site_redirect: CrossSiteRedirect,
+    pub(crate) origin_aliases: OriginAliases,
+    pub(crate) sent_cookies: Option<SentCookies>,
+    pub(crate) transfer: Transfer,
+    pub(crate) tenant: Option<TenantId>,
+    pub(crate) jar: Option<PathBuf>,
+    pub(crate) clock_skew: ClockSkew,
+    pub(crate) site_data_clearing: SiteDataClearing,
+    pub(crate) strictness: Strictness,
+    pub(crate) value_quoting: ValueQuotingRules,
+    pub(crate) extensions: ExtensionAttributes,
+    pub(crate) accounts: AccountRouting,
+    pub(crate) journal: Option<Journal>,
+    pub(crate) environments: Environments<S>,
+    pub(crate) fetch_metadata: FetchMetadata,
+    pub(crate) growth: Option<JarGrowth>,
+    pub(crate) counters: Counters,
+    pub(crate) metrics_hooks: MetricsHooks,
+    pub(crate) connections: ConnectionReuse,
+    /// The page whose subresource the next request is, if any
+    pub(crate) initiator: Option<Url>,
+    /// The options of the next request, if any
+    pub(crate) request_opts: Option<RequestOpts>,
+    pub(crate) policy_log: PolicyLog,
+    pub(crate) duplicates: DuplicateSetCookies,
+    pub(crate) public_suffixes: PublicSuffixes,
+    pub(crate) cookie_policy: CookiePolicy,
+    pub(crate) limiter: CookieLimiter,
+    /// The cookies of the responses to the last request which were not stored
+    pub(crate) rejected_cookies: Vec<RejectedCookie>,
+    pub(crate) retry: Option<RetryPolicy>,
+    pub(crate) rate_limiter: Option<RateLimiter>,
+    pub(crate) backoff: Option<HostBackoff>,
+    pub(crate) robots: Option<RobotsPolicy>,
+    pub(crate) hsts: Option<Hsts>,
+    pub(crate) response_cache: Option<ResponseCacheBox>,
+    pub(crate) auth: Option<AuthManager>,
+    pub(crate) redirect_mode: RedirectMode,
+    pub(crate) har: Option<HarRecorder>,
+}
+
+/// A request being made through a `Session`, and what is needed to handle its response
+pub(crate) struct Exchange<'a> {
+    pub(crate) method: &'a str,
+    pub(crate) url: &'a Url,
+    /// The URL the request is known to the store as (see `Session::with_equivalent_origin`)
+    pub(crate) cookie_url: &'a Url,
+    pub(crate) mode: (FetchMode, FetchDest),
+    /// The page whose subresource the request is, if any
+    pub(crate) initiator: Option<Url>,
+    pub(crate) opts: RequestOpts,
 }
 
 impl<C: SessionClient> Session<C> {
     pub fn new(client: C) -> Self {
-        Session {
-            client,
-            store: CookieStore::default(),
-        }
+        Self::from_store(client, CookieStore::default())
     }
 
+    #[cfg(feature = "persistence")]
     pub fn load<R, E, F>(
         client: C,
         reader: R,
         cookie_from_str: F,
-    ) -> Result<Session<C>, crate::Error>
+    ) -> Result<Session<C>, SessionError>
     where
         R: BufRead,
         F: Fn(&str) -> ::std::result::Result<Cookie<'static>, E>,
         E: std::error::Error + Send + Sync + 'static,
     {
         let store = CookieStore::load(reader, cookie_from_str)?;
-        Ok(Session { client, store })
+        Ok(Self::from_store(client, store))
+    }
+
+    /// Load a session from a jar in the `save_json` format, failing with a `jar::LoadError`
+    /// locating the first cookie which cannot be loaded, if any
+    #[cfg(feature = "persistence")]
+    pub fn load_json<R: BufRead>(client: C, reader: R) -> Result<Session<C>, SessionError> {
+        let (session, errors) = Self::load_json_partial(client, reader)?;
+        match errors.into_iter().next() {
+            Some(e) => Err(e.into()),
+            None => Ok(session),
+        }
+    }
+
+    /// Load a session from a jar in the `save_json` format, with what cookies can be loaded
+    /// from it if it is damaged, and a `jar::LoadError` for each which cannot be
+    #[cfg(feature = "persistence")]
+    pub fn load_json_partial<R: BufRead>(
+        client: C,
+        reader: R,
+    ) -> Result<(Session<C>, Vec<LoadError>), SessionError> {
+        let loaded = load_json_partial(reader)?;
+        let mut session = Self::from_store(client, loaded.store);
+        session.load_extension_attributes(&loaded.json);
+        Ok((session, loaded.errors))
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Create a `Session` using `store`, which may be any `CookieStorage` implementation
+    pub fn from_store(client: C, store: S) -> Self {
+        Session {
+            client,
+            store,
+            pinned: PinnedCookies::default(),
+            event_sinks: vec![],
+            change_observers: vec![],
+            unknown_final_url: UnknownFinalUrl::default(),
+            cross_site_redirect: CrossSiteRedirect::default(),
+            origin_aliases: OriginAliases::default(),
+            sent_cookies: None,
+            transfer: Transfer::default(),
+            tenant: None,
+            jar: None,
+            clock_skew: ClockSkew::default(),
+            site_data_clearing: SiteDataClearing::default(),
+            strictness: Strictness::default(),
+            value_quoting: ValueQuotingRules::default(),
+            extensions: ExtensionAttributes::default(),
+            accounts: AccountRouting::default(),
+            journal: None,
+            environments: Environments::default(),
+            fetch_metadata: FetchMetadata::default(),
+            growth: None,
+            counters: Counters::default(),
+            metrics_hooks: vec![],
+            connections: ConnectionReuse::default(),
+            initiator: None,
+            request_opts: None,
+            policy_log: PolicyLog::default(),
+            duplicates: DuplicateSetCookies::default(),
+            public_suffixes: PublicSuffixes::default(),
+            cookie_policy: CookiePolicy::default(),
+            limiter: CookieLimiter::default(),
+            rejected_cookies: vec![],
+            retry: None,
+            rate_limiter: None,
+            backoff: None,
+            robots: None,
+            hsts: None,
+            response_cache: None,
+            auth: None,
+            redirect_mode: RedirectMode::default(),
+            har: None,
+        }
     }
 
-    pub fn load_json<R: BufRead>(client: C, reader: R) -> Result<Session<C>, crate::Error> {
-        let store = CookieStore::load_json(reader)?;
-        Ok(Session { client, store })
+    /// Replace the store with `store` (e.g. an `LruCookieStore` for a throwaway crawl), keeping
+    /// the rest of the session's configuration
+    pub fn with_store<T: CookieStorage>(self, store: T) -> Session<C, T> {
+        Session {
+            client: self.client,
+            store,
+            pinned: self.pinned,
+            event_sinks: self.event_sinks,
+            change_observers: self.change_observers,
+            unknown_final_url: self.unknown_final_url,
+            cross_site_redirect: self.cross_site_redirect,
+            origin_aliases: self.origin_aliases,
+            sent_cookies: self.sent_cookies,
+            transfer: self.transfer,
+            tenant: self.tenant,
+            jar: self.jar,
+            clock_skew: self.clock_skew,
+            site_data_clearing: self.site_data_clearing,
+            strictness: self.strictness,
+            value_quoting: self.value_quoting,
+            extensions: self.extensions,
+            accounts: self.accounts,
+            journal: self.journal,
+            environments: self.environments.with_store(),
+            fetch_metadata: self.fetch_metadata,
+            growth: self.growth,
+            counters: self.counters,
+            metrics_hooks: self.metrics_hooks,
+            connections: self.connections,
+            initiator: self.initiator,
+            request_opts: self.request_opts,
+            policy_log: self.policy_log,
+            duplicates: self.duplicates,
+            public_suffixes: self.public_suffixes,
+            cookie_policy: self.cookie_policy,
+            limiter: self.limiter,
+            rejected_cookies: self.rejected_cookies,
+            retry: self.retry,
+            rate_limiter: self.rate_limiter,
+            backoff: self.backoff,
+            robots: self.robots,
+            hsts: self.hsts,
+            response_cache: self.response_cache,
+            auth: self.auth,
+            redirect_mode: self.redirect_mode,
+            har: self.har,
+        }
     }
 
-    pub fn save<W, E, F>(&self, writer: &mut W, cookie_to_string: F) -> Result<(), crate::Error>
+    #[cfg(feature = "persistence")]
+    pub fn save<W, E, F>(&self, writer: &mut W, cookie_to_string: F) -> Result<(), SessionError>
     where
         W: Write,
         F: Fn(&Cookie<'_>) -> ::std::result::Result<String, E>,
@@ -119,57 +497,478 @@ impl<C: SessionClient> Session<C> {
         self.store.save(writer, cookie_to_string)
     }
 
-    pub fn save_json<W: Write>(&self, writer: &mut W) -> Result<(), crate::Error> {
-        self.store.save_json(writer)
+    #[cfg(feature = "persistence")]
+    pub fn save_json<W: Write>(&self, writer: &mut W) -> Result<(), SessionError> {
+        self.save_json_with_extensions(writer)
     }
 
-    define_with_fn!(get_with, get_request);
-    define_with_fn!(put_with, put_request);
-    define_with_fn!(head_with, head_request);
-    define_with_fn!(delete_with, delete_request);
-    define_with_fn!(post_with, post_request);
+    define_with_fn!(get_with, get_request, "GET");
+    define_with_fn!(put_with, put_request, "PUT");
+    define_with_fn!(head_with, head_request, "HEAD");
+    define_with_fn!(delete_with, delete_request, "DELETE");
+    define_with_fn!(post_with, post_request, "POST");
+    define_with_fn!(patch_with, patch_request, "PATCH");
+    define_with_fn!(options_with, options_request, "OPTIONS");
+
+    /// Send a `method` request, for methods (e.g. WebDAV's `PROPFIND` or CalDAV's `REPORT`)
+    /// without a method of their own, through the session as `get_with` and the like are
+    pub fn request_with<U, P>(
+        &mut self,
+        method: &Method,
+        url: U,
+        prepare: P,
+    ) -> Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+    where
+        P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+        U: IntoUrl,
+    {
+        let url = self.request_url(url.into_url()?);
+        let request = self.client.request(method, &url);
+        self.run_request(request, method.as_str(), &url, prepare)
+    }
 
-    define_send_fn!(get, get_request);
-    define_send_fn!(put, put_request);
-    define_send_fn!(head, head_request);
-    define_send_fn!(delete, delete_request);
-    define_send_fn!(post, post_request);
+    define_send_fn!(get, get_request, "GET");
+    define_send_fn!(put, put_request, "PUT");
+    define_send_fn!(head, head_request, "HEAD");
+    define_send_fn!(delete, delete_request, "DELETE");
+    define_send_fn!(post, post_request, "POST");
+    define_send_fn!(patch, patch_request, "PATCH");
+    define_send_fn!(options, options_request, "OPTIONS");
+
+    /// The URL a request for `url` is made to, as rewritten for the session's environment (see
+    /// `with_environment`) and upgraded to HTTPS for known HSTS hosts (see `with_hsts`)
+    pub(crate) fn request_url(&self, url: Url) -> Url {
+        let url = self.environments.rewrite(url);
+        match self.hsts {
+            Some(ref hsts) => hsts.upgrade(url),
+            None => url,
+        }
+    }
 
-    fn run_request<P>(
+    pub(crate) fn run_request<P>(
         &mut self,
         request: <C as SessionClient>::Request,
+        method: &str,
         url: &Url,
         prepare: P,
     ) -> ::std::result::Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
     where
         P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
     {
+        self.rejected_cookies.clear();
+        let canonical = self.origin_aliases.canonical(url);
+        let exchange = Exchange {
+            method,
+            url,
+            cookie_url: canonical.as_ref().unwrap_or(url),
+            mode: self.fetch_metadata.take_mode(),
+            initiator: self.initiator.take(),
+            opts: self.request_opts.take().unwrap_or_default(),
+        };
+        let trace = RequestTrace::start(method, url, self.counters);
+        let response = trace.in_scope(|| {
+            let response = self.exchange(request, &exchange, prepare)?;
+            self.follow_redirects(response, &exchange)
+        });
+        let status = response.as_ref().ok().and_then(|r| r.status_code());
+        trace.finish(status, self.counters);
+        response
+    }
+
+    /// Send `request` for `exchange` (retrying it per any `RetryPolicy`, and answering any
+    /// authentication challenge per any `AuthManager`), and store the cookies of its response
+    pub(crate) fn exchange<P>(
+        &mut self,
+        request: <C as SessionClient>::Request,
+        exchange: &Exchange<'_>,
+        prepare: P,
+    ) -> ::std::result::Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+    where
+        P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+    {
+        if matches!(self.auth, Some(ref auth) if auth.has_credentials(exchange.url)) {
+            return self.run_with_auth(request, exchange, prepare);
+        }
+        self.run_exchange(request, exchange, prepare)
+    }
+
+    /// Send `request` for `exchange` (retrying it per any `RetryPolicy`), and store the cookies
+    /// of its response
+    pub(crate) fn run_exchange<P>(
+        &mut self,
+        request: <C as SessionClient>::Request,
+        exchange: &Exchange<'_>,
+        prepare: P,
+    ) -> ::std::result::Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+    where
+        P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+    {
+        let retry = self.retry.as_ref();
+        if let Some(retry) = retry.filter(|retry| retry.applies_to(exchange.method)) {
+            let retry = retry.clone();
+            return self.run_with_retries(request, exchange, prepare, &retry);
+        }
+        let request = self.add_request_cookies(request, exchange);
+        let response = self.send_request(prepare(request), exchange)?;
+        self.receive_response(&response, exchange)?;
+        Ok(response)
+    }
+
+    /// Add the cookies (and any fetch metadata headers) of the session for `exchange` to
+    /// `request`
+    pub(crate) fn add_request_cookies(
+        &mut self,
+        request: <C as SessionClient>::Request,
+        exchange: &Exchange<'_>,
+    ) -> <C as SessionClient>::Request {
+        let (url, cookie_url) = (exchange.url, exchange.cookie_url);
+        let cookies = if exchange.opts.send_cookies {
+            self.store.refresh(cookie_url);
+            let cookies = self.store.get_request_cookies(cookie_url);
+            self.accounts.select(cookie_url, cookies)
+        } else {
+            vec![]
+        };
+        let cookies = match exchange.initiator {
+            Some(ref initiator) => {
+                let suffixes = &self.public_suffixes;
+                let (sent, withheld) =
+                    subresource::outgoing(suffixes, initiator, cookie_url, cookies);
+                for cookie in &withheld {
+                    self.policy_log.decide(
+                        PolicyRule::SameSiteSend,
+                        PolicyAction::Withhold,
+                        cookie_url,
+                        cookie,
+                        None,
+                    );
+                }
+                sent
+            }
+            None => cookies,
+        };
+        self.limiter.touch_sent(&self.store, cookie_url, &cookies);
+        let cookies = self.accounts.unlabel(cookie_url, cookies);
+        self.counters.cookies_sent += cookies.len() as u64;
+        let cookies = exchange.opts.outgoing(cookies);
+        let request = match self.value_quoting.pairs(cookie_url, &cookies) {
+            Some(pairs) => request.add_cookie_pairs(pairs),
+            None => request.add_cookies(cookies.iter().collect()),
+        };
+        let headers = self
+            .fetch_metadata
+            .headers(&self.public_suffixes, exchange.method, cookie_url, exchange.mode);
+        if let Some(ref mut har) = self.har {
+            har.request(exchange.method, url, &cookies, &headers);
+        }
+        let request = headers
+            .into_iter()
+            .fold(request, |request, (name, value)| {
+                request.add_header(name, &value)
+            });
+        if let Some(ref mut sent) = self.sent_cookies {
+            sent.insert(url.clone(), cookies);
+        }
+        request
+    }
+
+    /// Send the prepared `request` for `exchange`
+    pub(crate) fn send_request(
+        &mut self,
+        request: <C as SessionClient>::Request,
+        exchange: &Exchange<'_>,
+    ) -> ::std::result::Result<<C as SessionClient>::Response, <C as SessionClient>::SendError>
+    {
+        let (method, url) = (exchange.method, exchange.url);
+        if let Some(ref backoff) = self.backoff {
+            backoff.wait(url);
+        }
+        if let Some(ref mut limiter) = self.rate_limiter {
+            limiter.wait(url);
+        }
+        self.counters.requests += 1;
+        for hook in self.metrics_hooks.iter_mut() {
+            hook.request_started(method, url);
+        }
+        // std's clock is unavailable on wasm32-unknown-unknown, so is only read when timing
+        let started = if self.metrics_hooks.is_empty() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+        let response = self.client.send(request);
+        let status = response.as_ref().ok().and_then(|r| r.status_code());
+        if let Some(started) = started {
+            for hook in self.metrics_hooks.iter_mut() {
+                hook.request_finished(method, url, status, started.elapsed());
+            }
+        }
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                self.counters.send_errors += 1;
+                if let Some(ref mut har) = self.har {
+                    har.response::<<C as SessionClient>::Response>(None);
+                }
+                return Err(e);
+            }
+        };
+        if let Some(ref mut har) = self.har {
+            har.response(Some(&response));
+        }
+        if let (Some(backoff), Some(status)) = (self.backoff.as_mut(), status) {
+            backoff.record(url, status, response.retry_after().as_deref());
+        }
+        if let (Some(hsts), Some(value)) = (
+            self.hsts.as_mut(),
+            response.strict_transport_security(),
+        ) {
+            let served_from = response.final_url().and_then(|u| u.clone().into_url().ok());
+            hsts.record(served_from.as_ref().unwrap_or(url), &value);
+        }
+        Ok(response)
+    }
+
+    /// Store the cookies of `response`, received for `exchange`
+    pub(crate) fn receive_response(
+        &mut self,
+        response: &<C as SessionClient>::Response,
+        exchange: &Exchange<'_>,
+    ) -> ::std::result::Result<(), <C as SessionClient>::SendError> {
+        let before = self.counters;
+        let result = self.store_received(response, exchange);
+        let stored = self.counters.cookies_stored - before.cookies_stored;
+        let rejected = self.counters.cookies_rejected - before.cookies_rejected;
+        for hook in self.metrics_hooks.iter_mut() {
+            hook.cookies_received(exchange.url, stored, rejected);
+        }
+        result
+    }
+
+    fn store_received(
+        &mut self,
+        response: &<C as SessionClient>::Response,
+        exchange: &Exchange<'_>,
+    ) -> ::std::result::Result<(), <C as SessionClient>::SendError> {
+        let (url, cookie_url) = (exchange.url, exchange.cookie_url);
+        if response.is_proxy_response() {
+            debug!("not storing cookies of proxy response for {}", url);
+            let cookies = response.parse_set_cookie();
+            self.reject_response_cookies(&cookies, url, RejectionReason::ProxyResponse);
+            return Ok(());
+        }
+        let raw_set_cookie = response.raw_set_cookie();
+        let cookies = match self.strictness {
+            Strictness::Lenient if !raw_set_cookie.is_empty() => parse_lenient(&raw_set_cookie),
+            _ => response.parse_set_cookie(),
+        };
+        let final_url = match response.final_url() {
+            Some(u) => Some((*u).clone().into_url()?),
+            None => attribute(self.unknown_final_url, url, &response.redirect_locations()),
+        };
+        let final_url = final_url.map(|u| self.origin_aliases.canonical(&u).unwrap_or(u));
+        let served_from = final_url.as_ref().unwrap_or(cookie_url);
+        self.fetch_metadata.navigated(exchange.mode.0, served_from);
+        if let Some(reused) = response.connection_reused() {
+            self.connections.record(served_from, reused);
+        }
+        if let Some(date) = response.date() {
+            self.clock_skew.record(served_from, &date);
+        }
+        let cookies = self
+            .value_quoting
+            .restore(served_from, cookies, &raw_set_cookie);
+        let cookies = self.clock_skew.compensate(served_from, cookies);
+        let (cookies, duplicates) = self.duplicates.dedupe(cookies);
+        self.reject_response_cookies(&duplicates, served_from, RejectionReason::Duplicate);
+        let cookies = match exchange.initiator {
+            Some(ref initiator) => {
+                let suffixes = &self.public_suffixes;
+                let (allowed, blocked) =
+                    subresource::incoming(suffixes, initiator, served_from, cookies);
+                self.reject_response_cookies(&blocked, served_from, RejectionReason::SameSite);
+                allowed
+            }
+            None => cookies,
+        };
+        match final_url {
+            Some(ref final_url) => {
+                let mut targets = redirect_targets(
+                    self.cross_site_redirect,
+                    &self.public_suffixes,
+                    cookie_url,
+                    final_url,
+                );
+                let last = targets.pop();
+                for target in targets.iter().chain(last.iter()) {
+                    self.extensions.record(&cookies, target, &raw_set_cookie);
+                }
+                for target in &targets {
+                    self.store_response_cookies(cookies.clone(), target);
+                }
+                if let Some(ref last) = last {
+                    self.store_response_cookies(cookies, last);
+                }
+                if let (Some(growth), Some(last)) = (self.growth.as_mut(), last.as_ref()) {
+                    growth.observe_store(&self.store, last);
+                }
+                if let Some(header) = response.clear_site_data() {
+                    self.clear_site_data(final_url, &header);
+                }
+            }
+            None => self.reject_response_cookies(&cookies, url, RejectionReason::UnknownUrl),
+        }
+        Ok(())
+    }
+
+    /// Notify any `StoreEventSink`s that `cookies`, received from `url`, were not stored
+    fn reject_response_cookies(
+        &mut self,
+        cookies: &[RawCookie<'static>],
+        url: &Url,
+        reason: RejectionReason,
+    ) {
+        self.counters.cookies_rejected += cookies.len() as u64;
+        for cookie in cookies {
+            reject(
+                &mut self.policy_log,
+                &mut self.event_sinks,
+                &mut self.rejected_cookies,
+                cookie,
+                url,
+                reason.clone(),
+            );
+        }
+    }
+
+    /// Store `cookies` received from `url`, except for any which would update a pinned cookie,
+    /// are for a public suffix (see `with_public_suffix_list`), or are not accepted under the
+    /// `CookiePolicy`, or exceed the `CookieLimits`, notifying any `StoreEventSink`s of
+    /// cookies which were not stored or which expired an existing cookie, and any
+    /// `on_cookie_change` callbacks of the cookies changed. Those stored are labelled with the
+    /// active account, if any (see `as_account`), only once checked.
+    pub(crate) fn store_response_cookies(&mut self, cookies: Vec<RawCookie<'static>>, url: &Url) {
         let Session {
-            ref client,
             ref mut store,
+            ref mut pinned,
+            ref mut event_sinks,
+            ref mut change_observers,
+            ref mut journal,
+            ref mut counters,
+            ref mut policy_log,
+            ref mut rejected_cookies,
+            ref public_suffixes,
+            ref cookie_policy,
+            ref mut limiter,
+            ref accounts,
+            ..
         } = self;
-        let response = {
-            let cookies = store.get_request_cookies(url).collect();
-            let request = request.add_cookies(cookies);
-            let request = prepare(request);
-            client.send(request)?
-        };
-        let cookies = response.parse_set_cookie();
-        let final_url = response
-            .final_url()
-            .map(|u| (*u).clone().into_url())
-            .transpose()?;
-        let final_url = final_url.as_ref().unwrap_or(url);
-        //let final_url: &Url = response.final_url().unwrap_or(url);
-        store.store_response_cookies(cookies.into_iter(), final_url);
-        Ok(response)
+        let cookies = pinned.filter_response_cookies(cookies, url);
+        counters.cookies_rejected += pinned.suppressed().len() as u64;
+        for cookie in pinned.suppressed() {
+            let reason = RejectionReason::Pinned;
+            reject(policy_log, event_sinks, rejected_cookies, cookie, url, reason);
+        }
+        let (cookies, supercookies) = public_suffixes.filter(url, cookies);
+        counters.cookies_rejected += supercookies.len() as u64;
+        for cookie in &supercookies {
+            let reason = RejectionReason::PublicSuffix;
+            reject(policy_log, event_sinks, rejected_cookies, cookie, url, reason);
+        }
+        let (cookies, refused) = cookie_policy.filter(url, cookies);
+        counters.cookies_rejected += refused.len() as u64;
+        for (cookie, reason) in &refused {
+            debug!("not storing {} under cookie policy: {}", cookie.name(), reason);
+            let reason = RejectionReason::Policy(reason.clone());
+            reject(policy_log, event_sinks, rejected_cookies, cookie, url, reason);
+        }
+        let (cookies, oversized) = limiter.filter_size(cookies);
+        counters.cookies_rejected += oversized.len() as u64;
+        for cookie in &oversized {
+            debug!("not storing {}, exceeding the cookie size limit", cookie.name());
+            let reason = RejectionReason::TooLarge;
+            reject(policy_log, event_sinks, rejected_cookies, cookie, url, reason);
+        }
+        // checked above by the names the server gave them, not as labelled for the account
+        let cookies = accounts.incoming(url, cookies);
+        for cookie in &cookies {
+            debug!("inserting Set-Cookie '{:?}'", cookie);
+            let parsed = Cookie::try_from_raw_cookie(cookie, url).ok();
+            let expires_existing = parsed.as_ref().map(|c| c.is_expired()).unwrap_or(false);
+            let observed = parsed.clone().filter(|_| !change_observers.is_empty());
+            let old = observed.as_ref().and_then(|parsed| stored(&*store, parsed));
+            let inserted = store.insert_raw(cookie, url);
+            if let (Ok(_), Some(journal)) = (&inserted, journal.as_mut()) {
+                journal.record(cookie, url);
+            }
+            if let (Ok(_), Some(parsed)) = (&inserted, observed) {
+                let new = stored(&*store, &parsed);
+                if let Some(change) = CookieChange::between(old.as_ref(), new.as_ref(), url) {
+                    for observer in change_observers.iter_mut() {
+                        observer(&change);
+                    }
+                }
+            }
+            if let (Ok(_), Some(parsed)) = (&inserted, parsed.filter(|_| limiter.limits_count())) {
+                if expires_existing {
+                    limiter.forget(&parsed);
+                } else {
+                    limiter.touch(&parsed);
+                    for evicted in limiter.evict(store, &String::from(&parsed.domain)) {
+                        notify(event_sinks, StoreEvent::Evicted { cookie: &evicted });
+                    }
+                }
+            }
+            match inserted {
+                Ok(_) if expires_existing => {
+                    counters.cookies_stored += 1;
+                    notify(event_sinks, StoreEvent::Expired { cookie, url });
+                }
+                Ok(_) => counters.cookies_stored += 1,
+                Err(e) => {
+                    debug!("unable to store Set-Cookie: {:?}", e);
+                    counters.cookies_rejected += 1;
+                    let reason = RejectionReason::Invalid(e);
+                    reject(policy_log, event_sinks, rejected_cookies, cookie, url, reason);
+                }
+            }
+        }
     }
 }
 
+/// Report that `cookie`, received from `url`, was not stored for `reason`, to the policy log, any
+/// `StoreEventSink`s, and the session's `rejected_cookies`
+fn reject(
+    policy_log: &mut PolicyLog,
+    event_sinks: &mut EventSinks,
+    rejected_cookies: &mut Vec<RejectedCookie>,
+    cookie: &RawCookie<'static>,
+    url: &Url,
+    reason: RejectionReason,
+) {
+    policy_log.rejected(cookie, url, &reason);
+    notify(
+        event_sinks,
+        StoreEvent::Rejected {
+            cookie,
+            url,
+            reason: reason.clone(),
+        },
+    );
+    rejected_cookies.push(RejectedCookie {
+        cookie: cookie.clone(),
+        url: url.clone(),
+        reason,
+    });
+}
+
 #[cfg(test)]
+// the fixtures of the `client` test, which saves and loads sessions
+#[cfg_attr(not(feature = "persistence"), allow(dead_code, unused_macros))]
 mod tests {
-    use super::{Session, SessionClient, SessionRequest, SessionResponse};
+    use super::{ClassifySendError, Session, SessionClient, SessionRequest, SessionResponse};
     use cookie::Cookie as RawCookie;
+    use http::Method;
     use std::io::{self, Read};
     use url::ParseError as ParseUrlError;
     use url::Url;
@@ -283,7 +1082,7 @@ mod tests {
 
     struct TestClient;
     impl TestClient {
-        fn request(&self, _: &Url) -> TestClientRequest<'_> {
+        fn new_request(&self, _: &Url) -> TestClientRequest<'_> {
             TestClientRequest {
                 cookies: vec![],
                 outgoing: vec![],
@@ -298,19 +1097,28 @@ mod tests {
         type SendError = TestError;
 
         fn get_request(&self, url: &Url) -> Self::Request {
-            self.request(url)
+            self.new_request(url)
         }
         fn put_request(&self, url: &Url) -> Self::Request {
-            self.request(url)
+            self.new_request(url)
         }
         fn head_request(&self, url: &Url) -> Self::Request {
-            self.request(url)
+            self.new_request(url)
         }
         fn delete_request(&self, url: &Url) -> Self::Request {
-            self.request(url)
+            self.new_request(url)
         }
         fn post_request(&self, url: &Url) -> Self::Request {
-            self.request(url)
+            self.new_request(url)
+        }
+        fn patch_request(&self, url: &Url) -> Self::Request {
+            self.new_request(url)
+        }
+        fn options_request(&self, url: &Url) -> Self::Request {
+            self.new_request(url)
+        }
+        fn request(&self, _: &Method, url: &Url) -> Self::Request {
+            self.new_request(url)
         }
 
         fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
@@ -322,6 +1130,7 @@ mod tests {
 
     #[derive(Debug, Clone, PartialEq)]
     struct TestError;
+    impl ClassifySendError for TestError {}
     use std::error;
     impl error::Error for TestError {
         fn description(&self) -> &str {
@@ -419,12 +1228,14 @@ mod tests {
         };
     }
 
+    #[cfg(feature = "persistence")]
     macro_rules! load_session {
         ($s: ident, $c: expr, $sd: ident) => {
             let mut $s = Session::load_json($c, &$sd[..]).unwrap();
         };
     }
 
+    #[cfg(feature = "persistence")]
     macro_rules! save_session {
         ($s: ident) => {{
             let mut output = vec![];
@@ -433,6 +1244,7 @@ mod tests {
         }};
     }
 
+    #[cfg(feature = "persistence")]
     #[test]
     fn client() {
         let session1 = {
@@ -735,4 +1547,209 @@ mod tests {
         })
         .unwrap();
     }
+
+    #[test]
+    fn pinned() {
+        let mut s = TestSession::new(&TestClient);
+        let url = Url::parse("http://www.example.com").unwrap();
+        s.pin_cookie(RawCookie::parse("bucket=a").unwrap(), &url)
+            .unwrap();
+        s.get_with("http://www.example.com", |mut r| {
+            let incoming = r.cookies.clone();
+            value_in_vec!(incoming, "bucket", "a");
+            r.set_outgoing(vec![
+                RawCookie::parse("bucket=b").unwrap(), // attempt to re-bucket, suppressed
+                RawCookie::parse("other=c").unwrap(),  // unpinned, stored
+            ]);
+            r
+        })
+        .unwrap();
+        has_value!(s, "www.example.com", "/", "bucket", "a");
+        has_value!(s, "www.example.com", "/", "other", "c");
+        let suppressed = s.suppressed_cookies();
+        value_in_vec!(suppressed, "bucket", "b");
+        not_in_vec!(suppressed, "other");
+
+        assert!(s.unpin_cookie("www.example.com", "/", "bucket"));
+        s.get_with("http://www.example.com", |mut r| {
+            r.set_outgoing(vec![RawCookie::parse("bucket=b").unwrap()]);
+            r
+        })
+        .unwrap();
+        has_value!(s, "www.example.com", "/", "bucket", "b");
+        assert!(s.suppressed_cookies().is_empty());
+    }
+
+    #[test]
+    fn event_sink() {
+        use crate::{RejectionReason, StoreEvent};
+        use std::sync::{Arc, Mutex};
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink_events = events.clone();
+        let mut s = TestSession::new(&TestClient).with_event_sink(move |e: &StoreEvent<'_>| {
+            sink_events.lock().unwrap().push(match e {
+                StoreEvent::Rejected {
+                    cookie,
+                    reason: RejectionReason::Pinned,
+                    ..
+                } => format!("pinned {}", cookie.name()),
+                StoreEvent::Rejected {
+                    cookie,
+                    reason: RejectionReason::Invalid(e),
+                    ..
+                } => format!("rejected {}: {:?}", cookie.name(), e),
+                StoreEvent::Expired { cookie, .. } => format!("expired {}", cookie.name()),
+                StoreEvent::Rejected { cookie, .. } => format!("unknown url {}", cookie.name()),
+                StoreEvent::Evicted { cookie } => format!("evicted {}", cookie.name()),
+                StoreEvent::Cleared { cookie, .. } => format!("cleared {}", cookie.name()),
+            })
+        });
+        let url = Url::parse("http://www.example.com").unwrap();
+        s.store.parse("old=1", &url).unwrap();
+        s.pin_cookie(RawCookie::parse("pin=1").unwrap(), &url)
+            .unwrap();
+        s.get_with("http://www.example.com", |mut r| {
+            r.set_outgoing(vec![
+                RawCookie::parse("new=1").unwrap(),
+                RawCookie::parse("pin=2").unwrap(),
+                RawCookie::parse("old=2; Max-Age=0").unwrap(),
+                RawCookie::parse("bad=1; Domain=invalid.com").unwrap(),
+            ]);
+            r
+        })
+        .unwrap();
+        assert_eq!(
+            vec![
+                "pinned pin".to_string(),
+                "expired old".to_string(),
+                "rejected bad: DomainMismatch".to_string(),
+            ],
+            *events.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_final_url() {
+        use crate::UnknownFinalUrl;
+
+        let mut s = TestSession::new(&TestClient).with_unknown_final_url(UnknownFinalUrl::Discard);
+        s.get_with("http://www.example.com", |mut r| {
+            r.set_outgoing(vec![RawCookie::parse("0=a").unwrap()]);
+            r
+        })
+        .unwrap();
+        not_has!(s, "0"); // TestClientResponse has no final_url, so the cookie is discarded
+    }
+
+    #[test]
+    fn sent_cookies() {
+        let mut s = TestSession::new(&TestClient).with_sent_cookie_tracking();
+        let url = Url::parse("http://www.example.com/").unwrap();
+        assert!(s.last_sent_cookies(&url).is_none());
+        s.store.parse("0=a", &url).unwrap();
+        s.store.parse("secure=b; Secure", &url).unwrap();
+        s.get(url.clone()).unwrap();
+        let sent = s.last_sent_cookies(&url).unwrap();
+        value_in_vec!(sent, "0", "a");
+        not_in_vec!(sent, "secure");
+    }
+
+    #[test]
+    fn equivalent_origin() {
+        let canonical = Url::parse("https://www.example.com/").unwrap();
+        let alias = Url::parse("http://localhost:3000/").unwrap();
+        let mut s = TestSession::new(&TestClient)
+            .with_equivalent_origin(&alias, &canonical)
+            .with_sent_cookie_tracking();
+        s.store.parse("0=a; Secure", &canonical).unwrap();
+        s.get_with(alias.join("/login").unwrap(), |mut r| {
+            r.set_outgoing(vec![RawCookie::parse("1=b").unwrap()]);
+            r
+        })
+        .unwrap();
+        let sent = s
+            .last_sent_cookies(&alias.join("/login").unwrap())
+            .unwrap();
+        value_in_vec!(sent, "0", "a");
+        has_value!(s, "www.example.com", "/", "1", "b");
+        assert!(s.store.get("localhost", "/", "1").is_none());
+    }
+
+    #[test]
+    fn custom_store() {
+        #[cfg(feature = "persistence")]
+        use crate::error::SessionError;
+        use crate::storage::CookieStorage;
+        use cookie_store::{Cookie, CookieError, CookieStore};
+        #[cfg(feature = "persistence")]
+        use std::io::{BufRead, Write};
+
+        // a store which counts the cookies inserted into it
+        #[derive(Default)]
+        struct CountingStore(CookieStore, usize);
+        impl CookieStorage for CountingStore {
+            fn get_request_cookies(&self, url: &Url) -> Vec<RawCookie<'static>> {
+                CookieStorage::get_request_cookies(&self.0, url)
+            }
+            fn insert_raw(
+                &mut self,
+                cookie: &RawCookie<'static>,
+                url: &Url,
+            ) -> Result<(), CookieError> {
+                self.1 += 1;
+                CookieStorage::insert_raw(&mut self.0, cookie, url)
+            }
+            fn iter_any(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_> {
+                CookieStorage::iter_any(&self.0)
+            }
+            #[cfg(feature = "persistence")]
+            fn save<W, E, F>(&self, writer: &mut W, cookie_to_string: F) -> Result<(), SessionError>
+            where
+                W: Write,
+                F: Fn(&Cookie<'_>) -> Result<String, E>,
+                E: std::error::Error + Send + Sync + 'static,
+            {
+                CookieStorage::save(&self.0, writer, cookie_to_string)
+            }
+            #[cfg(feature = "persistence")]
+            fn save_json<W: Write>(&self, writer: &mut W) -> Result<(), SessionError> {
+                CookieStorage::save_json(&self.0, writer)
+            }
+            #[cfg(feature = "persistence")]
+            fn load<R, E, F>(reader: R, cookie_from_str: F) -> Result<Self, SessionError>
+            where
+                R: BufRead,
+                F: Fn(&str) -> Result<Cookie<'static>, E>,
+                E: std::error::Error + Send + Sync + 'static,
+            {
+                Ok(CountingStore(CookieStore::load(reader, cookie_from_str)?, 0))
+            }
+            #[cfg(feature = "persistence")]
+            fn load_json<R: BufRead>(reader: R) -> Result<Self, SessionError> {
+                Ok(CountingStore(CookieStore::load_json(reader)?, 0))
+            }
+        }
+
+        let mut s = Session::from_store(&TestClient, CountingStore::default());
+        let url = Url::parse("http://www.example.com/").unwrap();
+        s.get_with(url.clone(), |mut r| {
+            r.set_outgoing(vec![
+                RawCookie::parse("0=a; Max-Age=60").unwrap(),
+                RawCookie::parse("1=b").unwrap(),
+            ]);
+            r
+        })
+        .unwrap();
+        assert_eq!(2, s.store.1);
+        let sent = s.store.get_request_cookies(&url);
+        is_in_vec!(sent, "0");
+        is_in_vec!(sent, "1");
+        #[cfg(feature = "persistence")]
+        {
+            let mut saved = vec![];
+            s.save_json(&mut saved).unwrap();
+            assert_eq!(1, saved.lines().count()); // only the persistent cookie
+        }
+    }
 }
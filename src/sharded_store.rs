@@ -0,0 +1,259 @@
+use crate::attribution::domain_site;
+use crate::error::SessionError;
+use crate::storage::CookieStorage;
+use cookie::Cookie as RawCookie;
+use cookie_store::{Cookie, CookieError, CookieStore};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "persistence")]
+use std::io::{BufRead, Write};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use url::Url;
+
+/// Each site's cookies, within a shard
+type Shard = HashMap<String, CookieStore>;
+
+/// A `CookieStorage` for sharing one cookie jar between many concurrent sessions (e.g. one per
+/// thread or task). Clones share the same cookies. Cookies are partitioned by site (see
+/// `CrossSiteRedirect`) across a fixed number of shards, each behind its own lock, so requests to
+/// different sites rarely contend.
+#[derive(Debug, Clone)]
+pub struct ShardedCookieStore {
+    shards: Arc<Vec<RwLock<Shard>>>,
+}
+
+impl Default for ShardedCookieStore {
+    /// A store with 64 shards
+    fn default() -> Self {
+        ShardedCookieStore::new(64)
+    }
+}
+
+impl ShardedCookieStore {
+    /// Create an empty store with `shards` shards (at least one)
+    pub fn new(shards: usize) -> Self {
+        ShardedCookieStore {
+            shards: Arc::new((0..shards.max(1)).map(|_| RwLock::default()).collect()),
+        }
+    }
+
+    fn shard_index(&self, site: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        site.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    // a panic while holding a shard's lock leaves the shard's stores usable, so poisoning is
+    // ignored
+    fn read(&self, site: &str) -> RwLockReadGuard<'_, Shard> {
+        let shard = &self.shards[self.shard_index(site)];
+        shard.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self, site: &str) -> RwLockWriteGuard<'_, Shard> {
+        let shard = &self.shards[self.shard_index(site)];
+        shard.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn each_store<F: FnMut(&CookieStore) -> Result<(), SessionError>>(
+        &self,
+        mut f: F,
+    ) -> Result<(), SessionError> {
+        for shard in self.shards.iter() {
+            let shard = shard.read().unwrap_or_else(|e| e.into_inner());
+            for store in shard.values() {
+                f(store)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CookieStorage for ShardedCookieStore {
+    fn get_request_cookies(&self, url: &Url) -> Vec<RawCookie<'static>> {
+        // keyed by the same site as the domains of the cookies inserted
+        let site = match url.host_str() {
+            Some(host) => domain_site(host),
+            None => return vec![],
+        };
+        match self.read(&site).get(&site) {
+            Some(store) => store.get_request_cookies(url).cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    fn insert_raw(&mut self, cookie: &RawCookie<'static>, url: &Url) -> Result<(), CookieError> {
+        let site = domain_site(&String::from(
+            &Cookie::try_from_raw_cookie(cookie, url)?.domain,
+        ));
+        let mut shard = self.write(&site);
+        shard
+            .entry(site)
+            .or_default()
+            .insert_raw(cookie, url)
+            .map(|_| ())
+    }
+
+    fn iter_any(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_> {
+        let mut cookies = vec![];
+        let _ = self.each_store(|store| {
+            cookies.extend(store.iter_any().cloned());
+            Ok(())
+        });
+        Box::new(cookies.into_iter())
+    }
+
+    fn remove(&mut self, domain: &str, path: &str, name: &str) -> Option<Cookie<'static>> {
+        let site = domain_site(domain);
+        let mut shard = self.write(&site);
+        let removed = shard.get_mut(&site)?.remove(domain, path, name);
+        removed.filter(|c| !c.is_expired())
+    }
+
+    fn remove_expired(&mut self) -> usize {
+        let mut removed = 0;
+        for shard in self.shards.iter() {
+            let mut shard = shard.write().unwrap_or_else(|e| e.into_inner());
+            for store in shard.values_mut() {
+                removed += CookieStorage::remove_expired(store);
+            }
+        }
+        removed
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save<W, E, F>(&self, writer: &mut W, cookie_to_string: F) -> Result<(), SessionError>
+    where
+        W: Write,
+        F: Fn(&Cookie<'_>) -> Result<String, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.each_store(|store| CookieStorage::save(store, writer, &cookie_to_string))
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save_json<W: Write>(&self, writer: &mut W) -> Result<(), SessionError> {
+        self.each_store(|store| CookieStorage::save_json(store, writer))
+    }
+
+    #[cfg(feature = "persistence")]
+    fn load<R, E, F>(reader: R, cookie_from_str: F) -> Result<Self, SessionError>
+    where
+        R: BufRead,
+        F: Fn(&str) -> Result<Cookie<'static>, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let sites = group_by_site(reader, |line| {
+            let cookie = cookie_from_str(line).map_err(SessionError::cookie_parse)?;
+            Ok(Some(String::from(&cookie.domain)))
+        })?;
+        Self::from_sites(sites, |lines| {
+            CookieStore::load(lines, &cookie_from_str).map_err(SessionError::from)
+        })
+    }
+
+    #[cfg(feature = "persistence")]
+    fn load_json<R: BufRead>(reader: R) -> Result<Self, SessionError> {
+        let sites = group_by_site(reader, |line| {
+            let store = CookieStore::load_json(line.as_bytes())?;
+            let domain = store.iter_any().next().map(|c| String::from(&c.domain));
+            Ok(domain)
+        })?;
+        Self::from_sites(sites, |lines| {
+            CookieStore::load_json(lines).map_err(SessionError::from)
+        })
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl ShardedCookieStore {
+    fn from_sites<L>(sites: HashMap<String, String>, load: L) -> Result<Self, SessionError>
+    where
+        L: Fn(&[u8]) -> Result<CookieStore, SessionError>,
+    {
+        let store = ShardedCookieStore::default();
+        for (site, lines) in sites {
+            let loaded = load(lines.as_bytes())?;
+            store.write(&site).insert(site, loaded);
+        }
+        Ok(store)
+    }
+}
+
+/// Group the lines of `reader` by the site of the domain returned by `domain_of`, skipping lines
+/// for which it returns `None`
+#[cfg(feature = "persistence")]
+fn group_by_site<R, D>(reader: R, domain_of: D) -> Result<HashMap<String, String>, SessionError>
+where
+    R: BufRead,
+    D: Fn(&str) -> Result<Option<String>, SessionError>,
+{
+    let mut sites = HashMap::<String, String>::new();
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(domain) = domain_of(&line)? {
+            let lines = sites.entry(domain_site(&domain)).or_default();
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+    }
+    Ok(sites)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShardedCookieStore;
+    use crate::storage::CookieStorage;
+    use cookie::Cookie as RawCookie;
+    use std::thread;
+    use url::Url;
+
+    #[test]
+    fn shared() {
+        let store = ShardedCookieStore::new(4);
+        let workers = (0..8)
+            .map(|i| {
+                let mut store = store.clone();
+                thread::spawn(move || {
+                    let url = Url::parse(&format!("http://www.site{}.com/", i)).unwrap();
+                    let cookie = RawCookie::parse(format!("worker={}; Max-Age=60", i)).unwrap();
+                    store.insert_raw(&cookie, &url).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(8, store.iter_any().count());
+        let url = Url::parse("http://login.site3.com/").unwrap();
+        assert!(store.get_request_cookies(&url).is_empty()); // host-only to www.site3.com
+
+        #[cfg(feature = "persistence")]
+        {
+            let mut saved = vec![];
+            store.save_json(&mut saved).unwrap();
+            let loaded = ShardedCookieStore::load_json(&saved[..]).unwrap();
+            let url = Url::parse("http://www.site3.com/").unwrap();
+            let sent = loaded.get_request_cookies(&url);
+            assert_eq!(1, sent.len());
+            assert_eq!("3", sent[0].value());
+        }
+    }
+
+    #[test]
+    fn ip_hosts() {
+        let mut store = ShardedCookieStore::new(64);
+        for url in &["http://127.0.0.1:8080/", "http://[::1]/"] {
+            let url = Url::parse(url).unwrap();
+            let cookie = RawCookie::parse("id=1").unwrap();
+            store.insert_raw(&cookie, &url).unwrap();
+            assert_eq!(1, store.get_request_cookies(&url).len());
+        }
+        let other = Url::parse("http://10.0.0.1/").unwrap();
+        assert!(store.get_request_cookies(&other).is_empty());
+        assert!(store.remove("127.0.0.1", "/", "id").is_some());
+        let url = Url::parse("http://127.0.0.1:8080/").unwrap();
+        assert!(store.get_request_cookies(&url).is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::error::SessionError;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::tenant::TenantId;
+use crate::transfer::TransferStats;
+#[cfg(feature = "persistence")]
+use log::debug;
+#[cfg(feature = "persistence")]
+use std::fs::File;
+#[cfg(feature = "persistence")]
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// What `Session::shutdown` did
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShutdownReport {
+    pub tenant: Option<TenantId>,
+    /// The jar the store was written to, if the session has one
+    pub jar: Option<PathBuf>,
+    /// The number of (unexpired, persistent) cookies written to the jar
+    pub cookies_saved: usize,
+    /// The bytes transferred by the session over its lifetime
+    pub transfer: TransferStats,
+}
+
+/// Write the jar of `store` to `path` with `save`, via a temporary file renamed into place, so
+/// that an interrupted write leaves any previous jar intact. Returns the number of cookies
+/// written.
+#[cfg(feature = "persistence")]
+pub(crate) fn write_jar_with<S, F>(store: &S, path: &Path, save: F) -> Result<usize, SessionError>
+where
+    S: CookieStorage,
+    F: FnOnce(&S, &mut BufWriter<File>) -> Result<(), SessionError>,
+{
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let mut writer = BufWriter::new(File::create(&temp)?);
+    save(store, &mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    std::fs::rename(&temp, path)?;
+    let saved = store.iter_unexpired().filter(|c| c.is_persistent()).count();
+    debug!("saved {} cookies to {}", saved, path.display());
+    Ok(saved)
+}
+
+/// Write the `save_json` jar of `store` to `path`, as by `write_jar_with`
+#[cfg(feature = "persistence")]
+pub(crate) fn write_jar<S: CookieStorage>(store: &S, path: &Path) -> Result<usize, SessionError> {
+    write_jar_with(store, path, |store, writer| store.save_json(writer))
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Save the store to the jar at `path` on `shutdown`
+    #[cfg(feature = "persistence")]
+    pub fn with_jar<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.jar = Some(path.into());
+        self
+    }
+
+    /// The jar the store is saved to on `shutdown`, if any
+    pub fn jar(&self) -> Option<&Path> {
+        self.jar.as_deref()
+    }
+
+    /// Save the store to its jar now, if it has one (see `with_jar`), e.g. from a command-line
+    /// tool's interrupt handler, when the session may not be shut down. Returns the number of
+    /// cookies written.
+    #[cfg(feature = "persistence")]
+    pub fn save_jar(&mut self) -> Result<usize, SessionError> {
+        self.store.flush()?;
+        match self.jar {
+            Some(ref jar) => {
+                let saved = write_jar(&self.store, jar)?;
+                self.save_hsts_beside(jar)?;
+                Ok(saved)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// End the session, flushing any writes the store has pending (see `CookieStorage::flush`)
+    /// and saving it to its jar (see `with_jar`), if it has one. The session is consumed, so no
+    /// request can be made after its state is saved. The session does its work on the calling
+    /// thread, so from async code this should be called as any other blocking call (e.g. via
+    /// `tokio::task::spawn_blocking`).
+    pub fn shutdown(mut self) -> Result<ShutdownReport, SessionError> {
+        self.store.flush()?;
+        #[cfg(feature = "persistence")]
+        let cookies_saved = match self.jar {
+            Some(ref jar) => {
+                let saved = write_jar(&self.store, jar)?;
+                self.save_hsts_beside(jar)?;
+                saved
+            }
+            None => 0,
+        };
+        #[cfg(not(feature = "persistence"))]
+        let cookies_saved = 0;
+        Ok(ShutdownReport {
+            tenant: self.tenant,
+            jar: self.jar,
+            cookies_saved,
+            transfer: self.transfer.stats(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "persistence", feature = "reqwest-session"))]
+mod tests {
+    use super::ShutdownReport;
+    use crate::transfer::TransferStats;
+    use crate::ReqwestSession;
+    use url::Url;
+
+    #[test]
+    fn shutdown() {
+        let dir = std::env::temp_dir().join(format!("user_agent-shutdown-{}", std::process::id()));
+        let jar = dir.join("jar.json");
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new()).with_jar(&jar);
+        session.store.parse("id=1; Max-Age=3600", &url).unwrap();
+        session.store.parse("tmp=2", &url).unwrap();
+        assert_eq!(
+            ShutdownReport {
+                tenant: None,
+                jar: Some(jar.clone()),
+                cookies_saved: 1,
+                transfer: TransferStats::default(),
+            },
+            session.shutdown().unwrap()
+        );
+        let jar = std::io::BufReader::new(std::fs::File::open(&jar).unwrap());
+        let session = ReqwestSession::load_json(reqwest::blocking::Client::new(), jar).unwrap();
+        assert!(session.store.contains("www.example.com", "/", "id"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
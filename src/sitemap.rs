@@ -0,0 +1,287 @@
+use crate::content::{ContentError, ContentResponse};
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use flate2::read::GzDecoder;
+use log::debug;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::{HashSet, VecDeque};
+use std::io::Read;
+use url::Url;
+
+/// The largest (decompressed) sitemap the protocol allows
+const MAX_SITEMAP_SIZE: u64 = 50 << 20;
+
+/// A `<url>` of a sitemap: a page to crawl, with the hints given for scheduling it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SitemapEntry {
+    pub loc: Url,
+    /// When the page was last modified, as given (a W3C datetime, e.g. `2020-03-01` or
+    /// `2020-03-01T12:00:00+00:00`)
+    pub lastmod: Option<String>,
+    pub changefreq: Option<String>,
+    /// The page's priority relative to the site's other pages, from 0.0 to 1.0
+    pub priority: Option<f32>,
+}
+
+/// The fields of a `SitemapEntry` as they are parsed
+#[derive(Debug, Default)]
+struct Fields {
+    loc: Option<Url>,
+    lastmod: Option<String>,
+    changefreq: Option<String>,
+    priority: Option<f32>,
+}
+
+/// The contents of a sitemap document
+#[derive(Debug, Clone, PartialEq)]
+enum Sitemap {
+    /// A `<urlset>` of pages
+    UrlSet(Vec<SitemapEntry>),
+    /// A `<sitemapindex>` of further sitemaps
+    Index(Vec<Url>),
+}
+
+/// The failure of fetching or parsing a sitemap through `Session::sitemap`
+#[derive(Debug)]
+pub enum SitemapError<E> {
+    /// Fetching the sitemap failed
+    Content(ContentError<E>),
+    /// The sitemap was gzipped, but could not be decompressed
+    Gzip(std::io::Error),
+    Xml(quick_xml::Error),
+    /// The (decompressed) sitemap was larger than the 50 MiB the protocol allows
+    TooLarge,
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for SitemapError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SitemapError::Content(e) => write!(f, "{}", e),
+            SitemapError::Gzip(e) => write!(f, "gzip error: {}", e),
+            SitemapError::Xml(e) => write!(f, "XML error: {}", e),
+            SitemapError::TooLarge => write!(f, "sitemap exceeds {} bytes", MAX_SITEMAP_SIZE),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for SitemapError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SitemapError::Content(e) => Some(e),
+            SitemapError::Gzip(e) => Some(e),
+            SitemapError::Xml(e) => Some(e),
+            SitemapError::TooLarge => None,
+        }
+    }
+}
+
+/// `body`, decompressed if it is gzipped (as `sitemap.xml.gz` files are, whatever their
+/// Content-Type)
+fn decompress<E>(body: Vec<u8>) -> Result<Vec<u8>, SitemapError<E>> {
+    if !body.starts_with(&[0x1f, 0x8b]) {
+        return Ok(body);
+    }
+    let mut decompressed = vec![];
+    GzDecoder::new(&body[..])
+        .take(MAX_SITEMAP_SIZE + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(SitemapError::Gzip)?;
+    if decompressed.len() as u64 > MAX_SITEMAP_SIZE {
+        return Err(SitemapError::TooLarge);
+    }
+    Ok(decompressed)
+}
+
+/// Parse a `<urlset>` or `<sitemapindex>` document. Entries whose `<loc>` is not a URL are
+/// skipped, as are elements of extensions (e.g. `<image:loc>`), which are always prefixed.
+fn parse<E>(body: &[u8]) -> Result<Sitemap, SitemapError<E>> {
+    let mut reader = Reader::from_reader(body);
+    reader.trim_text(true);
+    let mut buf = vec![];
+    let mut index = false;
+    let mut entries = vec![];
+    let mut entry: Option<Fields> = None;
+    let mut element = vec![];
+    loop {
+        let text = match reader.read_event(&mut buf).map_err(SitemapError::Xml)? {
+            Event::Start(e) => {
+                match e.name() {
+                    b"sitemapindex" => index = true,
+                    b"url" | b"sitemap" => entry = Some(Default::default()),
+                    name => element = name.to_vec(),
+                }
+                None
+            }
+            Event::Text(e) => Some(e.unescape_and_decode(&reader).map_err(SitemapError::Xml)?),
+            Event::CData(e) => Some(String::from_utf8_lossy(e.escaped()).into_owned()),
+            Event::End(e) => {
+                match e.name() {
+                    b"url" | b"sitemap" => match entry.take() {
+                        Some(Fields {
+                            loc: Some(loc),
+                            lastmod,
+                            changefreq,
+                            priority,
+                        }) => entries.push(SitemapEntry {
+                            loc,
+                            lastmod,
+                            changefreq,
+                            priority,
+                        }),
+                        _ => debug!("skipping sitemap entry without a valid <loc>"),
+                    },
+                    _ => element.clear(),
+                }
+                None
+            }
+            Event::Eof => break,
+            _ => None,
+        };
+        if let (Some(text), Some(fields)) = (text, entry.as_mut()) {
+            match &element[..] {
+                b"loc" => fields.loc = Url::parse(text.trim()).ok(),
+                b"lastmod" => fields.lastmod = Some(text),
+                b"changefreq" => fields.changefreq = Some(text),
+                b"priority" => fields.priority = text.trim().parse().ok(),
+                _ => {}
+            }
+        }
+        buf.clear();
+    }
+    Ok(if index {
+        Sitemap::Index(entries.into_iter().map(|entry| entry.loc).collect())
+    } else {
+        Sitemap::UrlSet(entries)
+    })
+}
+
+/// An iterator over the pages listed by a sitemap, from `Session::sitemap`. The sitemaps listed
+/// by a sitemap index are fetched in turn as the iterator is advanced; iteration ends after the
+/// last page, or after the first sitemap which cannot be fetched or parsed.
+pub struct SitemapEntries<'s, C: SessionClient, S: CookieStorage> {
+    session: &'s mut Session<C, S>,
+    sitemaps: VecDeque<Url>,
+    fetched: HashSet<Url>,
+    entries: std::vec::IntoIter<SitemapEntry>,
+    error: Option<SitemapError<<C as SessionClient>::SendError>>,
+}
+
+impl<'s, C, S> Iterator for SitemapEntries<'s, C, S>
+where
+    C: SessionClient,
+    S: CookieStorage,
+    <C as SessionClient>::Response: ContentResponse,
+    <C as SessionClient>::SendError:
+        From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+{
+    type Item = Result<SitemapEntry, SitemapError<<C as SessionClient>::SendError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(e) = self.error.take() {
+                self.sitemaps.clear();
+                return Some(Err(e));
+            }
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            let url = self.sitemaps.pop_front()?;
+            if !self.fetched.insert(url.clone()) {
+                continue;
+            }
+            debug!("fetching sitemap {}", url);
+            let sitemap = self
+                .session
+                .get_bytes(url)
+                .map_err(SitemapError::Content)
+                .and_then(decompress)
+                .and_then(|body| parse(&body));
+            match sitemap {
+                Ok(Sitemap::UrlSet(entries)) => self.entries = entries.into_iter(),
+                Ok(Sitemap::Index(sitemaps)) => self.sitemaps.extend(sitemaps),
+                Err(e) => self.error = Some(e),
+            }
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Iterate over the pages listed by the sitemap (optionally gzipped) at `url`, following any
+    /// sitemap index to the sitemaps it lists. Each sitemap is fetched through the session, so
+    /// carries its cookies, and is subject to any `with_download_limit`.
+    pub fn sitemap<U: IntoUrl>(&mut self, url: U) -> SitemapEntries<'_, C, S> {
+        let mut entries = SitemapEntries {
+            session: self,
+            sitemaps: VecDeque::new(),
+            fetched: HashSet::new(),
+            entries: vec![].into_iter(),
+            error: None,
+        };
+        match url.into_url() {
+            Ok(url) => entries.sitemaps.push_back(url),
+            Err(e) => entries.error = Some(SitemapError::Content(ContentError::Send(e.into()))),
+        }
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, parse, Sitemap, SitemapEntry};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use url::Url;
+
+    #[test]
+    fn sitemap() {
+        let urlset = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <url>
+                    <loc>https://www.example.com/?a=1&amp;b=2</loc>
+                    <lastmod>2020-03-01</lastmod>
+                    <changefreq>daily</changefreq>
+                    <priority>0.8</priority>
+                    <image:image><image:loc>https://www.example.com/a.png</image:loc></image:image>
+                </url>
+                <url><loc>not a url</loc></url>
+                <url><loc><![CDATA[https://www.example.com/about]]></loc></url>
+            </urlset>"#;
+        let mut gzipped = GzEncoder::new(vec![], Compression::default());
+        gzipped.write_all(urlset).unwrap();
+        let body = decompress::<()>(gzipped.finish().unwrap()).unwrap();
+        assert_eq!(
+            Sitemap::UrlSet(vec![
+                SitemapEntry {
+                    loc: Url::parse("https://www.example.com/?a=1&b=2").unwrap(),
+                    lastmod: Some("2020-03-01".to_owned()),
+                    changefreq: Some("daily".to_owned()),
+                    priority: Some(0.8),
+                },
+                SitemapEntry {
+                    loc: Url::parse("https://www.example.com/about").unwrap(),
+                    lastmod: None,
+                    changefreq: None,
+                    priority: None,
+                },
+            ]),
+            parse::<()>(&body).unwrap()
+        );
+
+        let index = br#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <sitemap>
+                    <loc>https://www.example.com/sitemap1.xml.gz</loc>
+                    <lastmod>2020-03-01T12:00:00+00:00</lastmod>
+                </sitemap>
+            </sitemapindex>"#;
+        assert_eq!(
+            Sitemap::Index(vec![
+                Url::parse("https://www.example.com/sitemap1.xml.gz").unwrap()
+            ]),
+            parse::<()>(&decompress::<()>(index.to_vec()).unwrap()).unwrap()
+        );
+        assert!(parse::<()>(b"<urlset><url></urlset>").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::{http_date, unix_secs};
+use cookie::Cookie as RawCookie;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::time::SystemTime;
+use url::{Origin, Url};
+
+/// The skew, in seconds, beyond which a server's clock is reported as skewed
+const NOTABLE_SKEW: i64 = 60;
+
+/// The skew of the clocks of the servers a `Session` has received responses from, relative to
+/// the local clock, as last observed from each origin's `Date` headers
+#[derive(Debug, Default)]
+pub(crate) struct ClockSkew {
+    /// Whether to compensate for skew when storing cookies with an `Expires` attribute
+    compensate: bool,
+    /// The server's clock less the local clock, in seconds, by origin
+    skews: HashMap<Origin, i64>,
+}
+
+/// `cookie`, given a `Max-Age` equivalent to its `Expires` by a server clock `skew` seconds ahead
+/// of the local clock at `now`, if it has an `Expires` but no `Max-Age`
+fn compensate_cookie(cookie: RawCookie<'static>, skew: i64, now: i64) -> RawCookie<'static> {
+    if cookie.max_age().is_some() {
+        return cookie;
+    }
+    // read and write the attributes textually, independent of the version of `time` used by
+    // `cookie`
+    let set_cookie = cookie.to_string();
+    let expires = set_cookie.split(';').skip(1).find_map(|attribute| {
+        let mut attribute = attribute.splitn(2, '=');
+        let name = attribute.next()?.trim();
+        match attribute.next() {
+            Some(value) if name.eq_ignore_ascii_case("expires") => http_date(value.trim()),
+            _ => None,
+        }
+    });
+    let expires = match expires {
+        Some(expires) => expires,
+        None => return cookie,
+    };
+    let max_age = (expires - (now + skew)).max(0);
+    match RawCookie::parse(format!("{}; Max-Age={}", set_cookie, max_age)) {
+        Ok(compensated) => {
+            debug!("compensated {} for {}s clock skew", compensated, skew);
+            compensated
+        }
+        Err(_) => cookie,
+    }
+}
+
+impl ClockSkew {
+    /// Record the skew shown by the `Date` header `date` of a response from `url`
+    pub(crate) fn record(&mut self, url: &Url, date: &str) {
+        let server = match http_date(date) {
+            Some(server) => server,
+            None => {
+                debug!("unable to parse Date {}", date);
+                return;
+            }
+        };
+        let skew = server - unix_secs(SystemTime::now());
+        let origin = url.origin();
+        if skew.abs() >= NOTABLE_SKEW && !self.skews.contains_key(&origin) {
+            warn!(
+                "the clock of {} is {}s {} the local clock",
+                origin.ascii_serialization(),
+                skew.abs(),
+                if skew > 0 { "ahead of" } else { "behind" }
+            );
+        }
+        self.skews.insert(origin, skew);
+    }
+
+    /// `cookies` received from `url`, compensated for the skew of its origin's clock if enabled
+    pub(crate) fn compensate(
+        &self,
+        url: &Url,
+        cookies: Vec<RawCookie<'static>>,
+    ) -> Vec<RawCookie<'static>> {
+        let skew = match self.skews.get(&url.origin()) {
+            Some(&skew) if self.compensate && skew != 0 => skew,
+            _ => return cookies,
+        };
+        let now = unix_secs(SystemTime::now());
+        cookies
+            .into_iter()
+            .map(|cookie| compensate_cookie(cookie, skew, now))
+            .collect()
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Compensate for the skew of servers' clocks (as shown by their responses' `Date` headers)
+    /// when storing cookies with an `Expires` attribute, so that a cookie lasts as long as the
+    /// server intended even if the local or server clock is wrong. Cookies with a `Max-Age` are
+    /// unaffected.
+    pub fn with_clock_skew_compensation(mut self) -> Self {
+        self.clock_skew.compensate = true;
+        self
+    }
+
+    /// The skew of the clock of `url`'s origin (its clock less the local clock, in seconds), as
+    /// last shown by the `Date` header of a response from it
+    pub fn clock_skew(&self, url: &Url) -> Option<i64> {
+        self.clock_skew.skews.get(&url.origin()).cloned()
+    }
+
+    /// The skew of each origin's clock, as for `clock_skew`, by ASCII serialized origin (e.g.
+    /// `https://www.example.com`), e.g. for export as metrics
+    pub fn clock_skews(&self) -> impl Iterator<Item = (String, i64)> + '_ {
+        self.clock_skew
+            .skews
+            .iter()
+            .map(|(origin, skew)| (origin.ascii_serialization(), *skew))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compensate_cookie, ClockSkew};
+    use cookie::Cookie as RawCookie;
+    use url::Url;
+
+    #[test]
+    fn skew() {
+        // read textually, as the type of `max_age` depends on the version of `cookie`
+        let max_age = |cookie: &RawCookie| -> Option<i64> {
+            let set_cookie = cookie.to_string();
+            let max_age = set_cookie
+                .split("; ")
+                .find_map(|a| a.strip_prefix("Max-Age="));
+            max_age.and_then(|max_age| max_age.parse().ok())
+        };
+        // Wed, 04 Mar 2020 05:06:07 GMT
+        let now = 1_583_298_367;
+        let cookie = RawCookie::parse("id=1; Expires=Wed, 04 Mar 2020 06:06:07 GMT").unwrap();
+        // the server's clock is ten minutes ahead, so the cookie lasts fifty minutes from now
+        let compensated = compensate_cookie(cookie, 600, now);
+        assert_eq!(Some(3000), max_age(&compensated));
+        let cookie = RawCookie::parse("id=1; Expires=Wed, 04 Mar 2020 05:06:07 GMT").unwrap();
+        let compensated = compensate_cookie(cookie, -600, now);
+        assert_eq!(Some(600), max_age(&compensated));
+        let cookie = RawCookie::parse("id=1; Max-Age=60").unwrap();
+        let compensated = compensate_cookie(cookie, 600, now);
+        assert_eq!(Some(60), max_age(&compensated));
+
+        let url = Url::parse("https://www.example.com/a").unwrap();
+        let mut skew = ClockSkew::default();
+        skew.record(&url, "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert!(skew.skews[&url.origin()] < -1_500_000_000);
+        let cookies =
+            vec![RawCookie::parse("id=1; Expires=Thu, 01 Jan 1970 01:00:00 GMT").unwrap()];
+        assert_eq!(None, max_age(&skew.compensate(&url, cookies.clone())[0]));
+        skew.compensate = true;
+        let compensated = skew.compensate(&url, cookies);
+        assert!((3590..=3600).contains(&max_age(&compensated[0]).unwrap()));
+    }
+}
@@ -0,0 +1,266 @@
+use crate::session::{Session, SessionClient, SessionRequest, SessionResponse};
+use crate::storage::CookieStorage;
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use url::Url;
+
+/// The HTTP methods a `RequestSpec` may use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "persistence", serde(rename_all = "UPPERCASE"))]
+pub enum SpecMethod {
+    #[default]
+    Get,
+    Put,
+    Head,
+    Delete,
+    Post,
+    Patch,
+    Options,
+}
+
+impl SpecMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SpecMethod::Get => "GET",
+            SpecMethod::Put => "PUT",
+            SpecMethod::Head => "HEAD",
+            SpecMethod::Delete => "DELETE",
+            SpecMethod::Post => "POST",
+            SpecMethod::Patch => "PATCH",
+            SpecMethod::Options => "OPTIONS",
+        }
+    }
+}
+
+/// A declarative description of a request, (de)serializable (e.g. from JSON or YAML) with the
+/// `persistence` feature, for data-driven test suites and monitoring probes; see
+/// `Session::execute_spec`. The `url`, header values, and `body` are templates, in which
+/// `{{name}}` is replaced by the variable `name`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct RequestSpec {
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub method: SpecMethod,
+    pub url: String,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub headers: BTreeMap<String, String>,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub body: Option<String>,
+    /// The status the response must have; any status is accepted if unset
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub expect_status: Option<u16>,
+}
+
+/// Requests which can be given the headers and body of a `RequestSpec`, implemented for each
+/// integration's request type
+pub trait SpecRequest: SessionRequest {
+    fn header(self, name: &str, value: &str) -> Self;
+    fn body(self, body: String) -> Self;
+}
+
+/// Responses whose status code can be read, implemented for each integration's response type
+pub trait StatusResponse: SessionResponse {
+    fn status(&self) -> u16;
+}
+
+#[cfg(feature = "reqwest-session")]
+impl SpecRequest for reqwest::blocking::RequestBuilder {
+    fn header(self, name: &str, value: &str) -> Self {
+        reqwest::blocking::RequestBuilder::header(self, name, value)
+    }
+
+    fn body(self, body: String) -> Self {
+        reqwest::blocking::RequestBuilder::body(self, body)
+    }
+}
+
+#[cfg(feature = "reqwest-session")]
+impl StatusResponse for reqwest::blocking::Response {
+    fn status(&self) -> u16 {
+        reqwest::blocking::Response::status(self).as_u16()
+    }
+}
+
+/// The failure of a `Session::execute_spec`
+#[derive(Debug)]
+pub enum SpecError<E> {
+    /// A template referred to a variable which was not given
+    MissingVariable(String),
+    /// The rendered `url` could not be parsed
+    ParseUrlError(url::ParseError),
+    /// Sending the request failed
+    Send(E),
+    /// The response did not have the expected status
+    Status { expected: u16, found: u16 },
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for SpecError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SpecError::MissingVariable(name) => write!(f, "no value for variable {}", name),
+            SpecError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            SpecError::Send(e) => write!(f, "{}", e),
+            SpecError::Status { expected, found } => {
+                write!(f, "expected status {}, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for SpecError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SpecError::ParseUrlError(e) => Some(e),
+            SpecError::Send(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Replace each `{{name}}` in `template` with the value of `name` in `vars`
+pub(crate) fn render<E>(template: &str, vars: &HashMap<String, String>) -> Result<String, SpecError<E>> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = rest[start + 2..end].trim();
+        let value = vars
+            .get(name)
+            .ok_or_else(|| SpecError::MissingVariable(name.to_owned()))?;
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(value);
+        rest = &rest[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Render `spec` with `vars`, and send it through the session
+    pub fn execute_spec(
+        &mut self,
+        spec: &RequestSpec,
+        vars: &HashMap<String, String>,
+    ) -> Result<<C as SessionClient>::Response, SpecError<<C as SessionClient>::SendError>>
+    where
+        <C as SessionClient>::Request: SpecRequest,
+        <C as SessionClient>::Response: StatusResponse,
+    {
+        let url = Url::parse(&render(&spec.url, vars)?).map_err(SpecError::ParseUrlError)?;
+        let url = self.request_url(url);
+        let mut headers = vec![];
+        for (name, value) in &spec.headers {
+            headers.push((name.as_str(), render(value, vars)?));
+        }
+        let body = match spec.body {
+            Some(ref body) => Some(render(body, vars)?),
+            None => None,
+        };
+        let request = match spec.method {
+            SpecMethod::Get => self.client.get_request(&url),
+            SpecMethod::Put => self.client.put_request(&url),
+            SpecMethod::Head => self.client.head_request(&url),
+            SpecMethod::Delete => self.client.delete_request(&url),
+            SpecMethod::Post => self.client.post_request(&url),
+            SpecMethod::Patch => self.client.patch_request(&url),
+            SpecMethod::Options => self.client.options_request(&url),
+        };
+        if let Some(ref body) = body {
+            self.uploading(body.len());
+        }
+        let response = self
+            .run_request(request, spec.method.as_str(), &url, |request| {
+                let request = headers.iter().fold(request, |request, (name, value)| {
+                    request.header(name, value)
+                });
+                match body {
+                    Some(body) => request.body(body),
+                    None => request,
+                }
+            })
+            .map_err(SpecError::Send)?;
+        match spec.expect_status {
+            Some(expected) if expected != response.status() => Err(SpecError::Status {
+                expected,
+                found: response.status(),
+            }),
+            _ => Ok(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, SpecError};
+    use std::collections::HashMap;
+
+    #[test]
+    fn render_vars() {
+        let vars = [("host", "example.com"), ("name", "x")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            "https://example.com/api/items",
+            render::<()>("https://{{ host }}/api/items", &vars).unwrap()
+        );
+        assert_eq!("a {{b", render::<()>("a {{b", &vars).unwrap());
+        match render::<()>("{{missing}}", &vars) {
+            Err(SpecError::MissingVariable(name)) => assert_eq!("missing", name),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "reqwest-session")]
+    #[test]
+    fn reqwest_request() {
+        use super::SpecRequest;
+
+        let request = reqwest::blocking::Client::new().post("https://example.com/");
+        let request = SpecRequest::header(request, "Authorization", "Bearer t1");
+        let request = SpecRequest::body(request, String::from("b"))
+            .build()
+            .unwrap();
+        assert_eq!("Bearer t1", request.headers()["Authorization"]);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn spec() {
+        use super::{RequestSpec, SpecMethod};
+
+        let spec: RequestSpec = serde_json::from_str(
+            r#"{
+                "method": "POST",
+                "url": "https://{{ host }}/api/items",
+                "headers": { "Authorization": "Bearer {{token}}" },
+                "body": "{\"name\": \"{{name}}\"}",
+                "expect_status": 201
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(SpecMethod::Post, spec.method);
+        let vars = [("host", "example.com"), ("token", "t1"), ("name", "x")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            "https://example.com/api/items",
+            render::<()>(&spec.url, &vars).unwrap()
+        );
+        assert_eq!(
+            r#"{"name": "x"}"#,
+            render::<()>(spec.body.as_ref().unwrap(), &vars).unwrap()
+        );
+
+        let spec: RequestSpec =
+            serde_json::from_str(r#"{ "url": "https://example.com/" }"#).unwrap();
+        assert_eq!(SpecMethod::Get, spec.method);
+        assert_eq!(None, spec.expect_status);
+    }
+}
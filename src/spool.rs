@@ -0,0 +1,218 @@
+use crate::content::{ContentError, ContentResponse};
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use log::debug;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// Where `Session::get_spooled` keeps response bodies: in memory up to `memory_limit` bytes, and
+/// in a temporary file (in `dir`, or the system temporary directory) beyond that
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpoolPolicy {
+    pub memory_limit: usize,
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for SpoolPolicy {
+    /// Bodies of up to 1 MiB are kept in memory
+    fn default() -> Self {
+        SpoolPolicy {
+            memory_limit: 1 << 20,
+            dir: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Spooled {
+    Memory(Vec<u8>),
+    File(NamedTempFile, u64),
+}
+
+/// A response body read by `Session::get_spooled`, held in memory or in a temporary file which
+/// is removed when the handle is dropped
+#[derive(Debug)]
+pub struct BodyHandle {
+    body: Spooled,
+}
+
+impl BodyHandle {
+    /// The length of the body in bytes
+    pub fn len(&self) -> u64 {
+        match self.body {
+            Spooled::Memory(ref body) => body.len() as u64,
+            Spooled::File(_, len) => len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The path of the temporary file holding the body, if it was spilled to disk
+    pub fn path(&self) -> Option<&Path> {
+        match self.body {
+            Spooled::Memory(_) => None,
+            Spooled::File(ref file, _) => Some(file.path()),
+        }
+    }
+
+    /// A reader over the body, from its start
+    pub fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        match self.body {
+            Spooled::Memory(ref body) => Ok(Box::new(&body[..])),
+            Spooled::File(ref file, _) => {
+                let mut file = file.reopen()?;
+                file.seek(SeekFrom::Start(0))?;
+                Ok(Box::new(file))
+            }
+        }
+    }
+
+    /// Read the whole body into memory
+    pub fn into_bytes(self) -> io::Result<Vec<u8>> {
+        match self.body {
+            Spooled::Memory(body) => Ok(body),
+            Spooled::File(file, len) => {
+                let mut body = Vec::with_capacity(len as usize);
+                File::open(file.path())?.read_to_end(&mut body)?;
+                Ok(body)
+            }
+        }
+    }
+
+    /// Keep the body at `path`, moving the temporary file there if the body was spilled to disk
+    pub fn persist<P: AsRef<Path>>(self, path: P) -> io::Result<()> {
+        match self.body {
+            Spooled::Memory(body) => std::fs::write(path, body),
+            Spooled::File(file, _) => file.persist(path).map(|_| ()).map_err(|e| e.error),
+        }
+    }
+}
+
+/// Accumulates a body chunk by chunk, spilling it to a temporary file once it exceeds the
+/// policy's memory limit
+struct Spooler<'p> {
+    policy: &'p SpoolPolicy,
+    body: Spooled,
+    error: Option<io::Error>,
+}
+
+impl<'p> Spooler<'p> {
+    fn new(policy: &'p SpoolPolicy) -> Self {
+        Spooler {
+            policy,
+            body: Spooled::Memory(vec![]),
+            error: None,
+        }
+    }
+
+    /// Add `chunk` to the body; `false` if spooling failed, and the body should not be read
+    /// further
+    fn write(&mut self, chunk: &[u8]) -> bool {
+        match self.try_write(chunk) {
+            Ok(()) => true,
+            Err(e) => {
+                self.error = Some(e);
+                false
+            }
+        }
+    }
+
+    fn try_write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        if let Spooled::Memory(ref mut body) = self.body {
+            if body.len() + chunk.len() <= self.policy.memory_limit {
+                body.extend_from_slice(chunk);
+                return Ok(());
+            }
+            let mut builder = tempfile::Builder::new();
+            builder.prefix("user_agent-");
+            let mut file = match self.policy.dir {
+                Some(ref dir) => builder.tempfile_in(dir)?,
+                None => builder.tempfile()?,
+            };
+            debug!("spooling body to {}", file.path().display());
+            file.write_all(body)?;
+            let len = body.len() as u64;
+            self.body = Spooled::File(file, len);
+        }
+        if let Spooled::File(ref mut file, ref mut len) = self.body {
+            file.write_all(chunk)?;
+            *len += chunk.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> io::Result<BodyHandle> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let mut body = self.body;
+        if let Spooled::File(ref mut file, _) = body {
+            file.flush()?;
+        }
+        Ok(BodyHandle { body })
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// GET `url` and read the body of a response of any Content-Type, keeping it in memory or
+    /// spilling it to disk according to `policy`. Large bodies are never held in memory whole.
+    pub fn get_spooled<U>(
+        &mut self,
+        url: U,
+        policy: &SpoolPolicy,
+    ) -> Result<BodyHandle, ContentError<<C as SessionClient>::SendError>>
+    where
+        U: IntoUrl,
+        <C as SessionClient>::Response: ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let response = self.get(url).map_err(ContentError::Send)?;
+        let mut spooler = Spooler::new(policy);
+        response
+            .stream_body(&mut |chunk| {
+                self.downloaded(chunk.len());
+                spooler.write(chunk)
+            })
+            .map_err(|e| ContentError::Send(<C as SessionClient>::SendError::from(e)))?;
+        spooler.finish().map_err(ContentError::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpoolPolicy, Spooler};
+    use std::io::Read;
+
+    #[test]
+    fn spool() {
+        let policy = SpoolPolicy {
+            memory_limit: 8,
+            ..Default::default()
+        };
+        let mut spooler = Spooler::new(&policy);
+        assert!(spooler.write(b"small"));
+        let small = spooler.finish().unwrap();
+        assert_eq!(None, small.path());
+        assert_eq!(b"small".to_vec(), small.into_bytes().unwrap());
+
+        let mut spooler = Spooler::new(&policy);
+        for chunk in &[&b"abcdef"[..], b"ghijkl", b"mn"] {
+            assert!(spooler.write(chunk));
+        }
+        let large = spooler.finish().unwrap();
+        assert_eq!(14, large.len());
+        let path = large.path().unwrap().to_owned();
+        assert!(path.exists());
+        let mut read = String::new();
+        large.reader().unwrap().read_to_string(&mut read).unwrap();
+        assert_eq!("abcdefghijklmn", read);
+        drop(large);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,259 @@
+use crate::error::SessionError;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::{unix_secs, unix_time};
+use cookie::Cookie as RawCookie;
+use cookie_store::{Cookie, CookieError, CookieStore};
+use log::{debug, warn};
+use rusqlite::{params, Connection};
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::SystemTime;
+use url::Url;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS cookies (
+    domain TEXT NOT NULL,
+    path TEXT NOT NULL,
+    name TEXT NOT NULL,
+    cookie TEXT NOT NULL,
+    expires INTEGER,
+    PRIMARY KEY (domain, path, name)
+)";
+
+const UPSERT: &str = "INSERT INTO cookies (domain, path, name, cookie, expires)
+    VALUES (?1, ?2, ?3, ?4, ?5)
+    ON CONFLICT (domain, path, name)
+    DO UPDATE SET cookie = excluded.cookie, expires = excluded.expires";
+
+/// Open the database at `path`, creating the `cookies` table if need be, and deleting the
+/// cookies in it which have expired
+fn open_database(path: &Path) -> rusqlite::Result<Connection> {
+    let connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    let expired = connection.execute(
+        "DELETE FROM cookies WHERE expires <= ?1",
+        params![unix_secs(SystemTime::now())],
+    )?;
+    debug!(
+        "deleted {} expired cookies from {}",
+        expired,
+        path.display()
+    );
+    Ok(connection)
+}
+
+/// The store of the cookies of the database `connection`
+fn load_database(connection: &Connection) -> Result<CookieStore, SessionError> {
+    let mut statement = connection.prepare("SELECT cookie FROM cookies")?;
+    let jar = statement
+        .query_map(params![], |row| row.get::<_, String>(0))?
+        .map(|cookie| cookie.map(|cookie| cookie + "\n"))
+        .collect::<rusqlite::Result<String>>()?;
+    CookieStore::load_json(jar.as_bytes()).map_err(SessionError::from)
+}
+
+/// Write `cookie` (in `save_json` format) to the database `connection`, as the cookie `name` of
+/// `domain` and `path`
+fn upsert(
+    connection: &Connection,
+    domain: &str,
+    path: &str,
+    name: &str,
+    cookie: &str,
+) -> rusqlite::Result<()> {
+    let value = serde_json::from_str::<serde_json::Value>(cookie).unwrap_or_default();
+    let expires = value["expires"]["AtUtc"].as_str().and_then(unix_time);
+    connection.execute(UPSERT, params![domain, path, name, cookie, expires])?;
+    Ok(())
+}
+
+/// A `CookieStorage` kept in a SQLite database, for long-running crawlers with more cookies than
+/// a `save_json` jar can be rewritten with on every change. Cookies are held in the table
+/// `cookies`, keyed by (`domain`, `path`, `name`), in `save_json` format with their expiry (in
+/// seconds since the Unix epoch, or `NULL` for session cookies).
+///
+/// The database is loaded when opened, and the store is expected to be its only writer; each
+/// received cookie is written through immediately, as an upsert of its row (or a delete, if it
+/// expired the cookie or lasts only for the session, as `save_json` does not save such cookies).
+/// Writes which fail are logged, and retried on `flush`.
+pub struct SqliteCookieStore {
+    connection: Connection,
+    cache: CookieStore,
+    /// The (domain, path, name) of each cookie which could not be written to the database
+    unsaved: BTreeSet<(String, String, String)>,
+}
+
+impl SqliteCookieStore {
+    /// Open (or create) the database at `path`, deleting the cookies in it which have expired
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SessionError> {
+        let connection = open_database(path.as_ref())?;
+        let cache = load_database(&connection)?;
+        Ok(SqliteCookieStore {
+            connection,
+            cache,
+            unsaved: BTreeSet::new(),
+        })
+    }
+
+    /// Write the cookie `name` of `domain` and `path`, as now held in the cache
+    fn save_row(&self, domain: &str, path: &str, name: &str) -> Result<(), SessionError> {
+        match self.cache.get(domain, path, name) {
+            Some(cookie) if cookie.is_persistent() && !cookie.is_expired() => {
+                let cookie = serde_json::to_string(cookie)?;
+                upsert(&self.connection, domain, path, name, &cookie)?;
+            }
+            _ => {
+                self.connection.execute(
+                    "DELETE FROM cookies WHERE domain = ?1 AND path = ?2 AND name = ?3",
+                    params![domain, path, name],
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CookieStorage for SqliteCookieStore {
+    fn get_request_cookies(&self, url: &Url) -> Vec<RawCookie<'static>> {
+        CookieStorage::get_request_cookies(&self.cache, url)
+    }
+
+    fn insert_raw(&mut self, cookie: &RawCookie<'static>, url: &Url) -> Result<(), CookieError> {
+        let parsed = Cookie::try_from_raw_cookie(cookie, url)?;
+        let (domain, path) = (String::from(&parsed.domain), String::from(&parsed.path));
+        CookieStorage::insert_raw(&mut self.cache, cookie, url)?;
+        if let Err(e) = self.save_row(&domain, &path, cookie.name()) {
+            warn!("unable to save cookie {} to SQLite: {}", cookie.name(), e);
+            self.unsaved
+                .insert((domain, path, cookie.name().to_owned()));
+        }
+        Ok(())
+    }
+
+    fn iter_any(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_> {
+        CookieStorage::iter_any(&self.cache)
+    }
+
+    fn save<W, E, F>(&self, writer: &mut W, cookie_to_string: F) -> Result<(), SessionError>
+    where
+        W: Write,
+        F: Fn(&Cookie<'_>) -> Result<String, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        CookieStorage::save(&self.cache, writer, cookie_to_string)
+    }
+
+    fn save_json<W: Write>(&self, writer: &mut W) -> Result<(), SessionError> {
+        CookieStorage::save_json(&self.cache, writer)
+    }
+
+    fn flush(&mut self) -> Result<(), SessionError> {
+        let unsaved = std::mem::take(&mut self.unsaved);
+        for (domain, path, name) in unsaved {
+            if let Err(e) = self.save_row(&domain, &path, &name) {
+                self.unsaved.insert((domain, path, name));
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn load<R, E, F>(_: R, _: F) -> Result<Self, SessionError>
+    where
+        R: BufRead,
+        F: Fn(&str) -> Result<Cookie<'static>, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Err("a SqliteCookieStore must be created with SqliteCookieStore::open".into())
+    }
+
+    fn load_json<R: BufRead>(_: R) -> Result<Self, SessionError> {
+        Err("a SqliteCookieStore must be created with SqliteCookieStore::open".into())
+    }
+}
+
+impl<C: SessionClient> Session<C> {
+    /// Load a session from the SQLite database at `path`, as written by `save_sqlite` (or a
+    /// `SqliteCookieStore`)
+    pub fn load_sqlite<P: AsRef<Path>>(client: C, path: P) -> Result<Session<C>, SessionError> {
+        let store = load_database(&open_database(path.as_ref())?)?;
+        Ok(Self::from_store(client, store))
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Save the unexpired, persistent cookies of the store to the SQLite database at `path`
+    /// (creating it if need be), replacing the cookies it held, in one transaction
+    pub fn save_sqlite<P: AsRef<Path>>(&self, path: P) -> Result<(), SessionError> {
+        let mut connection = open_database(path.as_ref())?;
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM cookies", params![])?;
+        for cookie in self.store.iter_unexpired().filter(|c| c.is_persistent()) {
+            let (domain, path) = (String::from(&cookie.domain), String::from(&cookie.path));
+            let json = serde_json::to_string(&cookie)?;
+            upsert(&transaction, &domain, &path, cookie.name(), &json)?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqliteCookieStore;
+    use crate::storage::CookieStorage;
+    use crate::ReqwestSession;
+    use cookie::Cookie as RawCookie;
+    use rusqlite::{params, Connection};
+    use std::path::Path;
+    use url::Url;
+
+    fn count(path: &Path) -> i64 {
+        Connection::open(path)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM cookies", params![], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn sqlite_store() {
+        let dir = std::env::temp_dir().join(format!("user_agent-sqlite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = dir.join("cookies.sqlite");
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let cookie = |s: &str| RawCookie::parse(s.to_owned()).unwrap();
+
+        let mut store = SqliteCookieStore::open(&db).unwrap();
+        store
+            .insert_raw(&cookie("id=1; Max-Age=3600"), &url)
+            .unwrap();
+        store
+            .insert_raw(&cookie("id=2; Max-Age=3600"), &url)
+            .unwrap();
+        store.insert_raw(&cookie("tmp=1"), &url).unwrap();
+        store
+            .insert_raw(&cookie("old=1; Max-Age=3600"), &url)
+            .unwrap();
+        store
+            .insert_raw(&cookie("old=1; Max-Age=0"), &url)
+            .unwrap_or(());
+        assert_eq!(1, count(&db));
+        drop(store);
+
+        let store = SqliteCookieStore::open(&db).unwrap();
+        let cookies = store.get_request_cookies(&url);
+        assert_eq!(
+            vec!["id=2"],
+            cookies.iter().map(|c| c.to_string()).collect::<Vec<_>>()
+        );
+
+        let session = ReqwestSession::load_sqlite(reqwest::blocking::Client::new(), &db).unwrap();
+        assert!(session.store.contains("www.example.com", "/", "id"));
+        let copy = dir.join("copy.sqlite");
+        session.save_sqlite(&copy).unwrap();
+        session.save_sqlite(&copy).unwrap();
+        assert_eq!(1, count(&copy));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,335 @@
+use crate::reqwest_session::{ReqwestSession, ReqwestSessionError};
+use crate::utils::IntoUrl;
+use log::debug;
+use reqwest::header::{ACCEPT, CACHE_CONTROL, CONTENT_TYPE};
+use reqwest::StatusCode;
+use std::io::{self, BufRead, BufReader};
+use std::time::Duration;
+use url::Url;
+
+/// The reconnection delay used until the server specifies one with a `retry` field
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+
+/// A single Server-Sent Event, as dispatched by an `EventSource`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerSentEvent {
+    /// The event type; "message" if the server did not specify one
+    pub event: String,
+    /// The event data, with multiple `data` lines joined by '\n'
+    pub data: String,
+    /// The last event ID seen on the stream at the time of dispatch, if any
+    pub id: Option<String>,
+}
+
+/// Incremental parser for the `text/event-stream` format, fed one line at a time
+#[derive(Debug, Default)]
+struct EventParser {
+    event: Option<String>,
+    data: String,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl EventParser {
+    /// Process a single line (without its line terminator), returning a `ServerSentEvent` if the line
+    /// completes one
+    fn feed_line(&mut self, line: &str) -> Option<ServerSentEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // comment
+            return None;
+        }
+        let (field, value) = match line.find(':') {
+            Some(i) => {
+                let value = &line[i + 1..];
+                (&line[..i], value.strip_prefix(' ').unwrap_or(value))
+            }
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_owned()),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_owned()),
+            "retry" => match value.parse::<u64>() {
+                Ok(ms) => self.retry = Some(Duration::from_millis(ms)),
+                Err(_) => debug!("ignoring invalid retry value {:?}", value),
+            },
+            _ => debug!("ignoring unknown field {:?}", field),
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<ServerSentEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+        Some(ServerSentEvent {
+            event: event.unwrap_or_else(|| "message".to_owned()),
+            data,
+            id: self.last_event_id.clone(),
+        })
+    }
+
+    /// Discard any partially received event, as on a dropped connection
+    fn reset(&mut self) {
+        self.event = None;
+        self.data.clear();
+    }
+}
+
+/// An `Iterator` over the events of a `text/event-stream` resource, requested through a
+/// `ReqwestSession`. Each (re)connection is a normal session request, so the current cookies in
+/// the store are sent and any Set-Cookie headers are stored. When the stream ends or errors, or a
+/// (re)connection fails (the error is yielded), the `EventSource` waits for the server-specified
+/// retry delay and reconnects, sending `Last-Event-ID` if the server has provided event IDs. As
+/// in browsers, a response with an error status or a Content-Type other than `text/event-stream`
+/// is yielded as an error and closes the `EventSource`. Invalid UTF-8 in the stream is replaced
+/// with U+FFFD.
+pub struct EventSource<'s> {
+    session: &'s mut ReqwestSession,
+    url: Url,
+    parser: EventParser,
+    reader: Option<BufReader<reqwest::blocking::Response>>,
+    reconnecting: bool,
+    closed: bool,
+}
+
+impl<'s> EventSource<'s> {
+    /// The last event ID received, which will be sent as `Last-Event-ID` on reconnection
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.parser.last_event_id.as_deref()
+    }
+
+    /// Close the connection; the iterator will yield no further events
+    pub fn close(&mut self) {
+        self.reader = None;
+        self.closed = true;
+    }
+
+    fn connect(&mut self) -> Result<(), ReqwestSessionError> {
+        let last_event_id = self.parser.last_event_id.clone();
+        let response = self.session.get_with(self.url.clone(), |r| {
+            let r = r
+                .header(ACCEPT, "text/event-stream")
+                .header(CACHE_CONTROL, "no-cache");
+            match last_event_id {
+                Some(id) => r.header("Last-Event-ID", id),
+                None => r,
+            }
+        })?;
+        if response.status() == StatusCode::NO_CONTENT {
+            // the server has asked us to stop reconnecting
+            debug!("event stream {} closed by server", self.url);
+            self.closed = true;
+            return Ok(());
+        }
+        // neither an error status nor the wrong content type is retried, as by browsers
+        let response = match response.error_for_status() {
+            Ok(response) => response,
+            Err(e) => {
+                self.closed = true;
+                return Err(e.into());
+            }
+        };
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        let essence = content_type.split(';').next().unwrap_or("").trim();
+        if !essence.eq_ignore_ascii_case("text/event-stream") {
+            self.closed = true;
+            return Err(ReqwestSessionError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected text/event-stream, got {:?}", content_type),
+            )));
+        }
+        self.reader = Some(BufReader::new(response));
+        Ok(())
+    }
+}
+
+impl<'s> Iterator for EventSource<'s> {
+    type Item = Result<ServerSentEvent, ReqwestSessionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = Vec::new();
+        while !self.closed {
+            let reader = match self.reader {
+                Some(ref mut reader) => reader,
+                None => {
+                    if std::mem::take(&mut self.reconnecting) {
+                        std::thread::sleep(self.parser.retry.unwrap_or(DEFAULT_RETRY));
+                    }
+                    if let Err(e) = self.connect() {
+                        self.reconnecting = true;
+                        return Some(Err(e));
+                    }
+                    continue;
+                }
+            };
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => {
+                    debug!("event stream {} interrupted; reconnecting", self.url);
+                    self.reader = None;
+                    self.reconnecting = true;
+                    self.parser.reset();
+                }
+                Ok(n) => {
+                    self.session.downloaded(n);
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim_end_matches('\n').trim_end_matches('\r');
+                    if let Some(event) = self.parser.feed_line(line) {
+                        return Some(Ok(event));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+impl ReqwestSession {
+    /// Open an `EventSource` for the `text/event-stream` resource at `url`. The connection is
+    /// made on the first call to `next()`.
+    pub fn event_source<U: IntoUrl>(
+        &mut self,
+        url: U,
+    ) -> Result<EventSource<'_>, ReqwestSessionError> {
+        let url = self.request_url(url.into_url()?);
+        Ok(EventSource {
+            session: self,
+            url,
+            parser: EventParser::default(),
+            reader: None,
+            reconnecting: false,
+            closed: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventParser, ServerSentEvent};
+    use crate::ReqwestSession;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    fn parse(stream: &str) -> (Vec<ServerSentEvent>, EventParser) {
+        let mut parser = EventParser::default();
+        let events = stream
+            .split('\n')
+            .filter_map(|line| parser.feed_line(line))
+            .collect();
+        (events, parser)
+    }
+
+    #[test]
+    fn events() {
+        let (events, parser) = parse(
+            ": keep-alive\n\
+             data: first\n\
+             \n\
+             event: update\n\
+             id: 7\n\
+             data:line one\n\
+             data: line two\n\
+             \n\
+             retry: 500\n\
+             id\n\
+             data\n\
+             \n\
+             \n\
+             data: never dispatched",
+        );
+        assert_eq!(
+            vec![
+                ServerSentEvent {
+                    event: "message".to_owned(),
+                    data: "first".to_owned(),
+                    id: None,
+                },
+                ServerSentEvent {
+                    event: "update".to_owned(),
+                    data: "line one\nline two".to_owned(),
+                    id: Some("7".to_owned()),
+                },
+                ServerSentEvent {
+                    event: "message".to_owned(),
+                    data: "".to_owned(),
+                    id: Some("".to_owned()),
+                },
+            ],
+            events
+        );
+        assert_eq!(Some(Duration::from_millis(500)), parser.retry);
+    }
+
+    #[test]
+    fn reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let stream = |body: &[u8]| {
+                let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                    Connection: close\r\n\r\n"
+                    .to_vec();
+                response.extend_from_slice(body);
+                response
+            };
+            let responses = vec![
+                Some(stream(b"retry: 10\nid: 1\ndata: a\n\n")),
+                None, // dropped without a response
+                Some(stream(b"data: b\xff\r\n\r\ndata: c\n\n")),
+                Some(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n"
+                        .to_vec(),
+                ),
+            ];
+            let mut last_event_ids = vec![];
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while reader.read_line(&mut request).unwrap() > 2 {}
+                last_event_ids.push(request.lines().find_map(|h| {
+                    h.to_lowercase()
+                        .strip_prefix("last-event-id: ")
+                        .map(String::from)
+                }));
+                if let Some(response) = response {
+                    (&stream).write_all(&response).unwrap();
+                }
+            }
+            last_event_ids
+        });
+        let mut s = ReqwestSession::new(reqwest::blocking::Client::new());
+        let mut events = s.event_source(&url[..]).unwrap();
+        assert_eq!("a", events.next().unwrap().unwrap().data);
+        assert!(events.next().unwrap().is_err());
+        assert_eq!("b\u{fffd}", events.next().unwrap().unwrap().data);
+        assert_eq!("c", events.next().unwrap().unwrap().data);
+        assert!(events.next().unwrap().is_err()); // not text/event-stream
+        assert!(events.next().is_none());
+        assert_eq!(
+            vec![
+                None,
+                Some("1".to_owned()),
+                Some("1".to_owned()),
+                Some("1".to_owned())
+            ],
+            server.join().unwrap()
+        );
+    }
+}
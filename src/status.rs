@@ -0,0 +1,199 @@
+use crate::content::ContentResponse;
+use crate::session::{Session, SessionClient};
+use crate::spec::StatusResponse;
+use crate::storage::CookieStorage;
+use crate::utils::IntoUrl;
+use url::Url;
+
+/// How much of the body of a response with an unexpected status is kept in its `StatusError`
+const SNIPPET_LEN: usize = 512;
+
+/// The failure of a `Session::get_expecting`, or of `EnsureStatus::ensure_status` or
+/// `ensure_success`
+#[derive(Debug)]
+pub enum StatusError<E> {
+    /// Sending the request, or reading the response body, failed
+    Send(E),
+    /// The response did not have the expected status
+    Status {
+        /// The URL the response was served from, if known
+        url: Option<Url>,
+        /// The status expected, or `None` for any success (2xx) status
+        expected: Option<u16>,
+        found: u16,
+        /// The start of the response body, e.g. the error message of an API
+        snippet: String,
+    },
+}
+
+impl<E> StatusError<E> {
+    fn map_send<F: From<E>>(self) -> StatusError<F> {
+        match self {
+            StatusError::Send(e) => StatusError::Send(e.into()),
+            StatusError::Status {
+                url,
+                expected,
+                found,
+                snippet,
+            } => StatusError::Status {
+                url,
+                expected,
+                found,
+                snippet,
+            },
+        }
+    }
+
+    /// The status the response had, for `Status` errors
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            StatusError::Status { found, .. } => Some(*found),
+            StatusError::Send(_) => None,
+        }
+    }
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for StatusError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StatusError::Send(e) => write!(f, "{}", e),
+            StatusError::Status {
+                url,
+                expected,
+                found,
+                snippet,
+            } => {
+                match expected {
+                    Some(expected) => write!(f, "expected status {}, found {}", expected, found)?,
+                    None => write!(f, "unsuccessful status {}", found)?,
+                }
+                if let Some(url) = url {
+                    write!(f, " from {}", url)?;
+                }
+                if !snippet.is_empty() {
+                    write!(f, ": {}", snippet)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for StatusError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StatusError::Send(e) => Some(e),
+            StatusError::Status { .. } => None,
+        }
+    }
+}
+
+/// Checks of the status of a response, failing with a `StatusError` holding the start of the
+/// body of a response which does not have the status expected; implemented for responses with a
+/// status and a body
+pub trait EnsureStatus: StatusResponse + ContentResponse + Sized {
+    /// The response, if its status is `expected`
+    fn ensure_status(self, expected: u16) -> Result<Self, StatusError<Self::BodyError>> {
+        let found = self.status();
+        check(self, Some(expected), found == expected)
+    }
+
+    /// The response, if its status is a success (2xx)
+    fn ensure_success(self) -> Result<Self, StatusError<Self::BodyError>> {
+        let found = self.status();
+        check(self, None, (200..300).contains(&found))
+    }
+}
+
+impl<R: StatusResponse + ContentResponse> EnsureStatus for R {}
+
+fn check<R: StatusResponse + ContentResponse>(
+    response: R,
+    expected: Option<u16>,
+    ok: bool,
+) -> Result<R, StatusError<R::BodyError>> {
+    if ok {
+        return Ok(response);
+    }
+    let found = response.status();
+    let url = response
+        .final_url()
+        .and_then(|url| url.clone().into_url().ok());
+    let mut snippet = vec![];
+    response
+        .stream_body(&mut |chunk| {
+            snippet.extend_from_slice(chunk);
+            snippet.len() < SNIPPET_LEN
+        })
+        .map_err(StatusError::Send)?;
+    snippet.truncate(SNIPPET_LEN);
+    Err(StatusError::Status {
+        url,
+        expected,
+        found,
+        snippet: String::from_utf8_lossy(&snippet).trim().to_owned(),
+    })
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// GET `url`, failing with a `StatusError` (with the start of the body) unless the response
+    /// has the status `expected`, e.g. `StatusCode::OK` or `200`
+    pub fn get_expecting<U, T>(
+        &mut self,
+        url: U,
+        expected: T,
+    ) -> Result<<C as SessionClient>::Response, StatusError<<C as SessionClient>::SendError>>
+    where
+        U: IntoUrl,
+        T: Into<u16>,
+        <C as SessionClient>::Response: StatusResponse + ContentResponse,
+        <C as SessionClient>::SendError:
+            From<<<C as SessionClient>::Response as ContentResponse>::BodyError>,
+    {
+        let response = self.get(url).map_err(StatusError::Send)?;
+        response
+            .ensure_status(expected.into())
+            .map_err(StatusError::map_send)
+    }
+}
+
+#[cfg(all(test, feature = "reqwest-session"))]
+mod tests {
+    use super::{EnsureStatus, StatusError};
+
+    #[test]
+    fn ensure_status() {
+        let response = |status: u16, body: &str| {
+            reqwest::blocking::Response::from(
+                http::Response::builder()
+                    .status(status)
+                    .body(body.to_owned())
+                    .unwrap(),
+            )
+        };
+        assert!(response(204, "").ensure_success().is_ok());
+        assert!(response(200, "").ensure_status(200).is_ok());
+        match response(200, "").ensure_status(201) {
+            Err(StatusError::Status {
+                expected: Some(201),
+                found: 200,
+                ..
+            }) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+
+        let body = format!("  {{\"error\":\"login required\"}}{}", "x".repeat(1000));
+        match response(401, &body).ensure_success() {
+            Err(StatusError::Status {
+                expected: None,
+                found: 401,
+                snippet,
+                ..
+            }) => {
+                assert!(snippet.starts_with("{\"error\":\"login required\"}xx"));
+                assert_eq!(510, snippet.len());
+            }
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+}
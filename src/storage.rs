@@ -0,0 +1,146 @@
+use crate::clear_site_data::expiring;
+use crate::error::SessionError;
+use cookie::Cookie as RawCookie;
+use cookie_store::{Cookie, CookieError, CookieStore};
+#[cfg(feature = "persistence")]
+use std::io::{BufRead, Write};
+use url::Url;
+
+/// The operations a `Session` requires of its cookie store, allowing alternatives to
+/// `cookie_store::CookieStore` (e.g. sharded, remote, or encrypted stores) to be used via
+/// `Session::from_store`
+pub trait CookieStorage {
+    /// Bring the cookies for `url` up to date before a request to it, for stores backed by shared
+    /// storage; does nothing unless implemented
+    fn refresh(&mut self, _url: &Url) {}
+
+    /// The cookies to include in a request to `url`
+    fn get_request_cookies(&self, url: &Url) -> Vec<RawCookie<'static>>;
+
+    /// Store `cookie`, received in a response from `url`
+    fn insert_raw(&mut self, cookie: &RawCookie<'static>, url: &Url) -> Result<(), CookieError>;
+
+    /// All cookies in the store, including any which have expired. Cookies are returned by value,
+    /// so that stores may hold them behind locks or remotely.
+    fn iter_any(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_>;
+
+    /// All unexpired cookies in the store
+    fn iter_unexpired(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_> {
+        Box::new(self.iter_any().filter(|c| !c.is_expired()))
+    }
+
+    /// Remove the cookie identified by `domain`, `path`, and `name`, returning it if it was in the
+    /// store and unexpired. By default the cookie is expired in place, by storing an expired
+    /// cookie over it, which any store supports.
+    fn remove(&mut self, domain: &str, path: &str, name: &str) -> Option<Cookie<'static>> {
+        let cookie = self.iter_unexpired().find(|c| {
+            String::from(&c.domain) == domain && String::from(&c.path) == path && c.name() == name
+        })?;
+        let (expired, url) = expiring(&cookie).ok()?;
+        self.insert_raw(&expired, &url).ok()?;
+        Some(cookie)
+    }
+
+    /// Remove the expired cookies the store retains (e.g. those expired in place by `remove`),
+    /// returning the number removed. Expired cookies are never sent, so by default none are.
+    fn remove_expired(&mut self) -> usize {
+        0
+    }
+
+    /// Write the unexpired, persistent cookies in the store to `writer`, one per line, formatted
+    /// by `cookie_to_string`
+    #[cfg(feature = "persistence")]
+    fn save<W, E, F>(&self, writer: &mut W, cookie_to_string: F) -> Result<(), SessionError>
+    where
+        W: Write,
+        F: Fn(&Cookie<'_>) -> Result<String, E>,
+        E: std::error::Error + Send + Sync + 'static;
+
+    /// As `save`, with cookies serialized as JSON
+    #[cfg(feature = "persistence")]
+    fn save_json<W: Write>(&self, writer: &mut W) -> Result<(), SessionError>;
+
+    /// Complete any writes to the store's backing storage which are pending (e.g. which failed,
+    /// and are to be retried), before the store is dropped; does nothing unless implemented
+    fn flush(&mut self) -> Result<(), SessionError> {
+        Ok(())
+    }
+
+    /// Load a store from `reader`, with one cookie per line parsed by `cookie_from_str`
+    #[cfg(feature = "persistence")]
+    fn load<R, E, F>(reader: R, cookie_from_str: F) -> Result<Self, SessionError>
+    where
+        Self: Sized,
+        R: BufRead,
+        F: Fn(&str) -> Result<Cookie<'static>, E>,
+        E: std::error::Error + Send + Sync + 'static;
+
+    /// Load a store written by `save_json`
+    #[cfg(feature = "persistence")]
+    fn load_json<R: BufRead>(reader: R) -> Result<Self, SessionError>
+    where
+        Self: Sized;
+}
+
+impl CookieStorage for CookieStore {
+    fn get_request_cookies(&self, url: &Url) -> Vec<RawCookie<'static>> {
+        CookieStore::get_request_cookies(self, url).cloned().collect()
+    }
+
+    fn insert_raw(&mut self, cookie: &RawCookie<'static>, url: &Url) -> Result<(), CookieError> {
+        CookieStore::insert_raw(self, cookie, url).map(|_| ())
+    }
+
+    fn iter_any(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_> {
+        Box::new(CookieStore::iter_any(self).cloned())
+    }
+
+    fn iter_unexpired(&self) -> Box<dyn Iterator<Item = Cookie<'static>> + '_> {
+        Box::new(CookieStore::iter_unexpired(self).cloned())
+    }
+
+    fn remove(&mut self, domain: &str, path: &str, name: &str) -> Option<Cookie<'static>> {
+        CookieStore::remove(self, domain, path, name).filter(|c| !c.is_expired())
+    }
+
+    fn remove_expired(&mut self) -> usize {
+        let expired = CookieStore::iter_any(self)
+            .filter(|c| c.is_expired())
+            .map(|c| (String::from(&c.domain), String::from(&c.path), c.name().to_owned()))
+            .collect::<Vec<_>>();
+        for (domain, path, name) in &expired {
+            CookieStore::remove(self, domain, path, name);
+        }
+        expired.len()
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save<W, E, F>(&self, writer: &mut W, cookie_to_string: F) -> Result<(), SessionError>
+    where
+        W: Write,
+        F: Fn(&Cookie<'_>) -> Result<String, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        CookieStore::save(self, writer, cookie_to_string).map_err(SessionError::from)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save_json<W: Write>(&self, writer: &mut W) -> Result<(), SessionError> {
+        CookieStore::save_json(self, writer).map_err(SessionError::from)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn load<R, E, F>(reader: R, cookie_from_str: F) -> Result<Self, SessionError>
+    where
+        R: BufRead,
+        F: Fn(&str) -> Result<Cookie<'static>, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        CookieStore::load(reader, cookie_from_str).map_err(SessionError::from)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn load_json<R: BufRead>(reader: R) -> Result<Self, SessionError> {
+        CookieStore::load_json(reader).map_err(SessionError::from)
+    }
+}
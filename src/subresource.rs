@@ -0,0 +1,211 @@
+use crate::fetch_metadata::{fetch_site, FetchDest};
+use crate::public_suffix::PublicSuffixes;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use cookie::{Cookie as RawCookie, SameSite};
+use log::debug;
+use url::Url;
+
+/// The result of fetching one of a page's subresources with `Session::fetch_subresources`
+#[derive(Debug)]
+pub struct SubresourceFetch<R, E> {
+    /// The URL of the subresource, as given
+    pub url: String,
+    /// The URL of the subresource, resolved against the page's, if it could be parsed
+    pub resolved: Option<Url>,
+    /// Whether the subresource is cross-site to the page, so was sent, and could set, only
+    /// `SameSite=None; Secure` cookies
+    pub cross_site: bool,
+    pub result: Result<R, E>,
+}
+
+/// Whether `cookie` may be sent with, or set by, a request cross-site to the page initiating it:
+/// only if it is explicitly `SameSite=None`, and `Secure`, as browsers require. Cookies without
+/// a `SameSite` attribute are treated as `Lax`, as by browsers' Lax-by-default.
+fn allowed_cross_site(cookie: &RawCookie<'_>) -> bool {
+    cookie.same_site() == Some(SameSite::None) && cookie.secure() == Some(true)
+}
+
+/// Whether `url` is cross-site to `initiator`, with sites per `suffixes`
+fn is_cross_site(suffixes: &PublicSuffixes, initiator: &Url, url: &Url) -> bool {
+    fetch_site(suffixes, Some(initiator), url) == "cross-site"
+}
+
+/// `cookies`, to be sent to `url` as a subresource of `initiator`, split into those a browser
+/// would send with it, and those it would withhold
+pub(crate) fn outgoing(
+    suffixes: &PublicSuffixes,
+    initiator: &Url,
+    url: &Url,
+    cookies: Vec<RawCookie<'static>>,
+) -> (Vec<RawCookie<'static>>, Vec<RawCookie<'static>>) {
+    if !is_cross_site(suffixes, initiator, url) {
+        return (cookies, vec![]);
+    }
+    cookies.into_iter().partition(|cookie| {
+        let allowed = allowed_cross_site(cookie);
+        if !allowed {
+            debug!("not sending {} to cross-site {}", cookie.name(), url);
+        }
+        allowed
+    })
+}
+
+/// `cookies`, received from `url` as a subresource of `initiator`, split into those a browser
+/// would store, and those it would block
+pub(crate) fn incoming(
+    suffixes: &PublicSuffixes,
+    initiator: &Url,
+    url: &Url,
+    cookies: Vec<RawCookie<'static>>,
+) -> (Vec<RawCookie<'static>>, Vec<RawCookie<'static>>) {
+    if !is_cross_site(suffixes, initiator, url) {
+        return (cookies, vec![]);
+    }
+    cookies.into_iter().partition(|c| allowed_cross_site(c))
+}
+
+/// The destination of the subresource at `url`, as guessed from the extension of its path
+fn destination(url: &Url) -> FetchDest {
+    let extension = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("js") | Some("mjs") => FetchDest::Script,
+        Some("css") => FetchDest::Style,
+        Some("woff") | Some("woff2") | Some("ttf") | Some("otf") => FetchDest::Font,
+        Some("png") | Some("jpg") | Some("jpeg") | Some("gif") | Some("svg") | Some("webp")
+        | Some("ico") | Some("avif") => FetchDest::Image,
+        _ => FetchDest::Empty,
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// GET each of `urls` (which may be relative), as the subresources of the page at `page_url`
+    /// a browser would load for it, e.g. to audit the cookies trackers embedded in the page are
+    /// sent and set. Cookies are sent to, and stored from, subresources cross-site to the page
+    /// only if `SameSite=None; Secure`; those blocked from being stored are reported to any
+    /// `StoreEventSink` as `RejectionReason::SameSite`. With `with_fetch_metadata`, each request
+    /// is sent in `no-cors` mode, with the page as its initiator and its destination guessed
+    /// from its extension. The page itself is not fetched.
+    pub fn fetch_subresources<I>(
+        &mut self,
+        page_url: &Url,
+        urls: I,
+    ) -> Vec<SubresourceFetch<<C as SessionClient>::Response, <C as SessionClient>::SendError>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        urls.into_iter()
+            .map(|url| {
+                let url = url.as_ref().to_owned();
+                let resolved = match page_url.join(&url) {
+                    Ok(resolved) => resolved,
+                    Err(e) => {
+                        return SubresourceFetch {
+                            url,
+                            resolved: None,
+                            cross_site: false,
+                            result: Err(e.into()),
+                        }
+                    }
+                };
+                let cross_site = is_cross_site(&self.public_suffixes, page_url, &resolved);
+                let document = self
+                    .fetch_metadata
+                    .begin_subresource(page_url, destination(&resolved));
+                self.initiator = Some(page_url.clone());
+                let result = self.get(resolved.clone());
+                self.initiator = None;
+                self.fetch_metadata.end_subresource(document);
+                SubresourceFetch {
+                    url,
+                    resolved: Some(resolved),
+                    cross_site,
+                    result,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{destination, incoming, outgoing};
+    use crate::public_suffix::PublicSuffixes;
+    use crate::FetchDest;
+    use cookie::Cookie as RawCookie;
+    use url::Url;
+
+    #[test]
+    fn same_site() {
+        let page = Url::parse("https://www.example.com/article").unwrap();
+        let cdn = Url::parse("https://static.example.com/app.js").unwrap();
+        let tracker = Url::parse("https://pixel.tracker.net/p.gif").unwrap();
+        let suffixes = &PublicSuffixes::default();
+        let cookies = || {
+            vec![
+                RawCookie::parse("lax=1; SameSite=Lax; Secure").unwrap(),
+                RawCookie::parse("default=1; Secure").unwrap(),
+                RawCookie::parse("insecure=1; SameSite=None").unwrap(),
+                RawCookie::parse("none=1; SameSite=None; Secure").unwrap(),
+            ]
+        };
+        assert_eq!(4, outgoing(suffixes, &page, &cdn, cookies()).0.len());
+        let (sent, withheld) = outgoing(suffixes, &page, &tracker, cookies());
+        assert_eq!(3, withheld.len());
+        assert_eq!(
+            vec!["none"],
+            sent.iter().map(|c| c.name()).collect::<Vec<_>>()
+        );
+
+        let (stored, blocked) = incoming(suffixes, &page, &tracker, cookies());
+        assert_eq!(1, stored.len());
+        assert_eq!(3, blocked.len());
+        let http = Url::parse("http://www.example.com/app.js").unwrap();
+        assert_eq!(1, incoming(suffixes, &page, &http, cookies()).0.len());
+
+        assert_eq!(FetchDest::Script, destination(&cdn));
+        assert_eq!(FetchDest::Image, destination(&tracker));
+        assert_eq!(FetchDest::Empty, destination(&page));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn fetch_subresources() {
+        use crate::testing::{MockClient, MockResponse};
+        use crate::Session;
+
+        let client = MockClient::new()
+            .with_response(
+                MockResponse::new(200)
+                    .with_set_cookie("none=1; SameSite=None; Secure")
+                    .with_set_cookie("lax=1; SameSite=Lax; Secure")
+                    .with_set_cookie("default=1; Secure"),
+            )
+            .with_response(MockResponse::new(200));
+        let mut session = Session::new(client);
+        let page = Url::parse("https://www.example.com/article").unwrap();
+        let tracker = "https://pixel.tracker.net/p.gif";
+        let fetches = session.fetch_subresources(&page, vec![tracker]);
+        assert!(fetches[0].cross_site && fetches[0].result.is_ok());
+        assert!(session
+            .get_cookie("pixel.tracker.net", "/", "none")
+            .is_some());
+        assert!(session
+            .get_cookie("pixel.tracker.net", "/", "lax")
+            .is_none());
+        assert!(session
+            .get_cookie("pixel.tracker.net", "/", "default")
+            .is_none());
+
+        session.fetch_subresources(&page, vec![tracker]);
+        let last = session.client.last_request().unwrap();
+        let names = last.cookie_names();
+        assert!(names.contains(&"none"));
+        assert!(!names.contains(&"lax") && !names.contains(&"default"));
+    }
+}
@@ -0,0 +1,410 @@
+//! A `Session` over [surf](https://crates.io/crates/surf) 2.x, with its async-std `h1-client`.
+//!
+//! As `Session` is blocking, each request is sent (and each response body read) with
+//! `async_std::task::block_on`, so a `SurfSession` must not be used from within an async task;
+//! async-std applications can run it with `async_std::task::spawn_blocking`. The session's
+//! cookies are added to a request as a `Cookie` header before it is sent, so the middleware of
+//! the `surf::Client` (see `surf::Client::with`) sees them as it does any other header. surf
+//! does not follow redirects unless its client has the `Redirect` middleware, and does not
+//! report the URL a redirected response was served from, so each response's `final_url` is the
+//! URL it was requested from; use `RedirectMode::Session` to have the session follow redirects,
+//! storing the cookies of each.
+use crate::content::{BodyChunks, ContentResponse};
+use crate::paginate::HeaderResponse;
+use crate::session::{
+    ClassifySendError, SendErrorKind, Session, SessionClient, SessionRequest, SessionResponse,
+};
+use crate::spec::{SpecRequest, StatusResponse};
+use crate::utils::parse_set_cookie_values;
+use async_std::io::ReadExt;
+use async_std::task::block_on;
+use cookie::Cookie as RawCookie;
+use log::debug;
+use std::str::FromStr;
+use surf::http::headers::{HeaderName, HeaderValue, CONTENT_TYPE, COOKIE, DATE, SET_COOKIE};
+use surf::http::Method;
+use surf::{Request, Response, StatusCode};
+use url::Url;
+
+/// A request to be sent by a `surf::Client`
+#[derive(Debug, Clone)]
+pub struct SurfRequest {
+    pub request: Request,
+    pub body: Option<Vec<u8>>,
+    /// The method of the request, if surf does not support it
+    unsupported_method: Option<String>,
+}
+
+impl SurfRequest {
+    fn new(method: &http::Method, url: &Url) -> Self {
+        let (surf_method, unsupported_method) = match Method::from_str(method.as_str()) {
+            Ok(surf_method) => (surf_method, None),
+            Err(_) => (Method::Get, Some(method.to_string())),
+        };
+        SurfRequest {
+            request: Request::new(surf_method, url.clone()),
+            body: None,
+            unsupported_method,
+        }
+    }
+
+    /// Set the header `name` to `value`; surf panics on invalid headers, so those are skipped
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        match (HeaderName::from_str(name), HeaderValue::from_str(value)) {
+            (Ok(name), Ok(value)) => {
+                self.request.insert_header(name, value);
+            }
+            _ => debug!("not adding invalid header {}", name),
+        }
+        self
+    }
+
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+impl SessionRequest for SurfRequest {
+    fn add_cookies(self, cookies: Vec<&RawCookie<'static>>) -> Self {
+        if cookies.is_empty() {
+            debug!("no cookies to add to request");
+            return self;
+        }
+        let pairs = cookies
+            .iter()
+            .map(|rc| rc.encoded().to_string())
+            .collect::<Vec<_>>();
+        self.add_cookie_pairs(pairs)
+    }
+
+    fn add_cookie_pairs(self, pairs: Vec<String>) -> Self {
+        if pairs.is_empty() {
+            self
+        } else {
+            self.header(COOKIE.as_str(), &pairs.join("; "))
+        }
+    }
+
+    fn add_header(self, name: &str, value: &str) -> Self {
+        self.header(name, value)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+impl SpecRequest for SurfRequest {
+    fn header(self, name: &str, value: &str) -> Self {
+        SurfRequest::header(self, name, value)
+    }
+
+    fn body(self, body: String) -> Self {
+        SurfRequest::body(self, body)
+    }
+}
+
+/// A response received by a `surf::Client`, whose body is read as it is consumed
+#[derive(Debug)]
+pub struct SurfResponse {
+    response: Response,
+    url: Url,
+}
+
+impl SurfResponse {
+    pub fn status(&self) -> StatusCode {
+        self.response.status()
+    }
+
+    /// The URL the response was requested from
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn into_inner(self) -> Response {
+        self.response
+    }
+
+    fn header_strs(&self, name: &str) -> impl Iterator<Item = &str> {
+        HeaderName::from_str(name)
+            .ok()
+            .and_then(|name| self.response.header(name))
+            .into_iter()
+            .flat_map(|values| values.iter().map(HeaderValue::as_str))
+    }
+}
+
+impl SessionResponse for SurfResponse {
+    type Url = Url;
+
+    fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+        parse_set_cookie_values(self.header_strs(SET_COOKIE.as_str()).map(str::as_bytes))
+    }
+
+    fn final_url(&self) -> Option<&Url> {
+        Some(&self.url)
+    }
+
+    fn date(&self) -> Option<String> {
+        self.header_strs(DATE.as_str()).next().map(str::to_owned)
+    }
+
+    fn raw_set_cookie(&self) -> Vec<String> {
+        self.header_strs(SET_COOKIE.as_str())
+            .chain(self.header_strs("set-cookie2"))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    fn clear_site_data(&self) -> Option<String> {
+        let values = self.header_strs("clear-site-data").collect::<Vec<_>>();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    fn is_proxy_response(&self) -> bool {
+        self.status() == StatusCode::ProxyAuthenticationRequired
+    }
+
+    fn status_code(&self) -> Option<u16> {
+        Some(self.status().into())
+    }
+
+    fn retry_after(&self) -> Option<String> {
+        self.header_strs("retry-after").next().map(str::to_owned)
+    }
+
+    fn location(&self) -> Option<String> {
+        self.header_strs("location").next().map(str::to_owned)
+    }
+
+    fn strict_transport_security(&self) -> Option<String> {
+        self.header_strs("strict-transport-security")
+            .next()
+            .map(str::to_owned)
+    }
+
+    fn www_authenticate(&self) -> Vec<String> {
+        self.header_strs("www-authenticate")
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+impl StatusResponse for SurfResponse {
+    fn status(&self) -> u16 {
+        SurfResponse::status(self).into()
+    }
+}
+
+impl HeaderResponse for SurfResponse {
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.header_strs(name).collect()
+    }
+
+    fn header_names(&self) -> Vec<String> {
+        let names = self.response.header_names();
+        names.map(|name| name.as_str().to_owned()).collect()
+    }
+}
+
+impl ContentResponse for SurfResponse {
+    type BodyError = SurfSessionError;
+
+    fn content_type(&self) -> Option<&str> {
+        self.header_strs(CONTENT_TYPE.as_str()).next()
+    }
+
+    fn into_body(mut self) -> Result<Vec<u8>, Self::BodyError> {
+        block_on(self.response.body_bytes()).map_err(SurfSessionError::Surf)
+    }
+
+    fn into_chunks(mut self) -> BodyChunks<Self::BodyError> {
+        let mut body = self.response.take_body();
+        let mut buffer = [0; 8192];
+        Box::new(std::iter::from_fn(move || {
+            match block_on(body.read(&mut buffer)) {
+                Ok(0) => None,
+                Ok(read) => Some(Ok(buffer[..read].to_vec())),
+                Err(e) => Some(Err(SurfSessionError::Body(e))),
+            }
+        }))
+    }
+}
+
+/// The start of the message of the error surf's h1 client fails with when it cannot connect
+const POOL_CONNECT_ERROR: &str = "An error occured while creating a new object";
+
+#[derive(Debug)]
+pub enum SurfSessionError {
+    ParseUrlError(url::ParseError),
+    /// surf does not support the method of the request
+    UnsupportedMethod(String),
+    /// Sending the request, or reading a response body, failed
+    Surf(surf::Error),
+    /// Reading a response body failed
+    Body(std::io::Error),
+}
+
+impl std::fmt::Display for SurfSessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SurfSessionError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            SurfSessionError::UnsupportedMethod(method) => {
+                write!(f, "Unsupported method: {}", method)
+            }
+            SurfSessionError::Surf(e) => write!(f, "surf error: {}", e),
+            SurfSessionError::Body(e) => write!(f, "Body error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SurfSessionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SurfSessionError::ParseUrlError(e) => Some(e),
+            SurfSessionError::UnsupportedMethod(_) => None,
+            SurfSessionError::Surf(e) => Some(e.as_ref()),
+            SurfSessionError::Body(e) => Some(e),
+        }
+    }
+}
+
+impl ClassifySendError for SurfSessionError {
+    fn kind(&self) -> SendErrorKind {
+        use std::io::ErrorKind;
+        match self {
+            SurfSessionError::ParseUrlError(_) => SendErrorKind::Url,
+            SurfSessionError::Surf(e) => match e.downcast_ref::<std::io::Error>().map(|e| e.kind())
+            {
+                Some(ErrorKind::TimedOut) => SendErrorKind::Timeout,
+                Some(ErrorKind::ConnectionRefused)
+                | Some(ErrorKind::ConnectionReset)
+                | Some(ErrorKind::ConnectionAborted) => SendErrorKind::Connect,
+                // the h1 client reports failures to connect only by the message of its
+                // connection pool's error
+                _ if e.to_string().starts_with(POOL_CONNECT_ERROR) => SendErrorKind::Connect,
+                _ => SendErrorKind::Other,
+            },
+            _ => SendErrorKind::Other,
+        }
+    }
+}
+
+impl From<url::ParseError> for SurfSessionError {
+    fn from(e: url::ParseError) -> Self {
+        SurfSessionError::ParseUrlError(e)
+    }
+}
+
+pub type SurfSession = Session<surf::Client>;
+
+impl SessionClient for surf::Client {
+    type Request = SurfRequest;
+    type Response = SurfResponse;
+    type SendError = SurfSessionError;
+
+    fn request(&self, method: &http::Method, url: &Url) -> Self::Request {
+        SurfRequest::new(method, url)
+    }
+
+    fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let SurfRequest {
+            mut request,
+            body,
+            unsupported_method,
+        } = request;
+        if let Some(method) = unsupported_method {
+            return Err(SurfSessionError::UnsupportedMethod(method));
+        }
+        if let Some(body) = body {
+            request.set_body(body);
+        }
+        let url = request.url().clone();
+        let response =
+            block_on(surf::Client::send(self, request)).map_err(SurfSessionError::Surf)?;
+        Ok(SurfResponse { response, url })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SurfSession, SurfSessionError};
+    use crate::{ClassifySendError, ContentResponse, Method, SendErrorKind};
+    use std::convert::TryInto;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve `responses` in turn, one per connection, returning the requests' headers
+    fn serve(responses: Vec<&'static str>) -> (u16, thread::JoinHandle<Vec<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let reader = BufReader::new(stream.try_clone().unwrap());
+                    let headers = reader
+                        .lines()
+                        .map(Result::unwrap)
+                        .take_while(|line| !line.is_empty())
+                        .collect::<Vec<_>>();
+                    (&stream).write_all(response.as_bytes()).unwrap();
+                    headers
+                })
+                .collect()
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn surf_session() {
+        let (port, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nSet-Cookie: id=1\r\nSet-Cookie: theme=dark\r\n\
+             Content-Length: 5\r\nConnection: close\r\n\r\nhello",
+            "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 207 Multi-Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let url = format!("http://127.0.0.1:{}/", port);
+        // the server closes each connection, so none may be reused from the pool
+        let config = surf::Config::new().set_http_keep_alive(false);
+        let mut session = SurfSession::new(config.try_into().unwrap());
+        let response = session.get(&url).unwrap();
+        assert_eq!(b"hello".to_vec(), response.into_body().unwrap());
+        session
+            .get_with(&url, |r| r.header("x-probe", "1"))
+            .unwrap();
+        let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+        session.request_with(&propfind, &url, |r| r).unwrap();
+
+        let requests = server.join().unwrap();
+        let has = |headers: &[String], name: &str, value: &str| {
+            headers.iter().any(|h| {
+                let mut parts = h.splitn(2, ':');
+                parts.next().map(|n| n.eq_ignore_ascii_case(name)) == Some(true)
+                    && parts.next().map(str::trim) == Some(value)
+            })
+        };
+        assert!(!requests[0]
+            .iter()
+            .any(|h| h.to_lowercase().starts_with("cookie:")));
+        assert!(has(&requests[1], "x-probe", "1"));
+        assert!(has(&requests[1], "cookie", "id=1; theme=dark"));
+        assert_eq!("PROPFIND / HTTP/1.1", requests[2][0]);
+        assert!(has(&requests[2], "cookie", "id=1; theme=dark"));
+
+        let refused = session.get(&url).err().unwrap();
+        assert_eq!(SendErrorKind::Connect, refused.kind());
+        let unsupported = Method::from_bytes(b"FROB").unwrap();
+        match session.request_with(&unsupported, &url, |r| r) {
+            Err(SurfSessionError::UnsupportedMethod(method)) => assert_eq!("FROB", method),
+            other => panic!("expected an unsupported method error, got {:?}", other),
+        }
+    }
+}
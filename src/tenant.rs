@@ -0,0 +1,232 @@
+#[cfg(feature = "persistence")]
+use crate::error::SessionError;
+use crate::session::{Session, SessionClient};
+#[cfg(feature = "persistence")]
+use crate::shutdown::{write_jar, ShutdownReport};
+use crate::storage::CookieStorage;
+#[cfg(feature = "persistence")]
+use cookie_store::CookieStore;
+#[cfg(feature = "persistence")]
+use log::debug;
+#[cfg(feature = "persistence")]
+use std::collections::HashMap;
+#[cfg(feature = "persistence")]
+use std::fs::File;
+#[cfg(feature = "persistence")]
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// The identifier of a tenant of a multi-tenant application, used to namespace its sessions'
+/// persistent state. Tenant ids are 1 to 64 ASCII letters, digits, `-`, and `_`, so that they
+/// are safe as path components and key prefixes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TenantId(String);
+
+/// A string which is not a valid `TenantId`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTenantId(pub String);
+
+impl std::fmt::Display for InvalidTenantId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid tenant id: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidTenantId {}
+
+impl TenantId {
+    pub fn new(id: &str) -> Result<Self, InvalidTenantId> {
+        let valid = (1..=64).contains(&id.len())
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if valid {
+            Ok(TenantId(id.to_owned()))
+        } else {
+            Err(InvalidTenantId(id.to_owned()))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The path of the tenant's cookie jar under `dir`: `{dir}/{tenant}/cookies.json`
+    pub fn jar_path(&self, dir: &Path) -> PathBuf {
+        dir.join(&self.0).join("cookies.json")
+    }
+
+    /// `prefix` namespaced to the tenant, e.g. for the keys of a `RedisCookieStore` shared by
+    /// several tenants: `{prefix}:{tenant}`
+    pub fn key_prefix(&self, prefix: &str) -> String {
+        format!("{}:{}", prefix, self.0)
+    }
+}
+
+impl std::fmt::Display for TenantId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The sessions of several tenants, each with its own client and store, and persisted to its
+/// own jar under a common directory. A tenant's session is only reachable through its
+/// `TenantId`, and no store is shared between tenants, so one tenant's requests can never carry
+/// or update another's cookies.
+#[cfg(feature = "persistence")]
+pub struct TenantSessions<C: SessionClient, S: CookieStorage = CookieStore> {
+    dir: PathBuf,
+    new_client: Box<dyn Fn(&TenantId) -> C>,
+    sessions: HashMap<TenantId, Session<C, S>>,
+}
+
+#[cfg(feature = "persistence")]
+impl<C: SessionClient, S: CookieStorage + Default> TenantSessions<C, S> {
+    /// Keep tenants' jars under `dir`, creating each tenant's client with `new_client`
+    pub fn new<P, F>(dir: P, new_client: F) -> Self
+    where
+        P: Into<PathBuf>,
+        F: Fn(&TenantId) -> C + 'static,
+    {
+        TenantSessions {
+            dir: dir.into(),
+            new_client: Box::new(new_client),
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// The session of `tenant`, created on first use with its store loaded from its jar, if
+    /// that exists
+    pub fn session(&mut self, tenant: &TenantId) -> Result<&mut Session<C, S>, SessionError> {
+        if !self.sessions.contains_key(tenant) {
+            let jar = tenant.jar_path(&self.dir);
+            let store = if jar.exists() {
+                debug!(
+                    "loading cookies of tenant {} from {}",
+                    tenant,
+                    jar.display()
+                );
+                S::load_json(BufReader::new(File::open(&jar)?))?
+            } else {
+                S::default()
+            };
+            let client = (self.new_client)(tenant);
+            let session = Session::from_store(client, store)
+                .with_tenant(tenant.clone())
+                .with_jar(jar);
+            self.sessions.insert(tenant.clone(), session);
+        }
+        Ok(self.sessions.get_mut(tenant).expect("session inserted"))
+    }
+
+    /// Write the store of `tenant`'s session, if it has one, to its jar
+    pub fn save(&self, tenant: &TenantId) -> Result<(), SessionError> {
+        if let Some(session) = self.sessions.get(tenant) {
+            debug!("saving cookies of tenant {}", tenant);
+            write_jar(&session.store, &tenant.jar_path(&self.dir))?;
+        }
+        Ok(())
+    }
+
+    /// Write the store of every tenant's session to its jar
+    pub fn save_all(&self) -> Result<(), SessionError> {
+        self.sessions
+            .keys()
+            .try_for_each(|tenant| self.save(tenant))
+    }
+
+    /// Remove `tenant`'s session, without saving it
+    pub fn remove(&mut self, tenant: &TenantId) -> Option<Session<C, S>> {
+        self.sessions.remove(tenant)
+    }
+
+    /// The tenants with a session
+    pub fn tenants(&self) -> impl Iterator<Item = &TenantId> {
+        self.sessions.keys()
+    }
+
+    /// `Session::shutdown` every tenant's session, saving each to its jar. Every session is shut
+    /// down even if another fails; the first failure is returned.
+    pub fn shutdown(self) -> Result<Vec<ShutdownReport>, SessionError> {
+        let mut reports = vec![];
+        let mut error = None;
+        for (_, session) in self.sessions {
+            match session.shutdown() {
+                Ok(report) => reports.push(report),
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(reports),
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Tag the session as `tenant`'s, for namespacing its persistent state and reports
+    pub fn with_tenant(mut self, tenant: TenantId) -> Self {
+        self.tenant = Some(tenant);
+        self
+    }
+
+    /// The tenant the session belongs to, if tagged with one
+    pub fn tenant(&self) -> Option<&TenantId> {
+        self.tenant.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TenantId;
+    use std::path::Path;
+
+    #[test]
+    fn tenants() {
+        assert!(TenantId::new("acme-corp_1").is_ok());
+        for invalid in &["", "../acme", "a/b", "a b", &"a".repeat(65)] {
+            assert!(TenantId::new(invalid).is_err());
+        }
+        let acme = TenantId::new("acme").unwrap();
+        assert_eq!(
+            Path::new("/var/jars/acme/cookies.json"),
+            acme.jar_path(Path::new("/var/jars"))
+        );
+        assert_eq!("cookies:acme", acme.key_prefix("cookies"));
+    }
+
+    #[cfg(all(feature = "persistence", feature = "reqwest-session"))]
+    #[test]
+    fn tenant_sessions() {
+        use super::TenantSessions;
+        use crate::ReqwestSession;
+        use url::Url;
+
+        let acme = TenantId::new("acme").unwrap();
+        let globex = TenantId::new("globex").unwrap();
+        let dir = std::env::temp_dir().join(format!("user_agent-tenants-{}", std::process::id()));
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let new_sessions = || -> TenantSessions<reqwest::blocking::Client> {
+            TenantSessions::new(&dir, |_| reqwest::blocking::Client::new())
+        };
+        let mut sessions = new_sessions();
+        let session: &mut ReqwestSession = sessions.session(&acme).unwrap();
+        assert_eq!(Some(&acme), session.tenant());
+        session.store.parse("id=1; Max-Age=3600", &url).unwrap();
+        sessions.session(&globex).unwrap();
+        sessions.save_all().unwrap();
+
+        let mut sessions = new_sessions();
+        assert!(sessions
+            .session(&acme)
+            .unwrap()
+            .store
+            .contains("www.example.com", "/", "id"));
+        assert!(!sessions
+            .session(&globex)
+            .unwrap()
+            .store
+            .contains("www.example.com", "/", "id"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,473 @@
+//! A scriptable `SessionClient` for testing code built on `Session` without a server.
+//!
+//! A `MockClient` answers requests with the responses (or failures) it is scripted with, in
+//! order, and records every request it is sent, with the cookies and headers the session added
+//! to it:
+//!
+//! ```
+//! use user_agent::testing::{MockClient, MockResponse};
+//! use user_agent::Session;
+//!
+//! let client = MockClient::new()
+//!     .with_response(MockResponse::new(200).with_set_cookie("id=1; Path=/"))
+//!     .with_response(MockResponse::new(204));
+//! let mut session = Session::new(client);
+//! session.get("https://www.example.com/login").unwrap();
+//! session.get("https://www.example.com/account").unwrap();
+//!
+//! let requests = session.client.requests();
+//! assert_eq!(None, requests[0].header_value("cookie"));
+//! assert_eq!(Some("id=1"), requests[1].header_value("cookie"));
+//! ```
+//!
+//! A `SessionRecorder` records the responses of a real client into a `Cassette`, for a
+//! `ReplayClient` to serve back in later runs.
+pub use crate::cassette::{Cassette, ReplayClient, SessionRecorder};
+use crate::content::ContentResponse;
+use crate::paginate::HeaderResponse;
+use crate::session::{
+    ClassifySendError, SendErrorKind, SessionClient, SessionRequest, SessionResponse,
+};
+use crate::spec::{SpecRequest, StatusResponse};
+use crate::utils::parse_set_cookie_values;
+use cookie::Cookie as RawCookie;
+use http::Method;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use url::{ParseError as ParseUrlError, Url};
+
+/// A request sent to a `MockClient`, as recorded by it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRequest {
+    pub method: Method,
+    pub url: Url,
+    /// The headers of the request, in the order added, including its `Cookie` header
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    pub(crate) fn new(method: Method, url: &Url) -> Self {
+        MockRequest {
+            method,
+            url: url.clone(),
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// The value of the first header `name` of the request (ignoring case), if it has one
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The names of the cookies sent with the request, in the order sent
+    pub fn cookie_names(&self) -> Vec<&str> {
+        self.header_value("cookie")
+            .map(|cookies| {
+                cookies
+                    .split(';')
+                    .filter_map(|pair| pair.split('=').next())
+                    .map(str::trim)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl SessionRequest for MockRequest {
+    fn add_cookies(self, cookies: Vec<&RawCookie<'static>>) -> Self {
+        let pairs = cookies
+            .iter()
+            .map(|rc| rc.encoded().to_string())
+            .collect::<Vec<_>>();
+        self.add_cookie_pairs(pairs)
+    }
+
+    fn add_cookie_pairs(self, pairs: Vec<String>) -> Self {
+        if pairs.is_empty() {
+            self
+        } else {
+            self.header("cookie", &pairs.join("; "))
+        }
+    }
+
+    fn add_header(self, name: &str, value: &str) -> Self {
+        self.header(name, value)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+impl SpecRequest for MockRequest {
+    fn header(self, name: &str, value: &str) -> Self {
+        MockRequest::header(self, name, value)
+    }
+
+    fn body(self, body: String) -> Self {
+        MockRequest::body(self, body)
+    }
+}
+
+/// A response for a `MockClient` to answer a request with
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    url: Option<Url>,
+}
+
+impl MockResponse {
+    /// A response with `status`, no headers, and an empty body
+    pub fn new(status: u16) -> Self {
+        MockResponse {
+            status,
+            headers: vec![],
+            body: vec![],
+            url: None,
+        }
+    }
+
+    /// Add the header `name` with `value`
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Add a Set-Cookie header with `set_cookie`, e.g. `"id=1; Path=/; HttpOnly"`
+    pub fn with_set_cookie(self, set_cookie: &str) -> Self {
+        self.with_header("set-cookie", set_cookie)
+    }
+
+    pub fn with_body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Report the response as served from `url` (as if redirected to it), rather than the URL it
+    /// was requested from
+    pub fn with_url(mut self, url: Url) -> Self {
+        self.url = Some(url);
+        self
+    }
+
+    /// The URL the response was served from
+    pub fn url(&self) -> Option<&Url> {
+        self.url.as_ref()
+    }
+
+    fn header_strs(&self, name: &str) -> impl Iterator<Item = &str> {
+        let name = name.to_owned();
+        self.headers
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(&name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl SessionResponse for MockResponse {
+    type Url = Url;
+
+    fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+        parse_set_cookie_values(self.header_strs("set-cookie").map(str::as_bytes))
+    }
+
+    fn final_url(&self) -> Option<&Url> {
+        self.url.as_ref()
+    }
+
+    fn date(&self) -> Option<String> {
+        self.header_strs("date").next().map(str::to_owned)
+    }
+
+    fn raw_set_cookie(&self) -> Vec<String> {
+        self.header_strs("set-cookie")
+            .chain(self.header_strs("set-cookie2"))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    fn clear_site_data(&self) -> Option<String> {
+        let values = self.header_strs("clear-site-data").collect::<Vec<_>>();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    fn is_proxy_response(&self) -> bool {
+        self.status == 407
+    }
+
+    fn status_code(&self) -> Option<u16> {
+        Some(self.status)
+    }
+
+    fn retry_after(&self) -> Option<String> {
+        self.header_strs("retry-after").next().map(str::to_owned)
+    }
+
+    fn location(&self) -> Option<String> {
+        self.header_strs("location").next().map(str::to_owned)
+    }
+
+    fn strict_transport_security(&self) -> Option<String> {
+        self.header_strs("strict-transport-security")
+            .next()
+            .map(str::to_owned)
+    }
+
+    fn www_authenticate(&self) -> Vec<String> {
+        self.header_strs("www-authenticate")
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+impl StatusResponse for MockResponse {
+    fn status(&self) -> u16 {
+        self.status
+    }
+}
+
+impl HeaderResponse for MockResponse {
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.header_strs(name).collect()
+    }
+
+    fn header_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        for (name, _) in &self.headers {
+            if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                names.push(name.to_lowercase());
+            }
+        }
+        names
+    }
+}
+
+impl ContentResponse for MockResponse {
+    type BodyError = MockError;
+
+    fn content_type(&self) -> Option<&str> {
+        self.header_strs("content-type").next()
+    }
+
+    fn into_body(self) -> Result<Vec<u8>, Self::BodyError> {
+        Ok(self.body)
+    }
+}
+
+/// The failure of a request sent to a `MockClient`
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockError {
+    ParseUrlError(ParseUrlError),
+    /// A failure the client was scripted with (see `MockClient::with_error`)
+    Scripted(SendErrorKind),
+    /// A request to a `ReplayClient` with the method and URL of no recorded response
+    Unrecorded(Method, Url),
+}
+
+impl std::fmt::Display for MockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MockError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            MockError::Scripted(kind) => write!(f, "scripted failure: {:?}", kind),
+            MockError::Unrecorded(method, url) => {
+                write!(f, "no response recorded for {} {}", method, url)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MockError::ParseUrlError(e) => Some(e),
+            MockError::Scripted(_) | MockError::Unrecorded(_, _) => None,
+        }
+    }
+}
+
+impl ClassifySendError for MockError {
+    fn kind(&self) -> SendErrorKind {
+        match self {
+            MockError::ParseUrlError(_) => SendErrorKind::Url,
+            MockError::Scripted(kind) => *kind,
+            MockError::Unrecorded(_, _) => SendErrorKind::Other,
+        }
+    }
+}
+
+impl From<ParseUrlError> for MockError {
+    fn from(e: ParseUrlError) -> Self {
+        MockError::ParseUrlError(e)
+    }
+}
+
+/// A `SessionClient` answering requests with scripted responses, and recording the requests it
+/// is sent. Requests are answered in the order the responses were scripted; once they run out,
+/// each request is answered with an empty `200 OK`. A response not given a URL with
+/// `MockResponse::with_url` is reported as served from the URL it was requested from.
+#[derive(Debug, Default)]
+pub struct MockClient {
+    script: RefCell<VecDeque<Result<MockResponse, SendErrorKind>>>,
+    requests: RefCell<Vec<MockRequest>>,
+}
+
+impl MockClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the next unanswered request with `response`
+    pub fn with_response(self, response: MockResponse) -> Self {
+        self.push_response(response);
+        self
+    }
+
+    /// Fail the next unanswered request with `MockError::Scripted(kind)`, e.g.
+    /// `SendErrorKind::Connect` for a refused connection
+    pub fn with_error(self, kind: SendErrorKind) -> Self {
+        self.script.borrow_mut().push_back(Err(kind));
+        self
+    }
+
+    /// Answer the next unanswered request with `response`, once the client is in use by a session
+    pub fn push_response(&self, response: MockResponse) {
+        self.script.borrow_mut().push_back(Ok(response));
+    }
+
+    /// The requests sent so far, in order
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.borrow().clone()
+    }
+
+    /// The last request sent, if any
+    pub fn last_request(&self) -> Option<MockRequest> {
+        self.requests.borrow().last().cloned()
+    }
+
+    /// Take the requests sent so far, so that later assertions see only those sent after
+    pub fn take_requests(&self) -> Vec<MockRequest> {
+        self.requests.borrow_mut().split_off(0)
+    }
+}
+
+impl SessionClient for MockClient {
+    type Request = MockRequest;
+    type Response = MockResponse;
+    type SendError = MockError;
+
+    fn request(&self, method: &Method, url: &Url) -> Self::Request {
+        MockRequest::new(method.clone(), url)
+    }
+
+    fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let url = request.url.clone();
+        self.requests.borrow_mut().push(request);
+        let next = self.script.borrow_mut().pop_front();
+        match next.unwrap_or_else(|| Ok(MockResponse::new(200))) {
+            Ok(mut response) => {
+                response.url.get_or_insert(url);
+                Ok(response)
+            }
+            Err(kind) => Err(MockError::Scripted(kind)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MockClient, MockError, MockResponse};
+    use crate::session::{SendErrorKind, Session};
+    use crate::{ContentResponse, RetryPolicy};
+    use std::time::Duration;
+
+    #[test]
+    fn mock_client() {
+        let client = MockClient::new()
+            .with_response(
+                MockResponse::new(200)
+                    .with_set_cookie("id=1; Path=/")
+                    .with_set_cookie("theme=dark")
+                    .with_body("hello"),
+            )
+            .with_error(SendErrorKind::Connect);
+        let mut session = Session::new(client);
+        let url = "https://www.example.com/";
+        let response = session.get(url).unwrap();
+        assert_eq!(Some(url), response.url().map(|u| u.as_str()));
+        assert_eq!(b"hello".to_vec(), response.into_body().unwrap());
+        assert_eq!(
+            Some(MockError::Scripted(SendErrorKind::Connect)),
+            session.post_with(url, |r| r.body("x")).err()
+        );
+        session.client.push_response(MockResponse::new(204));
+        session.delete(url).unwrap();
+        session.get_with(url, |r| r.header("x-probe", "1")).unwrap();
+
+        let requests = session.client.take_requests();
+        assert_eq!(4, requests.len());
+        assert_eq!(None, requests[0].header_value("cookie"));
+        assert_eq!(http::Method::POST, requests[1].method);
+        assert_eq!(b"x".to_vec(), requests[1].body);
+        // in the order stored only with `preserve_order`
+        let mut names = requests[2].cookie_names();
+        names.sort();
+        assert_eq!(vec!["id", "theme"], names);
+        assert_eq!(Some("1"), requests[3].header_value("X-Probe"));
+        assert!(session.client.requests().is_empty());
+    }
+
+    #[test]
+    fn request_with() {
+        let client = MockClient::new()
+            .with_response(MockResponse::new(207).with_set_cookie("id=1"))
+            .with_response(MockResponse::new(200));
+        let mut session = Session::new(client);
+        let url = "https://dav.example.com/calendars/";
+        let propfind = http::Method::from_bytes(b"PROPFIND").unwrap();
+        session
+            .request_with(&propfind, url, |r| r.header("depth", "1"))
+            .unwrap();
+        // built with `request`, by default
+        session.patch(url).unwrap();
+
+        let requests = session.client.take_requests();
+        assert_eq!(propfind, requests[0].method);
+        assert_eq!(Some("1"), requests[0].header_value("depth"));
+        assert_eq!(http::Method::PATCH, requests[1].method);
+        assert_eq!(vec!["id"], requests[1].cookie_names());
+    }
+
+    #[test]
+    fn retried() {
+        let client = MockClient::new()
+            .with_response(MockResponse::new(503).with_set_cookie("retry=1"))
+            .with_response(MockResponse::new(200));
+        let policy =
+            RetryPolicy::new().with_backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let mut session = Session::new(client).with_retry_policy(policy);
+        session.get("https://www.example.com/").unwrap();
+        let last = session.client.last_request().unwrap();
+        assert_eq!(vec!["retry"], last.cookie_names());
+    }
+}
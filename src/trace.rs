@@ -0,0 +1,136 @@
+use crate::metrics::Counters;
+use url::Url;
+
+/// The `tracing` span of a request made through a `Session`, including any redirects it
+/// follows, recording the cookies attached to the request and stored from its responses.
+/// Without the `tracing` feature, nothing is recorded.
+pub(crate) struct RequestTrace {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    /// The counters of the session as the request was started
+    #[cfg(feature = "tracing")]
+    started: Counters,
+}
+
+impl RequestTrace {
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn start(method: &str, url: &Url, counters: Counters) -> Self {
+        RequestTrace {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "request",
+                method,
+                url = %url,
+                status = tracing::field::Empty,
+                cookies_sent = tracing::field::Empty,
+                cookies_stored = tracing::field::Empty,
+                cookies_rejected = tracing::field::Empty,
+                redirects = tracing::field::Empty,
+            ),
+            #[cfg(feature = "tracing")]
+            started: counters,
+        }
+    }
+
+    /// Run `f` (sending the request, and following its redirects) within the span
+    pub(crate) fn in_scope<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        #[cfg(feature = "tracing")]
+        let result = self.span.in_scope(f);
+        #[cfg(not(feature = "tracing"))]
+        let result = f();
+        result
+    }
+
+    /// Record the outcome of the request: the `status` of its final response, if it has one,
+    /// and the cookies counted by the session since it was started
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn finish(self, status: Option<u16>, counters: Counters) {
+        #[cfg(feature = "tracing")]
+        {
+            let started = self.started;
+            if let Some(status) = status {
+                self.span.record("status", status);
+            }
+            let span = &self.span;
+            span.record("cookies_sent", counters.cookies_sent - started.cookies_sent);
+            span.record(
+                "cookies_stored",
+                counters.cookies_stored - started.cookies_stored,
+            );
+            let rejected = counters.cookies_rejected - started.cookies_rejected;
+            span.record("cookies_rejected", rejected);
+            span.record("redirects", counters.redirects - started.redirects);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tracing", feature = "testing"))]
+mod tests {
+    use crate::testing::{MockClient, MockResponse};
+    use crate::{RedirectMode, Session};
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Records the fields of every span
+    #[derive(Default)]
+    struct Spans(Arc<Mutex<HashMap<String, String>>>);
+
+    impl Visit for Spans {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            let value = format!("{:?}", value);
+            self.0
+                .lock()
+                .unwrap()
+                .insert(field.name().to_owned(), value);
+        }
+    }
+
+    impl Subscriber for Spans {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            span.record(&mut Spans(self.0.clone()));
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut Spans(self.0.clone()));
+        }
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn request_span() {
+        let client = MockClient::new()
+            .with_response(
+                MockResponse::new(302)
+                    .with_header("location", "/home")
+                    .with_set_cookie("id=1")
+                    .with_set_cookie("other=1; Domain=example.org"),
+            )
+            .with_response(MockResponse::new(200));
+        let mut session =
+            Session::new(client).with_redirect_mode(RedirectMode::Session { max_redirects: 5 });
+        let fields = Arc::new(Mutex::new(HashMap::new()));
+        let subscriber = Spans(fields.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            session.get("https://www.example.com/login").unwrap();
+        });
+        let fields = fields.lock().unwrap();
+        let field = |name: &str| fields.get(name).map(String::as_str);
+        assert_eq!(Some("\"GET\""), field("method"));
+        assert_eq!(Some("https://www.example.com/login"), field("url"));
+        assert_eq!(Some("200"), field("status"));
+        assert_eq!(Some("1"), field("cookies_sent"));
+        assert_eq!(Some("1"), field("cookies_stored"));
+        assert_eq!(Some("1"), field("cookies_rejected"));
+        assert_eq!(Some("1"), field("redirects"));
+    }
+}
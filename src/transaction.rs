@@ -0,0 +1,158 @@
+use crate::clear_site_data::expiring;
+use crate::delta::StoreSnapshot;
+use crate::expiry::Expiry;
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use crate::utils::{fixdate, unix_secs, IntoUrl};
+use cookie::Cookie as RawCookie;
+use cookie_store::Cookie;
+use log::{debug, warn};
+use url::Url;
+
+/// The failure of a `Session::get_transactional`
+#[derive(Debug)]
+pub enum TransactionError<S, E> {
+    /// Sending the request failed
+    Send(S),
+    /// The handler failed, and the changes the response made to the store were rolled back
+    Handler(E),
+}
+
+impl<S: std::fmt::Display, E: std::fmt::Display> std::fmt::Display for TransactionError<S, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TransactionError::Send(e) => write!(f, "{}", e),
+            TransactionError::Handler(e) => write!(f, "{} (cookie changes rolled back)", e),
+        }
+    }
+}
+
+impl<S, E> std::error::Error for TransactionError<S, E>
+where
+    S: std::error::Error + 'static,
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TransactionError::Send(e) => Some(e),
+            TransactionError::Handler(e) => Some(e),
+        }
+    }
+}
+
+/// A Set-Cookie restoring `cookie` as it is, and the URL to store it as received from: with its
+/// path explicit, and its expiry (which may have been set by `Max-Age`) as an absolute time
+pub(crate) fn restoring(cookie: &Cookie<'_>) -> Result<(RawCookie<'static>, Url), String> {
+    let domain = String::from(&cookie.domain);
+    let path = String::from(&cookie.path);
+    let raw = cookie.to_string();
+    let mut set_cookie = raw
+        .split(';')
+        .filter(|attribute| {
+            let name = attribute.split('=').next().unwrap_or("").trim();
+            !["path", "max-age", "expires"]
+                .iter()
+                .any(|a| name.eq_ignore_ascii_case(a))
+        })
+        .collect::<Vec<_>>()
+        .join(";");
+    set_cookie.push_str(&format!("; Path={}", path));
+    if let Some(expires) = Expiry::of(cookie).time() {
+        set_cookie.push_str(&format!("; Expires={}", fixdate(unix_secs(expires))));
+    }
+    let url = Url::parse(&format!("https://{}{}", domain, path)).map_err(|e| e.to_string())?;
+    let restored = RawCookie::parse(set_cookie).map_err(|e| e.to_string())?;
+    Ok((restored, url))
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// As `get_with`, with the response passed to `handle`; if `handle` fails (e.g. a step of a
+    /// multi-step flow finds the response is not what it expected), the changes the response
+    /// made to the store are rolled back, so that the flow can be retried from a consistent
+    /// state. Rolled back cookies are restored with their expiry as it was.
+    pub fn get_transactional<U, P, H, T, E>(
+        &mut self,
+        url: U,
+        prepare: P,
+        handle: H,
+    ) -> Result<T, TransactionError<<C as SessionClient>::SendError, E>>
+    where
+        U: IntoUrl,
+        P: FnOnce(<C as SessionClient>::Request) -> <C as SessionClient>::Request,
+        H: FnOnce(<C as SessionClient>::Response) -> Result<T, E>,
+    {
+        let snapshot = StoreSnapshot::take(&self.store);
+        let response = self
+            .get_with(url, prepare)
+            .map_err(TransactionError::Send)?;
+        handle(response).map_err(|e| {
+            self.roll_back(&snapshot);
+            TransactionError::Handler(e)
+        })
+    }
+
+    /// Return the store to its state when `snapshot` was taken of it
+    pub(crate) fn roll_back(&mut self, snapshot: &StoreSnapshot) {
+        let delta = snapshot.delta(&self.store);
+        let expire = delta.added.iter().map(|cookie| expiring(cookie));
+        let restore = delta
+            .updated
+            .iter()
+            .filter_map(|cookie| snapshot.before(cookie))
+            .chain(delta.expired.iter())
+            .map(|cookie| restoring(cookie));
+        for change in expire.chain(restore) {
+            let rolled_back = change.and_then(|(set_cookie, url)| {
+                debug!("rolling back to Set-Cookie '{}'", set_cookie);
+                self.store
+                    .insert_raw(&set_cookie, &url)
+                    .map_err(|e| e.to_string())?;
+                if let Some(ref mut journal) = self.journal {
+                    journal.record(&set_cookie, &url);
+                }
+                Ok(())
+            });
+            if let Err(e) = rolled_back {
+                warn!("unable to roll back cookie change: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "reqwest-session"))]
+mod tests {
+    use crate::delta::StoreSnapshot;
+    use crate::ReqwestSession;
+    use url::Url;
+
+    #[test]
+    fn roll_back() {
+        let url = Url::parse("https://www.example.com/app/").unwrap();
+        let mut session = ReqwestSession::new(reqwest::blocking::Client::new());
+        for set_cookie in &[
+            "changed=1; Path=/app; Max-Age=3600",
+            "gone=1; Domain=example.com; Secure",
+            "kept=1",
+        ] {
+            session.store.parse(set_cookie, &url).unwrap();
+        }
+        let snapshot = StoreSnapshot::take(&session.store);
+        for set_cookie in &[
+            "changed=2; Path=/app",
+            "gone=; Domain=example.com; Max-Age=0",
+            "new=1; Path=/",
+        ] {
+            session.store.parse(set_cookie, &url).unwrap();
+        }
+        assert!(!snapshot.delta(&session.store).is_empty());
+
+        session.roll_back(&snapshot);
+        // restored cookies are set anew, so differ from those in the snapshot in their raw form
+        let delta = snapshot.delta(&session.store);
+        assert!(delta.added.is_empty() && delta.expired.is_empty());
+        let changed = session.store.get("www.example.com", "/app", "changed");
+        assert_eq!("1", changed.unwrap().value());
+        assert!(session.store.get("example.com", "/app", "gone").is_some());
+        assert!(session.store.get("www.example.com", "/", "new").is_none());
+    }
+}
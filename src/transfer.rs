@@ -0,0 +1,135 @@
+use crate::session::{Session, SessionClient};
+use crate::storage::CookieStorage;
+use std::time::{Duration, Instant};
+
+/// The bytes transferred by a `Session`, as reported by `Session::transfer_stats`. Downloads are
+/// counted for the bodies the session reads itself (`get_bytes`, `get_text`, `get_json`,
+/// `run_scenario`, and `EventSource`), and uploads for the bodies it attaches itself
+/// (`execute_spec`); bodies read or attached by the caller are not seen by the session.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferStats {
+    pub downloaded: u64,
+    pub uploaded: u64,
+}
+
+/// Limits a transfer to a rate, by sleeping until each chunk would have been transferred at that
+/// rate
+#[derive(Debug)]
+struct Throttle {
+    bytes_per_sec: u64,
+    /// When the chunks transferred so far are paid for
+    paid_until: Instant,
+}
+
+impl Throttle {
+    fn new(bytes_per_sec: u64) -> Self {
+        Throttle {
+            bytes_per_sec: bytes_per_sec.max(1),
+            paid_until: Instant::now(),
+        }
+    }
+
+    fn pace(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        self.paid_until = self.paid_until.max(now) + cost;
+        if self.paid_until > now {
+            std::thread::sleep(self.paid_until - now);
+        }
+    }
+}
+
+/// The transfer accounting and rate limits of a `Session`
+#[derive(Debug, Default)]
+pub(crate) struct Transfer {
+    stats: TransferStats,
+    download: Option<Throttle>,
+    upload: Option<Throttle>,
+}
+
+impl Transfer {
+    pub(crate) fn stats(&self) -> TransferStats {
+        self.stats
+    }
+
+    /// Account for `bytes` downloaded, sleeping as needed to keep within the download limit
+    pub(crate) fn downloaded(&mut self, bytes: usize) {
+        self.stats.downloaded += bytes as u64;
+        if let Some(ref mut throttle) = self.download {
+            throttle.pace(bytes);
+        }
+    }
+
+    /// Account for `bytes` about to be uploaded, sleeping as needed to keep within the upload
+    /// limit
+    pub(crate) fn uploading(&mut self, bytes: usize) {
+        self.stats.uploaded += bytes as u64;
+        if let Some(ref mut throttle) = self.upload {
+            throttle.pace(bytes);
+        }
+    }
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Limit the rate the session reads response bodies at; see `TransferStats` for which reads
+    /// are limited
+    pub fn with_download_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.transfer.download = Some(Throttle::new(bytes_per_sec));
+        self
+    }
+
+    /// Limit the rate the session sends request bodies at; see `TransferStats` for which bodies
+    /// are limited
+    pub fn with_upload_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.transfer.upload = Some(Throttle::new(bytes_per_sec));
+        self
+    }
+
+    /// The bytes transferred by the session so far
+    pub fn transfer_stats(&self) -> TransferStats {
+        self.transfer.stats()
+    }
+
+    /// Account for `bytes` of a response body read by the session
+    pub(crate) fn downloaded(&mut self, bytes: usize) {
+        self.transfer.downloaded(bytes);
+        for hook in self.metrics_hooks.iter_mut() {
+            hook.bytes_downloaded(bytes as u64);
+        }
+    }
+
+    /// Account for `bytes` of a request body about to be sent by the session
+    pub(crate) fn uploading(&mut self, bytes: usize) {
+        self.transfer.uploading(bytes);
+        for hook in self.metrics_hooks.iter_mut() {
+            hook.bytes_uploaded(bytes as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Throttle, Transfer, TransferStats};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn throttle() {
+        let mut transfer = Transfer {
+            download: Some(Throttle::new(1000)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        for _ in 0..3 {
+            transfer.downloaded(100);
+        }
+        transfer.uploading(10);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(
+            TransferStats {
+                downloaded: 300,
+                uploaded: 10,
+            },
+            transfer.stats
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! A `Session` over [ureq](https://crates.io/crates/ureq) 1.x.
+//!
+//! ureq reports failures to send a request as "synthetic" responses; a `UreqSession` returns
+//! these as `UreqSessionError`s instead. The `ureq` dependency is built without its `cookies`
+//! feature, so that the agent keeps no cookies of its own alongside those of the session (unless
+//! another crate in the build enables it).
+use crate::content::{BodyChunks, ContentResponse};
+use crate::paginate::HeaderResponse;
+use crate::session::{
+    ClassifySendError, SendErrorKind, Session, SessionClient, SessionRequest, SessionResponse,
+};
+use crate::spec::{SpecRequest, StatusResponse};
+use crate::utils::parse_set_cookie_values;
+use cookie::Cookie as RawCookie;
+use log::debug;
+use std::io::{ErrorKind, Read};
+use url::Url;
+
+/// A request to be sent by a `ureq::Agent`, with the body to send it with
+#[derive(Clone)]
+pub struct UreqRequest {
+    pub request: ureq::Request,
+    pub body: Option<Vec<u8>>,
+}
+
+impl UreqRequest {
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.request.set(name, value);
+        self
+    }
+
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+impl SessionRequest for UreqRequest {
+    fn add_cookies(self, cookies: Vec<&RawCookie<'static>>) -> Self {
+        if cookies.is_empty() {
+            debug!("no cookies to add to request");
+            return self;
+        }
+        let pairs = cookies
+            .iter()
+            .map(|rc| rc.encoded().to_string())
+            .collect::<Vec<_>>();
+        self.add_cookie_pairs(pairs)
+    }
+
+    fn add_cookie_pairs(self, pairs: Vec<String>) -> Self {
+        if pairs.is_empty() {
+            self
+        } else {
+            self.header("Cookie", &pairs.join("; "))
+        }
+    }
+
+    fn add_header(self, name: &str, value: &str) -> Self {
+        self.header(name, value)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+impl SpecRequest for UreqRequest {
+    fn header(self, name: &str, value: &str) -> Self {
+        UreqRequest::header(self, name, value)
+    }
+
+    fn body(self, body: String) -> Self {
+        UreqRequest::body(self, body)
+    }
+}
+
+/// A response received by a `ureq::Agent`
+pub struct UreqResponse {
+    response: ureq::Response,
+    url: Url,
+}
+
+impl UreqResponse {
+    pub fn status(&self) -> u16 {
+        self.response.status()
+    }
+
+    /// The URL the response was served from, after any redirects ureq followed
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn into_inner(self) -> ureq::Response {
+        self.response
+    }
+}
+
+impl std::ops::Deref for UreqResponse {
+    type Target = ureq::Response;
+
+    fn deref(&self) -> &ureq::Response {
+        &self.response
+    }
+}
+
+impl SessionResponse for UreqResponse {
+    type Url = Url;
+
+    fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+        parse_set_cookie_values(
+            self.response
+                .all("set-cookie")
+                .into_iter()
+                .map(str::as_bytes),
+        )
+    }
+
+    fn final_url(&self) -> Option<&Url> {
+        Some(&self.url)
+    }
+
+    fn date(&self) -> Option<String> {
+        self.response.header("date").map(str::to_owned)
+    }
+
+    fn raw_set_cookie(&self) -> Vec<String> {
+        self.response
+            .all("set-cookie")
+            .into_iter()
+            .chain(self.response.all("set-cookie2"))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    fn clear_site_data(&self) -> Option<String> {
+        let values = self.response.all("clear-site-data");
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    fn is_proxy_response(&self) -> bool {
+        self.response.status() == 407
+    }
+
+    fn status_code(&self) -> Option<u16> {
+        Some(self.response.status())
+    }
+
+    fn retry_after(&self) -> Option<String> {
+        self.response.header("retry-after").map(str::to_owned)
+    }
+
+    fn location(&self) -> Option<String> {
+        self.response.header("location").map(str::to_owned)
+    }
+
+    fn strict_transport_security(&self) -> Option<String> {
+        self.response
+            .header("strict-transport-security")
+            .map(str::to_owned)
+    }
+
+    fn www_authenticate(&self) -> Vec<String> {
+        self.response
+            .all("www-authenticate")
+            .into_iter()
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+impl StatusResponse for UreqResponse {
+    fn status(&self) -> u16 {
+        self.response.status()
+    }
+}
+
+impl HeaderResponse for UreqResponse {
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.response.all(name)
+    }
+
+    fn header_names(&self) -> Vec<String> {
+        let mut names = self.response.headers_names();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+impl ContentResponse for UreqResponse {
+    type BodyError = UreqSessionError;
+
+    fn content_type(&self) -> Option<&str> {
+        self.response.header("content-type")
+    }
+
+    fn into_body(self) -> Result<Vec<u8>, Self::BodyError> {
+        let mut body = vec![];
+        self.response
+            .into_reader()
+            .read_to_end(&mut body)
+            .map_err(UreqSessionError::Io)?;
+        Ok(body)
+    }
+
+    fn into_chunks(self) -> BodyChunks<Self::BodyError> {
+        let mut reader = self.response.into_reader();
+        let mut buffer = [0; 8192];
+        Box::new(std::iter::from_fn(move || match reader.read(&mut buffer) {
+            Ok(0) => None,
+            Ok(read) => Some(Ok(buffer[..read].to_vec())),
+            Err(e) => Some(Err(UreqSessionError::Io(e))),
+        }))
+    }
+}
+
+#[derive(Debug)]
+pub enum UreqSessionError {
+    ParseUrlError(url::ParseError),
+    /// Sending the request failed, as reported by ureq's synthetic response
+    Ureq(ureq::Error),
+    /// Reading a response body failed
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for UreqSessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UreqSessionError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            UreqSessionError::Ureq(e) => write!(f, "ureq error: {}", e),
+            UreqSessionError::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for UreqSessionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UreqSessionError::ParseUrlError(e) => Some(e),
+            UreqSessionError::Ureq(e) => Some(e),
+            UreqSessionError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl ClassifySendError for UreqSessionError {
+    fn kind(&self) -> SendErrorKind {
+        let io_kind = |e: &std::io::Error| match e.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => SendErrorKind::Timeout,
+            ErrorKind::ConnectionRefused => SendErrorKind::Connect,
+            _ => SendErrorKind::Other,
+        };
+        match self {
+            UreqSessionError::ParseUrlError(_) => SendErrorKind::Url,
+            UreqSessionError::Ureq(ureq::Error::BadUrl(_))
+            | UreqSessionError::Ureq(ureq::Error::UnknownScheme(_)) => SendErrorKind::Url,
+            UreqSessionError::Ureq(ureq::Error::DnsFailed(_))
+            | UreqSessionError::Ureq(ureq::Error::ConnectionFailed(_)) => SendErrorKind::Connect,
+            UreqSessionError::Ureq(ureq::Error::Io(e)) | UreqSessionError::Io(e) => io_kind(e),
+            _ => SendErrorKind::Other,
+        }
+    }
+}
+
+impl From<url::ParseError> for UreqSessionError {
+    fn from(e: url::ParseError) -> Self {
+        UreqSessionError::ParseUrlError(e)
+    }
+}
+
+pub type UreqSession = Session<ureq::Agent>;
+
+impl SessionClient for ureq::Agent {
+    type Request = UreqRequest;
+    type Response = UreqResponse;
+    type SendError = UreqSessionError;
+
+    fn request(&self, method: &http::Method, url: &Url) -> Self::Request {
+        UreqRequest {
+            request: ureq::Agent::request(self, method.as_str(), url.as_str()),
+            body: None,
+        }
+    }
+
+    fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let UreqRequest { mut request, body } = request;
+        let response = match body {
+            Some(body) => request.send_bytes(&body),
+            None => request.call(),
+        };
+        if response.synthetic() {
+            let e = response
+                .into_synthetic_error()
+                .expect("synthetic responses have errors");
+            return Err(UreqSessionError::Ureq(e));
+        }
+        let url = Url::parse(response.get_url())?;
+        Ok(UreqResponse { response, url })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UreqSession;
+    use crate::{ClassifySendError, ContentResponse, Method, SendErrorKind};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve `responses` in turn, one per connection, returning the requests' headers
+    fn serve(responses: Vec<&'static str>) -> (u16, thread::JoinHandle<Vec<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let reader = BufReader::new(stream.try_clone().unwrap());
+                    let headers = reader
+                        .lines()
+                        .map(Result::unwrap)
+                        .take_while(|line| !line.is_empty())
+                        .collect::<Vec<_>>();
+                    (&stream).write_all(response.as_bytes()).unwrap();
+                    headers
+                })
+                .collect()
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn ureq_session() {
+        let (port, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nSet-Cookie: id=1\r\nSet-Cookie: theme=dark\r\n\
+             Content-Length: 5\r\nConnection: close\r\n\r\nhello",
+            "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 207 Multi-Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let url = format!("http://127.0.0.1:{}/", port);
+        let mut session = UreqSession::new(ureq::agent());
+        let response = session.get(&url).unwrap();
+        assert_eq!(b"hello".to_vec(), response.into_body().unwrap());
+        session
+            .get_with(&url, |r| r.header("x-probe", "1"))
+            .unwrap();
+        let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+        session.request_with(&propfind, &url, |r| r).unwrap();
+
+        let requests = server.join().unwrap();
+        let has = |headers: &[String], header: &str| {
+            headers.iter().any(|h| h.eq_ignore_ascii_case(header))
+        };
+        assert!(!requests[0]
+            .iter()
+            .any(|h| h.to_lowercase().starts_with("cookie:")));
+        assert!(has(&requests[1], "x-probe: 1"));
+        assert!(has(&requests[1], "cookie: id=1; theme=dark"));
+        assert_eq!("PROPFIND / HTTP/1.1", requests[2][0]);
+        assert!(has(&requests[2], "cookie: id=1; theme=dark"));
+
+        let refused = session.get(&url).err().unwrap();
+        assert_eq!(SendErrorKind::Connect, refused.kind());
+    }
+}
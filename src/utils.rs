@@ -1,5 +1,10 @@
+use cookie::Cookie as RawCookie;
+use cookie_store::Cookie;
+use log::debug;
+use std::convert::TryFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
 use url::ParseError as UrlError;
-use url::Url;
+use url::{Host, Url};
 
 pub trait IntoUrl {
     fn into_url(self) -> Result<Url, UrlError>;
@@ -22,3 +27,289 @@ impl<'a> IntoUrl for &'a String {
         Url::parse(self)
     }
 }
+
+/// Parse the raw values of Set-Cookie headers into `RawCookie`s, skipping (and logging) any
+/// which are not valid UTF-8 or fail to parse
+#[cfg_attr(not(feature = "reqwest-session"), allow(dead_code))]
+pub(crate) fn parse_set_cookie_values<'a, I>(values: I) -> Vec<RawCookie<'static>>
+where
+    I: Iterator<Item = &'a [u8]>,
+{
+    values
+        .filter_map(|set_cookie| match std::str::from_utf8(set_cookie) {
+            Ok(sc) => match RawCookie::parse(sc.to_owned()) {
+                Ok(raw_cookie) => Some(raw_cookie),
+                Err(e) => {
+                    debug!(
+                        "error parsing Set-Cookie to RawCookie {:?}: {:?}",
+                        set_cookie, e
+                    );
+                    None
+                }
+            },
+            Err(e) => {
+                debug!(
+                    "error parsing Set-Cookie to String {:?}: {:?}",
+                    set_cookie, e
+                );
+                None
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Whether `cookie` is host-only, i.e. was set without a `Domain` attribute. `cookie_store` does
+/// not export the type of its domain, so it is compared to the one a `Domain` attribute of the
+/// same value would set.
+pub(crate) fn is_host_only(cookie: &Cookie<'_>) -> bool {
+    let domain = String::from(&cookie.domain);
+    let suffix = TryFrom::try_from(&domain[..]).ok();
+    suffix.as_ref() != Some(&cookie.domain)
+}
+
+/// The domains whose cookies may be sent to `url`: its host, and each parent domain
+pub(crate) fn request_domains(url: &Url) -> Vec<String> {
+    match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+            let mut domains = vec![domain.clone()];
+            let mut rest = &domain[..];
+            while let Some(i) = rest.find('.') {
+                rest = &rest[i + 1..];
+                domains.push(rest.to_owned());
+            }
+            domains
+        }
+        Some(host) => vec![host.to_string()],
+        None => vec![],
+    }
+}
+
+/// `time` as seconds since the Unix epoch
+pub(crate) fn unix_secs(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Parse a UTC time in the `%Y-%m-%dT%H:%M:%SZ` format used by `save_json`, as seconds since the
+/// Unix epoch
+#[cfg(any(test, feature = "redis-store", feature = "sqlite-store"))]
+pub(crate) fn unix_time(s: &str) -> Option<i64> {
+    let field = |range: std::ops::Range<usize>| s.get(range)?.parse::<i64>().ok();
+    if s.len() != 20 || !s.ends_with('Z') {
+        return None;
+    }
+    let (y, m, d) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hh, mm, ss) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    Some(civil_time(y, m, d) + hh * 3_600 + mm * 60 + ss)
+}
+
+/// The seconds since the Unix epoch of midnight UTC on the given date
+pub(crate) fn civil_time(y: i64, m: i64, d: i64) -> i64 {
+    // days from civil date, per http://howardhinnant.github.io/date_algorithms.html
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    days * 86_400
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parse an HTTP date in the preferred IMF-fixdate format (e.g. `Sun, 06 Nov 1994 08:49:37 GMT`),
+/// as seconds since the Unix epoch
+pub(crate) fn http_date(s: &str) -> Option<i64> {
+    let mut parts = s.split_whitespace().skip(1);
+    let d = parts.next()?.parse::<i64>().ok()?;
+    let month = parts.next()?;
+    let m = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as i64 + 1;
+    let y = parts.next()?.parse::<i64>().ok()?;
+    let mut time = parts.next()?.split(':').map(|n| n.parse::<i64>().ok());
+    let (hh, mm, ss) = (time.next()??, time.next()??, time.next()??);
+    if parts.next() != Some("GMT") || time.next().is_some() {
+        return None;
+    }
+    Some(civil_time(y, m, d) + hh * 3_600 + mm * 60 + ss)
+}
+
+/// Parse a cookie date in any of the formats browsers accept (e.g. `Wednesday, 04-Mar-20
+/// 05:06:07 GMT` or `Wed Mar 4 05:06:07 2020`), per the algorithm of RFC 6265 section 5.1.1, as
+/// seconds since the Unix epoch
+pub(crate) fn cookie_date(s: &str) -> Option<i64> {
+    // `n` digits followed by anything but a digit
+    let digits = |token: &str, n: std::ops::RangeInclusive<usize>| {
+        let len = token
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(token.len());
+        if n.contains(&len) {
+            token[..len].parse::<i64>().ok()
+        } else {
+            None
+        }
+    };
+    let is_delimiter = |c: char| matches!(c, '\t' | ' '..='/' | ';'..='@' | '['..='`' | '{'..='~');
+    let (mut time, mut day, mut month, mut year) = (None, None, None, None);
+    for token in s.split(is_delimiter).filter(|t| !t.is_empty()) {
+        if time.is_none() {
+            let mut parts = token.splitn(3, ':');
+            let hh = parts.next().and_then(|p| digits(p, 1..=2));
+            let mm = parts.next().and_then(|p| digits(p, 1..=2));
+            let ss = parts.next().and_then(|p| digits(p, 1..=2));
+            if let (Some(hh), Some(mm), Some(ss)) = (hh, mm, ss) {
+                time = Some((hh, mm, ss));
+                continue;
+            }
+        }
+        if day.is_none() {
+            if let Some(d) = digits(token, 1..=2) {
+                day = Some(d);
+                continue;
+            }
+        }
+        if month.is_none() {
+            let prefix = token.get(..3).unwrap_or("");
+            if let Some(m) = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(prefix)) {
+                month = Some(m as i64 + 1);
+                continue;
+            }
+        }
+        if year.is_none() {
+            year = digits(token, 2..=4);
+        }
+    }
+    let year = match year? {
+        y @ 70..=99 => y + 1900,
+        y @ 0..=69 => y + 2000,
+        y => y,
+    };
+    let ((hh, mm, ss), d, m) = (time?, day?, month?);
+    if !(1..=31).contains(&d) || year < 1601 || hh > 23 || mm > 59 || ss > 59 {
+        return None;
+    }
+    Some(civil_time(year, m, d) + hh * 3_600 + mm * 60 + ss)
+}
+
+/// The year, month, and day of the date `days` since the Unix epoch
+fn civil_date(days: i64) -> (i64, i64, i64) {
+    // civil date from days, per http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+/// Format `secs` since the Unix epoch as an HTTP date in the IMF-fixdate format
+pub(crate) fn fixdate(secs: i64) -> String {
+    const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    let (days, secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (y, m, d) = civil_date(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days + 4).rem_euclid(7) as usize],
+        d,
+        MONTHS[m as usize - 1],
+        y,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+/// Format `time` as an ISO 8601 UTC time with milliseconds (e.g. `2020-03-04T05:06:07.089Z`)
+#[cfg(feature = "persistence")]
+pub(crate) fn iso8601(time: SystemTime) -> String {
+    let (secs, millis) = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => (since.as_secs() as i64, since.subsec_millis()),
+        Err(_) => (unix_secs(time), 0),
+    };
+    let (days, secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (y, m, d) = civil_date(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        y,
+        m,
+        d,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60,
+        millis
+    )
+}
+
+/// Fragments of cookie names suggesting the cookie holds a credential
+#[cfg(feature = "persistence")]
+const SENSITIVE_NAMES: &[&str] = &["sess", "auth", "token", "sid", "csrf", "jwt", "login"];
+
+/// Whether the cookie name `name` suggests the cookie holds a credential
+#[cfg(feature = "persistence")]
+pub(crate) fn is_sensitive_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SENSITIVE_NAMES.iter().any(|s| name.contains(s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cookie_date, fixdate, http_date, request_domains, unix_time};
+    use url::Url;
+
+    #[test]
+    fn domains() {
+        let url = |s| Url::parse(s).unwrap();
+        assert_eq!(
+            vec!["www.example.com", "example.com", "com"],
+            request_domains(&url("https://WWW.example.com./a"))
+        );
+        assert_eq!(
+            vec!["127.0.0.1"],
+            request_domains(&url("http://127.0.0.1/"))
+        );
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(Some(0), unix_time("1970-01-01T00:00:00Z"));
+        assert_eq!(Some(1_583_298_367), unix_time("2020-03-04T05:06:07Z"));
+        assert_eq!(Some(-86_400), unix_time("1969-12-31T00:00:00Z"));
+        assert_eq!(None, unix_time("2020-03-04T05:06:07+00:00"));
+        assert_eq!(
+            Some(1_583_298_367),
+            http_date("Wed, 04 Mar 2020 05:06:07 GMT")
+        );
+        assert_eq!(None, http_date("Wednesday, 04-Mar-20 05:06:07 GMT"));
+        for date in &[
+            "Wed, 04 Mar 2020 05:06:07 GMT",
+            "Wednesday, 04-Mar-20 05:06:07 GMT",
+            "Wed Mar  4 05:06:07 2020",
+            "04 march 2020 5:6:7",
+        ] {
+            assert_eq!(Some(1_583_298_367), cookie_date(date), "{}", date);
+        }
+        assert_eq!(Some(0), cookie_date("Thu, 01-Jan-70 00:00:00 GMT"));
+        assert_eq!(None, cookie_date("Wed, 04 Mar 2020"));
+        assert_eq!(None, cookie_date("Wed, 32 Mar 2020 05:06:07 GMT"));
+        assert_eq!("Wed, 04 Mar 2020 05:06:07 GMT", fixdate(1_583_298_367));
+        assert_eq!("Wed, 31 Dec 1969 23:59:59 GMT", fixdate(-1));
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn iso8601_times() {
+        use super::iso8601;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let time = UNIX_EPOCH + Duration::from_millis(1_583_298_367_089);
+        assert_eq!("2020-03-04T05:06:07.089Z", iso8601(time));
+    }
+}
@@ -0,0 +1,389 @@
+//! A `Session` over the `XMLHttpRequest` of a JavaScript host, for `wasm32-unknown-unknown`
+//! targets. Build with `default-features = false, features = ["wasm-session"]`, as the default
+//! `reqwest-session` feature pulls in reqwest's blocking client, which does not build for wasm32.
+//!
+//! `Session` is blocking, so requests are sent synchronously, which browsers allow only off their
+//! main thread, i.e. in a Web Worker. Browsers keep cookies from scripts: a `Cookie` header added
+//! to a request is dropped, and `Set-Cookie` headers are hidden from responses, so there the
+//! browser's own cookies apply (see `WasmClient::with_credentials`) rather than the session's.
+//! Hosts which expose them, such as `XMLHttpRequest` implementations for Node, send and report
+//! cookies as usual. Response bodies are read in full before `send` returns.
+//!
+//! std's clock is unavailable on `wasm32-unknown-unknown`, so the opt-ins timed by it (rate
+//! limiting, host backoff, transfer limits, metrics timing, and HAR recording) must not be enabled.
+use crate::content::ContentResponse;
+use crate::fallback::{is_challenge_status, ChallengeResponse};
+use crate::paginate::HeaderResponse;
+use crate::session::{
+    ClassifySendError, SendErrorKind, Session, SessionClient, SessionRequest, SessionResponse,
+};
+use crate::spec::{SpecRequest, StatusResponse};
+use crate::utils::parse_set_cookie_values;
+use cookie::Cookie as RawCookie;
+use http::Method;
+use log::debug;
+use url::Url;
+use wasm_bindgen::JsValue;
+use web_sys::XmlHttpRequest;
+
+/// Sends requests with a synchronous `XMLHttpRequest`
+#[derive(Clone, Debug, Default)]
+pub struct WasmClient {
+    with_credentials: bool,
+}
+
+impl WasmClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether cross-origin requests are sent with the browser's own cookies, and may set them
+    /// (`XMLHttpRequest.withCredentials`); off by default
+    pub fn with_credentials(mut self, with_credentials: bool) -> Self {
+        self.with_credentials = with_credentials;
+        self
+    }
+}
+
+/// A request to be sent by a `WasmClient`
+#[derive(Clone, Debug)]
+pub struct WasmRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+impl WasmRequest {
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+impl SessionRequest for WasmRequest {
+    fn add_cookies(self, cookies: Vec<&RawCookie<'static>>) -> Self {
+        if cookies.is_empty() {
+            debug!("no cookies to add to request");
+            return self;
+        }
+        let pairs = cookies
+            .iter()
+            .map(|rc| rc.encoded().to_string())
+            .collect::<Vec<_>>();
+        self.add_cookie_pairs(pairs)
+    }
+
+    fn add_cookie_pairs(self, pairs: Vec<String>) -> Self {
+        if pairs.is_empty() {
+            self
+        } else {
+            self.header("Cookie", &pairs.join("; "))
+        }
+    }
+
+    fn add_header(self, name: &str, value: &str) -> Self {
+        self.header(name, value)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+impl SpecRequest for WasmRequest {
+    fn header(self, name: &str, value: &str) -> Self {
+        WasmRequest::header(self, name, value)
+    }
+
+    fn body(self, body: String) -> Self {
+        WasmRequest::body(self, body)
+    }
+}
+
+/// A response received by a `WasmClient`, with its body read in full
+#[derive(Debug)]
+pub struct WasmResponse {
+    status: u16,
+    url: Url,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl WasmResponse {
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The URL the response was served from, after any redirects the host followed
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The value of the first `name` header
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.header_values(name).into_iter().next()
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+impl SessionResponse for WasmResponse {
+    type Url = Url;
+
+    fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+        parse_set_cookie_values(
+            self.header_values("set-cookie")
+                .into_iter()
+                .map(str::as_bytes),
+        )
+    }
+
+    fn final_url(&self) -> Option<&Url> {
+        Some(&self.url)
+    }
+
+    fn date(&self) -> Option<String> {
+        self.header("date").map(str::to_owned)
+    }
+
+    fn raw_set_cookie(&self) -> Vec<String> {
+        self.header_values("set-cookie")
+            .into_iter()
+            .chain(self.header_values("set-cookie2"))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    fn clear_site_data(&self) -> Option<String> {
+        let values = self.header_values("clear-site-data");
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    fn is_proxy_response(&self) -> bool {
+        self.status == 407
+    }
+
+    fn status_code(&self) -> Option<u16> {
+        Some(self.status)
+    }
+
+    fn retry_after(&self) -> Option<String> {
+        self.header("retry-after").map(str::to_owned)
+    }
+
+    fn location(&self) -> Option<String> {
+        self.header("location").map(str::to_owned)
+    }
+
+    fn strict_transport_security(&self) -> Option<String> {
+        self.header("strict-transport-security").map(str::to_owned)
+    }
+
+    fn www_authenticate(&self) -> Vec<String> {
+        self.header_values("www-authenticate")
+            .into_iter()
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+impl StatusResponse for WasmResponse {
+    fn status(&self) -> u16 {
+        self.status
+    }
+}
+
+impl HeaderResponse for WasmResponse {
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    fn header_names(&self) -> Vec<String> {
+        let mut names = self
+            .headers
+            .iter()
+            .map(|(name, _)| name.to_ascii_lowercase())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+impl ChallengeResponse for WasmResponse {
+    fn is_challenge(&self) -> bool {
+        self.header("cf-mitigated") == Some("challenge")
+            || is_challenge_status(self.status, self.header("server"))
+    }
+}
+
+impl ContentResponse for WasmResponse {
+    type BodyError = WasmSessionError;
+
+    fn content_type(&self) -> Option<&str> {
+        self.header("content-type")
+    }
+
+    fn into_body(self) -> Result<Vec<u8>, Self::BodyError> {
+        Ok(self.body)
+    }
+}
+
+#[derive(Debug)]
+pub enum WasmSessionError {
+    ParseUrlError(url::ParseError),
+    /// The `XMLHttpRequest` threw, e.g. with a `NetworkError`; the exception, as debug-formatted
+    Xhr(String),
+}
+
+impl std::fmt::Display for WasmSessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WasmSessionError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            WasmSessionError::Xhr(e) => write!(f, "XMLHttpRequest error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WasmSessionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WasmSessionError::ParseUrlError(e) => Some(e),
+            WasmSessionError::Xhr(_) => None,
+        }
+    }
+}
+
+impl ClassifySendError for WasmSessionError {
+    fn kind(&self) -> SendErrorKind {
+        match self {
+            WasmSessionError::ParseUrlError(_) => SendErrorKind::Url,
+            WasmSessionError::Xhr(e) if e.contains("NetworkError") => SendErrorKind::Connect,
+            WasmSessionError::Xhr(_) => SendErrorKind::Other,
+        }
+    }
+}
+
+impl From<url::ParseError> for WasmSessionError {
+    fn from(e: url::ParseError) -> Self {
+        WasmSessionError::ParseUrlError(e)
+    }
+}
+
+impl From<JsValue> for WasmSessionError {
+    fn from(e: JsValue) -> Self {
+        WasmSessionError::Xhr(format!("{:?}", e))
+    }
+}
+
+/// Parse the headers returned by `XMLHttpRequest.getAllResponseHeaders`, one `name: value` per
+/// CRLF-terminated line
+fn parse_headers(all: &str) -> Vec<(String, String)> {
+    all.split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+        .collect()
+}
+
+/// Recover the bytes of a body read as text with the `x-user-defined` charset, which maps each
+/// byte above 0x7F to a character in U+F780..=U+F7FF
+fn user_defined_bytes(text: &str) -> Vec<u8> {
+    text.chars().map(|c| (c as u32 & 0xFF) as u8).collect()
+}
+
+pub type WasmSession = Session<WasmClient>;
+
+impl SessionClient for WasmClient {
+    type Request = WasmRequest;
+    type Response = WasmResponse;
+    type SendError = WasmSessionError;
+
+    fn request(&self, method: &Method, url: &Url) -> Self::Request {
+        WasmRequest {
+            method: method.clone(),
+            url: url.clone(),
+            headers: vec![],
+            body: None,
+        }
+    }
+
+    fn send(&self, request: Self::Request) -> Result<Self::Response, Self::SendError> {
+        let xhr = XmlHttpRequest::new()?;
+        xhr.open_with_async(request.method.as_str(), request.url.as_str(), false)?;
+        xhr.set_with_credentials(self.with_credentials);
+        // synchronous requests cannot set a binary `responseType`, so read the body as text
+        // with each byte mapped to a character
+        xhr.override_mime_type("text/plain; charset=x-user-defined")?;
+        for (name, value) in request.headers.iter() {
+            xhr.set_request_header(name, value)?;
+        }
+        xhr.send_with_opt_u8_array(request.body.as_deref())?;
+        let url = match xhr.response_url().as_str() {
+            "" => request.url,
+            served => Url::parse(served)?,
+        };
+        Ok(WasmResponse {
+            status: xhr.status()?,
+            url,
+            headers: parse_headers(&xhr.get_all_response_headers()?),
+            body: user_defined_bytes(&xhr.response_text()?.unwrap_or_default()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_headers, user_defined_bytes, WasmResponse};
+    use crate::paginate::HeaderResponse;
+    use crate::SessionResponse;
+    use url::Url;
+
+    #[test]
+    fn response_headers() {
+        let headers = parse_headers(
+            "content-type: text/html\r\nset-cookie: a=1\r\nSet-Cookie: b=2; Path=/\r\n\
+             x-empty:\r\n",
+        );
+        let response = WasmResponse {
+            status: 200,
+            url: Url::parse("https://example.com/").unwrap(),
+            headers,
+            body: vec![],
+        };
+        assert_eq!(Some(""), response.header("X-Empty"));
+        assert_eq!(
+            vec!["content-type", "set-cookie", "x-empty"],
+            response.header_names()
+        );
+        let names = response
+            .parse_set_cookie()
+            .iter()
+            .map(|c| c.name().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["a", "b"], names);
+    }
+
+    #[test]
+    fn binary_body() {
+        assert_eq!(
+            vec![b'h', b'i', 0x00, 0x80, 0xFF],
+            user_defined_bytes("hi\u{0}\u{F780}\u{F7FF}")
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::session::{Session, SessionClient, SessionRequest, SessionResponse};
+use crate::storage::CookieStorage;
+use crate::utils::{parse_set_cookie_values, IntoUrl};
+use cookie::Cookie as RawCookie;
+use log::debug;
+use tungstenite::client::{AutoStream, IntoClientRequest};
+use tungstenite::handshake::client::{Request, Response};
+use tungstenite::http::header::{HeaderValue, COOKIE, SET_COOKIE};
+use tungstenite::WebSocket;
+use url::Url;
+
+impl SessionResponse for Response {
+    type Url = url::Url;
+    fn parse_set_cookie(&self) -> Vec<RawCookie<'static>> {
+        parse_set_cookie_values(
+            self.headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .map(|set_cookie| set_cookie.as_bytes()),
+        )
+    }
+
+    fn final_url(&self) -> Option<&url::Url> {
+        None
+    }
+}
+
+impl SessionRequest for Request {
+    fn add_cookies(mut self, cookies: Vec<&RawCookie<'static>>) -> Self {
+        if cookies.is_empty() {
+            debug!("no cookies to add to upgrade request");
+        }
+        for cookie in cookies {
+            match HeaderValue::from_str(&cookie.encoded().to_string()) {
+                Ok(value) => {
+                    self.headers_mut().append(COOKIE, value);
+                }
+                Err(e) => debug!("unable to add cookie {:?} to request: {:?}", cookie, e),
+            }
+        }
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum WebSocketSessionError {
+    ParseUrlError(url::ParseError),
+    TungsteniteError(tungstenite::Error),
+}
+
+impl std::fmt::Display for WebSocketSessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WebSocketSessionError::ParseUrlError(e) => write!(f, "URL parse error: {}", e),
+            WebSocketSessionError::TungsteniteError(e) => write!(f, "WebSocket error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WebSocketSessionError {}
+
+impl From<url::ParseError> for WebSocketSessionError {
+    fn from(e: url::ParseError) -> Self {
+        WebSocketSessionError::ParseUrlError(e)
+    }
+}
+
+impl From<tungstenite::Error> for WebSocketSessionError {
+    fn from(e: tungstenite::Error) -> Self {
+        WebSocketSessionError::TungsteniteError(e)
+    }
+}
+
+/// Cookies are scoped to http(s) URLs; a `ws://` URL is treated as `http://` and a `wss://` URL
+/// as `https://` when matching and storing cookies, as browsers do.
+fn cookie_url(url: &Url) -> Url {
+    let scheme = match url.scheme() {
+        "ws" => "http",
+        "wss" => "https",
+        other => other,
+    };
+    let mut cookie_url = url.clone();
+    if cookie_url.set_scheme(scheme).is_err() {
+        debug!(
+            "unable to map {} to an http(s) URL for cookie matching",
+            url
+        );
+    }
+    cookie_url
+}
+
+impl<C: SessionClient, S: CookieStorage> Session<C, S> {
+    /// Open a WebSocket connection to `url` (`ws://` or `wss://`). The upgrade request carries
+    /// the cookies in the store for `url`, and may be further modified by `prepare` (e.g. to add an
+    /// `Origin` header). Any Set-Cookie headers on the handshake response are stored.
+    pub fn websocket_with<U, P>(
+        &mut self,
+        url: U,
+        prepare: P,
+    ) -> Result<(WebSocket<AutoStream>, Response), WebSocketSessionError>
+    where
+        U: IntoUrl,
+        P: FnOnce(Request) -> Request,
+    {
+        let url = self.request_url(url.into_url()?);
+        let cookie_url = cookie_url(&url);
+        self.store.refresh(&cookie_url);
+        let request = {
+            let cookies = self.store.get_request_cookies(&cookie_url);
+            let request = url.into_client_request()?.add_cookies(cookies.iter().collect());
+            prepare(request)
+        };
+        let (socket, response) = tungstenite::connect(request)?;
+        self.store_response_cookies(response.parse_set_cookie(), &cookie_url);
+        Ok((socket, response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cookie_url;
+    use crate::session::{SessionRequest, SessionResponse};
+    use cookie::Cookie as RawCookie;
+    use tungstenite::handshake::client::{Request, Response};
+    use tungstenite::http::header::{COOKIE, SET_COOKIE};
+    use url::Url;
+
+    #[test]
+    fn cookie_url_scheme() {
+        let url = |s| Url::parse(s).unwrap();
+        assert_eq!(
+            url("http://example.com/chat"),
+            cookie_url(&url("ws://example.com/chat"))
+        );
+        assert_eq!(
+            url("https://example.com:8443/chat?room=1"),
+            cookie_url(&url("wss://example.com:8443/chat?room=1"))
+        );
+    }
+
+    #[test]
+    fn upgrade_cookies() {
+        let c1 = RawCookie::parse("a=1").unwrap();
+        let c2 = RawCookie::parse("b=2").unwrap();
+        let request = Request::get("ws://example.com/")
+            .body(())
+            .unwrap()
+            .add_cookies(vec![&c1, &c2]);
+        let sent = request
+            .headers()
+            .get_all(COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["a=1", "b=2"], sent);
+
+        let response = Response::builder()
+            .status(101)
+            .header(SET_COOKIE, "c=3; Path=/")
+            .header(SET_COOKIE, "d=4")
+            .body(())
+            .unwrap();
+        let received = response.parse_set_cookie();
+        assert_eq!(2, received.len());
+        assert_eq!("c", received[0].name());
+        assert_eq!("4", received[1].value());
+    }
+}